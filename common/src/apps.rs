@@ -0,0 +1,83 @@
+//! The processes behind windows, as `consolation-shelld` manages them.
+//!
+//! Paused windows, playtime, time limits and windows that won't close are
+//! the daemon's to keep track of rather than the switcher's, so nothing is
+//! left stopped, uncounted or unenforced when the switcher isn't running.
+//! Clients connect to [`socket_path`], are sent an [`AppsSignal::Status`]
+//! then every change, and send [`AppsAction`]s back, using
+//! [`crate::ipc::send`] and [`crate::ipc::read_messages`].
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use crate::limits::LimitPeriod;
+use crate::process::{ProcessInfo, Signal};
+use crate::{ipc, ToplevelId};
+
+/// Sent from `consolation-shelld` to its apps clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AppsSignal {
+    /// Sent on connecting and after every change.
+    Status {
+        /// Windows whose processes are stopped.
+        paused: Vec<ToplevelId>,
+        /// Windows that have ignored a close, or a SIGTERM, for longer
+        /// than `close_timeout`, and can be force quit.
+        overdue: Vec<ToplevelId>,
+    },
+    /// Playtime has been written to [`crate::playtime::PlaytimeTracker::path`],
+    /// for clients showing it to load again.
+    Playtime,
+    /// A rule has run out, its windows have been paused or closed and
+    /// won't be activated. Sent every time something is refused.
+    TimeUp { rule: String, period: LimitPeriod },
+    /// The PIN was right and [`AppsAction::GrantTime`] added time.
+    Granted { rule: String },
+    /// The PIN was wrong, or came too soon after a wrong one. Nothing will
+    /// be checked for this many seconds.
+    WrongPin { retry_after: u64 },
+    /// The answer to [`AppsAction::ForceQuit`], for the user to confirm.
+    /// `processes` are exactly those that will be signalled, and is empty
+    /// if none could be found. `by_name` is set when none of them are the
+    /// compositor's clients, so they were only matched by name.
+    ForceQuit {
+        id: ToplevelId,
+        signal: Signal,
+        processes: Vec<ProcessInfo>,
+        by_name: bool,
+    },
+}
+
+/// Sent from clients to `consolation-shelld`. Actions on windows that have
+/// since closed are ignored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AppsAction {
+    /// SIGSTOP every process belonging to the window.
+    Pause { id: ToplevelId },
+    /// SIGCONT whatever pausing stopped.
+    Resume { id: ToplevelId },
+    /// Add `extra_minutes` to a rule that has run out, if the PIN is right.
+    GrantTime { rule: String, pin: String },
+    /// Find the processes behind a window that won't close, and the signal
+    /// to send them. SIGTERM first, SIGKILL once that has been ignored.
+    ForceQuit { id: ToplevelId },
+    /// Send the signal last offered for the window.
+    ConfirmForceQuit { id: ToplevelId },
+}
+
+/// `$XDG_RUNTIME_DIR/consolation/apps.sock`
+pub fn socket_path() -> Option<PathBuf> {
+    ipc::runtime_socket("apps.sock")
+}
+
+/// Connect to `consolation-shelld`, which should already be running for
+/// the switcher's window list.
+pub fn connect() -> io::Result<UnixStream> {
+    let path = socket_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+    UnixStream::connect(path)
+}
@@ -0,0 +1,216 @@
+//! Screen brightness.
+//!
+//! A [`Backlight`] reads and sets the brightness of the built in screen.
+//! [`SysfsBacklight`] uses `/sys/class/backlight` directly, which needs
+//! write access that users often don't have. [`LogindBacklight`] reads
+//! sysfs the same way but asks logind's `SetBrightness` to make changes,
+//! which is allowed for the active session.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use zbus::blocking;
+
+const SYSFS_ROOT: &str = "/sys/class/backlight";
+const LOGIND_NAME: &str = "org.freedesktop.login1";
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+// Preferred kinds of backlight device, best first. Firmware and platform
+// interfaces know about the panel, raw ones are the GPU's guess
+const TYPES: [&str; 3] = ["firmware", "platform", "raw"];
+
+/// A backlight device's brightness, in its own units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Brightness {
+    pub device: String,
+    pub value: u32,
+    pub max: u32,
+}
+
+impl Brightness {
+    pub fn percentage(&self) -> u8 {
+        match self.max {
+            0 => 0,
+            max => ((self.value as u64 * 100 + max as u64 / 2) / max as u64).min(100) as u8,
+        }
+    }
+
+    /// The device value for `percentage`. Anything above 0% stays lit, as
+    /// a value of 0 turns some panels off entirely.
+    pub fn value_at(&self, percentage: u8) -> u32 {
+        let value = (self.max as u64 * percentage.min(100) as u64 + 50) / 100;
+        match percentage {
+            0 => 0,
+            _ => (value as u32).max(1),
+        }
+    }
+}
+
+pub trait Backlight {
+    fn brightness(&self) -> io::Result<Brightness>;
+
+    /// Set a value between 0 and [`Brightness::max`].
+    fn set_brightness(&self, value: u32) -> io::Result<()>;
+
+    /// Set a percentage of the maximum.
+    fn set_percentage(&self, percentage: u8) -> io::Result<()> {
+        let brightness = self.brightness()?;
+        self.set_brightness(brightness.value_at(percentage))
+    }
+}
+
+/// Reads and writes a `backlight` class directory.
+#[derive(Debug, Clone)]
+pub struct SysfsBacklight {
+    root: PathBuf,
+}
+
+impl SysfsBacklight {
+    /// Use the devices in `root`. Tests point this at a fake tree.
+    pub fn new(root: impl Into<PathBuf>) -> SysfsBacklight {
+        SysfsBacklight { root: root.into() }
+    }
+
+    /// `/sys/class/backlight`
+    pub fn system() -> SysfsBacklight {
+        SysfsBacklight::new(SYSFS_ROOT)
+    }
+
+    /// The device to use, by type and then name. `None` when there isn't
+    /// one, like on a desktop with an external monitor.
+    pub fn device(&self) -> Option<PathBuf> {
+        let mut devices: Vec<_> = std::fs::read_dir(&self.root)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.join("max_brightness").exists())
+            .collect();
+        devices.sort_by_key(|path| {
+            let kind = attribute(path, "type").unwrap_or_default();
+            let rank = TYPES.iter().position(|other| *other == kind);
+            (
+                rank.unwrap_or(TYPES.len()),
+                path.file_name().map(|name| name.to_owned()),
+            )
+        });
+        devices.into_iter().next()
+    }
+
+    /// True when brightness can be written without help, as root or with
+    /// a udev rule granting access.
+    pub fn is_writable(&self) -> bool {
+        self.device().is_some_and(|device| {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(device.join("brightness"))
+                .is_ok()
+        })
+    }
+
+    fn existing_device(&self) -> io::Result<PathBuf> {
+        self.device().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No backlight in {}", self.root.display()),
+            )
+        })
+    }
+}
+
+impl Backlight for SysfsBacklight {
+    fn brightness(&self) -> io::Result<Brightness> {
+        let device = self.existing_device()?;
+        // actual_brightness is what the hardware is doing, brightness only
+        // what was last asked for
+        let value = number(&device, "actual_brightness")
+            .or_else(|| number(&device, "brightness"))
+            .ok_or_else(|| io::Error::other("Unreadable brightness"))?;
+        let max = number(&device, "max_brightness")
+            .ok_or_else(|| io::Error::other("Unreadable max_brightness"))?;
+        Ok(Brightness {
+            device: device
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            value: value.min(max),
+            max,
+        })
+    }
+
+    fn set_brightness(&self, value: u32) -> io::Result<()> {
+        let device = self.existing_device()?;
+        let max = number(&device, "max_brightness").unwrap_or(value);
+        std::fs::write(device.join("brightness"), value.min(max).to_string())
+    }
+}
+
+/// Reads sysfs, and sets brightness through the logind session.
+pub struct LogindBacklight {
+    sysfs: SysfsBacklight,
+    session: blocking::Proxy<'static>,
+}
+
+impl LogindBacklight {
+    /// The caller's session on the system bus.
+    pub fn system(sysfs: SysfsBacklight) -> zbus::Result<LogindBacklight> {
+        LogindBacklight::with_connection(sysfs, blocking::Connection::system()?)
+    }
+
+    /// A logind on the bus at `address`, for tests.
+    pub fn at_address(sysfs: SysfsBacklight, address: &str) -> zbus::Result<LogindBacklight> {
+        let connection = blocking::connection::Builder::address(address)?.build()?;
+        LogindBacklight::with_connection(sysfs, connection)
+    }
+
+    fn with_connection(
+        sysfs: SysfsBacklight,
+        connection: blocking::Connection,
+    ) -> zbus::Result<LogindBacklight> {
+        let session =
+            blocking::Proxy::new(&connection, LOGIND_NAME, SESSION_PATH, SESSION_INTERFACE)?;
+        Ok(LogindBacklight { sysfs, session })
+    }
+}
+
+impl Backlight for LogindBacklight {
+    fn brightness(&self) -> io::Result<Brightness> {
+        self.sysfs.brightness()
+    }
+
+    fn set_brightness(&self, value: u32) -> io::Result<()> {
+        let brightness = self.sysfs.brightness()?;
+        self.session
+            .call_method(
+                "SetBrightness",
+                &("backlight", brightness.device, value.min(brightness.max)),
+            )
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+}
+
+/// The screen's backlight, written directly when allowed and through
+/// logind otherwise. `None` without a backlight.
+pub fn system_backlight() -> Option<Box<dyn Backlight + Send>> {
+    let sysfs = SysfsBacklight::system();
+    sysfs.device()?;
+    if sysfs.is_writable() {
+        return Some(Box::new(sysfs));
+    }
+    match LogindBacklight::system(sysfs.clone()) {
+        Ok(logind) => Some(Box::new(logind)),
+        Err(e) => {
+            println!("logind unavailable, brightness may be read only: {}", e);
+            Some(Box::new(sysfs))
+        }
+    }
+}
+
+fn attribute(path: &Path, name: &str) -> Option<String> {
+    std::fs::read_to_string(path.join(name))
+        .ok()
+        .map(|value| value.trim().to_owned())
+}
+
+fn number(path: &Path, name: &str) -> Option<u32> {
+    attribute(path, name)?.parse().ok()
+}
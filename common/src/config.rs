@@ -0,0 +1,420 @@
+//! `shell.toml`, the configuration every binary reads.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::hooks::Hook;
+use crate::launcher::{desktop, profile::LaunchProfile, retroarch::RetroArchConfig};
+use crate::limits::LimitsConfig;
+use crate::nightlight::Schedule;
+use crate::rules::WindowRuleConfig;
+
+/// Shell wide configuration, read from $XDG_CONFIG_HOME/consolation/shell.toml
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ShellConfig {
+    pub switcher: SwitcherConfig,
+    pub launcher: LauncherConfig,
+    pub profiles: HashMap<String, LaunchProfile>,
+    pub limits: LimitsConfig,
+    pub hooks: Vec<Hook>,
+    pub window_rules: Vec<WindowRuleConfig>,
+    pub notifications: NotificationsConfig,
+    pub power: PowerConfig,
+    pub desktop: DesktopConfig,
+    pub night_light: NightLightConfig,
+    pub idle: IdleConfig,
+    pub lock: LockConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SwitcherConfig {
+    /// Seconds to wait after asking a window to close before offering to
+    /// force quit it
+    pub close_timeout: u64,
+    /// SIGSTOP the foreground game when switching to another window
+    pub auto_pause: bool,
+    /// app_ids auto_pause applies to. When empty, anything whose desktop
+    /// file is in the Game category
+    pub auto_pause_apps: Vec<String>,
+}
+
+impl Default for SwitcherConfig {
+    fn default() -> Self {
+        SwitcherConfig {
+            close_timeout: 5,
+            auto_pause: false,
+            auto_pause_apps: vec![],
+        }
+    }
+}
+
+impl SwitcherConfig {
+    pub fn is_game(&self, app_id: &str) -> bool {
+        if !self.auto_pause_apps.is_empty() {
+            return self.auto_pause_apps.iter().any(|a| a == app_id);
+        }
+        desktop::categories_for_app_id(app_id)
+            .iter()
+            .any(|c| c == "Game")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LauncherConfig {
+    /// Include applications from XDG .desktop files
+    pub desktop_entries: bool,
+    pub retroarch: Option<RetroArchConfig>,
+    /// Launcher entry ID or program name -> names of profiles to apply
+    pub assign: HashMap<String, Vec<String>>,
+}
+
+impl Default for LauncherConfig {
+    fn default() -> Self {
+        LauncherConfig {
+            desktop_entries: true,
+            retroarch: None,
+            assign: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Seconds a notification stays up when the sender leaves it to us
+    pub timeout: u64,
+    /// Toasts shown at once, the rest wait for a free spot
+    pub max_toasts: usize,
+    /// Toast width in logical pixels
+    pub width: u32,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        NotificationsConfig {
+            timeout: 8,
+            max_toasts: 3,
+            width: 720,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PowerConfig {
+    /// Notify about the battery and AC adapter
+    pub warnings: bool,
+    /// Battery percentages to warn at on the way down
+    pub low: Vec<u8>,
+    /// Battery percentage for the critical warning, which stays until
+    /// dismissed
+    pub critical: u8,
+    /// Seconds between checks
+    pub interval: u64,
+    /// Controller battery percentage to warn at
+    pub controller_low: u8,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        PowerConfig {
+            warnings: true,
+            low: vec![20, 10],
+            critical: 5,
+            interval: 30,
+            controller_low: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DesktopConfig {
+    /// Used on outputs without a background of their own
+    pub background: BackgroundConfig,
+    /// Output name, like "HDMI-A-1" -> its background
+    pub outputs: HashMap<String, BackgroundConfig>,
+    /// Show the focused app's own image over the background, when it has one
+    pub app_themed: bool,
+    /// app_id -> image, for apps without a Steam library hero
+    pub app_images: HashMap<String, String>,
+    /// Milliseconds to crossfade between images
+    pub fade: u64,
+}
+
+impl Default for DesktopConfig {
+    fn default() -> Self {
+        DesktopConfig {
+            background: BackgroundConfig::default(),
+            outputs: HashMap::new(),
+            app_themed: true,
+            app_images: HashMap::new(),
+            fade: 500,
+        }
+    }
+}
+
+impl DesktopConfig {
+    pub fn background_for(&self, output: Option<&str>) -> &BackgroundConfig {
+        output
+            .and_then(|name| self.outputs.get(name))
+            .unwrap_or(&self.background)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NightLightConfig {
+    /// On when consolation-shelld starts. Toggled from the switcher after
+    pub enabled: bool,
+    /// Colour temperature at night, in kelvin. Daytime is 6500
+    pub temperature: u32,
+    /// Local times of day, "HH:MM", night starts and ends
+    pub start: String,
+    pub end: String,
+    /// Follow sunset and sunrise here instead of start and end, in degrees
+    /// north and east
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Minutes to fade over, around each change
+    pub transition: u32,
+    /// Leave colours alone while a fullscreen game is in front
+    pub pause_for_games: bool,
+}
+
+impl Default for NightLightConfig {
+    fn default() -> Self {
+        NightLightConfig {
+            enabled: false,
+            temperature: 4000,
+            start: "21:00".to_owned(),
+            end: "07:00".to_owned(),
+            latitude: None,
+            longitude: None,
+            transition: 60,
+            pause_for_games: true,
+        }
+    }
+}
+
+impl NightLightConfig {
+    /// Times that don't parse fall back to the defaults
+    pub fn schedule(&self) -> Schedule {
+        if let (Some(latitude), Some(longitude)) = (self.latitude, self.longitude) {
+            return Schedule::Sun {
+                latitude,
+                longitude,
+            };
+        }
+        let defaults = NightLightConfig::default();
+        let minutes = |time: &str, default: &str| {
+            parse_time_of_day(time).unwrap_or_else(|| {
+                println!("Unable to parse night light time {}, expected HH:MM", time);
+                parse_time_of_day(default).unwrap_or_default()
+            })
+        };
+        Schedule::Fixed {
+            dusk: minutes(&self.start, &defaults.start),
+            dawn: minutes(&self.end, &defaults.end),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    pub enabled: bool,
+    /// Seconds without input before dimming and blanking the screen, 0 for
+    /// never
+    pub dim_after: u64,
+    pub blank_after: u64,
+    /// Brightness percentage to dim to. Screens already darker are left
+    pub dim_level: u8,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        IdleConfig {
+            enabled: true,
+            dim_after: 300,
+            blank_after: 600,
+            dim_level: 20,
+        }
+    }
+}
+
+impl IdleConfig {
+    pub fn dim_after(&self) -> Option<Duration> {
+        (self.dim_after > 0).then(|| Duration::from_secs(self.dim_after))
+    }
+
+    pub fn blank_after(&self) -> Option<Duration> {
+        (self.blank_after > 0).then(|| Duration::from_secs(self.blank_after))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LockConfig {
+    /// Hash from `consolation-switcher --hash-pin`. Without one the PIN is
+    /// checked through PAM, as the user's password
+    pub pin_hash: Option<String>,
+    /// PAM service to check with
+    pub pam_service: String,
+    /// Lock before suspending
+    pub on_suspend: bool,
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        LockConfig {
+            pin_hash: None,
+            pam_service: "login".to_owned(),
+            on_suspend: true,
+        }
+    }
+}
+
+// "HH:MM" to minutes past midnight
+fn parse_time_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackgroundConfig {
+    // Colours are "#rrggbb"
+    Colour {
+        colour: String,
+    },
+    Gradient {
+        from: String,
+        to: String,
+        // Degrees, 0 runs bottom to top
+        #[serde(default)]
+        angle: f32,
+    },
+    Image {
+        path: String,
+        #[serde(default)]
+        fit: ImageFit,
+    },
+    // Every image in the directory in name order, changing every interval
+    // seconds
+    Slideshow {
+        directory: String,
+        #[serde(default = "default_slideshow_interval")]
+        interval: u64,
+        #[serde(default)]
+        fit: ImageFit,
+    },
+}
+
+impl Default for BackgroundConfig {
+    fn default() -> Self {
+        BackgroundConfig::Colour {
+            colour: "#000000".to_owned(),
+        }
+    }
+}
+
+fn default_slideshow_interval() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFit {
+    // Fill the output, cropping what doesn't fit
+    #[default]
+    Crop,
+    // Show the whole image, with black bars
+    Scale,
+    // Fill the output, ignoring the aspect ratio
+    Stretch,
+}
+
+impl ShellConfig {
+    /// Profiles assigned to a launcher entry or program
+    pub fn profiles_for(&self, key: &str) -> Vec<&LaunchProfile> {
+        let Some(names) = self.launcher.assign.get(key) else {
+            return vec![];
+        };
+        names
+            .iter()
+            .filter_map(|name| self.profiles.get(name))
+            .collect()
+    }
+
+    /// Drop assignments of profiles that don't exist, saying so once
+    /// rather than on every launch. Returns the entries and names dropped
+    pub fn drop_unknown_profiles(&mut self) -> Vec<(String, String)> {
+        let mut unknown = vec![];
+        for (key, names) in self.launcher.assign.iter_mut() {
+            names.retain(|name| {
+                let known = self.profiles.contains_key(name);
+                if !known {
+                    unknown.push((key.clone(), name.clone()));
+                }
+                known
+            });
+        }
+        unknown.sort();
+        unknown
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        let dirs = xdg::BaseDirectories::with_prefix("consolation").ok()?;
+        dirs.find_config_file("shell.toml")
+    }
+
+    /// Where settings are saved, creating the directory if needed
+    pub fn write_path() -> Option<PathBuf> {
+        let dirs = xdg::BaseDirectories::with_prefix("consolation").ok()?;
+        dirs.place_config_file("shell.toml").ok()
+    }
+
+    /// Missing or broken config falls back to defaults, the shell should
+    /// always come up
+    pub fn load() -> ShellConfig {
+        let Some(path) = ShellConfig::path() else {
+            return ShellConfig::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => match toml::from_str::<ShellConfig>(&contents) {
+                Ok(mut config) => {
+                    for (key, name) in config.drop_unknown_profiles() {
+                        println!("Unknown launch profile {} assigned to {}", name, key);
+                    }
+                    config
+                }
+                Err(e) => {
+                    println!("Unable to parse {}: {}", path.display(), e);
+                    ShellConfig::default()
+                }
+            },
+            Err(e) => {
+                println!("Unable to read {}: {}", path.display(), e);
+                ShellConfig::default()
+            }
+        }
+    }
+}
+
+/// Expand a leading ~ in user supplied paths
+pub fn expand_home(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = std::env::var_os("HOME") {
+            return PathBuf::from(home).join(rest);
+        }
+    }
+    PathBuf::from(path)
+}
@@ -0,0 +1,100 @@
+//! Editing `shell.toml` in place, for the settings app.
+//!
+//! Changes are made to the document as the user wrote it, so saving keeps
+//! their comments, ordering and anything we don't know about.
+
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, Value};
+
+use crate::config::ShellConfig;
+
+pub struct ConfigEdit {
+    path: PathBuf,
+    document: DocumentMut,
+}
+
+impl ConfigEdit {
+    /// A missing file starts empty. A broken one is an error rather than
+    /// something to write over
+    pub fn load(path: &Path) -> Result<ConfigEdit, String> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Unable to read {}: {}", path.display(), e)),
+        };
+        let document = contents
+            .parse::<DocumentMut>()
+            .map_err(|e| format!("Unable to parse {}: {}", path.display(), e))?;
+        Ok(ConfigEdit {
+            path: path.to_owned(),
+            document,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The typed config the file would load as, defaults included
+    pub fn config(&self) -> Result<ShellConfig, String> {
+        toml::from_str(&self.document.to_string()).map_err(|e| e.to_string())
+    }
+
+    pub fn get(&self, key: &[&str]) -> Option<&Item> {
+        let (last, tables) = key.split_last()?;
+        let mut item = self.document.as_item();
+        for name in tables {
+            item = item.get(name)?;
+        }
+        item.get(last)
+    }
+
+    /// Set a value, adding any tables on the way. Existing tables keep
+    /// their style, inline or not
+    pub fn set(&mut self, key: &[&str], value: impl Into<Value>) {
+        let Some((last, tables)) = key.split_last() else {
+            return;
+        };
+        let mut item = self.document.as_item_mut();
+        for name in tables {
+            if !item.get(name).is_some_and(|item| item.is_table_like()) {
+                let mut table = toml_edit::Table::new();
+                // Only the tables with values get a [header]
+                table.set_implicit(true);
+                item[name] = Item::Table(table);
+            }
+            item = &mut item[name];
+        }
+        match item.get_mut(last).and_then(|item| item.as_value_mut()) {
+            // Keeps any comment after the value
+            Some(existing) => {
+                let decor = existing.decor().clone();
+                *existing = value.into();
+                *existing.decor_mut() = decor;
+            }
+            None => item[last] = toml_edit::value(value),
+        }
+    }
+
+    /// Make some changes, keeping them only if the document still loads.
+    /// Otherwise it is put back as it was and the error returned
+    pub fn change(&mut self, change: impl FnOnce(&mut ConfigEdit)) -> Result<ShellConfig, String> {
+        let before = self.document.clone();
+        change(self);
+        self.config().inspect_err(|_| self.document = before)
+    }
+
+    /// Only written when it loads as a valid config, and then all at once
+    /// so nothing reading it sees half a file
+    pub fn save(&self) -> Result<ShellConfig, String> {
+        let config = self.config()?;
+        let contents = self.document.to_string();
+        let partial = self.path.with_extension("toml.partial");
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(dir)
+            .and_then(|_| std::fs::write(&partial, contents))
+            .and_then(|_| std::fs::rename(&partial, &self.path))
+            .map_err(|e| format!("Unable to write {}: {}", self.path.display(), e))?;
+        Ok(config)
+    }
+}
@@ -0,0 +1,130 @@
+//! User commands run when windows open, close or change.
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::{ToplevelDetails, ToplevelState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookEvent {
+    New,
+    Title,
+    Activated,
+    Fullscreen,
+    Closed,
+}
+
+impl HookEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            HookEvent::New => "new",
+            HookEvent::Title => "title",
+            HookEvent::Activated => "activated",
+            HookEvent::Fullscreen => "fullscreen",
+            HookEvent::Closed => "closed",
+        }
+    }
+}
+
+/// `[[hooks]]` in shell.toml
+#[derive(Debug, Clone, Deserialize)]
+pub struct Hook {
+    pub event: HookEvent,
+    pub command: Vec<String>,
+    /// Only run for these app_ids. Empty runs for every window
+    #[serde(default)]
+    pub app_ids: Vec<String>,
+}
+
+// What the hook gets as JSON on stdin
+#[derive(Serialize)]
+struct HookPayload<'a> {
+    event: HookEvent,
+    app_id: Option<&'a str>,
+    title: Option<&'a str>,
+    state: Vec<&'static str>,
+}
+
+/// Work out which events a Done represents, given what we had at the
+/// previous Done for the same toplevel
+pub fn events_for(previous: Option<&ToplevelDetails>, current: &ToplevelDetails) -> Vec<HookEvent> {
+    let Some(previous) = previous else {
+        let mut events = vec![HookEvent::New];
+        if current.state.contains(ToplevelState::ACTIVATED) {
+            events.push(HookEvent::Activated);
+        }
+        if current.state.contains(ToplevelState::FULLSCREEN) {
+            events.push(HookEvent::Fullscreen);
+        }
+        return events;
+    };
+    let mut events = vec![];
+    if previous.title != current.title {
+        events.push(HookEvent::Title);
+    }
+    let gained = current.state.difference(previous.state);
+    if gained.contains(ToplevelState::ACTIVATED) {
+        events.push(HookEvent::Activated);
+    }
+    if gained.contains(ToplevelState::FULLSCREEN) {
+        events.push(HookEvent::Fullscreen);
+    }
+    events
+}
+
+pub fn run(hooks: &[Hook], event: HookEvent, details: &ToplevelDetails) {
+    for hook in hooks.iter().filter(|hook| hook.event == event) {
+        if !hook.app_ids.is_empty()
+            && !hook
+                .app_ids
+                .iter()
+                .any(|app_id| Some(app_id) == details.app_id.as_ref())
+        {
+            continue;
+        }
+        spawn(hook, event, details);
+    }
+}
+
+// Details go in both the environment and as JSON on stdin, scripts can
+// use whichever is easier
+fn spawn(hook: &Hook, event: HookEvent, details: &ToplevelDetails) {
+    let Some((program, args)) = hook.command.split_first() else {
+        return;
+    };
+    let states = details.state.names();
+    let payload = HookPayload {
+        event,
+        app_id: details.app_id.as_deref(),
+        title: details.title.as_deref(),
+        state: states.clone(),
+    };
+    let json = serde_json::to_string(&payload).unwrap_or_default();
+    let child = Command::new(program)
+        .args(args)
+        .env("CONSOLATION_EVENT", event.as_str())
+        .env(
+            "CONSOLATION_APP_ID",
+            details.app_id.as_deref().unwrap_or(""),
+        )
+        .env("CONSOLATION_TITLE", details.title.as_deref().unwrap_or(""))
+        .env("CONSOLATION_STATE", states.join(","))
+        .stdin(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            println!("Unable to run hook {}: {}", program, e);
+            return;
+        }
+    };
+    // Don't hold up the Wayland thread on slow scripts
+    std::thread::spawn(move || {
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(json.as_bytes());
+        }
+        let _ = child.wait();
+    });
+}
@@ -0,0 +1,163 @@
+//! Controller input, read from the joystick devices.
+//!
+//! Games often read controllers themselves, so that input never reaches
+//! the compositor and can't keep the seat from going idle. logind gives
+//! the active session access to `/dev/input/js*`, which is enough to see
+//! that somebody is playing.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const INPUT_DIR: &str = "/dev/input";
+// How often to look for controllers plugged in since
+const RESCAN: Duration = Duration::from_secs(5);
+
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+// Sent once per button and axis on opening, with the current state
+const JS_EVENT_INIT: u8 = 0x80;
+
+/// How far an axis has to move to count, out of 32767. Worn sticks and
+/// triggers drift by less than this at rest.
+pub const AXIS_THRESHOLD: i32 = 4000;
+
+/// A `struct js_event` from the kernel's joystick interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsEvent {
+    pub value: i16,
+    pub kind: u8,
+    pub number: u8,
+}
+
+impl JsEvent {
+    pub fn parse(bytes: [u8; 8]) -> JsEvent {
+        // The first four bytes are a timestamp
+        JsEvent {
+            value: i16::from_ne_bytes([bytes[4], bytes[5]]),
+            kind: bytes[6],
+            number: bytes[7],
+        }
+    }
+
+    pub fn is_init(&self) -> bool {
+        self.kind & JS_EVENT_INIT != 0
+    }
+
+    pub fn is_button(&self) -> bool {
+        self.kind & !JS_EVENT_INIT == JS_EVENT_BUTTON
+    }
+
+    pub fn is_axis(&self) -> bool {
+        self.kind & !JS_EVENT_INIT == JS_EVENT_AXIS
+    }
+}
+
+/// Tells real input apart from axis noise, for one device.
+#[derive(Debug, Default)]
+pub struct ActivityFilter {
+    // Where each axis was when it last counted
+    axes: HashMap<u8, i16>,
+}
+
+impl ActivityFilter {
+    pub fn new() -> ActivityFilter {
+        ActivityFilter::default()
+    }
+
+    pub fn is_activity(&mut self, event: &JsEvent) -> bool {
+        if event.is_axis() {
+            if event.is_init() {
+                self.axes.insert(event.number, event.value);
+                return false;
+            }
+            // Measured from where the axis last counted, so jitter around
+            // one spot never does
+            let moved = self
+                .axes
+                .get(&event.number)
+                .is_none_or(|last| (event.value as i32 - *last as i32).abs() >= AXIS_THRESHOLD);
+            if moved {
+                self.axes.insert(event.number, event.value);
+            }
+            return moved;
+        }
+        event.is_button() && !event.is_init()
+    }
+}
+
+/// Call `on_activity` from background threads whenever a controller is
+/// used, including ones connected later. Everything stops once it returns
+/// false.
+pub fn watch<F>(on_activity: F)
+where
+    F: Fn() -> bool + Send + Sync + 'static,
+{
+    let on_activity = Arc::new(on_activity);
+    let open: Arc<Mutex<HashSet<PathBuf>>> = Arc::default();
+    let stopped = Arc::new(AtomicBool::new(false));
+    std::thread::spawn(move || {
+        // Reported once each, they won't become readable by themselves
+        let mut unreadable = HashSet::new();
+        while !stopped.load(Ordering::Relaxed) {
+            for path in joysticks(Path::new(INPUT_DIR)) {
+                if open.lock().unwrap().contains(&path) || unreadable.contains(&path) {
+                    continue;
+                }
+                match File::open(&path) {
+                    Ok(file) => {
+                        open.lock().unwrap().insert(path.clone());
+                        let on_activity = on_activity.clone();
+                        let open = open.clone();
+                        let stopped = stopped.clone();
+                        std::thread::spawn(move || {
+                            if read_events(file, &*on_activity) {
+                                stopped.store(true, Ordering::Relaxed);
+                            }
+                            open.lock().unwrap().remove(&path);
+                        });
+                    }
+                    Err(e) => {
+                        println!("Unable to read controller {}: {}", path.display(), e);
+                        unreadable.insert(path);
+                    }
+                }
+            }
+            std::thread::sleep(RESCAN);
+        }
+    });
+}
+
+// True when on_activity asked to stop, false when the device went away
+fn read_events(mut file: File, on_activity: &dyn Fn() -> bool) -> bool {
+    let mut filter = ActivityFilter::new();
+    let mut bytes = [0; 8];
+    loop {
+        if file.read_exact(&mut bytes).is_err() {
+            return false;
+        }
+        if filter.is_activity(&JsEvent::parse(bytes)) && !on_activity() {
+            return true;
+        }
+    }
+}
+
+fn joysticks(dir: &Path) -> Vec<PathBuf> {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return vec![],
+        Err(e) => {
+            println!("Unable to list {}: {}", dir.display(), e);
+            return vec![];
+        }
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("js"))
+        .map(|entry| entry.path())
+        .collect()
+}
@@ -0,0 +1,125 @@
+//! Dimming and blanking the screen when nobody is using it.
+//!
+//! The compositor says when the seat has gone idle through
+//! `ext_idle_notify_v1`, see [`wayland::IdleNotifier`]. [`IdleState`]
+//! decides what to do about it, taking into account what the compositor
+//! can't see: windows that should keep the screen on, and controllers it
+//! never gets input from, which [`gamepad::watch`] reads directly.
+
+use std::time::{Duration, Instant};
+
+pub mod gamepad;
+pub mod wayland;
+
+/// Gamepad input restarts the idle timers at most this often. Restarting
+/// means new notifications from the compositor, too much for every stick
+/// movement.
+pub const RESTART_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Each stage has an idle notification of its own, with its own timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleStage {
+    Dim,
+    Blank,
+}
+
+/// What the compositor said about one of the stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleEvent {
+    Idled(IdleStage),
+    Resumed(IdleStage),
+}
+
+/// Things for the owner of an [`IdleState`] to do, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleCommand {
+    Dim,
+    Undim,
+    Blank,
+    Unblank,
+    /// Start the idle timers again from now.
+    Restart,
+}
+
+/// Whether the screen is dimmed or blanked, and whether it's allowed to
+/// be.
+#[derive(Debug, Default)]
+pub struct IdleState {
+    inhibited: bool,
+    dimmed: bool,
+    blanked: bool,
+    last_restart: Option<Instant>,
+}
+
+impl IdleState {
+    pub fn new() -> IdleState {
+        IdleState::default()
+    }
+
+    pub fn dimmed(&self) -> bool {
+        self.dimmed
+    }
+
+    pub fn blanked(&self) -> bool {
+        self.blanked
+    }
+
+    pub fn handle(&mut self, event: IdleEvent) -> Vec<IdleCommand> {
+        match event {
+            IdleEvent::Idled(_) if self.inhibited => vec![],
+            IdleEvent::Idled(IdleStage::Dim) if !self.dimmed => {
+                self.dimmed = true;
+                vec![IdleCommand::Dim]
+            }
+            IdleEvent::Idled(IdleStage::Blank) if !self.blanked => {
+                self.blanked = true;
+                vec![IdleCommand::Blank]
+            }
+            IdleEvent::Idled(_) => vec![],
+            // Either stage resuming means there was input
+            IdleEvent::Resumed(_) => self.wake(),
+        }
+    }
+
+    /// Windows that keep the screen on have come or gone. The timers start
+    /// again once the last one goes, rather than blanking straight away
+    /// after a long film.
+    pub fn set_inhibited(&mut self, inhibited: bool) -> Vec<IdleCommand> {
+        if self.inhibited == inhibited {
+            return vec![];
+        }
+        self.inhibited = inhibited;
+        match inhibited {
+            true => self.wake(),
+            false => vec![IdleCommand::Restart],
+        }
+    }
+
+    /// Controller input at `now`, which the compositor might not have
+    /// seen.
+    pub fn gamepad(&mut self, now: Instant) -> Vec<IdleCommand> {
+        if self.inhibited {
+            return vec![];
+        }
+        let mut commands = self.wake();
+        let due = self
+            .last_restart
+            .is_none_or(|last| now.duration_since(last) >= RESTART_INTERVAL);
+        if due || !commands.is_empty() {
+            self.last_restart = Some(now);
+            commands.push(IdleCommand::Restart);
+        }
+        commands
+    }
+
+    fn wake(&mut self) -> Vec<IdleCommand> {
+        let mut commands = vec![];
+        if std::mem::take(&mut self.blanked) {
+            commands.push(IdleCommand::Unblank);
+        }
+        if std::mem::take(&mut self.dimmed) {
+            commands.push(IdleCommand::Undim);
+        }
+        commands
+    }
+}
@@ -0,0 +1,219 @@
+//! Wayland side of idle dimming and blanking.
+//!
+//! [`IdleNotifier`] binds `ext_idle_notifier_v1`, and
+//! `zwlr_output_power_manager_v1` when there is one to blank with. Like
+//! [`crate::toplevel::StagingData`] it lives in the owning program's state
+//! type and is delegated to with [`delegate_idle!`](crate::delegate_idle),
+//! so it can share the seat and outputs the toplevel list already has.
+
+use std::time::Duration;
+use wayland_client::{
+    globals::GlobalList,
+    protocol::{wl_output::WlOutput, wl_seat::WlSeat},
+    Connection, Dispatch, QueueHandle,
+};
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+use wayland_protocols_wlr::output_power_management::v1::client::{
+    zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1,
+    zwlr_output_power_v1::{self, ZwlrOutputPowerV1},
+};
+
+use super::{IdleEvent, IdleStage};
+
+/// Idle notifications for each stage with a timeout, and the outputs
+/// turned off while blanked.
+pub struct IdleNotifier {
+    notifier: Option<ExtIdleNotifierV1>,
+    power_manager: Option<ZwlrOutputPowerManagerV1>,
+    timeouts: Vec<(IdleStage, Duration)>,
+    notifications: Vec<ExtIdleNotificationV1>,
+    powers: Vec<ZwlrOutputPowerV1>,
+    events: Vec<IdleEvent>,
+}
+
+impl IdleNotifier {
+    /// Stages without a timeout never happen.
+    pub fn new(dim_after: Option<Duration>, blank_after: Option<Duration>) -> IdleNotifier {
+        let timeouts = [(IdleStage::Dim, dim_after), (IdleStage::Blank, blank_after)]
+            .into_iter()
+            .filter_map(|(stage, timeout)| Some((stage, timeout?)))
+            .collect();
+        IdleNotifier {
+            notifier: None,
+            power_manager: None,
+            timeouts,
+            notifications: vec![],
+            powers: vec![],
+            events: vec![],
+        }
+    }
+
+    /// Bind the idle notifier, and the output power manager if there is
+    /// one. Fails when the compositor has no `ext_idle_notifier_v1`.
+    pub fn bind<D>(&mut self, globals: &GlobalList, qh: &QueueHandle<D>) -> Result<(), String>
+    where
+        D: Dispatch<ExtIdleNotifierV1, ()> + Dispatch<ZwlrOutputPowerManagerV1, ()> + 'static,
+    {
+        let notifier = globals
+            .bind::<ExtIdleNotifierV1, D, ()>(qh, 1..=1, ())
+            .map_err(|e| format!("No idle notifier: {}", e))?;
+        self.notifier = Some(notifier);
+        self.power_manager = globals
+            .bind::<ZwlrOutputPowerManagerV1, D, ()>(qh, 1..=1, ())
+            .map_err(|e| println!("Unable to blank the screen: {}", e))
+            .ok();
+        Ok(())
+    }
+
+    /// Whether the idle timers are running, which needs a seat.
+    pub fn started(&self) -> bool {
+        !self.notifications.is_empty()
+    }
+
+    /// Start every stage's timer again from now. The compositor measures
+    /// from when a notification is created, so they're replaced.
+    pub fn restart<D>(&mut self, seat: &WlSeat, qh: &QueueHandle<D>)
+    where
+        D: Dispatch<ExtIdleNotificationV1, IdleStage> + 'static,
+    {
+        let Some(notifier) = &self.notifier else {
+            return;
+        };
+        for notification in self.notifications.drain(..) {
+            notification.destroy();
+        }
+        for (stage, timeout) in self.timeouts.iter() {
+            let millis = timeout.as_millis().min(u32::MAX as u128) as u32;
+            let notification = notifier.get_idle_notification(millis, seat, qh, *stage);
+            self.notifications.push(notification);
+        }
+    }
+
+    /// What the compositor has said since the last call.
+    pub fn take_events(&mut self) -> Vec<IdleEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Turn `outputs` off, or every output turned off back on with
+    /// `None`. Does nothing without an output power manager.
+    pub fn set_blanked<D>(&mut self, outputs: Option<Vec<WlOutput>>, qh: &QueueHandle<D>)
+    where
+        D: Dispatch<ZwlrOutputPowerV1, ()> + 'static,
+    {
+        for power in self.powers.drain(..) {
+            power.set_mode(zwlr_output_power_v1::Mode::On);
+            power.destroy();
+        }
+        let (Some(manager), Some(outputs)) = (&self.power_manager, outputs) else {
+            return;
+        };
+        for output in outputs {
+            let power = manager.get_output_power(&output, qh, ());
+            power.set_mode(zwlr_output_power_v1::Mode::Off);
+            self.powers.push(power);
+        }
+    }
+}
+
+/// Implement `Dispatch` for everything [`IdleNotifier`] binds on a type
+/// that is `AsMut<IdleNotifier>`.
+#[macro_export]
+macro_rules! delegate_idle {
+    ($ty:ty) => {
+        $crate::wayland_client::delegate_dispatch!($ty: [
+            $crate::wayland_protocols::ext::idle_notify::v1::client::ext_idle_notifier_v1::ExtIdleNotifierV1: ()
+        ] => $crate::idle::wayland::IdleNotifier);
+        $crate::wayland_client::delegate_dispatch!($ty: [
+            $crate::wayland_protocols::ext::idle_notify::v1::client::ext_idle_notification_v1::ExtIdleNotificationV1: $crate::idle::IdleStage
+        ] => $crate::idle::wayland::IdleNotifier);
+        $crate::wayland_client::delegate_dispatch!($ty: [
+            $crate::wayland_protocols_wlr::output_power_management::v1::client::zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1: ()
+        ] => $crate::idle::wayland::IdleNotifier);
+        $crate::wayland_client::delegate_dispatch!($ty: [
+            $crate::wayland_protocols_wlr::output_power_management::v1::client::zwlr_output_power_v1::ZwlrOutputPowerV1: ()
+        ] => $crate::idle::wayland::IdleNotifier);
+    };
+}
+
+impl<D> Dispatch<ExtIdleNotificationV1, IdleStage, D> for IdleNotifier
+where
+    D: Dispatch<ExtIdleNotificationV1, IdleStage> + AsMut<IdleNotifier>,
+{
+    fn event(
+        state: &mut D,
+        proxy: &ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        stage: &IdleStage,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<D>,
+    ) {
+        let state = state.as_mut();
+        // Events can still arrive for notifications replaced by a restart
+        if !state.notifications.contains(proxy) {
+            return;
+        }
+        match event {
+            ext_idle_notification_v1::Event::Idled => {
+                state.events.push(IdleEvent::Idled(*stage));
+            }
+            ext_idle_notification_v1::Event::Resumed => {
+                state.events.push(IdleEvent::Resumed(*stage));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputPowerV1, (), D> for IdleNotifier
+where
+    D: Dispatch<ZwlrOutputPowerV1, ()> + AsMut<IdleNotifier>,
+{
+    fn event(
+        state: &mut D,
+        proxy: &ZwlrOutputPowerV1,
+        event: zwlr_output_power_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<D>,
+    ) {
+        // The output has gone, or another client is managing its power
+        if let zwlr_output_power_v1::Event::Failed = event {
+            let state = state.as_mut();
+            state.powers.retain(|power| power != proxy);
+            proxy.destroy();
+        }
+    }
+}
+
+impl<D> Dispatch<ExtIdleNotifierV1, (), D> for IdleNotifier
+where
+    D: Dispatch<ExtIdleNotifierV1, ()>,
+{
+    fn event(
+        _state: &mut D,
+        _proxy: &ExtIdleNotifierV1,
+        _event: <ExtIdleNotifierV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<D>,
+    ) {
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputPowerManagerV1, (), D> for IdleNotifier
+where
+    D: Dispatch<ZwlrOutputPowerManagerV1, ()>,
+{
+    fn event(
+        _state: &mut D,
+        _proxy: &ZwlrOutputPowerManagerV1,
+        _event: <ZwlrOutputPowerManagerV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<D>,
+    ) {
+    }
+}
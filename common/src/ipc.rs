@@ -0,0 +1,217 @@
+//! The socket protocol between `consolation-shelld` and its clients.
+//!
+//! Messages are JSON, one per line. A client is sent a
+//! [`ToplevelSignal::Snapshot`] as soon as it connects, then every change
+//! after that, and may send [`ToplevelAction`]s back at any time.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::ToplevelState;
+
+/// Identifies a toplevel between the daemon and its clients.
+///
+/// Wayland handles only mean something on the daemon's own connection, so
+/// the daemon hands out numbers instead. They are never reused while the
+/// daemon is running.
+pub type ToplevelId = u64;
+
+/// The switcher's own window, which clients acting on every window leave
+/// alone.
+pub const SWITCHER_APP_ID: &str = "Consolation Switcher";
+
+/// Everything known about a toplevel as of its last `done` event.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToplevelDetails {
+    pub title: Option<String>,
+    pub app_id: Option<String>,
+    pub state: ToplevelState,
+    /// The toplevel this one is a dialog or other child of
+    pub parent: Option<ToplevelId>,
+}
+
+/// Sent from `consolation-shelld` to its clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToplevelSignal {
+    /// The whole current state, sent once on connecting.
+    Snapshot {
+        toplevels: Vec<(ToplevelId, ToplevelDetails)>,
+        /// Most recently activated first
+        mru: Vec<ToplevelId>,
+        outputs: Vec<String>,
+    },
+    /// A toplevel appeared or changed.
+    AddUpdateToplevel {
+        id: ToplevelId,
+        details: ToplevelDetails,
+    },
+    RemoveToplevel {
+        id: ToplevelId,
+    },
+    /// The most recently used order changed, most recent first.
+    Mru {
+        order: Vec<ToplevelId>,
+    },
+    OutputChanged {
+        name: String,
+    },
+    /// The compositor went away, the daemon is exiting.
+    Closed,
+}
+
+/// Sent from clients to `consolation-shelld`, asking it to act on a toplevel.
+/// Actions on toplevels that have since closed are ignored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ToplevelAction {
+    Activate {
+        id: ToplevelId,
+    },
+    Close {
+        id: ToplevelId,
+    },
+    Minimize {
+        id: ToplevelId,
+    },
+    Unminimize {
+        id: ToplevelId,
+    },
+    Maximize {
+        id: ToplevelId,
+    },
+    Unmaximize {
+        id: ToplevelId,
+    },
+    /// `output` is an output name, the compositor picks when it is `None`
+    /// or not connected.
+    Fullscreen {
+        id: ToplevelId,
+        output: Option<String>,
+    },
+    Unfullscreen {
+        id: ToplevelId,
+    },
+}
+
+impl ToplevelAction {
+    /// The toplevel this action is for.
+    pub fn id(&self) -> ToplevelId {
+        match self {
+            ToplevelAction::Activate { id }
+            | ToplevelAction::Close { id }
+            | ToplevelAction::Minimize { id }
+            | ToplevelAction::Unminimize { id }
+            | ToplevelAction::Maximize { id }
+            | ToplevelAction::Unmaximize { id }
+            | ToplevelAction::Fullscreen { id, .. }
+            | ToplevelAction::Unfullscreen { id } => *id,
+        }
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/consolation/shelld.sock`, creating the directory if
+/// needed.
+pub fn socket_path() -> Option<PathBuf> {
+    runtime_socket("shelld.sock")
+}
+
+/// `$XDG_RUNTIME_DIR/consolation/<name>`, creating the directory if needed.
+pub fn runtime_socket(name: &str) -> Option<PathBuf> {
+    let dirs = xdg::BaseDirectories::with_prefix("consolation").ok()?;
+    dirs.place_runtime_file(name).ok()
+}
+
+/// Listen at `path`. A socket left behind by a daemon that died is
+/// replaced, one that still answers means the daemon is already running.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is already in use", path.display()),
+        ));
+    }
+    let _ = std::fs::remove_file(path);
+    UnixListener::bind(path)
+}
+
+/// Accept clients until the listener fails, with a thread per client
+/// reading its messages. `connected` gets each new client, `received` each
+/// message from any of them. Either returning false stops.
+pub fn serve<T: DeserializeOwned>(
+    listener: UnixListener,
+    mut connected: impl FnMut(UnixStream) -> bool,
+    received: impl Fn(T) -> bool + Clone + Send + 'static,
+) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Unable to accept client: {}", e);
+                continue;
+            }
+        };
+        let Ok(reader) = stream.try_clone() else {
+            continue;
+        };
+        if !connected(stream) {
+            return;
+        }
+        let received = received.clone();
+        std::thread::spawn(move || {
+            for message in read_messages(reader) {
+                if !received(message) {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// Connect to the daemon, starting `consolation-shelld` first if nothing is
+/// listening.
+pub fn connect() -> io::Result<UnixStream> {
+    let path = socket_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+    if let Ok(stream) = UnixStream::connect(&path) {
+        return Ok(stream);
+    }
+    let mut child = Command::new("consolation-shelld")
+        .stdin(Stdio::null())
+        .spawn()?;
+    std::thread::spawn(move || child.wait());
+    // Give it a moment to reach the compositor and bind the socket
+    for _ in 0..50 {
+        std::thread::sleep(Duration::from_millis(100));
+        if let Ok(stream) = UnixStream::connect(&path) {
+            return Ok(stream);
+        }
+    }
+    UnixStream::connect(&path)
+}
+
+/// Write one message as a line of JSON.
+pub fn send(mut stream: &UnixStream, message: &impl Serialize) -> io::Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+/// Messages from the other end until it goes away. Lines that don't parse
+/// are skipped, so older clients survive newer messages.
+pub fn read_messages<T: DeserializeOwned>(stream: UnixStream) -> impl Iterator<Item = T> {
+    BufReader::new(stream)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| match serde_json::from_str(&line) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                println!("Ignoring message {}: {}", line, e);
+                None
+            }
+        })
+}
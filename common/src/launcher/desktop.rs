@@ -0,0 +1,293 @@
+//! Applications from XDG `.desktop` files.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{LauncherEntry, LauncherIcon, LauncherSource};
+
+/// A parsed .desktop file. Groups are kept in file order so that
+/// `[Desktop Action ...]` sections can be listed as the author wrote them.
+/// Values are kept escaped, as lists have to be split before unescaping
+#[derive(Debug, Clone, Default)]
+pub struct DesktopFile {
+    pub id: String,
+    pub path: PathBuf,
+    pub groups: Vec<(String, HashMap<String, String>)>,
+}
+
+impl DesktopFile {
+    pub fn parse(id: &str, path: &Path, contents: &str) -> DesktopFile {
+        let mut groups: Vec<(String, HashMap<String, String>)> = vec![];
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                groups.push((name.to_owned(), HashMap::new()));
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            if let Some((_, group)) = groups.last_mut() {
+                group.insert(key.trim().to_owned(), value.trim().to_owned());
+            }
+        }
+        DesktopFile {
+            id: id.to_owned(),
+            path: path.to_owned(),
+            groups,
+        }
+    }
+
+    pub fn load(id: &str, path: &Path) -> Option<DesktopFile> {
+        let contents = std::fs::read_to_string(path).ok()?;
+        Some(DesktopFile::parse(id, path, &contents))
+    }
+
+    fn raw(&self, group: &str, key: &str) -> Option<&str> {
+        self.groups
+            .iter()
+            .find(|(name, _)| name == group)?
+            .1
+            .get(key)
+            .map(|value| value.as_str())
+    }
+
+    fn get_in(&self, group: &str, key: &str) -> Option<String> {
+        self.raw(group, key).map(unescape)
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.get_in("Desktop Entry", key)
+    }
+
+    fn get_bool(&self, key: &str) -> bool {
+        self.raw("Desktop Entry", key) == Some("true")
+    }
+
+    pub fn list(&self, key: &str) -> Vec<String> {
+        split_list(self.raw("Desktop Entry", key).unwrap_or(""))
+    }
+
+    /// Should this show up in a list of applications at all
+    pub fn is_visible(&self) -> bool {
+        self.get("Type").as_deref() == Some("Application")
+            && !self.get_bool("NoDisplay")
+            && !self.get_bool("Hidden")
+            && self.get("Exec").is_some()
+    }
+
+    /// `[Desktop Action ...]` groups listed in the Actions key, in that order
+    pub fn actions(&self) -> Vec<DesktopAction> {
+        self.list("Actions")
+            .iter()
+            .filter_map(|id| {
+                let group = format!("Desktop Action {}", id);
+                let name = self.get_in(&group, "Name")?;
+                let icon = self.get_in(&group, "Icon");
+                let exec = exec_to_argv(&self.get_in(&group, "Exec")?, &name, icon.as_deref())?;
+                Some(DesktopAction {
+                    id: id.to_owned(),
+                    name,
+                    icon,
+                    exec,
+                })
+            })
+            .collect()
+    }
+
+    pub fn to_entry(&self) -> Option<LauncherEntry> {
+        let name = self.get("Name")?;
+        let icon = self.get("Icon");
+        let exec = exec_to_argv(&self.get("Exec")?, &name, icon.as_deref())?;
+        Some(LauncherEntry {
+            id: self.id.clone(),
+            name,
+            icon: icon.map(|icon| {
+                if icon.starts_with('/') {
+                    LauncherIcon::Path(PathBuf::from(icon))
+                } else {
+                    LauncherIcon::Name(icon)
+                }
+            }),
+            categories: self.list("Categories"),
+            exec,
+            source: LauncherSource::Desktop,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DesktopAction {
+    pub id: String,
+    pub name: String,
+    pub icon: Option<String>,
+    pub exec: Vec<String>,
+}
+
+/// $XDG_DATA_HOME/applications followed by each of $XDG_DATA_DIRS
+pub fn application_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if let Ok(base) = xdg::BaseDirectories::new() {
+        dirs.push(base.get_data_home().join("applications"));
+        for dir in base.get_data_dirs() {
+            dirs.push(dir.join("applications"));
+        }
+    }
+    dirs
+}
+
+/// Desktop file ID -> path. Earlier directories take priority, so a user's
+/// override in ~/.local/share/applications wins over the system copy
+pub fn find_all() -> HashMap<String, PathBuf> {
+    let mut found = HashMap::new();
+    for dir in application_dirs() {
+        collect(&dir, &dir, &mut found);
+    }
+    found
+}
+
+fn collect(root: &Path, dir: &Path, found: &mut HashMap<String, PathBuf>) {
+    let Ok(read) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for item in read.flatten() {
+        let path = item.path();
+        if path.is_dir() {
+            collect(root, &path, found);
+            continue;
+        }
+        if path.extension().and_then(|e| e.to_str()) != Some("desktop") {
+            continue;
+        }
+        // Files in subdirectories get their path joined with - as the ID
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let id = relative
+            .with_extension("")
+            .to_string_lossy()
+            .replace('/', "-");
+        found.entry(id).or_insert(path);
+    }
+}
+
+/// Look up the desktop file for a toplevel's app_id. Most apps use their
+/// desktop file ID as app_id, but plenty only match case-insensitively or
+/// on StartupWMClass
+pub fn find_for_app_id(app_id: &str) -> Option<DesktopFile> {
+    let all = find_all();
+    if let Some(path) = all.get(app_id) {
+        return DesktopFile::load(app_id, path);
+    }
+    let lower = app_id.to_lowercase();
+    let mut fallback = None;
+    for (id, path) in all.iter() {
+        if id.to_lowercase() == lower || id.to_lowercase().ends_with(&format!(".{}", lower)) {
+            return DesktopFile::load(id, path);
+        }
+        if fallback.is_none() {
+            if let Some(file) = DesktopFile::load(id, path) {
+                if file.get("StartupWMClass").map(|c| c.to_lowercase()) == Some(lower.clone()) {
+                    fallback = Some(file);
+                }
+            }
+        }
+    }
+    fallback
+}
+
+/// Categories of the app owning a window. Steam games rarely have a desktop
+/// file matching their app_id, but are always games
+pub fn categories_for_app_id(app_id: &str) -> Vec<String> {
+    if app_id.starts_with("steam_app_") {
+        return vec!["Game".to_owned()];
+    }
+    find_for_app_id(app_id)
+        .map(|file| file.list("Categories"))
+        .unwrap_or_default()
+}
+
+pub fn load_entries() -> Vec<LauncherEntry> {
+    find_all()
+        .iter()
+        .filter_map(|(id, path)| DesktopFile::load(id, path))
+        .filter(|file| file.is_visible())
+        .filter_map(|file| file.to_entry())
+        .collect()
+}
+
+/// Turn an Exec key into argv, dropping field codes we have nothing to
+/// fill in with
+pub fn exec_to_argv(exec: &str, name: &str, icon: Option<&str>) -> Option<Vec<String>> {
+    let mut argv = vec![];
+    for arg in shlex::split(exec)? {
+        match arg.as_str() {
+            "%f" | "%F" | "%u" | "%U" | "%d" | "%D" | "%n" | "%N" | "%v" | "%m" | "%k" => {}
+            "%i" => {
+                if let Some(icon) = icon {
+                    argv.push("--icon".to_owned());
+                    argv.push(icon.to_owned());
+                }
+            }
+            "%c" => argv.push(name.to_owned()),
+            _ => argv.push(arg.replace("%%", "%")),
+        }
+    }
+    if argv.is_empty() {
+        None
+    } else {
+        Some(argv)
+    }
+}
+
+// Split on each ; that isn't escaped as \;, then unescape the items. In the
+// other order, an escaped backslash before a ; would hide the split
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![];
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => items.push(std::mem::take(&mut item)),
+            '\\' => match chars.next() {
+                Some(';') => item.push(';'),
+                Some(next) => {
+                    item.push('\\');
+                    item.push(next);
+                }
+                None => item.push('\\'),
+            },
+            c => item.push(c),
+        }
+    }
+    items.push(item);
+    items
+        .iter()
+        .filter(|item| !item.is_empty())
+        .map(|item| unescape(item))
+        .collect()
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
@@ -0,0 +1,70 @@
+//! Everything the launcher can start, and how to start it.
+
+use std::path::PathBuf;
+
+use crate::config::{LauncherConfig, ShellConfig};
+use profile::{program_name, LaunchCommand};
+
+pub mod desktop;
+pub mod profile;
+pub mod retroarch;
+
+/// Where an entry came from. Used for grouping and for deciding how to
+/// find artwork
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LauncherSource {
+    Desktop,
+    RetroArch { playlist: String },
+}
+
+#[derive(Debug, Clone)]
+pub enum LauncherIcon {
+    Name(String),
+    Path(PathBuf),
+}
+
+/// A single launchable item, native or emulated
+#[derive(Debug, Clone)]
+pub struct LauncherEntry {
+    pub id: String,
+    pub name: String,
+    pub icon: Option<LauncherIcon>,
+    pub categories: Vec<String>,
+    pub exec: Vec<String>,
+    pub source: LauncherSource,
+}
+
+impl LauncherEntry {
+    /// Final command line with any launch profiles attached to this entry
+    pub fn command(&self, config: &ShellConfig) -> LaunchCommand {
+        let mut profiles = config.profiles_for(&self.id);
+        if profiles.is_empty() {
+            if let Some(program) = self.exec.first() {
+                profiles = config.profiles_for(program_name(program));
+            }
+        }
+        LaunchCommand::with_profiles(self.exec.clone(), &profiles)
+    }
+}
+
+/// A command typed into the run prompt. Profiles are matched on the
+/// program name
+pub fn run_prompt_command(input: &str, config: &ShellConfig) -> Option<LaunchCommand> {
+    let argv = shlex::split(input)?;
+    let program = argv.first()?;
+    let profiles = config.profiles_for(program_name(program));
+    Some(LaunchCommand::with_profiles(argv, &profiles))
+}
+
+/// Collect entries from every enabled source, sorted by name
+pub fn load_entries(config: &LauncherConfig) -> Vec<LauncherEntry> {
+    let mut entries = vec![];
+    if config.desktop_entries {
+        entries.extend(desktop::load_entries());
+    }
+    if let Some(retroarch) = &config.retroarch {
+        entries.extend(retroarch::load_entries(retroarch));
+    }
+    entries.sort_by_key(|entry| entry.name.to_lowercase());
+    entries
+}
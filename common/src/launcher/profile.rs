@@ -0,0 +1,140 @@
+//! Launch profiles, wrapping commands with environment, wrappers and Proton.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::config::expand_home;
+
+/// `[profiles.<name>]` in shell.toml. Several profiles can be attached to the
+/// same entry, eg mangohud + gamescope
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LaunchProfile {
+    pub env: BTreeMap<String, String>,
+    /// Command the game is wrapped in, eg `["gamescope", "-W", "1920", "-H", "1080", "--"]`
+    pub wrapper: Vec<String>,
+    /// Extra arguments appended to the game's own
+    pub args: Vec<String>,
+    /// Name of a Proton install in compatibilitytools.d or steamapps/common
+    pub proton: Option<String>,
+    /// Wine prefix used with Proton. Defaults to `~/.local/share/consolation/proton/<program>`
+    pub proton_prefix: Option<String>,
+}
+
+/// A fully resolved command, ready to spawn or show to the user
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LaunchCommand {
+    pub env: BTreeMap<String, String>,
+    pub argv: Vec<String>,
+}
+
+impl LaunchCommand {
+    /// Apply each profile in order. Wrappers are nested so the first
+    /// profile's wrapper ends up outermost
+    pub fn with_profiles(argv: Vec<String>, profiles: &[&LaunchProfile]) -> LaunchCommand {
+        let mut env = BTreeMap::new();
+        let mut wrappers = vec![];
+        let mut args = vec![];
+        let mut proton = None;
+        for profile in profiles {
+            env.extend(profile.env.clone());
+            wrappers.extend(profile.wrapper.iter().cloned());
+            args.extend(profile.args.iter().cloned());
+            if profile.proton.is_some() {
+                proton = Some(*profile);
+            }
+        }
+        let program = argv
+            .first()
+            .map(|p| program_name(p).to_owned())
+            .unwrap_or_default();
+        let mut full = wrappers;
+        if let Some(profile) = proton {
+            let name = profile.proton.as_deref().unwrap_or_default();
+            full.push(proton_path(name).to_string_lossy().into_owned());
+            full.push("run".to_owned());
+            let prefix = match &profile.proton_prefix {
+                Some(prefix) => expand_home(prefix),
+                None => xdg::BaseDirectories::with_prefix("consolation")
+                    .map(|dirs| dirs.get_data_home().join("proton").join(&program))
+                    .unwrap_or_default(),
+            };
+            env.entry("STEAM_COMPAT_DATA_PATH".to_owned())
+                .or_insert(prefix.to_string_lossy().into_owned());
+            env.entry("STEAM_COMPAT_CLIENT_INSTALL_PATH".to_owned())
+                .or_insert(steam_root().to_string_lossy().into_owned());
+        }
+        full.extend(argv);
+        full.extend(args);
+        LaunchCommand { env, argv: full }
+    }
+
+    /// Shell style rendering, for showing the user what will actually run
+    pub fn preview(&self) -> String {
+        let mut parts = vec![];
+        for (key, value) in self.env.iter() {
+            parts.push(format!("{}={}", key, quote(value)));
+        }
+        for arg in self.argv.iter() {
+            parts.push(quote(arg));
+        }
+        parts.join(" ")
+    }
+
+    /// Start a detached process. A thread is left waiting on it so we don't
+    /// leave zombies behind while the shell is running
+    pub fn spawn(&self) -> std::io::Result<()> {
+        let Some((program, args)) = self.argv.split_first() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Empty command line",
+            ));
+        };
+        if let Some(prefix) = self.env.get("STEAM_COMPAT_DATA_PATH") {
+            let _ = std::fs::create_dir_all(prefix);
+        }
+        let mut child = Command::new(program)
+            .args(args)
+            .envs(self.env.iter())
+            .stdin(Stdio::null())
+            .spawn()?;
+        std::thread::spawn(move || {
+            let _ = child.wait();
+        });
+        Ok(())
+    }
+}
+
+/// Basename of the program, used to match run prompt commands to profiles
+pub fn program_name(program: &str) -> &str {
+    program.rsplit('/').next().unwrap_or(program)
+}
+
+fn quote(arg: &str) -> String {
+    match shlex::try_quote(arg) {
+        Ok(quoted) => quoted.into_owned(),
+        Err(_) => format!("{:?}", arg),
+    }
+}
+
+pub fn steam_root() -> PathBuf {
+    expand_home("~/.steam/steam")
+}
+
+// Custom Protons live in compatibilitytools.d, Valve's own in steamapps/common
+fn proton_path(name: &str) -> PathBuf {
+    if name.contains('/') {
+        return expand_home(name);
+    }
+    let root = steam_root();
+    let custom = root.join("compatibilitytools.d").join(name).join("proton");
+    if custom.exists() {
+        return custom;
+    }
+    root.join("steamapps")
+        .join("common")
+        .join(name)
+        .join("proton")
+}
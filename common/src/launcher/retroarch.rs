@@ -0,0 +1,387 @@
+//! Games from RetroArch playlists and scanned ROM directories.
+
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use super::{LauncherEntry, LauncherIcon, LauncherSource};
+use crate::config::expand_home;
+
+// The archives RetroArch can load a ROM from
+const ARCHIVE_EXTENSIONS: [&str; 2] = ["zip", "7z"];
+
+/// `[launcher.retroarch]` in shell.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetroArchConfig {
+    /// Command used to start RetroArch, eg `["flatpak", "run", "org.libretro.RetroArch"]`
+    pub command: Vec<String>,
+    /// RetroArch's own config directory. Playlists, cores and thumbnails
+    /// are looked up relative to this unless set explicitly
+    pub config_dir: String,
+    pub playlists: bool,
+    pub playlist_dir: Option<String>,
+    pub cores_dir: Option<String>,
+    pub thumbnails_dir: Option<String>,
+    /// Directories to scan for ROMs matching one of the rules
+    pub rom_dirs: Vec<String>,
+    pub rules: Vec<RomRule>,
+}
+
+impl Default for RetroArchConfig {
+    fn default() -> Self {
+        RetroArchConfig {
+            command: vec!["retroarch".to_owned()],
+            config_dir: "~/.config/retroarch".to_owned(),
+            playlists: true,
+            playlist_dir: None,
+            cores_dir: None,
+            thumbnails_dir: None,
+            rom_dirs: vec![],
+            rules: vec![],
+        }
+    }
+}
+
+/// Maps file extensions to either a libretro core or a standalone emulator
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RomRule {
+    pub extensions: Vec<String>,
+    /// Core name ("snes9x") or full path to a _libretro.so
+    pub core: Option<String>,
+    /// Standalone emulator command line. {rom} is replaced by the ROM path
+    pub command: Option<Vec<String>>,
+    /// RetroArch system name, eg "Nintendo - Super Nintendo Entertainment System".
+    /// Used to find thumbnails
+    pub system: Option<String>,
+}
+
+impl RomRule {
+    fn matches(&self, path: &Path) -> bool {
+        let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+            return false;
+        };
+        self.extensions
+            .iter()
+            .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(ext))
+    }
+}
+
+impl RetroArchConfig {
+    fn config_dir(&self) -> PathBuf {
+        expand_home(&self.config_dir)
+    }
+
+    fn playlist_dir(&self) -> PathBuf {
+        match &self.playlist_dir {
+            Some(dir) => expand_home(dir),
+            None => self.config_dir().join("playlists"),
+        }
+    }
+
+    fn cores_dir(&self) -> PathBuf {
+        match &self.cores_dir {
+            Some(dir) => expand_home(dir),
+            None => self.config_dir().join("cores"),
+        }
+    }
+
+    fn thumbnails_dir(&self) -> PathBuf {
+        match &self.thumbnails_dir {
+            Some(dir) => expand_home(dir),
+            None => self.config_dir().join("thumbnails"),
+        }
+    }
+
+    /// Resolve a core name to a path, checking RetroArch's cores directory
+    /// before the distro location
+    pub fn core_path(&self, core: &str) -> PathBuf {
+        if core.contains('/') {
+            return expand_home(core);
+        }
+        let file = if core.ends_with("_libretro.so") {
+            core.to_owned()
+        } else {
+            format!("{}_libretro.so", core)
+        };
+        for dir in [
+            self.cores_dir(),
+            PathBuf::from("/usr/lib/libretro"),
+            PathBuf::from("/usr/lib64/libretro"),
+            PathBuf::from("/usr/lib/x86_64-linux-gnu/libretro"),
+        ] {
+            let path = dir.join(&file);
+            if path.exists() {
+                return path;
+            }
+        }
+        self.cores_dir().join(file)
+    }
+
+    pub fn core_command(&self, core: &Path, rom: &Path) -> Vec<String> {
+        let mut argv = self.command.clone();
+        argv.push("-L".to_owned());
+        argv.push(core.to_string_lossy().into_owned());
+        argv.push(rom.to_string_lossy().into_owned());
+        argv
+    }
+
+    fn rule_for(&self, rom: &Path) -> Option<&RomRule> {
+        self.rules.iter().find(|rule| rule.matches(rom))
+    }
+
+    fn rule_command(&self, rule: &RomRule, rom: &Path) -> Option<Vec<String>> {
+        if let Some(command) = &rule.command {
+            let rom = rom.to_string_lossy();
+            return Some(
+                command
+                    .iter()
+                    .map(|arg| arg.replace("{rom}", &rom))
+                    .collect(),
+            );
+        }
+        let core = rule.core.as_ref()?;
+        Some(self.core_command(&self.core_path(core), rom))
+    }
+
+    /// RetroArch lays thumbnails out as
+    /// `<thumbnails>/<system>/Named_Boxarts/<label>.png`
+    pub fn boxart(&self, system: &str, label: &str) -> Option<PathBuf> {
+        let dir = self.thumbnails_dir().join(system).join("Named_Boxarts");
+        let path = dir.join(format!("{}.png", thumbnail_name(label)));
+        if path.exists() {
+            return Some(path);
+        }
+        // Fall back to the filename without region/revision tags
+        let short = label.split(" (").next().unwrap_or(label);
+        let path = dir.join(format!("{}.png", thumbnail_name(short)));
+        path.exists().then_some(path)
+    }
+}
+
+/// Characters RetroArch replaces with _ when naming thumbnail files
+pub fn thumbnail_name(label: &str) -> String {
+    label
+        .chars()
+        .map(|c| match c {
+            '&' | '*' | '/' | ':' | '`' | '<' | '>' | '?' | '\\' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// One game in a playlist, after resolving which format it came from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaylistItem {
+    pub path: String,
+    pub label: String,
+    pub core_path: String,
+    pub db_name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct JsonPlaylist {
+    default_core_path: String,
+    items: Vec<JsonPlaylistItem>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct JsonPlaylistItem {
+    path: String,
+    label: String,
+    core_path: String,
+    db_name: String,
+}
+
+/// Playlists are JSON since RetroArch 1.7.6. Older ones are six lines per
+/// game: path, label, core path, core name, crc, database name
+pub fn parse_playlist(contents: &str) -> Vec<PlaylistItem> {
+    if contents.trim_start().starts_with('{') {
+        let Ok(playlist) = serde_json::from_str::<JsonPlaylist>(contents) else {
+            return vec![];
+        };
+        return playlist
+            .items
+            .into_iter()
+            .map(|item| PlaylistItem {
+                core_path: if is_detect(&item.core_path) {
+                    playlist.default_core_path.clone()
+                } else {
+                    item.core_path
+                },
+                path: item.path,
+                label: item.label,
+                db_name: item.db_name,
+            })
+            .collect();
+    }
+    let lines: Vec<&str> = contents.lines().collect();
+    lines
+        .chunks_exact(6)
+        .map(|chunk| PlaylistItem {
+            path: chunk[0].to_owned(),
+            label: chunk[1].to_owned(),
+            core_path: chunk[2].to_owned(),
+            db_name: chunk[5].to_owned(),
+        })
+        .collect()
+}
+
+fn is_detect(core_path: &str) -> bool {
+    core_path.is_empty() || core_path == "DETECT"
+}
+
+// Archive members are addressed as /path/game.zip#game.sfc. Any other # is
+// part of a name, like "#1 Hits.sfc"
+fn rom_file(path: &str) -> &Path {
+    for (idx, _) in path.match_indices('#') {
+        let archive = Path::new(&path[..idx]);
+        let extension = archive.extension().and_then(|e| e.to_str());
+        let is_archive = ARCHIVE_EXTENSIONS
+            .iter()
+            .any(|archive| extension.is_some_and(|e| e.eq_ignore_ascii_case(archive)));
+        if is_archive && archive.is_file() {
+            return archive;
+        }
+    }
+    Path::new(path)
+}
+
+fn playlist_entries(config: &RetroArchConfig, seen: &mut HashSet<PathBuf>) -> Vec<LauncherEntry> {
+    let mut entries = vec![];
+    let Ok(read) = std::fs::read_dir(config.playlist_dir()) else {
+        return entries;
+    };
+    for file in read.flatten() {
+        let path = file.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("lpl") {
+            continue;
+        }
+        let Some(playlist) = path.file_stem().map(|s| s.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        for item in parse_playlist(&contents) {
+            let rom = rom_file(&item.path).to_owned();
+            let exec = if !is_detect(&item.core_path) {
+                Some(config.core_command(Path::new(&item.core_path), Path::new(&item.path)))
+            } else {
+                config
+                    .rule_for(&rom)
+                    .and_then(|rule| config.rule_command(rule, Path::new(&item.path)))
+            };
+            // No core assigned and no rule to pick one, RetroArch itself
+            // would ask the user here. Nothing sensible for us to run
+            let Some(exec) = exec else {
+                continue;
+            };
+            let system = item
+                .db_name
+                .strip_suffix(".lpl")
+                .unwrap_or(&playlist)
+                .to_owned();
+            let label = if item.label.is_empty() {
+                rom.file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            } else {
+                item.label.clone()
+            };
+            seen.insert(rom.clone());
+            entries.push(LauncherEntry {
+                id: format!("retroarch:{}", item.path),
+                icon: config.boxart(&system, &label).map(LauncherIcon::Path),
+                name: label,
+                categories: vec!["Game".to_owned(), system],
+                exec,
+                source: LauncherSource::RetroArch {
+                    playlist: playlist.clone(),
+                },
+            });
+        }
+    }
+    entries
+}
+
+// Symlinked directories are followed, as ROMs often live on another drive,
+// but each is only scanned once so a link back up the tree can't loop
+fn scan_dir(
+    config: &RetroArchConfig,
+    dir: &Path,
+    visited: &mut HashSet<PathBuf>,
+    seen: &mut HashSet<PathBuf>,
+    entries: &mut Vec<LauncherEntry>,
+) {
+    let Ok(canonical) = dir.canonicalize() else {
+        return;
+    };
+    if !visited.insert(canonical) {
+        return;
+    }
+    let Ok(read) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for file in read.flatten() {
+        let path = file.path();
+        if path.is_dir() {
+            scan_dir(config, &path, visited, seen, entries);
+            continue;
+        }
+        // Already listed by a playlist
+        if seen.contains(&path) {
+            continue;
+        }
+        let Some(rule) = config.rule_for(&path) else {
+            continue;
+        };
+        let Some(exec) = config.rule_command(rule, &path) else {
+            continue;
+        };
+        let label = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let icon = rule
+            .system
+            .as_ref()
+            .and_then(|system| config.boxart(system, &label))
+            .map(LauncherIcon::Path);
+        let mut categories = vec!["Game".to_owned()];
+        categories.extend(rule.system.clone());
+        seen.insert(path.clone());
+        entries.push(LauncherEntry {
+            id: format!("retroarch:{}", path.display()),
+            name: label,
+            icon,
+            categories,
+            exec,
+            source: LauncherSource::RetroArch {
+                playlist: rule.system.clone().unwrap_or_default(),
+            },
+        });
+    }
+}
+
+pub fn load_entries(config: &RetroArchConfig) -> Vec<LauncherEntry> {
+    let mut seen = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut entries = vec![];
+    if config.playlists {
+        entries.extend(playlist_entries(config, &mut seen));
+    }
+    for dir in config.rom_dirs.iter() {
+        scan_dir(
+            config,
+            &expand_home(dir),
+            &mut visited,
+            &mut seen,
+            &mut entries,
+        );
+    }
+    entries
+}
@@ -0,0 +1,61 @@
+//! Pieces shared by the Consolation shell binaries.
+//!
+//! - [`ipc`] is the socket protocol spoken by `consolation-shelld` and its
+//!   clients.
+//! - [`model`] keeps the toplevel list and most recently used order,
+//!   without touching Wayland.
+//! - [`toplevel`] feeds the model from a Wayland connection.
+//! - [`record`] saves the events fed to the model, and plays them back.
+//! - [`notifications`] is the `org.freedesktop.Notifications` server.
+//! - [`power`] reads the battery and AC adapter, and decides when to warn.
+//! - [`outputs`] lists displays and changes their modes and layout.
+//! - [`backlight`], [`volume`] and [`profiles`] are the quick settings:
+//!   screen brightness, output volume and power profile.
+//! - [`logind`] suspends, reboots and powers off.
+//! - [`nightlight`] warms the screen's colours after dark.
+//! - [`idle`] dims and blanks the screen when nobody is using it.
+//! - [`config`] is `shell.toml`, with [`hooks`], window [`rules`], the
+//!   [`launcher`]'s sources and profiles, and time [`limits`].
+//!   [`config_edit`] changes it without losing the user's comments.
+//! - [`playtime`] records foreground time per app, and [`process`] finds
+//!   and signals the processes behind a window.
+//! - [`apps`] is the socket protocol for pausing windows.
+//! - [`pin`] hashes and checks PINs.
+
+pub mod apps;
+pub mod backlight;
+pub mod config;
+pub mod config_edit;
+pub mod hooks;
+pub mod idle;
+pub mod ipc;
+pub mod launcher;
+pub mod limits;
+pub mod logind;
+pub mod model;
+pub mod nightlight;
+pub mod notifications;
+pub mod outputs;
+pub mod pin;
+pub mod playtime;
+pub mod power;
+pub mod process;
+pub mod profiles;
+pub mod record;
+pub mod rules;
+mod state;
+pub mod toplevel;
+pub mod volume;
+
+pub use ipc::{ToplevelAction, ToplevelDetails, ToplevelId, ToplevelSignal};
+pub use model::{ToplevelChange, ToplevelEvent, ToplevelModel};
+pub use state::ToplevelState;
+pub use toplevel::StagingData;
+
+// Used by delegate_toplevels!
+#[doc(hidden)]
+pub use wayland_client;
+#[doc(hidden)]
+pub use wayland_protocols;
+#[doc(hidden)]
+pub use wayland_protocols_wlr;
@@ -0,0 +1,391 @@
+//! Daily and weekly time limits per app or category.
+
+use chrono::{DateTime, Datelike, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::pin::Backoff;
+
+/// `[limits]` in shell.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Hash from `consolation-switcher --hash-pin`. Without one extra time
+    /// can't be granted
+    pub pin_hash: Option<String>,
+    /// Minutes added each time the PIN is entered
+    pub extra_minutes: u64,
+    pub rules: HashMap<String, LimitRule>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            pin_hash: None,
+            extra_minutes: 30,
+            rules: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitAction {
+    #[default]
+    Pause,
+    Close,
+}
+
+/// `[limits.rules.<name>]`. Matching apps share one budget
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LimitRule {
+    pub app_ids: Vec<String>,
+    /// Desktop file categories, eg "Game"
+    pub categories: Vec<String>,
+    /// Minutes
+    pub daily: Option<u64>,
+    pub weekly: Option<u64>,
+    pub action: LimitAction,
+}
+
+impl LimitRule {
+    pub fn matches(&self, app_id: &str, categories: &[String]) -> bool {
+        self.app_ids.iter().any(|a| a == app_id)
+            || self.categories.iter().any(|c| categories.contains(c))
+    }
+}
+
+/// Which budget is running out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitPeriod {
+    Day,
+    Week,
+}
+
+impl LimitPeriod {
+    /// "today" or "this week", for messages
+    pub fn describe(self) -> &'static str {
+        match self {
+            LimitPeriod::Day => "today",
+            LimitPeriod::Week => "this week",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitEvent {
+    Warning {
+        rule: String,
+        minutes_left: u64,
+        period: LimitPeriod,
+    },
+    Expired {
+        rule: String,
+        action: LimitAction,
+        period: LimitPeriod,
+    },
+}
+
+/// Why [`TimeLimits::grant`] didn't add time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrantRefused {
+    /// There's no PIN in config, so time can't be granted
+    NoPin,
+    /// The PIN was wrong, or came too soon after a wrong one. Nothing is
+    /// checked until this has passed
+    Wait(Duration),
+}
+
+/// Where the time comes from, so tests can move it about
+pub trait Clock {
+    /// The wall clock, which can be set to anything
+    fn wall(&self) -> DateTime<Local>;
+    /// Changes every boot
+    fn boot_id(&self) -> String;
+    /// Seconds since boot, including time spent suspended
+    fn boottime(&self) -> u64;
+    /// Seconds since boot, not counting time spent suspended
+    fn uptime(&self) -> u64;
+}
+
+/// The real clocks, from chrono, /proc and clock_gettime
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn wall(&self) -> DateTime<Local> {
+        Local::now()
+    }
+
+    fn boot_id(&self) -> String {
+        std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+            .map(|id| id.trim().to_owned())
+            .unwrap_or_default()
+    }
+
+    fn boottime(&self) -> u64 {
+        clock_seconds(libc::CLOCK_BOOTTIME)
+    }
+
+    fn uptime(&self) -> u64 {
+        clock_seconds(libc::CLOCK_MONOTONIC)
+    }
+}
+
+// Minutes left at which a warning is sent
+const WARNINGS: [u64; 2] = [15, 5];
+
+// Persisted between runs in $XDG_DATA_HOME/consolation/limits.json
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct LimitState {
+    // Local date and ISO week usage is being counted against
+    day: String,
+    week: String,
+    daily: HashMap<String, u64>,
+    weekly: HashMap<String, u64>,
+    // Seconds granted by PIN today, per rule
+    extra: HashMap<String, u64>,
+    // Lowest warning already sent today, per rule
+    warned: HashMap<String, u64>,
+    // Our idea of the time at the last save, and the boot clock at that
+    // point. Used to work out the time without trusting the wall clock
+    last_time: i64,
+    boot_id: String,
+    boottime: u64,
+}
+
+pub struct TimeLimits {
+    config: LimitsConfig,
+    state: LimitState,
+    path: Option<PathBuf>,
+    clock: Box<dyn Clock + Send>,
+    // Clock::uptime at the last tick, so time suspended isn't counted
+    last_tick: u64,
+    backoff: Backoff,
+}
+
+impl TimeLimits {
+    pub fn path() -> Option<PathBuf> {
+        let dirs = xdg::BaseDirectories::with_prefix("consolation").ok()?;
+        dirs.place_data_file("limits.json").ok()
+    }
+
+    pub fn load(config: LimitsConfig) -> TimeLimits {
+        TimeLimits::with_clock(config, TimeLimits::path(), Box::new(SystemClock))
+    }
+
+    /// Usage is read from and saved to `path`, if there is one
+    pub fn with_clock(
+        config: LimitsConfig,
+        path: Option<PathBuf>,
+        clock: Box<dyn Clock + Send>,
+    ) -> TimeLimits {
+        let state = path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        let last_tick = clock.uptime();
+        let mut limits = TimeLimits {
+            config,
+            state,
+            path,
+            clock,
+            last_tick,
+            backoff: Backoff::default(),
+        };
+        limits.roll_over();
+        limits
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.config.rules.is_empty()
+    }
+
+    pub fn can_grant(&self) -> bool {
+        self.config.pin_hash.is_some()
+    }
+
+    /// The time usage is counted against. Within one boot the boot clock
+    /// is trusted over the wall clock, so changing the time can't be used
+    /// to reset the day. Across reboots time is never allowed to go
+    /// backwards
+    pub fn now(&self) -> DateTime<Local> {
+        let wall = self.clock.wall();
+        let boot_id = self.clock.boot_id();
+        let guess = if !boot_id.is_empty() && boot_id == self.state.boot_id {
+            let elapsed = self.clock.boottime().saturating_sub(self.state.boottime) as i64;
+            self.state.last_time + elapsed
+        } else {
+            wall.timestamp().max(self.state.last_time)
+        };
+        Local.timestamp_opt(guess, 0).single().unwrap_or(wall)
+    }
+
+    fn roll_over(&mut self) {
+        let now = self.now();
+        let day = now.format("%Y-%m-%d").to_string();
+        let iso = now.iso_week();
+        let week = format!("{}-W{:02}", iso.year(), iso.week());
+        // Only ever move forward, string compare works for these formats
+        if day > self.state.day {
+            self.state.day = day;
+            self.state.daily.clear();
+            self.state.extra.clear();
+            self.state.warned.clear();
+        }
+        if week > self.state.week {
+            self.state.week = week;
+            self.state.weekly.clear();
+        }
+        self.state.last_time = now.timestamp();
+        self.state.boot_id = self.clock.boot_id();
+        self.state.boottime = self.clock.boottime();
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let Ok(contents) = serde_json::to_string(&self.state) else {
+            return;
+        };
+        // Write then rename so a crash can't leave a truncated file that
+        // would reset everyone's usage
+        let tmp = path.with_extension("json.tmp");
+        if let Err(e) = std::fs::write(&tmp, contents).and_then(|_| std::fs::rename(&tmp, path)) {
+            println!("Unable to save limits to {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn rules_for(&self, app_id: &str, categories: &[String]) -> Vec<String> {
+        self.config
+            .rules
+            .iter()
+            .filter(|(_, rule)| rule.matches(app_id, categories))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub fn action(&self, rule: &str) -> LimitAction {
+        self.config
+            .rules
+            .get(rule)
+            .map(|rule| rule.action)
+            .unwrap_or_default()
+    }
+
+    /// Time left under a rule, None if it has no limits
+    pub fn remaining(&self, name: &str) -> Option<Duration> {
+        self.left(name).map(|(left, _)| left)
+    }
+
+    /// Whichever of the daily and weekly budgets has less left
+    fn left(&self, name: &str) -> Option<(Duration, LimitPeriod)> {
+        let rule = self.config.rules.get(name)?;
+        let extra = self.state.extra.get(name).copied().unwrap_or(0);
+        let daily = rule.daily.map(|minutes| {
+            let used = self.state.daily.get(name).copied().unwrap_or(0);
+            (minutes * 60 + extra).saturating_sub(used)
+        });
+        let weekly = rule.weekly.map(|minutes| {
+            let used = self.state.weekly.get(name).copied().unwrap_or(0);
+            (minutes * 60 + extra).saturating_sub(used)
+        });
+        let (left, period) = match (daily, weekly) {
+            (Some(d), Some(w)) if w < d => (w, LimitPeriod::Week),
+            (Some(d), _) => (d, LimitPeriod::Day),
+            (None, Some(w)) => (w, LimitPeriod::Week),
+            (None, None) => return None,
+        };
+        Some((Duration::from_secs(left), period))
+    }
+
+    /// The first of the rules with no time left, and which budget ran out
+    pub fn is_exhausted(&self, rules: &[String]) -> Option<(String, LimitPeriod)> {
+        rules.iter().find_map(|rule| match self.left(rule)? {
+            (left, period) if left.is_zero() => Some((rule.clone(), period)),
+            _ => None,
+        })
+    }
+
+    /// Count time since the last tick against whatever the foreground app
+    /// matches, and report warnings and expiries
+    pub fn tick(&mut self, foreground: &[String]) -> Vec<LimitEvent> {
+        let uptime = self.clock.uptime();
+        let elapsed = uptime.saturating_sub(self.last_tick);
+        self.last_tick = uptime;
+        self.roll_over();
+        let mut events = vec![];
+        for rule in foreground {
+            *self.state.daily.entry(rule.clone()).or_insert(0) += elapsed;
+            *self.state.weekly.entry(rule.clone()).or_insert(0) += elapsed;
+            let Some((left, period)) = self.left(rule) else {
+                continue;
+            };
+            let minutes_left = left.as_secs().div_ceil(60);
+            if left.is_zero() {
+                events.push(LimitEvent::Expired {
+                    rule: rule.clone(),
+                    action: self.action(rule),
+                    period,
+                });
+                continue;
+            }
+            let warned = self.state.warned.get(rule).copied().unwrap_or(u64::MAX);
+            if let Some(threshold) = WARNINGS
+                .iter()
+                .copied()
+                .filter(|threshold| minutes_left <= *threshold && *threshold < warned)
+                .min()
+            {
+                self.state.warned.insert(rule.clone(), threshold);
+                events.push(LimitEvent::Warning {
+                    rule: rule.clone(),
+                    minutes_left,
+                    period,
+                });
+            }
+        }
+        self.save();
+        events
+    }
+
+    /// Check the PIN and add extra time to a rule. Wrong PINs hold off
+    /// the next attempt for longer each time
+    pub fn grant(&mut self, rule: &str, pin: &str) -> Result<(), GrantRefused> {
+        let Some(hash) = &self.config.pin_hash else {
+            return Err(GrantRefused::NoPin);
+        };
+        let now = Instant::now();
+        if let Some(wait) = self.backoff.wait(now) {
+            return Err(GrantRefused::Wait(wait));
+        }
+        if !crate::pin::verify(hash, pin) {
+            return Err(GrantRefused::Wait(self.backoff.failed(now)));
+        }
+        self.backoff.succeeded();
+        *self.state.extra.entry(rule.to_owned()).or_insert(0) += self.config.extra_minutes * 60;
+        self.state.warned.remove(rule);
+        self.save();
+        Ok(())
+    }
+
+    pub fn extra_minutes(&self) -> u64 {
+        self.config.extra_minutes
+    }
+}
+
+fn clock_seconds(clock: libc::clockid_t) -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Safety: ts is a valid timespec for clock_gettime to write to
+    unsafe { libc::clock_gettime(clock, &mut ts) };
+    ts.tv_sec as u64
+}
@@ -0,0 +1,188 @@
+//! Suspending, rebooting and powering off through logind.
+//!
+//! A [`PowerControl`] says which [`PowerAction`]s the user is allowed to
+//! take and takes them. [`Logind`] asks `org.freedesktop.login1`, which
+//! checks with polkit, so these work without root for the active session.
+//!
+//! [`Logind`] also says when the machine is about to sleep, can hold that
+//! off for a moment with an inhibitor, and keeps the session's
+//! `LockedHint` for screen lockers.
+
+use std::io;
+use std::os::fd::OwnedFd;
+use zbus::blocking;
+
+const LOGIND_NAME: &str = "org.freedesktop.login1";
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    Suspend,
+    Hibernate,
+    Reboot,
+    PowerOff,
+}
+
+impl PowerAction {
+    pub const ALL: [PowerAction; 4] = [
+        PowerAction::Suspend,
+        PowerAction::Hibernate,
+        PowerAction::Reboot,
+        PowerAction::PowerOff,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PowerAction::Suspend => "Suspend",
+            PowerAction::Hibernate => "Hibernate",
+            PowerAction::Reboot => "Reboot",
+            PowerAction::PowerOff => "Power off",
+        }
+    }
+
+    pub fn icon(self) -> &'static str {
+        match self {
+            PowerAction::Suspend => "system-suspend",
+            PowerAction::Hibernate => "system-hibernate",
+            PowerAction::Reboot => "system-reboot",
+            PowerAction::PowerOff => "system-shutdown",
+        }
+    }
+
+    /// True for actions that end every running program, so it's worth
+    /// giving them the chance to save first.
+    pub fn ends_session(self) -> bool {
+        matches!(self, PowerAction::Reboot | PowerAction::PowerOff)
+    }
+
+    fn method(self) -> &'static str {
+        match self {
+            PowerAction::Suspend => "Suspend",
+            PowerAction::Hibernate => "Hibernate",
+            PowerAction::Reboot => "Reboot",
+            PowerAction::PowerOff => "PowerOff",
+        }
+    }
+}
+
+pub trait PowerControl {
+    /// Whether `action` is possible and allowed, perhaps after polkit asks
+    /// for a password.
+    fn can(&self, action: PowerAction) -> io::Result<bool>;
+
+    fn perform(&self, action: PowerAction) -> io::Result<()>;
+
+    /// End the caller's session, killing everything in it.
+    fn terminate_session(&self) -> io::Result<()>;
+
+    /// The actions [`PowerControl::can`] allows, in [`PowerAction::ALL`]
+    /// order.
+    fn available(&self) -> Vec<PowerAction> {
+        PowerAction::ALL
+            .into_iter()
+            .filter(|action| self.can(*action).unwrap_or(false))
+            .collect()
+    }
+}
+
+/// Talks to logind's manager and the caller's session.
+pub struct Logind {
+    manager: blocking::Proxy<'static>,
+    session: blocking::Proxy<'static>,
+}
+
+impl Logind {
+    /// logind on the system bus.
+    pub fn system() -> zbus::Result<Logind> {
+        Logind::with_connection(blocking::Connection::system()?)
+    }
+
+    /// A logind on the bus at `address`, for tests.
+    pub fn at_address(address: &str) -> zbus::Result<Logind> {
+        Logind::with_connection(blocking::connection::Builder::address(address)?.build()?)
+    }
+
+    fn with_connection(connection: blocking::Connection) -> zbus::Result<Logind> {
+        Ok(Logind {
+            manager: blocking::Proxy::new(
+                &connection,
+                LOGIND_NAME,
+                MANAGER_PATH,
+                MANAGER_INTERFACE,
+            )?,
+            // LockedHint changes under us, and is only read now and then
+            session: blocking::proxy::Builder::new(&connection)
+                .destination(LOGIND_NAME)?
+                .path(SESSION_PATH)?
+                .interface(SESSION_INTERFACE)?
+                .cache_properties(zbus::proxy::CacheProperties::No)
+                .build()?,
+        })
+    }
+
+    /// Delay sleeping until the returned descriptor is closed, or logind
+    /// gives up waiting.
+    pub fn inhibit_sleep(&self, who: &str, why: &str) -> io::Result<OwnedFd> {
+        let fd: zbus::zvariant::OwnedFd = self
+            .manager
+            .call("Inhibit", &("sleep", who, why, "delay"))
+            .map_err(io::Error::other)?;
+        Ok(fd.into())
+    }
+
+    /// `PrepareForSleep`, true before sleeping and false after waking.
+    pub fn sleep_signals(&self) -> io::Result<impl Iterator<Item = bool>> {
+        let signals = self
+            .manager
+            .receive_signal("PrepareForSleep")
+            .map_err(io::Error::other)?;
+        Ok(signals.filter_map(|message| message.body().deserialize::<bool>().ok()))
+    }
+
+    /// Whether a screen locker says the session is locked.
+    pub fn locked_hint(&self) -> io::Result<bool> {
+        self.session
+            .get_property("LockedHint")
+            .map_err(io::Error::other)
+    }
+
+    pub fn set_locked_hint(&self, locked: bool) -> io::Result<()> {
+        self.session
+            .call_method("SetLockedHint", &(locked,))
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+}
+
+impl PowerControl for Logind {
+    fn can(&self, action: PowerAction) -> io::Result<bool> {
+        let answer: String = self
+            .manager
+            .call(format!("Can{}", action.method()).as_str(), &())
+            .map_err(io::Error::other)?;
+        Ok(is_allowed(&answer))
+    }
+
+    fn perform(&self, action: PowerAction) -> io::Result<()> {
+        // Interactive, so polkit can ask for a password where it needs one
+        self.manager
+            .call_method(action.method(), &(true,))
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+
+    fn terminate_session(&self) -> io::Result<()> {
+        self.session
+            .call_method("Terminate", &())
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+}
+
+/// Reads a `Can*` answer. "challenge" is allowed, after authenticating.
+pub fn is_allowed(answer: &str) -> bool {
+    matches!(answer, "yes" | "challenge")
+}
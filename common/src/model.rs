@@ -0,0 +1,241 @@
+//! The toplevel list, without any Wayland objects.
+//!
+//! [`ToplevelModel`] is fed [`ToplevelEvent`]s and works out what changed.
+//! Keeping it separate from the protocol objects means it can be tested,
+//! and driven from anywhere, without a compositor.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ipc::{ToplevelDetails, ToplevelId, ToplevelSignal};
+use crate::ToplevelState;
+
+/// One event from the compositor, with toplevels referred to by id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ToplevelEvent {
+    New {
+        id: ToplevelId,
+    },
+    Title {
+        id: ToplevelId,
+        title: String,
+    },
+    AppId {
+        id: ToplevelId,
+        app_id: String,
+    },
+    State {
+        id: ToplevelId,
+        state: ToplevelState,
+    },
+    Parent {
+        id: ToplevelId,
+        parent: Option<ToplevelId>,
+    },
+    /// The preceding events for this toplevel are complete.
+    Done {
+        id: ToplevelId,
+    },
+    Closed {
+        id: ToplevelId,
+    },
+    /// An output's details are complete.
+    Output {
+        name: String,
+    },
+    /// The seat's capabilities, a `wl_seat.capability` bitfield. Only kept
+    /// so recordings show when the seat appeared.
+    Seat {
+        capabilities: u32,
+    },
+    /// The toplevel manager is gone, no more events will follow.
+    Finished,
+}
+
+/// What a [`ToplevelEvent`] changed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToplevelChange {
+    /// A toplevel appeared or changed. `previous` is its details as of the
+    /// last `done`, `None` when it is new.
+    Updated {
+        id: ToplevelId,
+        previous: Option<ToplevelDetails>,
+        details: ToplevelDetails,
+    },
+    Removed {
+        id: ToplevelId,
+        details: ToplevelDetails,
+    },
+    /// New most recently used order, most recent first.
+    Mru(Vec<ToplevelId>),
+    Output(String),
+    Finished,
+}
+
+impl ToplevelChange {
+    /// The message that tells clients about this change.
+    pub fn signal(&self) -> ToplevelSignal {
+        match self {
+            ToplevelChange::Updated { id, details, .. } => ToplevelSignal::AddUpdateToplevel {
+                id: *id,
+                details: details.clone(),
+            },
+            ToplevelChange::Removed { id, .. } => ToplevelSignal::RemoveToplevel { id: *id },
+            ToplevelChange::Mru(order) => ToplevelSignal::Mru {
+                order: order.clone(),
+            },
+            ToplevelChange::Output(name) => ToplevelSignal::OutputChanged { name: name.clone() },
+            ToplevelChange::Finished => ToplevelSignal::Closed,
+        }
+    }
+}
+
+/// The toplevel list and most recently used order.
+#[derive(Debug, Default)]
+pub struct ToplevelModel {
+    // Details being built up until the next done
+    pending: HashMap<ToplevelId, ToplevelDetails>,
+    // Details as of the last done. Toplevels that haven't had one yet are
+    // left out, they don't have a title or app_id to show
+    toplevels: BTreeMap<ToplevelId, ToplevelDetails>,
+    mru: Vec<ToplevelId>,
+    outputs: Vec<String>,
+    finished: bool,
+}
+
+impl ToplevelModel {
+    pub fn new() -> ToplevelModel {
+        ToplevelModel::default()
+    }
+
+    /// Apply an event, returning what changed as a result. Most events
+    /// only change anything once the toplevel's `done` arrives.
+    pub fn handle(&mut self, event: ToplevelEvent) -> Vec<ToplevelChange> {
+        match event {
+            ToplevelEvent::New { id } => {
+                self.pending.insert(id, ToplevelDetails::default());
+            }
+            ToplevelEvent::Title { id, title } => {
+                if let Some(details) = self.pending.get_mut(&id) {
+                    details.title = Some(title);
+                }
+            }
+            ToplevelEvent::AppId { id, app_id } => {
+                if let Some(details) = self.pending.get_mut(&id) {
+                    details.app_id = Some(app_id);
+                }
+            }
+            ToplevelEvent::State { id, state } => {
+                if let Some(details) = self.pending.get_mut(&id) {
+                    details.state = state;
+                }
+            }
+            ToplevelEvent::Parent { id, parent } => {
+                if let Some(details) = self.pending.get_mut(&id) {
+                    details.parent = parent;
+                }
+            }
+            ToplevelEvent::Done { id } => return self.done(id),
+            ToplevelEvent::Closed { id } => return self.closed(id),
+            ToplevelEvent::Output { name } => {
+                if !self.outputs.contains(&name) {
+                    self.outputs.push(name.clone());
+                }
+                return vec![ToplevelChange::Output(name)];
+            }
+            ToplevelEvent::Seat { .. } => {}
+            ToplevelEvent::Finished => {
+                self.finished = true;
+                return vec![ToplevelChange::Finished];
+            }
+        }
+        vec![]
+    }
+
+    fn done(&mut self, id: ToplevelId) -> Vec<ToplevelChange> {
+        let Some(details) = self.pending.get(&id).cloned() else {
+            return vec![];
+        };
+        let previous = self.toplevels.insert(id, details.clone());
+        let mut changes = vec![ToplevelChange::Updated {
+            id,
+            previous,
+            details: details.clone(),
+        }];
+        let activated = details.state.contains(ToplevelState::ACTIVATED);
+        if activated && self.mru.first() != Some(&id) {
+            self.mru.retain(|other| *other != id);
+            self.mru.insert(0, id);
+            changes.push(ToplevelChange::Mru(self.mru.clone()));
+        } else if !self.mru.contains(&id) {
+            self.mru.push(id);
+            changes.push(ToplevelChange::Mru(self.mru.clone()));
+        }
+        changes
+    }
+
+    fn closed(&mut self, id: ToplevelId) -> Vec<ToplevelChange> {
+        self.pending.remove(&id);
+        let Some(details) = self.toplevels.remove(&id) else {
+            return vec![];
+        };
+        self.mru.retain(|other| *other != id);
+        let mut changes = vec![ToplevelChange::Removed { id, details }];
+        // Children outliving their parent are orphaned, whether or not the
+        // compositor gets round to saying so
+        for details in self.pending.values_mut() {
+            if details.parent == Some(id) {
+                details.parent = None;
+            }
+        }
+        for (child, details) in self.toplevels.iter_mut() {
+            if details.parent == Some(id) {
+                let previous = details.clone();
+                details.parent = None;
+                changes.push(ToplevelChange::Updated {
+                    id: *child,
+                    previous: Some(previous),
+                    details: details.clone(),
+                });
+            }
+        }
+        changes
+    }
+
+    /// A toplevel's details as of its last `done`.
+    pub fn get(&self, id: ToplevelId) -> Option<&ToplevelDetails> {
+        self.toplevels.get(&id)
+    }
+
+    /// Every toplevel that has had a `done`, in the order they appeared.
+    pub fn toplevels(&self) -> impl Iterator<Item = (ToplevelId, &ToplevelDetails)> {
+        self.toplevels.iter().map(|(id, details)| (*id, details))
+    }
+
+    /// Toplevel ids, most recently activated first.
+    pub fn mru(&self) -> &[ToplevelId] {
+        &self.mru
+    }
+
+    pub fn outputs(&self) -> &[String] {
+        &self.outputs
+    }
+
+    /// Whether the compositor has said no more events will follow.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Everything a newly connected client needs.
+    pub fn snapshot(&self) -> ToplevelSignal {
+        ToplevelSignal::Snapshot {
+            toplevels: self
+                .toplevels()
+                .map(|(id, details)| (id, details.clone()))
+                .collect(),
+            mru: self.mru.clone(),
+            outputs: self.outputs.clone(),
+        }
+    }
+}
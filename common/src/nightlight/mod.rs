@@ -0,0 +1,209 @@
+//! Night light: warmer colours after dark, through gamma tables.
+//!
+//! [`whitepoint`] and [`gamma_ramps`] turn a colour temperature into the
+//! tables [`wayland::GammaControl`] hands to the compositor. A
+//! [`Schedule`] says how far into the night it is, either between fixed
+//! times of day or from sunset to sunrise where the user is.
+//!
+//! `consolation-shelld` runs the night light. Clients connect to
+//! [`socket_path`], are sent a [`NightLightSignal::Status`] then every
+//! change, and send [`NightLightAction`]s back, using
+//! [`crate::ipc::send`] and [`crate::ipc::read_messages`].
+
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::io;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use crate::ipc;
+
+pub mod wayland;
+
+/// Daylight, where the gamma tables are left as they are.
+pub const NEUTRAL: u32 = 6500;
+
+const MINUTES_PER_DAY: f64 = 1440.0;
+// Days from the Unix epoch to 2000-01-01, the J2000 epoch
+const J2000_DAYS: f64 = 10957.0;
+
+/// Sent from `consolation-shelld` to its night light clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NightLightSignal {
+    /// Sent on connecting and after every change.
+    Status {
+        enabled: bool,
+        /// Held off while a fullscreen game is in front.
+        suspended: bool,
+    },
+}
+
+/// Sent from clients to `consolation-shelld`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum NightLightAction {
+    SetEnabled { enabled: bool },
+}
+
+/// `$XDG_RUNTIME_DIR/consolation/nightlight.sock`
+pub fn socket_path() -> Option<PathBuf> {
+    ipc::runtime_socket("nightlight.sock")
+}
+
+/// Connect to the night light in `consolation-shelld`, which should
+/// already be running for the switcher's window list.
+pub fn connect() -> io::Result<UnixStream> {
+    let path = socket_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+    UnixStream::connect(path)
+}
+
+/// Red, green and blue for a colour temperature, relative to
+/// [`NEUTRAL`], so 6500K is `[1.0, 1.0, 1.0]`. Temperatures above it
+/// are treated as neutral, night light only ever warms.
+pub fn whitepoint(kelvin: u32) -> [f64; 3] {
+    let kelvin = kelvin.clamp(1000, NEUTRAL);
+    let colour = blackbody(kelvin as f64);
+    let neutral = blackbody(NEUTRAL as f64);
+    [0, 1, 2].map(|idx| (colour[idx] / neutral[idx]).clamp(0.0, 1.0))
+}
+
+// Tanner Helland's fit of the blackbody colours, 0 to 255, for 1000K to
+// 6600K
+fn blackbody(kelvin: f64) -> [f64; 3] {
+    let t = kelvin / 100.0;
+    let red = 255.0;
+    let green = 99.470_802_586_1 * t.ln() - 161.119_568_166_1;
+    let blue = match t {
+        t if t <= 19.0 => 0.0,
+        t => 138.517_731_223_1 * (t - 10.0).ln() - 305.044_792_730_7,
+    };
+    [red, green.clamp(0.0, 255.0), blue.clamp(0.0, 255.0)]
+}
+
+/// Gamma tables of `size` entries for `kelvin`: all of red, then green,
+/// then blue, as `zwlr_gamma_control_v1.set_gamma` wants them.
+pub fn gamma_ramps(size: usize, kelvin: u32) -> Vec<u16> {
+    let whitepoint = whitepoint(kelvin);
+    let last = size.saturating_sub(1).max(1) as f64;
+    whitepoint
+        .iter()
+        .flat_map(|channel| {
+            (0..size).map(move |idx| (idx as f64 / last * channel * 65535.0).round() as u16)
+        })
+        .collect()
+}
+
+/// When it's night.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// Between two local times of day, in minutes past midnight.
+    Fixed { dusk: u32, dawn: u32 },
+    /// From sunset to sunrise, in degrees north and east.
+    Sun { latitude: f64, longitude: f64 },
+}
+
+impl Schedule {
+    /// How far into the night it is at `unix` seconds, where local time
+    /// is `utc_offset` seconds ahead of UTC. 0 by day and 1 at night,
+    /// fading between over `transition` minutes centred on dusk and dawn.
+    pub fn night_level(&self, unix: i64, utc_offset: i64, transition: f64) -> f64 {
+        match *self {
+            Schedule::Fixed { dusk, dawn } => {
+                let now = minute_of_day(unix + utc_offset);
+                level_between(now, dawn as f64, dusk as f64, transition)
+            }
+            Schedule::Sun {
+                latitude,
+                longitude,
+            } => {
+                // Worked in local solar time, where noon is when the sun
+                // is highest, so no time zone is needed
+                let solar = unix + (longitude * 240.0) as i64;
+                let day = solar.div_euclid(86400);
+                match sun_times(day, latitude, longitude) {
+                    SunTimes::Rises { sunrise, sunset } => {
+                        level_between(minute_of_day(solar), sunrise, sunset, transition)
+                    }
+                    SunTimes::AlwaysDay => 0.0,
+                    SunTimes::AlwaysNight => 1.0,
+                }
+            }
+        }
+    }
+}
+
+/// Sunrise and sunset on a day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SunTimes {
+    /// Minutes past local solar midnight.
+    Rises { sunrise: f64, sunset: f64 },
+    /// The midnight sun of summer near the poles.
+    AlwaysDay,
+    /// Polar night.
+    AlwaysNight,
+}
+
+/// Sunrise and sunset on `day`, in days since the Unix epoch, at
+/// `latitude` and `longitude` in degrees. Uses the sunrise equation, which
+/// is good to a minute or two.
+pub fn sun_times(day: i64, latitude: f64, longitude: f64) -> SunTimes {
+    let radians = PI / 180.0;
+    // Mean solar noon, in days since J2000
+    let noon = day as f64 - J2000_DAYS + 0.0008 - longitude / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * noon).rem_euclid(360.0);
+    let centre = 1.9148 * (anomaly * radians).sin()
+        + 0.02 * (2.0 * anomaly * radians).sin()
+        + 0.0003 * (3.0 * anomaly * radians).sin();
+    let ecliptic = (anomaly + centre + 180.0 + 102.9372).rem_euclid(360.0);
+    // When the sun is highest, as a fraction of a day off the mean
+    let transit = 0.0053 * (anomaly * radians).sin() - 0.0069 * (2.0 * ecliptic * radians).sin();
+    let declination = ((ecliptic * radians).sin() * (23.4397 * radians).sin()).asin();
+    // The sun's centre 0.833° below the horizon, allowing for refraction
+    let hour_angle = ((-0.833 * radians).sin() - (latitude * radians).sin() * declination.sin())
+        / ((latitude * radians).cos() * declination.cos());
+    if hour_angle > 1.0 {
+        return SunTimes::AlwaysNight;
+    }
+    if hour_angle < -1.0 {
+        return SunTimes::AlwaysDay;
+    }
+    let half_day = hour_angle.acos() / radians / 360.0;
+    let noon_minutes = (0.5 + transit) * MINUTES_PER_DAY;
+    SunTimes::Rises {
+        sunrise: noon_minutes - half_day * MINUTES_PER_DAY,
+        sunset: noon_minutes + half_day * MINUTES_PER_DAY,
+    }
+}
+
+/// The temperature `level` of the way from `day` to `night`.
+pub fn temperature(level: f64, day: u32, night: u32) -> u32 {
+    let level = level.clamp(0.0, 1.0);
+    (day as f64 + (night as f64 - day as f64) * level).round() as u32
+}
+
+fn minute_of_day(seconds: i64) -> f64 {
+    seconds.rem_euclid(86400) as f64 / 60.0
+}
+
+// `a - b` the short way round the clock
+fn minutes_apart(a: f64, b: f64) -> f64 {
+    (a - b + MINUTES_PER_DAY / 2.0).rem_euclid(MINUTES_PER_DAY) - MINUTES_PER_DAY / 2.0
+}
+
+// Night from dusk round the clock to dawn, fading in and out either side
+// of each, even when that crosses midnight
+fn level_between(now: f64, dawn: f64, dusk: f64, transition: f64) -> f64 {
+    let transition = transition.max(1.0);
+    let is_night =
+        (now - dusk).rem_euclid(MINUTES_PER_DAY) < (dawn - dusk).rem_euclid(MINUTES_PER_DAY);
+    let nearest = minutes_apart(now, dusk)
+        .abs()
+        .min(minutes_apart(now, dawn).abs());
+    let offset = (nearest / transition).min(0.5);
+    match is_night {
+        true => 0.5 + offset,
+        false => 0.5 - offset,
+    }
+}
@@ -0,0 +1,253 @@
+//! Wayland side of the night light.
+//!
+//! [`GammaControl`] has a connection and event queue of its own, so it can
+//! run on a thread apart from the daemon's toplevel tracking. The
+//! compositor puts an output's gamma back when its control is destroyed,
+//! which is how the night light is turned off.
+
+use std::collections::HashMap;
+use std::io::{Seek, Write};
+use std::os::fd::AsFd;
+use wayland_client::{
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_output::{self, WlOutput},
+        wl_registry::{self, WlRegistry},
+    },
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+};
+use wayland_protocols_wlr::gamma_control::v1::client::{
+    zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1,
+    zwlr_gamma_control_v1::{self, ZwlrGammaControlV1},
+};
+
+use super::gamma_ramps;
+
+/// Sets every output's gamma tables through
+/// `zwlr_gamma_control_manager_v1`, including outputs plugged in later.
+pub struct GammaControl {
+    queue: EventQueue<GammaState>,
+    state: GammaState,
+}
+
+struct GammaState {
+    manager: ZwlrGammaControlManagerV1,
+    // By registry name, to notice outputs going away
+    outputs: HashMap<u32, GammaOutput>,
+}
+
+struct GammaOutput {
+    output: WlOutput,
+    control: Option<ZwlrGammaControlV1>,
+    // Entries per channel, once the compositor has said
+    size: Option<u32>,
+    // Temperature last sent
+    applied: Option<u32>,
+}
+
+impl GammaControl {
+    /// Connect and bind every output. Fails when the compositor doesn't
+    /// support wlr-gamma-control.
+    pub fn connect() -> Result<GammaControl, String> {
+        let conn = Connection::connect_to_env().map_err(|e| e.to_string())?;
+        let (globals, mut queue) =
+            registry_queue_init::<GammaState>(&conn).map_err(|e| e.to_string())?;
+        let qh = queue.handle();
+        let manager = globals
+            .bind::<ZwlrGammaControlManagerV1, GammaState, ()>(&qh, 1..=1, ())
+            .map_err(|e| format!("No gamma control: {}", e))?;
+        let mut state = GammaState {
+            manager,
+            outputs: HashMap::new(),
+        };
+        for global in globals.contents().clone_list() {
+            if global.interface == WlOutput::interface().name {
+                state.add_output(globals.registry(), global.name, global.version, &qh);
+            }
+        }
+        queue.roundtrip(&mut state).map_err(|e| e.to_string())?;
+        Ok(GammaControl { queue, state })
+    }
+
+    /// Warm every output to `kelvin`, or give the gamma tables back to the
+    /// compositor with `None`.
+    pub fn set_temperature(&mut self, kelvin: Option<u32>) -> Result<(), String> {
+        let qh = self.queue.handle();
+        // New outputs, and the gamma sizes of new controls
+        self.queue
+            .roundtrip(&mut self.state)
+            .map_err(|e| e.to_string())?;
+        let Some(kelvin) = kelvin else {
+            for output in self.state.outputs.values_mut() {
+                output.release();
+            }
+            return self.queue.flush().map_err(|e| e.to_string());
+        };
+
+        let mut created = false;
+        for output in self.state.outputs.values_mut() {
+            if output.control.is_none() {
+                let control = self
+                    .state
+                    .manager
+                    .get_gamma_control(&output.output, &qh, ());
+                output.control = Some(control);
+                created = true;
+            }
+        }
+        if created {
+            self.queue
+                .roundtrip(&mut self.state)
+                .map_err(|e| e.to_string())?;
+        }
+
+        for output in self.state.outputs.values_mut() {
+            if output.applied == Some(kelvin) {
+                continue;
+            }
+            let (Some(control), Some(size)) = (&output.control, output.size) else {
+                continue;
+            };
+            let table = gamma_table(size as usize, kelvin).map_err(|e| e.to_string())?;
+            control.set_gamma(table.as_fd());
+            output.applied = Some(kelvin);
+        }
+        self.queue.flush().map_err(|e| e.to_string())
+    }
+}
+
+impl GammaState {
+    fn add_output(
+        &mut self,
+        registry: &WlRegistry,
+        name: u32,
+        version: u32,
+        qh: &QueueHandle<Self>,
+    ) {
+        let output = registry.bind::<WlOutput, _, _>(name, version.min(4), qh, ());
+        self.outputs.insert(
+            name,
+            GammaOutput {
+                output,
+                control: None,
+                size: None,
+                applied: None,
+            },
+        );
+    }
+
+    fn output_for(&mut self, control: &ZwlrGammaControlV1) -> Option<&mut GammaOutput> {
+        self.outputs
+            .values_mut()
+            .find(|output| output.control.as_ref() == Some(control))
+    }
+}
+
+impl GammaOutput {
+    fn release(&mut self) {
+        if let Some(control) = self.control.take() {
+            control.destroy();
+        }
+        self.size = None;
+        self.applied = None;
+    }
+}
+
+// The tables go to the compositor as a file. One that's already unlinked
+// leaves nothing behind
+fn gamma_table(size: usize, kelvin: u32) -> std::io::Result<std::fs::File> {
+    let path = std::env::temp_dir().join(format!("consolation-gamma-{}", std::process::id()));
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)?;
+    let _ = std::fs::remove_file(&path);
+    let bytes: Vec<u8> = gamma_ramps(size, kelvin)
+        .into_iter()
+        .flat_map(u16::to_ne_bytes)
+        .collect();
+    file.write_all(&bytes)?;
+    file.rewind()?;
+    Ok(file)
+}
+
+impl Dispatch<ZwlrGammaControlManagerV1, ()> for GammaState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwlrGammaControlManagerV1,
+        _event: <ZwlrGammaControlManagerV1 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrGammaControlV1, ()> for GammaState {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrGammaControlV1,
+        event: zwlr_gamma_control_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.output_for(proxy) else {
+            return;
+        };
+        match event {
+            zwlr_gamma_control_v1::Event::GammaSize { size } => output.size = Some(size),
+            // Another client has the output's gamma, or the output can't
+            // do gamma at all. Tried again on the next change
+            zwlr_gamma_control_v1::Event::Failed => {
+                println!("Gamma control failed for an output");
+                output.release();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlOutput, ()> for GammaState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlOutput,
+        _event: wl_output::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for GammaState {
+    fn event(
+        state: &mut Self,
+        proxy: &WlRegistry,
+        event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        qhandle: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } if interface == WlOutput::interface().name => {
+                state.add_output(proxy, name, version, qhandle);
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(mut output) = state.outputs.remove(&name) {
+                    output.release();
+                    if output.output.version() >= 3 {
+                        output.output.release();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Notifications kept after their toast has gone, and the socket protocol
+//! for showing them elsewhere.
+//!
+//! `consolation-notifyd` owns the history and saves it after every change.
+//! Clients connect to [`socket_path`], are sent a
+//! [`HistorySignal::Snapshot`] then every change, and send
+//! [`HistoryAction`]s back, using [`crate::ipc::send`] and
+//! [`crate::ipc::read_messages`] like `consolation-shelld`'s clients.
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Notification, NotificationImage};
+use crate::ipc;
+
+/// Oldest notifications are dropped past this many.
+pub const HISTORY_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredNotification {
+    pub notification: Notification,
+    /// Seconds since the Unix epoch.
+    pub received: u64,
+    pub read: bool,
+}
+
+/// Sent from `consolation-notifyd` to its clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistorySignal {
+    /// The whole history, newest first, sent once on connecting.
+    Snapshot {
+        notifications: Vec<StoredNotification>,
+    },
+    /// A new notification, or a replacement for one with the same id,
+    /// which moves to the front.
+    Added {
+        notification: StoredNotification,
+    },
+    Removed {
+        id: u32,
+    },
+    /// Everything has been marked as read.
+    Read,
+}
+
+/// Sent from clients to `consolation-notifyd`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum HistoryAction {
+    Invoke { id: u32, key: String },
+    Dismiss { id: u32 },
+    DismissAll,
+    MarkRead,
+}
+
+/// `$XDG_RUNTIME_DIR/consolation/notifyd.sock`
+pub fn socket_path() -> Option<PathBuf> {
+    ipc::runtime_socket("notifyd.sock")
+}
+
+/// Connect to `consolation-notifyd`. Unlike the shell daemon it isn't
+/// started on demand, another notification daemon may be running instead.
+pub fn connect() -> io::Result<UnixStream> {
+    let path = socket_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+    UnixStream::connect(path)
+}
+
+/// The history, newest first.
+#[derive(Debug, Default)]
+pub struct NotificationHistory {
+    path: Option<PathBuf>,
+    entries: Vec<StoredNotification>,
+}
+
+impl NotificationHistory {
+    /// `$XDG_DATA_HOME/consolation/notifications.json`
+    pub fn default_path() -> Option<PathBuf> {
+        let dirs = xdg::BaseDirectories::with_prefix("consolation").ok()?;
+        dirs.place_data_file("notifications.json").ok()
+    }
+
+    /// Load from `path`, which is also where it's saved. A missing or
+    /// broken file gives an empty history.
+    pub fn load(path: &Path) -> NotificationHistory {
+        let entries = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                println!("Unable to parse {}: {}", path.display(), e);
+                vec![]
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => {
+                println!("Unable to read {}: {}", path.display(), e);
+                vec![]
+            }
+        };
+        NotificationHistory {
+            path: Some(path.to_owned()),
+            entries,
+        }
+    }
+
+    /// Write the whole history out, replacing the file in one go so a
+    /// crash can't leave half of it.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, serde_json::to_string(&self.entries)?)?;
+        std::fs::rename(&partial, path)
+    }
+
+    pub fn entries(&self) -> &[StoredNotification] {
+        &self.entries
+    }
+
+    pub fn get(&self, id: u32) -> Option<&StoredNotification> {
+        self.entries
+            .iter()
+            .find(|entry| entry.notification.id == id)
+    }
+
+    pub fn unread(&self) -> usize {
+        self.entries.iter().filter(|entry| !entry.read).count()
+    }
+
+    /// Add a notification as unread, replacing any with the same id.
+    /// Pixel images are too big to keep and are dropped. Returns the new
+    /// entry and the ids of any that fell off the end.
+    pub fn add(&mut self, mut notification: Notification) -> (StoredNotification, Vec<u32>) {
+        if let Some(NotificationImage::Pixels { .. }) = notification.image {
+            notification.image = None;
+        }
+        self.entries
+            .retain(|entry| entry.notification.id != notification.id);
+        let entry = StoredNotification {
+            notification,
+            received: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or(0),
+            read: false,
+        };
+        self.entries.insert(0, entry.clone());
+        let dropped = self
+            .entries
+            .split_off(self.entries.len().min(HISTORY_LIMIT))
+            .into_iter()
+            .map(|entry| entry.notification.id)
+            .collect();
+        (entry, dropped)
+    }
+
+    /// Returns false if there was nothing to remove.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.notification.id != id);
+        self.entries.len() != before
+    }
+
+    /// Empty the history, returning the ids that were in it.
+    pub fn clear(&mut self) -> Vec<u32> {
+        self.entries
+            .drain(..)
+            .map(|entry| entry.notification.id)
+            .collect()
+    }
+
+    /// Returns false if everything was already read.
+    pub fn mark_read(&mut self) -> bool {
+        let mut changed = false;
+        for entry in self.entries.iter_mut().filter(|entry| !entry.read) {
+            entry.read = true;
+            changed = true;
+        }
+        changed
+    }
+}
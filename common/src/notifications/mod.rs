@@ -0,0 +1,509 @@
+//! The `org.freedesktop.Notifications` server.
+//!
+//! [`NotificationServer`] owns the name on a bus and keeps track of the
+//! open notifications. Everything a display needs arrives as a
+//! [`NotificationEvent`] on a channel, and the display calls back into the
+//! server when the user dismisses a notification or picks one of its
+//! actions.
+//!
+//! With [`ServerOptions::persistence`], notifications stay open after
+//! their timeout, for a [`history::NotificationHistory`] to close later.
+//!
+//! Follows version 1.2 of the
+//! [Desktop Notifications Specification](https://specifications.freedesktop.org/notification-spec/latest/).
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use zbus::fdo::RequestNameFlags;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{blocking, interface, SignalContext};
+
+pub mod history;
+
+pub const BUS_NAME: &str = "org.freedesktop.Notifications";
+pub const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+const INTERFACE: &str = "org.freedesktop.Notifications";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    /// The `urgency` hint's byte. Unknown values are treated as normal.
+    pub fn from_hint(value: u8) -> Urgency {
+        match value {
+            0 => Urgency::Low,
+            2 => Urgency::Critical,
+            _ => Urgency::Normal,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationAction {
+    /// Sent back in `ActionInvoked`. `default` is the action for clicking
+    /// the notification itself.
+    pub key: String,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotificationImage {
+    /// A file path, `file://` URI or icon name.
+    Name { name: String },
+    /// Pixels from an `image-data` hint, converted to 8 bit RGBA.
+    Pixels {
+        width: u32,
+        height: u32,
+        rgba: Vec<u8>,
+    },
+}
+
+impl NotificationImage {
+    /// Convert `image-data` pixels, which may have no alpha channel and
+    /// padding at the end of each row. Only 8 bits per sample is allowed
+    /// by the spec.
+    pub fn from_raw(
+        width: i32,
+        height: i32,
+        rowstride: i32,
+        has_alpha: bool,
+        bits_per_sample: i32,
+        data: &[u8],
+    ) -> Option<NotificationImage> {
+        let channels = if has_alpha { 4 } else { 3 };
+        let (width, height, rowstride) = (
+            usize::try_from(width).ok()?,
+            usize::try_from(height).ok()?,
+            usize::try_from(rowstride).ok()?,
+        );
+        if bits_per_sample != 8 || width == 0 || height == 0 || rowstride < width * channels {
+            return None;
+        }
+        // The last row doesn't have to be padded
+        if data.len() < rowstride * (height - 1) + width * channels {
+            return None;
+        }
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for row in data.chunks(rowstride).take(height) {
+            for pixel in row[..width * channels].chunks(channels) {
+                rgba.extend_from_slice(&pixel[..3]);
+                rgba.push(if has_alpha { pixel[3] } else { 255 });
+            }
+        }
+        Some(NotificationImage::Pixels {
+            width: width as u32,
+            height: height as u32,
+            rgba,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    pub id: u32,
+    pub app_name: String,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    pub actions: Vec<NotificationAction>,
+    pub urgency: Urgency,
+    /// From the `image-data`, `image-path` or `icon_data` hints, in the
+    /// spec's order of preference.
+    pub image: Option<NotificationImage>,
+    /// Stays open after an action is invoked.
+    pub resident: bool,
+    /// Always expires, and is left out of the history.
+    pub transient: bool,
+    /// How long to show it for, `None` until dismissed. Without
+    /// persistence it closes after this long.
+    pub timeout: Option<Duration>,
+}
+
+impl Notification {
+    /// The action for clicking the notification itself, if it has one.
+    pub fn default_action(&self) -> Option<&NotificationAction> {
+        self.actions.iter().find(|action| action.key == "default")
+    }
+}
+
+/// Why a notification closed, as sent in `NotificationClosed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloseReason {
+    Expired = 1,
+    Dismissed = 2,
+    /// The sender called `CloseNotification`.
+    Closed = 3,
+    Undefined = 4,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotificationEvent {
+    /// A new notification, or one replacing the open notification with the
+    /// same id.
+    Show(Notification),
+    Closed {
+        id: u32,
+        reason: CloseReason,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// How long notifications are shown for when the sender leaves it to us.
+    pub default_timeout: Duration,
+    /// Keep notifications open once their timeout is up, unless they're
+    /// transient.
+    pub persistence: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // Open notifications, with a serial that changes each time one is
+    // replaced so an old expiry timer can tell it's stale
+    open: HashMap<u32, (u64, Notification)>,
+    next_id: u32,
+    next_serial: u64,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    events: Sender<NotificationEvent>,
+    options: ServerOptions,
+}
+
+/// Owns `org.freedesktop.Notifications` for as long as it, or a clone,
+/// is alive.
+#[derive(Debug, Clone)]
+pub struct NotificationServer {
+    shared: Arc<Shared>,
+    connection: blocking::Connection,
+}
+
+impl NotificationServer {
+    /// Serve on the session bus. Fails if another notification daemon
+    /// already owns the name.
+    pub fn session(
+        events: Sender<NotificationEvent>,
+        options: ServerOptions,
+    ) -> zbus::Result<NotificationServer> {
+        NotificationServer::start(blocking::connection::Builder::session()?, events, options)
+    }
+
+    /// Serve on the bus at `address`, like `unix:path=/run/user/1000/bus`.
+    pub fn at_address(
+        address: &str,
+        events: Sender<NotificationEvent>,
+        options: ServerOptions,
+    ) -> zbus::Result<NotificationServer> {
+        NotificationServer::start(
+            blocking::connection::Builder::address(address)?,
+            events,
+            options,
+        )
+    }
+
+    fn start(
+        builder: blocking::connection::Builder,
+        events: Sender<NotificationEvent>,
+        options: ServerOptions,
+    ) -> zbus::Result<NotificationServer> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            events,
+            options,
+        });
+        let connection = builder.build()?;
+        connection.object_server().at(
+            OBJECT_PATH,
+            Interface {
+                shared: shared.clone(),
+            },
+        )?;
+        connection.request_name_with_flags(BUS_NAME, RequestNameFlags::DoNotQueue.into())?;
+        Ok(NotificationServer { shared, connection })
+    }
+
+    /// Notifications that haven't closed yet, oldest first.
+    pub fn open(&self) -> Vec<Notification> {
+        let state = self.shared.state.lock().unwrap();
+        let mut open: Vec<_> = state
+            .open
+            .values()
+            .map(|(_, notification)| notification.clone())
+            .collect();
+        open.sort_by_key(|notification| notification.id);
+        open
+    }
+
+    /// Reopen notifications kept from before a restart, so their actions
+    /// can still be invoked. New notifications get ids after theirs.
+    pub fn restore(&self, notifications: impl IntoIterator<Item = Notification>) {
+        let mut state = self.shared.state.lock().unwrap();
+        for notification in notifications {
+            state.next_id = state.next_id.max(notification.id);
+            state.next_serial += 1;
+            let serial = state.next_serial;
+            state.open.insert(notification.id, (serial, notification));
+        }
+    }
+
+    /// The user picked an action. Closes the notification unless it's
+    /// resident. Returns false if it has already closed.
+    pub fn invoke_action(&self, id: u32, key: &str) -> bool {
+        let resident = match self.shared.state.lock().unwrap().open.get(&id) {
+            Some((_, notification)) => notification.resident,
+            None => return false,
+        };
+        if let Err(e) = self.connection.emit_signal(
+            None::<()>,
+            OBJECT_PATH,
+            INTERFACE,
+            "ActionInvoked",
+            &(id, key),
+        ) {
+            println!(
+                "Unable to send action {} for notification {}: {}",
+                key, id, e
+            );
+        }
+        if !resident {
+            self.close(id, CloseReason::Dismissed);
+        }
+        true
+    }
+
+    /// The user closed the notification. Returns false if it had already
+    /// closed.
+    pub fn dismiss(&self, id: u32) -> bool {
+        self.close(id, CloseReason::Dismissed)
+    }
+
+    fn close(&self, id: u32, reason: CloseReason) -> bool {
+        if !self.shared.remove(id, reason) {
+            return false;
+        }
+        if let Err(e) = self.connection.emit_signal(
+            None::<()>,
+            OBJECT_PATH,
+            INTERFACE,
+            "NotificationClosed",
+            &(id, reason as u32),
+        ) {
+            println!("Unable to send close for notification {}: {}", id, e);
+        }
+        true
+    }
+
+    // Close after the timeout, unless it has closed or been replaced since
+    fn expire_after(&self, id: u32, serial: u64, timeout: Duration) {
+        let server = self.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            let current = server
+                .shared
+                .state
+                .lock()
+                .unwrap()
+                .open
+                .get(&id)
+                .map(|(s, _)| *s);
+            if current == Some(serial) {
+                server.close(id, CloseReason::Expired);
+            }
+        });
+    }
+}
+
+impl Shared {
+    fn remove(&self, id: u32, reason: CloseReason) -> bool {
+        if self.state.lock().unwrap().open.remove(&id).is_none() {
+            return false;
+        }
+        let _ = self.events.send(NotificationEvent::Closed { id, reason });
+        true
+    }
+}
+
+struct Interface {
+    shared: Arc<Shared>,
+}
+
+#[interface(name = "org.freedesktop.Notifications")]
+impl Interface {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    ) -> u32 {
+        let urgency = hint::<u8>(&hints, "urgency")
+            .map(Urgency::from_hint)
+            .unwrap_or_default();
+        // -1 leaves it to us, 0 is never. Critical notifications don't
+        // expire unless the sender says so
+        let timeout = match expire_timeout {
+            0 => None,
+            ms if ms > 0 => Some(Duration::from_millis(ms as u64)),
+            _ if urgency == Urgency::Critical => None,
+            _ => Some(self.shared.options.default_timeout),
+        };
+        let transient = hint::<bool>(&hints, "transient").unwrap_or(false);
+        let mut notification = Notification {
+            id: 0,
+            app_name,
+            app_icon,
+            summary,
+            body,
+            actions: actions
+                .chunks_exact(2)
+                .map(|pair| NotificationAction {
+                    key: pair[0].clone(),
+                    label: pair[1].clone(),
+                })
+                .collect(),
+            urgency,
+            image: image_hint(&hints),
+            resident: hint::<bool>(&hints, "resident").unwrap_or(false),
+            transient,
+            timeout,
+        };
+
+        let (id, serial) = {
+            let mut state = self.shared.state.lock().unwrap();
+            // Replacing one that has already closed gets a new id, as if
+            // replaces_id was 0
+            let id = if state.open.contains_key(&replaces_id) {
+                replaces_id
+            } else {
+                state.next_id += 1;
+                state.next_id
+            };
+            state.next_serial += 1;
+            let serial = state.next_serial;
+            notification.id = id;
+            state.open.insert(id, (serial, notification.clone()));
+            (id, serial)
+        };
+        let _ = self
+            .shared
+            .events
+            .send(NotificationEvent::Show(notification));
+        // Persistent notifications are only hidden once the time is up,
+        // they close when the user gets to them
+        let expires = transient || !self.shared.options.persistence;
+        if let (Some(timeout), true) = (timeout, expires) {
+            let server = NotificationServer {
+                shared: self.shared.clone(),
+                connection: connection.clone().into(),
+            };
+            server.expire_after(id, serial, timeout);
+        }
+        id
+    }
+
+    async fn close_notification(&self, #[zbus(signal_context)] ctxt: SignalContext<'_>, id: u32) {
+        if self.shared.remove(id, CloseReason::Closed) {
+            if let Err(e) =
+                Interface::notification_closed(&ctxt, id, CloseReason::Closed as u32).await
+            {
+                println!("Unable to send close for notification {}: {}", id, e);
+            }
+        }
+    }
+
+    fn get_capabilities(&self) -> Vec<&'static str> {
+        let mut capabilities = vec!["actions", "body", "icon-static"];
+        if self.shared.options.persistence {
+            capabilities.push("persistence");
+        }
+        capabilities
+    }
+
+    #[zbus(out_args("name", "vendor", "version", "spec_version"))]
+    fn get_server_information(&self) -> (&'static str, &'static str, &'static str, &'static str) {
+        (
+            "Consolation",
+            "Consolation",
+            env!("CARGO_PKG_VERSION"),
+            "1.2",
+        )
+    }
+
+    #[zbus(signal)]
+    async fn notification_closed(
+        ctxt: &SignalContext<'_>,
+        id: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn action_invoked(
+        ctxt: &SignalContext<'_>,
+        id: u32,
+        action_key: &str,
+    ) -> zbus::Result<()>;
+}
+
+fn hint<'a, T>(hints: &'a HashMap<String, OwnedValue>, name: &str) -> Option<T>
+where
+    T: TryFrom<&'a Value<'a>>,
+{
+    T::try_from(hints.get(name)?).ok()
+}
+
+// image-data beats image-path beats the deprecated names. Raw images that
+// don't parse are skipped in favour of the next one
+fn image_hint(hints: &HashMap<String, OwnedValue>) -> Option<NotificationImage> {
+    let raw = |name: &str| {
+        let value = Value::from(hints.get(name)?.try_clone().ok()?);
+        let (width, height, rowstride, has_alpha, bits_per_sample, _channels, data): (
+            i32,
+            i32,
+            i32,
+            bool,
+            i32,
+            i32,
+            Vec<u8>,
+        ) = value.try_into().ok()?;
+        NotificationImage::from_raw(width, height, rowstride, has_alpha, bits_per_sample, &data)
+    };
+    let name = |name: &str| {
+        hint::<&str>(hints, name)
+            .filter(|name| !name.is_empty())
+            .map(|name| NotificationImage::Name {
+                name: name.to_owned(),
+            })
+    };
+    raw("image-data")
+        .or_else(|| raw("image_data"))
+        .or_else(|| name("image-path"))
+        .or_else(|| name("image_path"))
+        .or_else(|| raw("icon_data"))
+}
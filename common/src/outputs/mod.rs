@@ -0,0 +1,567 @@
+//! Display configuration, from `zwlr_output_manager_v1`.
+//!
+//! [`OutputModel`] is fed [`OutputEvent`]s and keeps the heads (connected
+//! displays) and their modes, without any Wayland objects, so it can be
+//! tested without a compositor. [`OutputModel::plan`] turns the
+//! [`HeadConfig`]s the user asked for into what to send, and
+//! [`wayland::OutputManager`] sends it.
+//!
+//! A change that leaves the user looking at a blank screen has to undo
+//! itself. [`Confirmation`] counts down to putting the previous
+//! configuration back.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+pub mod wayland;
+
+/// How long a new configuration has to be kept before it's reverted.
+pub const REVERT_AFTER: Duration = Duration::from_secs(15);
+
+pub type HeadId = u32;
+pub type ModeId = u32;
+
+/// `wl_output.transform`, clockwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 8] = [
+        Transform::Normal,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::Flipped,
+        Transform::Flipped90,
+        Transform::Flipped180,
+        Transform::Flipped270,
+    ];
+
+    /// Unknown values are treated as normal.
+    pub fn from_wire(value: u32) -> Transform {
+        Transform::ALL
+            .get(value as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn to_wire(self) -> u32 {
+        self as u32
+    }
+
+    /// True when the output is turned on its side.
+    pub fn swaps_axes(self) -> bool {
+        matches!(
+            self,
+            Transform::Rotate90
+                | Transform::Rotate270
+                | Transform::Flipped90
+                | Transform::Flipped270
+        )
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Transform::Normal => "Normal",
+            Transform::Rotate90 => "Rotated 90°",
+            Transform::Rotate180 => "Upside down",
+            Transform::Rotate270 => "Rotated 270°",
+            Transform::Flipped => "Flipped",
+            Transform::Flipped90 => "Flipped, rotated 90°",
+            Transform::Flipped180 => "Flipped, upside down",
+            Transform::Flipped270 => "Flipped, rotated 270°",
+        }
+    }
+}
+
+/// A resolution and refresh rate a head supports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mode {
+    pub width: i32,
+    pub height: i32,
+    /// In mHz, 0 when the compositor doesn't say.
+    pub refresh: i32,
+    pub preferred: bool,
+}
+
+impl Mode {
+    /// Same size and refresh rate, preferred or not.
+    pub fn same_as(&self, other: &Mode) -> bool {
+        (self.width, self.height, self.refresh) == (other.width, other.height, other.refresh)
+    }
+
+    /// Like "1920×1080".
+    pub fn resolution_label(&self) -> String {
+        format!("{}×{}", self.width, self.height)
+    }
+
+    /// Like "59.95 Hz", with trailing zeros dropped.
+    pub fn refresh_label(&self) -> String {
+        if self.refresh == 0 {
+            return "Unknown".to_owned();
+        }
+        let hz = format!("{:.2}", self.refresh as f64 / 1000.0);
+        format!("{} Hz", hz.trim_end_matches('0').trim_end_matches('.'))
+    }
+}
+
+/// A connected display, as of the last `done`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Head {
+    pub id: HeadId,
+    /// The connector, like "HDMI-A-1".
+    pub name: String,
+    pub description: String,
+    pub make: String,
+    pub model: String,
+    /// In millimetres, 0 when unknown.
+    pub physical_size: (i32, i32),
+    pub enabled: bool,
+    /// In the order the compositor sent them.
+    pub modes: Vec<Mode>,
+    /// `None` while disabled.
+    pub current_mode: Option<Mode>,
+    pub position: (i32, i32),
+    pub transform: Transform,
+    pub scale: f64,
+}
+
+impl Head {
+    fn new(id: HeadId) -> Head {
+        Head {
+            id,
+            name: String::new(),
+            description: String::new(),
+            make: String::new(),
+            model: String::new(),
+            physical_size: (0, 0),
+            enabled: false,
+            modes: vec![],
+            current_mode: None,
+            position: (0, 0),
+            transform: Transform::Normal,
+            scale: 1.0,
+        }
+    }
+
+    /// The configuration that would leave this head as it is.
+    pub fn config(&self) -> HeadConfig {
+        HeadConfig {
+            name: self.name.clone(),
+            enabled: self.enabled,
+            mode: self.current_mode,
+            position: self.position,
+            transform: self.transform,
+            scale: self.scale,
+        }
+    }
+
+    /// The mode to use when turning the head on: the preferred one, then
+    /// the first.
+    pub fn preferred_mode(&self) -> Option<Mode> {
+        self.modes
+            .iter()
+            .find(|mode| mode.preferred)
+            .or(self.modes.first())
+            .copied()
+    }
+
+    /// Each supported size once, largest first.
+    pub fn resolutions(&self) -> Vec<(i32, i32)> {
+        let mut resolutions: Vec<_> = self
+            .modes
+            .iter()
+            .map(|mode| (mode.width, mode.height))
+            .collect();
+        resolutions.sort_by_key(|(width, height)| std::cmp::Reverse(width * height));
+        resolutions.dedup();
+        resolutions
+    }
+
+    /// The modes with a given size, fastest first.
+    pub fn modes_sized(&self, width: i32, height: i32) -> Vec<Mode> {
+        let mut modes: Vec<_> = self
+            .modes
+            .iter()
+            .filter(|mode| (mode.width, mode.height) == (width, height))
+            .copied()
+            .collect();
+        modes.sort_by_key(|mode| std::cmp::Reverse(mode.refresh));
+        modes.dedup_by(|a, b| a.same_as(b));
+        modes
+    }
+}
+
+/// How a head should be set up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadConfig {
+    pub name: String,
+    pub enabled: bool,
+    /// One of the head's modes, or a custom mode. `None` leaves the mode to
+    /// the compositor.
+    pub mode: Option<Mode>,
+    pub position: (i32, i32),
+    pub transform: Transform,
+    pub scale: f64,
+}
+
+impl HeadConfig {
+    /// The space the head takes up in the layout, after rotating and
+    /// scaling. `None` without a mode.
+    pub fn logical_size(&self) -> Option<(i32, i32)> {
+        let mode = self.mode?;
+        let (width, height) = match self.transform.swaps_axes() {
+            true => (mode.height, mode.width),
+            false => (mode.width, mode.height),
+        };
+        let scale = if self.scale > 0.0 { self.scale } else { 1.0 };
+        Some((
+            (width as f64 / scale).round() as i32,
+            (height as f64 / scale).round() as i32,
+        ))
+    }
+}
+
+/// One event from the output manager, with heads and modes referred to by
+/// id.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputEvent {
+    Head {
+        head: HeadId,
+    },
+    Name {
+        head: HeadId,
+        name: String,
+    },
+    Description {
+        head: HeadId,
+        description: String,
+    },
+    Make {
+        head: HeadId,
+        make: String,
+    },
+    Model {
+        head: HeadId,
+        model: String,
+    },
+    PhysicalSize {
+        head: HeadId,
+        width: i32,
+        height: i32,
+    },
+    /// A mode of the head. Its details follow as `Mode*` events.
+    Mode {
+        head: HeadId,
+        mode: ModeId,
+    },
+    ModeSize {
+        mode: ModeId,
+        width: i32,
+        height: i32,
+    },
+    ModeRefresh {
+        mode: ModeId,
+        refresh: i32,
+    },
+    ModePreferred {
+        mode: ModeId,
+    },
+    ModeFinished {
+        mode: ModeId,
+    },
+    Enabled {
+        head: HeadId,
+        enabled: bool,
+    },
+    CurrentMode {
+        head: HeadId,
+        mode: ModeId,
+    },
+    Position {
+        head: HeadId,
+        x: i32,
+        y: i32,
+    },
+    Transform {
+        head: HeadId,
+        transform: Transform,
+    },
+    Scale {
+        head: HeadId,
+        scale: f64,
+    },
+    /// The head was unplugged.
+    HeadFinished {
+        head: HeadId,
+    },
+    /// The preceding events are complete. Configurations have to be made
+    /// against the latest serial.
+    Done {
+        serial: u32,
+    },
+    /// The output manager is gone, no more events will follow.
+    Finished,
+}
+
+#[derive(Debug, Clone)]
+struct HeadState {
+    head: Head,
+    modes: Vec<ModeId>,
+    current_mode: Option<ModeId>,
+}
+
+/// What to send for one head.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeadPlan {
+    Enable {
+        head: HeadId,
+        mode: Option<PlannedMode>,
+        position: (i32, i32),
+        transform: Transform,
+        scale: f64,
+    },
+    Disable {
+        head: HeadId,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlannedMode {
+    /// One the head advertised.
+    Existing(ModeId),
+    Custom(Mode),
+}
+
+/// The heads and their modes.
+#[derive(Debug, Default)]
+pub struct OutputModel {
+    heads: BTreeMap<HeadId, HeadState>,
+    modes: HashMap<ModeId, Mode>,
+    // Only set once the first done has arrived
+    serial: Option<u32>,
+    finished: bool,
+}
+
+impl OutputModel {
+    pub fn new() -> OutputModel {
+        OutputModel::default()
+    }
+
+    pub fn handle(&mut self, event: OutputEvent) {
+        match event {
+            OutputEvent::Head { head } => {
+                self.heads.insert(
+                    head,
+                    HeadState {
+                        head: Head::new(head),
+                        modes: vec![],
+                        current_mode: None,
+                    },
+                );
+            }
+            OutputEvent::Name { head, name } => self.update(head, |h| h.head.name = name),
+            OutputEvent::Description { head, description } => {
+                self.update(head, |h| h.head.description = description)
+            }
+            OutputEvent::Make { head, make } => self.update(head, |h| h.head.make = make),
+            OutputEvent::Model { head, model } => self.update(head, |h| h.head.model = model),
+            OutputEvent::PhysicalSize {
+                head,
+                width,
+                height,
+            } => self.update(head, |h| h.head.physical_size = (width, height)),
+            OutputEvent::Mode { head, mode } => {
+                self.modes.insert(mode, Mode::default());
+                self.update(head, |h| h.modes.push(mode));
+            }
+            OutputEvent::ModeSize {
+                mode,
+                width,
+                height,
+            } => {
+                if let Some(mode) = self.modes.get_mut(&mode) {
+                    mode.width = width;
+                    mode.height = height;
+                }
+            }
+            OutputEvent::ModeRefresh { mode, refresh } => {
+                if let Some(mode) = self.modes.get_mut(&mode) {
+                    mode.refresh = refresh;
+                }
+            }
+            OutputEvent::ModePreferred { mode } => {
+                if let Some(mode) = self.modes.get_mut(&mode) {
+                    mode.preferred = true;
+                }
+            }
+            OutputEvent::ModeFinished { mode } => {
+                self.modes.remove(&mode);
+                for state in self.heads.values_mut() {
+                    state.modes.retain(|other| *other != mode);
+                    if state.current_mode == Some(mode) {
+                        state.current_mode = None;
+                    }
+                }
+            }
+            OutputEvent::Enabled { head, enabled } => self.update(head, |h| {
+                h.head.enabled = enabled;
+                // Disabled heads don't send a current mode
+                if !enabled {
+                    h.current_mode = None;
+                }
+            }),
+            OutputEvent::CurrentMode { head, mode } => {
+                self.update(head, |h| h.current_mode = Some(mode))
+            }
+            OutputEvent::Position { head, x, y } => self.update(head, |h| h.head.position = (x, y)),
+            OutputEvent::Transform { head, transform } => {
+                self.update(head, |h| h.head.transform = transform)
+            }
+            OutputEvent::Scale { head, scale } => self.update(head, |h| h.head.scale = scale),
+            OutputEvent::HeadFinished { head } => {
+                if let Some(state) = self.heads.remove(&head) {
+                    for mode in state.modes {
+                        self.modes.remove(&mode);
+                    }
+                }
+            }
+            OutputEvent::Done { serial } => self.serial = Some(serial),
+            OutputEvent::Finished => self.finished = true,
+        }
+    }
+
+    fn update(&mut self, head: HeadId, change: impl FnOnce(&mut HeadState)) {
+        if let Some(state) = self.heads.get_mut(&head) {
+            change(state);
+        }
+    }
+
+    /// The serial to make configurations against, `None` before the first
+    /// `done`.
+    pub fn serial(&self) -> Option<u32> {
+        self.serial
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Every head that has a name, in the order they appeared.
+    pub fn heads(&self) -> Vec<Head> {
+        self.heads
+            .values()
+            .filter(|state| !state.head.name.is_empty())
+            .map(|state| {
+                let mut head = state.head.clone();
+                head.modes = self.modes_of(state).map(|(_, mode)| *mode).collect();
+                head.current_mode = state
+                    .current_mode
+                    .and_then(|mode| self.modes.get(&mode))
+                    .copied();
+                head
+            })
+            .collect()
+    }
+
+    fn modes_of<'a>(&'a self, state: &'a HeadState) -> impl Iterator<Item = (ModeId, &'a Mode)> {
+        state
+            .modes
+            .iter()
+            .filter_map(|id| self.modes.get(id).map(|mode| (*id, mode)))
+    }
+
+    /// What to send to set up the heads as `wanted`. Heads left out of
+    /// `wanted` stay as they are, as the protocol needs every head
+    /// configured.
+    pub fn plan(&self, wanted: &[HeadConfig]) -> Result<Vec<HeadPlan>, String> {
+        let heads = self.heads();
+        if let Some(config) = wanted
+            .iter()
+            .find(|config| !heads.iter().any(|head| head.name == config.name))
+        {
+            return Err(format!("{} isn't connected", config.name));
+        }
+
+        let mut plan = vec![];
+        for head in &heads {
+            let config = wanted
+                .iter()
+                .find(|config| config.name == head.name)
+                .cloned()
+                .unwrap_or_else(|| head.config());
+            if !config.enabled {
+                plan.push(HeadPlan::Disable { head: head.id });
+                continue;
+            }
+            if config.scale <= 0.0 {
+                return Err(format!("{} can't be scaled by {}", head.name, config.scale));
+            }
+            let mode = config.mode.map(|wanted| {
+                self.heads
+                    .get(&head.id)
+                    .and_then(|state| self.modes_of(state).find(|(_, mode)| mode.same_as(&wanted)))
+                    .map_or(PlannedMode::Custom(wanted), |(id, _)| {
+                        PlannedMode::Existing(id)
+                    })
+            });
+            plan.push(HeadPlan::Enable {
+                head: head.id,
+                mode,
+                position: config.position,
+                transform: config.transform,
+                scale: config.scale,
+            });
+        }
+
+        if !plan
+            .iter()
+            .any(|step| matches!(step, HeadPlan::Enable { .. }))
+        {
+            return Err("At least one display has to stay on".to_owned());
+        }
+        Ok(plan)
+    }
+}
+
+/// A configuration that has been applied but not kept yet.
+#[derive(Debug, Clone)]
+pub struct Confirmation {
+    previous: Vec<HeadConfig>,
+    deadline: Instant,
+}
+
+impl Confirmation {
+    /// `previous` is put back unless kept within `timeout` of `now`.
+    pub fn new(previous: Vec<HeadConfig>, timeout: Duration, now: Instant) -> Confirmation {
+        Confirmation {
+            previous,
+            deadline: now + timeout,
+        }
+    }
+
+    pub fn previous(&self) -> &[HeadConfig] {
+        &self.previous
+    }
+
+    /// Whole seconds left, rounded up so the countdown never shows 0 while
+    /// there is still time.
+    pub fn seconds_left(&self, now: Instant) -> u64 {
+        let left = self.deadline.saturating_duration_since(now);
+        left.as_secs() + u64::from(left.subsec_nanos() > 0)
+    }
+
+    pub fn expired(&self, now: Instant) -> bool {
+        now >= self.deadline
+    }
+}
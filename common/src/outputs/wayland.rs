@@ -0,0 +1,348 @@
+//! Wayland side of the display configuration.
+//!
+//! [`OutputManager`] has a connection and event queue of its own, so it can
+//! be used from a settings page without sharing the page's connection.
+
+use std::collections::HashMap;
+use wayland_client::{
+    event_created_child,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{wl_output, wl_registry::WlRegistry},
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols_wlr::output_management::v1::client::{
+    zwlr_output_configuration_head_v1::ZwlrOutputConfigurationHeadV1,
+    zwlr_output_configuration_v1::{self, ZwlrOutputConfigurationV1},
+    zwlr_output_head_v1::{self, ZwlrOutputHeadV1, EVT_MODE_OPCODE},
+    zwlr_output_manager_v1::{self, ZwlrOutputManagerV1, EVT_HEAD_OPCODE},
+    zwlr_output_mode_v1::{self, ZwlrOutputModeV1},
+};
+
+use super::{
+    Head, HeadConfig, HeadId, HeadPlan, ModeId, OutputEvent, OutputModel, PlannedMode, Transform,
+};
+
+/// Lists heads and applies configurations through
+/// `zwlr_output_manager_v1`.
+pub struct OutputManager {
+    queue: EventQueue<ManagerState>,
+    state: ManagerState,
+}
+
+struct ManagerState {
+    manager: ZwlrOutputManagerV1,
+    heads: HashMap<ZwlrOutputHeadV1, HeadId>,
+    modes: HashMap<ZwlrOutputModeV1, ModeId>,
+    next_id: u32,
+    model: OutputModel,
+    // How the last configuration went, once the compositor has said
+    result: Option<Result<(), String>>,
+}
+
+impl OutputManager {
+    /// Connect and wait for the current heads. Fails when the compositor
+    /// doesn't support wlr-output-management.
+    pub fn connect() -> Result<OutputManager, String> {
+        let conn = Connection::connect_to_env().map_err(|e| e.to_string())?;
+        let (globals, mut queue) =
+            registry_queue_init::<ManagerState>(&conn).map_err(|e| e.to_string())?;
+        let qh = queue.handle();
+        let manager = globals
+            .bind::<ZwlrOutputManagerV1, ManagerState, ()>(&qh, 1..=2, ())
+            .map_err(|e| format!("No output manager: {}", e))?;
+        let mut state = ManagerState {
+            manager,
+            heads: HashMap::new(),
+            modes: HashMap::new(),
+            next_id: 0,
+            model: OutputModel::new(),
+            result: None,
+        };
+        while state.model.serial().is_none() && !state.model.is_finished() {
+            queue
+                .blocking_dispatch(&mut state)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(OutputManager { queue, state })
+    }
+
+    pub fn heads(&self) -> Vec<Head> {
+        self.state.model.heads()
+    }
+
+    /// Pick up anything that changed since the last call, like a display
+    /// being plugged in.
+    pub fn refresh(&mut self) -> Result<(), String> {
+        self.queue
+            .roundtrip(&mut self.state)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Set up the heads as `wanted` and wait for the compositor to say
+    /// whether it worked. Heads left out stay as they are.
+    pub fn apply(&mut self, wanted: &[HeadConfig]) -> Result<(), String> {
+        self.refresh()?;
+        let serial = self
+            .state
+            .model
+            .serial()
+            .ok_or("The compositor hasn't listed its displays")?;
+        let plan = self.state.model.plan(wanted)?;
+
+        let qh = self.queue.handle();
+        let configuration = self.state.manager.create_configuration(serial, &qh, ());
+        for step in plan {
+            match step {
+                HeadPlan::Disable { head } => {
+                    if let Some(proxy) = self.state.head_for(head) {
+                        configuration.disable_head(&proxy);
+                    }
+                }
+                HeadPlan::Enable {
+                    head,
+                    mode,
+                    position,
+                    transform,
+                    scale,
+                } => {
+                    let Some(proxy) = self.state.head_for(head) else {
+                        continue;
+                    };
+                    let head = configuration.enable_head(&proxy, &qh, ());
+                    match mode {
+                        Some(PlannedMode::Existing(id)) => {
+                            if let Some(mode) = self.state.mode_for(id) {
+                                head.set_mode(&mode);
+                            }
+                        }
+                        Some(PlannedMode::Custom(mode)) => {
+                            head.set_custom_mode(mode.width, mode.height, mode.refresh)
+                        }
+                        None => {}
+                    }
+                    head.set_position(position.0, position.1);
+                    head.set_transform(to_wl_transform(transform));
+                    head.set_scale(scale);
+                }
+            }
+        }
+        configuration.apply();
+
+        self.state.result = None;
+        let result = loop {
+            if let Some(result) = self.state.result.take() {
+                break result;
+            }
+            if let Err(e) = self.queue.blocking_dispatch(&mut self.state) {
+                break Err(e.to_string());
+            }
+        };
+        configuration.destroy();
+        // The new state follows straight after
+        self.refresh()?;
+        result
+    }
+}
+
+impl ManagerState {
+    fn head_for(&self, id: HeadId) -> Option<ZwlrOutputHeadV1> {
+        self.heads
+            .iter()
+            .find(|(_, other)| **other == id)
+            .map(|(head, _)| head.clone())
+    }
+
+    fn mode_for(&self, id: ModeId) -> Option<ZwlrOutputModeV1> {
+        self.modes
+            .iter()
+            .find(|(_, other)| **other == id)
+            .map(|(mode, _)| mode.clone())
+    }
+
+    fn next_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+fn to_wl_transform(transform: Transform) -> wl_output::Transform {
+    wl_output::Transform::try_from(transform.to_wire()).unwrap_or(wl_output::Transform::Normal)
+}
+
+impl Dispatch<ZwlrOutputManagerV1, ()> for ManagerState {
+    event_created_child!(ManagerState, ZwlrOutputManagerV1, [
+        EVT_HEAD_OPCODE => (ZwlrOutputHeadV1, ())
+    ]);
+
+    fn event(
+        state: &mut Self,
+        _proxy: &ZwlrOutputManagerV1,
+        event: zwlr_output_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        let event = match event {
+            zwlr_output_manager_v1::Event::Head { head } => {
+                let id = state.next_id();
+                state.heads.insert(head, id);
+                OutputEvent::Head { head: id }
+            }
+            zwlr_output_manager_v1::Event::Done { serial } => OutputEvent::Done { serial },
+            zwlr_output_manager_v1::Event::Finished => {
+                // Don't wait for a result that can't come
+                state
+                    .result
+                    .get_or_insert(Err("The output manager has gone away".to_owned()));
+                OutputEvent::Finished
+            }
+            _ => return,
+        };
+        state.model.handle(event);
+    }
+}
+
+impl Dispatch<ZwlrOutputHeadV1, ()> for ManagerState {
+    event_created_child!(ManagerState, ZwlrOutputHeadV1, [
+        EVT_MODE_OPCODE => (ZwlrOutputModeV1, ())
+    ]);
+
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrOutputHeadV1,
+        event: zwlr_output_head_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        let Some(head) = state.heads.get(proxy).copied() else {
+            return;
+        };
+        let event = match event {
+            zwlr_output_head_v1::Event::Name { name } => OutputEvent::Name { head, name },
+            zwlr_output_head_v1::Event::Description { description } => {
+                OutputEvent::Description { head, description }
+            }
+            zwlr_output_head_v1::Event::Make { make } => OutputEvent::Make { head, make },
+            zwlr_output_head_v1::Event::Model { model } => OutputEvent::Model { head, model },
+            zwlr_output_head_v1::Event::PhysicalSize { width, height } => {
+                OutputEvent::PhysicalSize {
+                    head,
+                    width,
+                    height,
+                }
+            }
+            zwlr_output_head_v1::Event::Mode { mode } => {
+                let id = state.next_id();
+                state.modes.insert(mode, id);
+                OutputEvent::Mode { head, mode: id }
+            }
+            zwlr_output_head_v1::Event::Enabled { enabled } => OutputEvent::Enabled {
+                head,
+                enabled: enabled != 0,
+            },
+            zwlr_output_head_v1::Event::CurrentMode { mode } => {
+                let Some(mode) = state.modes.get(&mode).copied() else {
+                    return;
+                };
+                OutputEvent::CurrentMode { head, mode }
+            }
+            zwlr_output_head_v1::Event::Position { x, y } => OutputEvent::Position { head, x, y },
+            zwlr_output_head_v1::Event::Transform { transform } => OutputEvent::Transform {
+                head,
+                transform: match transform {
+                    WEnum::Value(transform) => Transform::from_wire(transform.into()),
+                    WEnum::Unknown(raw) => Transform::from_wire(raw),
+                },
+            },
+            zwlr_output_head_v1::Event::Scale { scale } => OutputEvent::Scale { head, scale },
+            zwlr_output_head_v1::Event::Finished => {
+                state.heads.remove(proxy);
+                OutputEvent::HeadFinished { head }
+            }
+            _ => return,
+        };
+        state.model.handle(event);
+    }
+}
+
+impl Dispatch<ZwlrOutputModeV1, ()> for ManagerState {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrOutputModeV1,
+        event: zwlr_output_mode_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        let Some(mode) = state.modes.get(proxy).copied() else {
+            return;
+        };
+        let event = match event {
+            zwlr_output_mode_v1::Event::Size { width, height } => OutputEvent::ModeSize {
+                mode,
+                width,
+                height,
+            },
+            zwlr_output_mode_v1::Event::Refresh { refresh } => {
+                OutputEvent::ModeRefresh { mode, refresh }
+            }
+            zwlr_output_mode_v1::Event::Preferred => OutputEvent::ModePreferred { mode },
+            zwlr_output_mode_v1::Event::Finished => {
+                state.modes.remove(proxy);
+                OutputEvent::ModeFinished { mode }
+            }
+            _ => return,
+        };
+        state.model.handle(event);
+    }
+}
+
+impl Dispatch<ZwlrOutputConfigurationV1, ()> for ManagerState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ZwlrOutputConfigurationV1,
+        event: zwlr_output_configuration_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        state.result = Some(match event {
+            zwlr_output_configuration_v1::Event::Succeeded => Ok(()),
+            zwlr_output_configuration_v1::Event::Failed => {
+                Err("The displays don't support that".to_owned())
+            }
+            // Something else changed the displays first
+            zwlr_output_configuration_v1::Event::Cancelled => {
+                Err("The displays changed, try again".to_owned())
+            }
+            _ => return,
+        });
+    }
+}
+
+// Configured heads don't send anything
+impl Dispatch<ZwlrOutputConfigurationHeadV1, ()> for ManagerState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwlrOutputConfigurationHeadV1,
+        _event: <ZwlrOutputConfigurationHeadV1 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for ManagerState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlRegistry,
+        _event: <WlRegistry as Proxy>::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
@@ -0,0 +1,70 @@
+//! Hashed PINs, for extra time and the lock screen.
+
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+
+/// How long a first wrong PIN holds off the next attempt
+pub const WRONG_DELAY: Duration = Duration::from_secs(2);
+/// The longest the delay grows to
+pub const MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// PINs are stored in config as `sha256:<salt>:<hex digest of salt + pin>`.
+/// Generate one with `consolation-switcher --hash-pin <pin>`
+pub fn hash(pin: &str) -> String {
+    let mut salt = [0u8; 8];
+    if let Ok(bytes) = std::fs::read("/proc/sys/kernel/random/uuid") {
+        for (idx, byte) in bytes.iter().enumerate() {
+            salt[idx % salt.len()] ^= byte;
+        }
+    }
+    let salt = hex::encode(salt);
+    format!("sha256:{}:{}", salt, digest(&salt, pin))
+}
+
+pub fn verify(stored: &str, pin: &str) -> bool {
+    let mut parts = stored.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("sha256"), Some(salt), Some(expected)) => digest(salt, pin) == expected,
+        _ => {
+            println!("Unrecognised PIN hash in config");
+            false
+        }
+    }
+}
+
+fn digest(salt: &str, pin: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(pin.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Holds off attempts after a wrong PIN, doubling the delay with each one
+/// so a short PIN can't just be counted through
+#[derive(Debug, Clone, Default)]
+pub struct Backoff {
+    failures: u32,
+    until: Option<Instant>,
+}
+
+impl Backoff {
+    /// How long until the next attempt is allowed, None if it is now
+    pub fn wait(&self, now: Instant) -> Option<Duration> {
+        let until = self.until?;
+        (until > now).then(|| until - now)
+    }
+
+    /// Count a wrong PIN, returning how long until the next attempt
+    pub fn failed(&mut self, now: Instant) -> Duration {
+        let delay = WRONG_DELAY
+            .saturating_mul(2u32.saturating_pow(self.failures))
+            .min(MAX_DELAY);
+        self.failures = self.failures.saturating_add(1);
+        self.until = Some(now + delay);
+        delay
+    }
+
+    pub fn succeeded(&mut self) {
+        *self = Backoff::default();
+    }
+}
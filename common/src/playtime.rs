@@ -0,0 +1,270 @@
+//! How long each app has spent in the foreground.
+
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::process::ProcFs;
+
+/// One stretch of time an app spent in the foreground
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub start: u64,
+    pub seconds: u64,
+    pub app_id: String,
+    pub steam_app_id: Option<String>,
+}
+
+impl Session {
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            self.start,
+            self.seconds,
+            self.app_id,
+            self.steam_app_id.as_deref().unwrap_or("")
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Session> {
+        let mut fields = line.split('\t');
+        let start = fields.next()?.parse().ok()?;
+        let seconds = fields.next()?.parse().ok()?;
+        let app_id = fields.next()?.to_owned();
+        let steam_app_id = fields
+            .next()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_owned());
+        Some(Session {
+            start,
+            seconds,
+            app_id,
+            steam_app_id,
+        })
+    }
+
+    // Keys this session counts towards, the app_id and the Steam game if any
+    fn keys(&self) -> Vec<String> {
+        let mut keys = vec![self.app_id.clone()];
+        if let Some(steam) = &self.steam_app_id {
+            keys.push(steam_key(steam));
+        }
+        keys
+    }
+}
+
+pub fn steam_key(steam_app_id: &str) -> String {
+    format!("steam:{}", steam_app_id)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Playtime {
+    pub today: Duration,
+    pub total: Duration,
+}
+
+impl Playtime {
+    pub fn describe(&self) -> String {
+        format!(
+            "played {} today / {} total",
+            format_duration(self.today),
+            format_duration(self.total)
+        )
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else if minutes < 600 {
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}h", minutes / 60)
+    }
+}
+
+struct Current {
+    app_id: String,
+    steam_app_id: Option<String>,
+    start: SystemTime,
+    started: Instant,
+}
+
+/// Foreground time per app, kept as an append-only log of sessions in
+/// $XDG_DATA_HOME/consolation/playtime.log
+pub struct PlaytimeTracker {
+    path: Option<PathBuf>,
+    sessions: Vec<Session>,
+    current: Option<Current>,
+}
+
+impl PlaytimeTracker {
+    pub fn path() -> Option<PathBuf> {
+        let dirs = xdg::BaseDirectories::with_prefix("consolation").ok()?;
+        dirs.place_data_file("playtime.log").ok()
+    }
+
+    pub fn load() -> PlaytimeTracker {
+        let path = PlaytimeTracker::path();
+        let sessions = path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| contents.lines().filter_map(Session::from_line).collect())
+            .unwrap_or_default();
+        PlaytimeTracker {
+            path,
+            sessions,
+            current: None,
+        }
+    }
+
+    pub fn current_app_id(&self) -> Option<&str> {
+        self.current.as_ref().map(|current| current.app_id.as_str())
+    }
+
+    /// Called whenever the activated window changes, with None when nothing
+    /// is in the foreground
+    pub fn set_foreground(&mut self, app_id: Option<&str>) {
+        if self.current_app_id() == app_id {
+            return;
+        }
+        self.end_session();
+        if let Some(app_id) = app_id {
+            self.current = Some(Current {
+                app_id: app_id.to_owned(),
+                steam_app_id: find_steam_app_id(app_id),
+                start: SystemTime::now(),
+                started: Instant::now(),
+            });
+        }
+    }
+
+    /// Write out the running session and start a fresh one for the same
+    /// app, so a crash or power loss costs at most one interval
+    pub fn checkpoint(&mut self) {
+        let Some(current) = self.current.take() else {
+            return;
+        };
+        let app_id = current.app_id.clone();
+        let steam_app_id = current.steam_app_id.clone();
+        self.current = Some(current);
+        self.end_session();
+        self.current = Some(Current {
+            app_id,
+            steam_app_id,
+            start: SystemTime::now(),
+            started: Instant::now(),
+        });
+    }
+
+    fn end_session(&mut self) {
+        let Some(current) = self.current.take() else {
+            return;
+        };
+        // Instant doesn't count time spent suspended, which is what we want
+        let seconds = current.started.elapsed().as_secs();
+        if seconds == 0 {
+            return;
+        }
+        let session = Session {
+            start: unix_time(current.start),
+            seconds,
+            app_id: current.app_id,
+            steam_app_id: current.steam_app_id,
+        };
+        if let Some(path) = &self.path {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(session.to_line().as_bytes()));
+            if let Err(e) = written {
+                println!("Unable to write playtime to {}: {}", path.display(), e);
+            }
+        }
+        self.sessions.push(session);
+    }
+
+    /// Totals for an app_id or `steam:<id>` key, including the running session
+    pub fn playtime(&self, key: &str) -> Playtime {
+        let midnight = local_midnight();
+        let mut playtime = Playtime::default();
+        for session in self.sessions.iter() {
+            if !session.keys().iter().any(|k| k == key) {
+                continue;
+            }
+            let duration = Duration::from_secs(session.seconds);
+            playtime.total += duration;
+            if session.start >= midnight {
+                playtime.today += duration;
+            }
+        }
+        if let Some(current) = &self.current {
+            let matches = current.app_id == key
+                || current.steam_app_id.as_deref().map(steam_key).as_deref() == Some(key);
+            if matches {
+                playtime.total += current.started.elapsed();
+                playtime.today += current.started.elapsed();
+            }
+        }
+        playtime
+    }
+
+    pub fn export_csv(&self) -> String {
+        let mut out = "start,seconds,app_id,steam_app_id\n".to_owned();
+        for session in self.sessions.iter() {
+            out += &format!(
+                "{},{},{},{}\n",
+                session.start,
+                session.seconds,
+                csv_field(&session.app_id),
+                session.steam_app_id.as_deref().unwrap_or("")
+            );
+        }
+        out
+    }
+
+    pub fn export_json(&self) -> String {
+        serde_json::to_string_pretty(&self.sessions).unwrap_or_default()
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn local_midnight() -> u64 {
+    let now = chrono::Local::now();
+    now.date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(chrono::Local).earliest())
+        .map(|midnight| midnight.timestamp() as u64)
+        .unwrap_or(0)
+}
+
+/// Proton games report `steam_app_<id>` as their app_id. Native games keep
+/// their own app_id, but Steam leaves SteamAppId in their environment
+pub fn find_steam_app_id(app_id: &str) -> Option<String> {
+    if let Some(id) = app_id.strip_prefix("steam_app_") {
+        return Some(id.to_owned());
+    }
+    let procfs = ProcFs::default();
+    procfs
+        .find_by_app_id(app_id)
+        .into_iter()
+        .find_map(|pid| procfs.environ(pid, "SteamAppId"))
+        .filter(|id| id != "0")
+}
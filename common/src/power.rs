@@ -0,0 +1,644 @@
+//! Battery and AC adapter state, and when to warn about it.
+//!
+//! A [`PowerSource`] reads the current [`PowerStatus`], from UPower when
+//! it's running or straight from `/sys/class/power_supply` otherwise.
+//! [`PowerMonitor`] is fed each reading and says which [`PowerWarning`]s
+//! are new, so a warning is given once rather than on every poll.
+//!
+//! Wireless controllers have batteries of their own, listed by
+//! [`SysfsPower::controllers`] and watched by a separate
+//! [`ControllerMonitor`].
+
+use std::io;
+use std::path::{Path, PathBuf};
+use zbus::blocking;
+
+const SYSFS_ROOT: &str = "/sys/class/power_supply";
+const UPOWER_NAME: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const DISPLAY_DEVICE_PATH: &str = "/org/freedesktop/UPower/devices/DisplayDevice";
+
+// Batteries often settle a little under 100% and report "Not charging"
+// rather than "Full", which isn't worth a warning
+const FULL_ENOUGH: u8 = 95;
+
+// With only an end threshold, how far under it the battery may sit
+// without charging. Firmware lets it drift down a little before topping up
+const END_MARGIN: u8 = 5;
+
+// Supply names given by controller drivers: hid-playstation, hid-sony,
+// hid-nintendo, hid-steam, hid-wiimote and xpadneo
+const CONTROLLER_NAMES: [&str; 4] = ["controller", "xpadneo", "wiimote", "gamepad"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SupplyKind {
+    Mains,
+    Battery,
+    Usb,
+    Other(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BatteryStatus {
+    Charging,
+    Discharging,
+    /// Plugged in, but the battery isn't taking charge.
+    NotCharging,
+    Full,
+    #[default]
+    Unknown,
+}
+
+/// The `capacity_level` attribute, for batteries that only report roughly
+/// how full they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapacityLevel {
+    Critical,
+    Low,
+    Normal,
+    High,
+    Full,
+}
+
+impl CapacityLevel {
+    pub fn from_sysfs(level: &str) -> Option<CapacityLevel> {
+        match level {
+            "Critical" => Some(CapacityLevel::Critical),
+            "Low" => Some(CapacityLevel::Low),
+            "Normal" => Some(CapacityLevel::Normal),
+            "High" => Some(CapacityLevel::High),
+            "Full" => Some(CapacityLevel::Full),
+            _ => None,
+        }
+    }
+}
+
+impl BatteryStatus {
+    /// The `status` attribute in sysfs.
+    pub fn from_sysfs(status: &str) -> BatteryStatus {
+        match status {
+            "Charging" => BatteryStatus::Charging,
+            "Discharging" => BatteryStatus::Discharging,
+            "Not charging" => BatteryStatus::NotCharging,
+            "Full" => BatteryStatus::Full,
+            _ => BatteryStatus::Unknown,
+        }
+    }
+
+    /// UPower's `State` property.
+    pub fn from_upower(state: u32) -> BatteryStatus {
+        match state {
+            1 => BatteryStatus::Charging,
+            2 | 3 => BatteryStatus::Discharging,
+            4 => BatteryStatus::Full,
+            5 | 6 => BatteryStatus::NotCharging,
+            _ => BatteryStatus::Unknown,
+        }
+    }
+}
+
+/// One entry in `/sys/class/power_supply`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PowerSupply {
+    /// The directory name, like `BAT0` or `AC`.
+    pub name: String,
+    pub kind: SupplyKind,
+    /// Whether an adapter is plugged in. Batteries don't have it.
+    pub online: Option<bool>,
+    pub present: bool,
+    /// Percent charged.
+    pub capacity: Option<u8>,
+    pub capacity_level: Option<CapacityLevel>,
+    pub status: BatteryStatus,
+    /// Powers a peripheral, like a controller or mouse, rather than the
+    /// system.
+    pub device_scope: bool,
+    pub model_name: Option<String>,
+    /// The name of the HID device it belongs to, like "Wireless Controller".
+    pub hid_name: Option<String>,
+    /// Energy or charge now and when full, in whatever unit the driver
+    /// uses.
+    pub energy: Option<(u64, u64)>,
+    /// Firmware charge limiting: charging only starts below this percentage.
+    pub charge_start: Option<u8>,
+    /// Firmware charge limiting: charging stops at this percentage.
+    pub charge_end: Option<u8>,
+}
+
+impl PowerSupply {
+    /// Whether it counts towards the system's battery level.
+    pub fn is_system_battery(&self) -> bool {
+        self.kind == SupplyKind::Battery && self.present && !self.device_scope
+    }
+
+    /// A wireless controller's battery.
+    pub fn is_controller(&self) -> bool {
+        let name = self.name.to_lowercase();
+        self.kind == SupplyKind::Battery
+            && self.present
+            && self.device_scope
+            && CONTROLLER_NAMES.iter().any(|part| name.contains(part))
+    }
+
+    /// A name to show for it.
+    pub fn label(&self) -> &str {
+        self.hid_name
+            .as_deref()
+            .or(self.model_name.as_deref())
+            .unwrap_or(&self.name)
+    }
+
+    pub fn is_charging(&self) -> bool {
+        matches!(self.status, BatteryStatus::Charging | BatteryStatus::Full)
+    }
+
+    /// Its [`battery_icon`], guessed from the capacity level when there's
+    /// no percentage.
+    pub fn icon(&self) -> String {
+        let percentage = match (self.capacity, self.capacity_level) {
+            (Some(capacity), _) => capacity,
+            (None, Some(CapacityLevel::Critical)) => 0,
+            (None, Some(CapacityLevel::Low)) => 20,
+            (None, Some(CapacityLevel::Normal)) => 50,
+            (None, Some(CapacityLevel::High)) => 80,
+            (None, Some(CapacityLevel::Full)) => 100,
+            (None, None) => return "battery-missing-symbolic".to_owned(),
+        };
+        battery_icon(percentage, self.is_charging())
+    }
+}
+
+/// The `battery-level-NN(-charging)-symbolic` icon name, to the nearest 10%.
+pub fn battery_icon(percentage: u8, charging: bool) -> String {
+    let level = (percentage.min(100) as u32 + 5) / 10 * 10;
+    match charging {
+        true => format!("battery-level-{}-charging-symbolic", level),
+        false => format!("battery-level-{}-symbolic", level),
+    }
+}
+
+/// The system's battery level, every battery combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryLevel {
+    pub percentage: u8,
+    pub status: BatteryStatus,
+    /// See [`PowerSupply::charge_start`].
+    pub charge_start: Option<u8>,
+    /// See [`PowerSupply::charge_end`].
+    pub charge_end: Option<u8>,
+}
+
+impl BatteryLevel {
+    /// Below this, plugged in and not charging is worth a warning. Over it
+    /// the firmware's charge limit, or being nearly full, explains it.
+    pub fn expected_charging_below(&self) -> u8 {
+        [
+            Some(FULL_ENOUGH),
+            self.charge_start,
+            self.charge_end.map(|end| end.saturating_sub(END_MARGIN)),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(FULL_ENOUGH)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PowerStatus {
+    /// None when there's no adapter to ask about.
+    pub ac_online: Option<bool>,
+    /// None on machines without a battery.
+    pub battery: Option<BatteryLevel>,
+}
+
+impl PowerStatus {
+    /// Combine sysfs entries. Peripheral batteries are left out.
+    pub fn from_supplies(supplies: &[PowerSupply]) -> PowerStatus {
+        let adapters: Vec<bool> = supplies
+            .iter()
+            .filter(|supply| matches!(supply.kind, SupplyKind::Mains | SupplyKind::Usb))
+            .filter_map(|supply| supply.online)
+            .collect();
+        let ac_online = match adapters.is_empty() {
+            true => None,
+            false => Some(adapters.contains(&true)),
+        };
+
+        let batteries: Vec<&PowerSupply> = supplies
+            .iter()
+            .filter(|supply| supply.is_system_battery())
+            .collect();
+        if batteries.is_empty() {
+            return PowerStatus {
+                ac_online,
+                battery: None,
+            };
+        }
+        // Weighted by size when every battery says how full it is,
+        // otherwise the plain average
+        let percentage = match batteries
+            .iter()
+            .map(|battery| battery.energy)
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(energy) if energy.iter().map(|(_, full)| full).sum::<u64>() > 0 => {
+                let now: u64 = energy.iter().map(|(now, _)| now).sum();
+                let full: u64 = energy.iter().map(|(_, full)| full).sum();
+                (now * 100 / full).min(100) as u8
+            }
+            _ => {
+                let capacities: Vec<u32> = batteries
+                    .iter()
+                    .filter_map(|battery| battery.capacity)
+                    .map(u32::from)
+                    .collect();
+                if capacities.is_empty() {
+                    return PowerStatus {
+                        ac_online,
+                        battery: None,
+                    };
+                }
+                (capacities.iter().sum::<u32>() / capacities.len() as u32) as u8
+            }
+        };
+        let statuses: Vec<BatteryStatus> = batteries.iter().map(|battery| battery.status).collect();
+        let status = [
+            BatteryStatus::Charging,
+            BatteryStatus::Discharging,
+            BatteryStatus::NotCharging,
+        ]
+        .into_iter()
+        .find(|status| statuses.contains(status))
+        .unwrap_or(match statuses.iter().all(|s| *s == BatteryStatus::Full) {
+            true => BatteryStatus::Full,
+            false => BatteryStatus::Unknown,
+        });
+        PowerStatus {
+            ac_online,
+            battery: Some(BatteryLevel {
+                percentage,
+                status,
+                charge_start: batteries.iter().filter_map(|b| b.charge_start).min(),
+                charge_end: batteries.iter().filter_map(|b| b.charge_end).min(),
+            }),
+        }
+    }
+
+    /// Running off the battery, as far as we can tell.
+    pub fn on_battery(&self) -> bool {
+        match (self.ac_online, self.battery) {
+            (Some(online), Some(_)) => !online,
+            (None, Some(battery)) => battery.status == BatteryStatus::Discharging,
+            (_, None) => false,
+        }
+    }
+}
+
+pub trait PowerSource {
+    fn status(&self) -> io::Result<PowerStatus>;
+}
+
+/// Reads a `power_supply` class directory.
+#[derive(Debug, Clone)]
+pub struct SysfsPower {
+    root: PathBuf,
+}
+
+impl SysfsPower {
+    /// Read supplies from `root`. Tests point this at a fake tree.
+    pub fn new(root: impl Into<PathBuf>) -> SysfsPower {
+        SysfsPower { root: root.into() }
+    }
+
+    /// `/sys/class/power_supply`
+    pub fn system() -> SysfsPower {
+        SysfsPower::new(SYSFS_ROOT)
+    }
+
+    /// Every supply, sorted by name. Entries that vanish while being read
+    /// are skipped.
+    pub fn supplies(&self) -> io::Result<Vec<PowerSupply>> {
+        let mut supplies = vec![];
+        for entry in std::fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            let Some(kind) = attribute(&path, "type") else {
+                continue;
+            };
+            let kind = match kind.as_str() {
+                "Mains" => SupplyKind::Mains,
+                "Battery" => SupplyKind::Battery,
+                "USB" => SupplyKind::Usb,
+                _ => SupplyKind::Other(kind),
+            };
+            let energy = number(&path, "energy_now")
+                .zip(number(&path, "energy_full"))
+                .or_else(|| number(&path, "charge_now").zip(number(&path, "charge_full")));
+            supplies.push(PowerSupply {
+                name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                kind,
+                online: number(&path, "online").map(|online| online != 0),
+                present: number(&path, "present").is_none_or(|present| present != 0),
+                capacity: number(&path, "capacity").map(|capacity| capacity.min(100) as u8),
+                capacity_level: attribute(&path, "capacity_level")
+                    .and_then(|level| CapacityLevel::from_sysfs(&level)),
+                status: attribute(&path, "status")
+                    .map(|status| BatteryStatus::from_sysfs(&status))
+                    .unwrap_or_default(),
+                device_scope: attribute(&path, "scope").as_deref() == Some("Device"),
+                model_name: attribute(&path, "model_name").filter(|name| !name.is_empty()),
+                hid_name: hid_name(&path),
+                energy,
+                charge_start: threshold(&path, "charge_control_start_threshold"),
+                charge_end: threshold(&path, "charge_control_end_threshold"),
+            });
+        }
+        supplies.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(supplies)
+    }
+
+    /// Connected wireless controllers, sorted by supply name so they keep
+    /// their place.
+    pub fn controllers(&self) -> io::Result<Vec<PowerSupply>> {
+        let mut supplies = self.supplies()?;
+        supplies.retain(|supply| supply.is_controller());
+        Ok(supplies)
+    }
+}
+
+impl PowerSource for SysfsPower {
+    fn status(&self) -> io::Result<PowerStatus> {
+        Ok(PowerStatus::from_supplies(&self.supplies()?))
+    }
+}
+
+fn attribute(path: &Path, name: &str) -> Option<String> {
+    std::fs::read_to_string(path.join(name))
+        .ok()
+        .map(|value| value.trim().to_owned())
+}
+
+// HID_NAME from the parent device's uevent
+fn hid_name(path: &Path) -> Option<String> {
+    let uevent = std::fs::read_to_string(path.join("device").join("uevent")).ok()?;
+    uevent
+        .lines()
+        .find_map(|line| line.strip_prefix("HID_NAME="))
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+}
+
+fn number(path: &Path, name: &str) -> Option<u64> {
+    attribute(path, name)?.parse().ok()
+}
+
+// A charge control threshold, 0 and 100 being the same as none
+fn threshold(path: &Path, name: &str) -> Option<u8> {
+    number(path, name)
+        .filter(|threshold| *threshold > 0 && *threshold < 100)
+        .map(|threshold| threshold as u8)
+}
+
+/// Asks UPower for its combined display device.
+pub struct UPower {
+    upower: blocking::Proxy<'static>,
+    display: blocking::Proxy<'static>,
+}
+
+impl UPower {
+    /// Connect on the system bus. Fails if UPower isn't running and can't
+    /// be started.
+    pub fn system() -> zbus::Result<UPower> {
+        let connection = blocking::Connection::system()?;
+        let upower = blocking::Proxy::new(&connection, UPOWER_NAME, UPOWER_PATH, UPOWER_NAME)?;
+        let display = blocking::Proxy::new(
+            &connection,
+            UPOWER_NAME,
+            DISPLAY_DEVICE_PATH,
+            "org.freedesktop.UPower.Device",
+        )?;
+        let upower = UPower { upower, display };
+        upower.read()?;
+        Ok(upower)
+    }
+
+    fn read(&self) -> zbus::Result<PowerStatus> {
+        let on_battery: bool = self.upower.get_property("OnBattery")?;
+        let present: bool = self.display.get_property("IsPresent")?;
+        if !present {
+            return Ok(PowerStatus {
+                ac_online: Some(!on_battery),
+                battery: None,
+            });
+        }
+        let percentage: f64 = self.display.get_property("Percentage")?;
+        let state: u32 = self.display.get_property("State")?;
+        // Only newer versions know about charge limits
+        let enabled = matches!(
+            self.display.get_property::<bool>("ChargeThresholdEnabled"),
+            Ok(true)
+        );
+        let threshold = |name: &str| {
+            self.display
+                .get_property::<u32>(name)
+                .ok()
+                .filter(|threshold| enabled && *threshold > 0 && *threshold < 100)
+                .map(|threshold| threshold as u8)
+        };
+        let charge_start = threshold("ChargeStartThreshold");
+        let charge_end = threshold("ChargeEndThreshold");
+        Ok(PowerStatus {
+            ac_online: Some(!on_battery),
+            battery: Some(BatteryLevel {
+                percentage: percentage.round().clamp(0.0, 100.0) as u8,
+                status: BatteryStatus::from_upower(state),
+                charge_start,
+                charge_end,
+            }),
+        })
+    }
+}
+
+impl PowerSource for UPower {
+    fn status(&self) -> io::Result<PowerStatus> {
+        self.read().map_err(io::Error::other)
+    }
+}
+
+/// UPower if it's there, sysfs otherwise.
+pub fn system_source() -> Box<dyn PowerSource + Send> {
+    match UPower::system() {
+        Ok(upower) => Box::new(upower),
+        Err(e) => {
+            println!("UPower unavailable, reading {}: {}", SYSFS_ROOT, e);
+            Box::new(SysfsPower::system())
+        }
+    }
+}
+
+/// Battery percentages to warn at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PowerThresholds {
+    /// Each gives one warning on the way down.
+    pub low: Vec<u8>,
+    pub critical: u8,
+}
+
+impl Default for PowerThresholds {
+    fn default() -> Self {
+        PowerThresholds {
+            low: vec![20, 10],
+            critical: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerWarning {
+    Low {
+        percentage: u8,
+    },
+    Critical {
+        percentage: u8,
+    },
+    /// An adapter is plugged in but the battery isn't charging, it may be
+    /// too weak or faulty.
+    NotCharging {
+        percentage: u8,
+    },
+}
+
+/// Turns readings into warnings, each once until the situation that caused
+/// it has passed.
+#[derive(Debug, Clone, Default)]
+pub struct PowerMonitor {
+    thresholds: PowerThresholds,
+    // The lowest threshold already warned about
+    warned_low: Option<u8>,
+    warned_critical: bool,
+    warned_not_charging: bool,
+}
+
+impl PowerMonitor {
+    pub fn new(thresholds: PowerThresholds) -> PowerMonitor {
+        PowerMonitor {
+            thresholds,
+            ..Default::default()
+        }
+    }
+
+    /// Warnings that weren't already given for earlier readings.
+    pub fn update(&mut self, status: &PowerStatus) -> Vec<PowerWarning> {
+        let mut warnings = vec![];
+        let Some(battery) = status.battery else {
+            *self = PowerMonitor::new(self.thresholds.clone());
+            return warnings;
+        };
+        let percentage = battery.percentage;
+
+        if status.on_battery() {
+            // Charging back over a threshold, even a little, re-arms it
+            self.warned_low = self.warned_low.filter(|warned| percentage <= *warned);
+            self.warned_critical &= percentage <= self.thresholds.critical;
+            let crossed = self
+                .thresholds
+                .low
+                .iter()
+                .copied()
+                .filter(|threshold| percentage <= *threshold)
+                .min();
+            if percentage <= self.thresholds.critical {
+                if !self.warned_critical {
+                    warnings.push(PowerWarning::Critical { percentage });
+                }
+                self.warned_critical = true;
+                self.warned_low = crossed.or(self.warned_low);
+            } else if let Some(threshold) = crossed {
+                // Only the lowest threshold passed, after a jump past several
+                if self.warned_low.is_none_or(|warned| threshold < warned) {
+                    warnings.push(PowerWarning::Low { percentage });
+                    self.warned_low = Some(threshold);
+                }
+            }
+        } else {
+            self.warned_low = None;
+            self.warned_critical = false;
+        }
+
+        let not_charging = status.ac_online == Some(true)
+            && matches!(
+                battery.status,
+                BatteryStatus::NotCharging | BatteryStatus::Discharging
+            )
+            && percentage < battery.expected_charging_below();
+        if not_charging && !self.warned_not_charging {
+            warnings.push(PowerWarning::NotCharging { percentage });
+        }
+        self.warned_not_charging = not_charging;
+        warnings
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerWarning {
+    /// The supply name, see [`PowerSupply::name`].
+    pub name: String,
+    pub label: String,
+    /// None when the controller only reports a [`CapacityLevel`].
+    pub percentage: Option<u8>,
+}
+
+/// Warns once per controller as its battery runs low, again after it has
+/// been charged or reconnected.
+#[derive(Debug, Clone, Default)]
+pub struct ControllerMonitor {
+    low: u8,
+    // Supply names already warned about
+    warned: Vec<String>,
+}
+
+impl ControllerMonitor {
+    /// Warn at or below `low` percent.
+    pub fn new(low: u8) -> ControllerMonitor {
+        ControllerMonitor {
+            low,
+            warned: vec![],
+        }
+    }
+
+    /// Warnings that weren't already given for earlier readings.
+    pub fn update(&mut self, controllers: &[PowerSupply]) -> Vec<ControllerWarning> {
+        let low: Vec<&PowerSupply> = controllers
+            .iter()
+            .filter(|controller| self.is_low(controller))
+            .collect();
+        let warnings = low
+            .iter()
+            .filter(|controller| !self.warned.contains(&controller.name))
+            .map(|controller| ControllerWarning {
+                name: controller.name.clone(),
+                label: controller.label().to_owned(),
+                percentage: controller.capacity,
+            })
+            .collect();
+        self.warned = low
+            .iter()
+            .map(|controller| controller.name.clone())
+            .collect();
+        warnings
+    }
+
+    fn is_low(&self, controller: &PowerSupply) -> bool {
+        if controller.is_charging() {
+            return false;
+        }
+        match (controller.capacity, controller.capacity_level) {
+            (Some(capacity), _) => capacity <= self.low,
+            (None, Some(level)) => matches!(level, CapacityLevel::Critical | CapacityLevel::Low),
+            (None, None) => false,
+        }
+    }
+}
@@ -0,0 +1,491 @@
+//! Finding and signalling the processes behind a window.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+/// wlr-foreign-toplevel doesn't tell us which client owns a window, so the
+/// owning process has to be found by matching the app_id against /proc,
+/// preferably among the compositor's Wayland clients. The root is
+/// configurable so the lookup can be pointed at a fake tree
+#[derive(Debug, Clone)]
+pub struct ProcFs {
+    root: PathBuf,
+}
+
+impl Default for ProcFs {
+    fn default() -> Self {
+        ProcFs::new("/proc")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Signal {
+    Term,
+    Kill,
+    Stop,
+    Cont,
+}
+
+/// A process, as shown before signalling it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: i32,
+    /// The command line, or the name if it has none
+    pub command: String,
+}
+
+/// The processes that look like they own a window
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Owners {
+    pub pids: Vec<i32>,
+    /// None of the compositor's clients matched, so these are every
+    /// process with a matching name. They may not be the right ones
+    pub by_name: bool,
+}
+
+impl Signal {
+    fn raw(self) -> libc::c_int {
+        match self {
+            Signal::Term => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+            Signal::Stop => libc::SIGSTOP,
+            Signal::Cont => libc::SIGCONT,
+        }
+    }
+}
+
+impl ProcFs {
+    pub fn new(root: impl AsRef<Path>) -> ProcFs {
+        ProcFs {
+            root: root.as_ref().to_owned(),
+        }
+    }
+
+    pub fn pids(&self) -> Vec<i32> {
+        let Ok(read) = std::fs::read_dir(&self.root) else {
+            return vec![];
+        };
+        read.flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+            .collect()
+    }
+
+    pub fn comm(&self, pid: i32) -> Option<String> {
+        let comm = std::fs::read_to_string(self.root.join(pid.to_string()).join("comm")).ok()?;
+        Some(comm.trim_end().to_owned())
+    }
+
+    pub fn cmdline(&self, pid: i32) -> Vec<String> {
+        let Ok(raw) = std::fs::read(self.root.join(pid.to_string()).join("cmdline")) else {
+            return vec![];
+        };
+        raw.split(|b| *b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect()
+    }
+
+    pub fn environ(&self, pid: i32, key: &str) -> Option<String> {
+        let raw = std::fs::read(self.root.join(pid.to_string()).join("environ")).ok()?;
+        raw.split(|b| *b == 0).find_map(|var| {
+            let var = String::from_utf8_lossy(var);
+            let (k, v) = var.split_once('=')?;
+            (k == key).then(|| v.to_owned())
+        })
+    }
+
+    /// Parent PID from `/proc/<pid>/stat`. comm can contain spaces and
+    /// brackets so parse from the last ')'
+    pub fn ppid(&self, pid: i32) -> Option<i32> {
+        let stat = std::fs::read_to_string(self.root.join(pid.to_string()).join("stat")).ok()?;
+        let (_, rest) = stat.rsplit_once(')')?;
+        rest.split_whitespace().nth(1)?.parse().ok()
+    }
+
+    /// Process state letter, R S D T Z etc
+    pub fn state(&self, pid: i32) -> Option<char> {
+        let stat = std::fs::read_to_string(self.root.join(pid.to_string()).join("stat")).ok()?;
+        let (_, rest) = stat.rsplit_once(')')?;
+        rest.split_whitespace().next()?.chars().next()
+    }
+
+    pub fn is_alive(&self, pid: i32) -> bool {
+        !matches!(self.state(pid), None | Some('Z') | Some('X'))
+    }
+
+    pub fn describe(&self, pid: i32) -> ProcessInfo {
+        let cmdline = self.cmdline(pid);
+        let command = match cmdline.is_empty() {
+            true => self.comm(pid).unwrap_or_default(),
+            false => cmdline.join(" "),
+        };
+        ProcessInfo { pid, command }
+    }
+
+    /// Does the process look like it owns a window with this app_id, by
+    /// its name, argv[0] or Flatpak ID. Names have to match exactly
+    pub fn matches_app_id(&self, pid: i32, app_id: &str) -> bool {
+        self.matches(pid, app_id, false)
+    }
+
+    // The last part of a reverse DNS app_id is also allowed when short
+    // is, org.example.Game matching a process called game. That's only
+    // safe among a handful of Wayland clients, not every process
+    fn matches(&self, pid: i32, app_id: &str, short: bool) -> bool {
+        let app_id = app_id.to_lowercase();
+        // Steam sets app_id to steam_app_<id> for games run through it
+        if let Some(steam_id) = app_id.strip_prefix("steam_app_") {
+            return self.environ(pid, "SteamAppId").as_deref() == Some(steam_id);
+        }
+        let short_id = match short {
+            true => app_id
+                .rsplit('.')
+                .next()
+                .filter(|short_id| *short_id != app_id),
+            false => None,
+        };
+        let mut names = vec![];
+        names.extend(self.comm(pid));
+        if let Some(argv0) = self.cmdline(pid).first() {
+            names.push(argv0.rsplit('/').next().unwrap_or(argv0).to_owned());
+        }
+        names.extend(self.environ(pid, "FLATPAK_ID"));
+        names.iter().any(|name| {
+            let name = name.to_lowercase();
+            name == app_id || Some(name.as_str()) == short_id
+        })
+    }
+
+    /// Every process that looks like it owns a window with this app_id,
+    /// minus those whose parent also matched. Signalling the top of each
+    /// tree is enough for a polite SIGTERM
+    pub fn find_by_app_id(&self, app_id: &str) -> Vec<i32> {
+        let matched: Vec<i32> = self
+            .pids()
+            .into_iter()
+            .filter(|pid| self.matches_app_id(*pid, app_id))
+            .collect();
+        self.tops(&matched)
+    }
+
+    fn tops(&self, matched: &[i32]) -> Vec<i32> {
+        matched
+            .iter()
+            .copied()
+            .filter(|pid| match self.ppid(*pid) {
+                Some(ppid) => !matched.contains(&ppid),
+                None => true,
+            })
+            .collect()
+    }
+
+    /// Whoever owns a window with this app_id. The compositor's `clients`
+    /// are looked at first, since one of them has to own it. Only if none
+    /// match is every process with a matching name taken instead, as X11
+    /// windows belong to Xwayland
+    pub fn owners(&self, app_id: &str, clients: &[i32]) -> Owners {
+        let matched: Vec<i32> = clients
+            .iter()
+            .copied()
+            .filter(|pid| self.matches(*pid, app_id, true))
+            .collect();
+        match matched.is_empty() {
+            false => Owners {
+                pids: self.tops(&matched),
+                by_name: false,
+            },
+            true => Owners {
+                pids: self.find_by_app_id(app_id),
+                by_name: true,
+            },
+        }
+    }
+
+    /// Inodes of the process's sockets, from the `socket:[<inode>]` links
+    /// in `/proc/<pid>/fd`
+    pub fn socket_inodes(&self, pid: i32) -> Vec<u64> {
+        let Ok(read) = std::fs::read_dir(self.root.join(pid.to_string()).join("fd")) else {
+            return vec![];
+        };
+        read.flatten()
+            .filter_map(|entry| {
+                let target = std::fs::read_link(entry.path()).ok()?;
+                let inode = target
+                    .to_str()?
+                    .strip_prefix("socket:[")?
+                    .strip_suffix(']')?;
+                inode.parse().ok()
+            })
+            .collect()
+    }
+
+    /// Processes holding the other end of any of the server's sockets.
+    /// `peers` maps each socket inode to its peer's, as from [`unix_peers`]
+    pub fn clients_of(&self, server: i32, peers: &HashMap<u64, u64>) -> Vec<i32> {
+        let client_ends: HashSet<u64> = self
+            .socket_inodes(server)
+            .iter()
+            .filter_map(|inode| peers.get(inode).copied())
+            .collect();
+        let mut clients: Vec<i32> = self
+            .pids()
+            .into_iter()
+            .filter(|pid| *pid != server)
+            .filter(|pid| {
+                self.socket_inodes(*pid)
+                    .iter()
+                    .any(|inode| client_ends.contains(inode))
+            })
+            .collect();
+        clients.sort();
+        clients
+    }
+
+    /// The process and all of its descendants, parents first
+    pub fn tree(&self, pid: i32) -> Vec<i32> {
+        let parents: Vec<(i32, i32)> = self
+            .pids()
+            .into_iter()
+            .filter_map(|p| Some((p, self.ppid(p)?)))
+            .collect();
+        let mut tree = vec![pid];
+        let mut idx = 0;
+        while idx < tree.len() {
+            let parent = tree[idx];
+            for (child, ppid) in parents.iter() {
+                if *ppid == parent && !tree.contains(child) {
+                    tree.push(*child);
+                }
+            }
+            idx += 1;
+        }
+        tree
+    }
+
+    /// Every process belonging to windows with this app_id, for signals
+    /// that have to reach children too
+    pub fn app_tree(&self, app_id: &str) -> Vec<i32> {
+        self.trees(&self.find_by_app_id(app_id))
+    }
+
+    /// The processes and all of their descendants, parents first
+    pub fn trees(&self, pids: &[i32]) -> Vec<i32> {
+        let mut trees = vec![];
+        for pid in pids {
+            for child in self.tree(*pid) {
+                if !trees.contains(&child) {
+                    trees.push(child);
+                }
+            }
+        }
+        trees
+    }
+}
+
+/// Signal every PID, carrying on past failures since some of a tree may
+/// already have exited
+pub fn signal_all(pids: &[i32], sig: Signal) {
+    for pid in pids {
+        if let Err(e) = signal(*pid, sig) {
+            println!("Unable to signal {}: {}", pid, e);
+        }
+    }
+}
+
+pub fn signal(pid: i32, signal: Signal) -> std::io::Result<()> {
+    // Safety: kill has no memory safety requirements
+    if unsafe { libc::kill(pid, signal.raw()) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// The compositor is whoever listens on the Wayland socket. SO_PEERCRED on
+/// a connection of our own gives the PID that created it
+pub fn compositor_pid() -> std::io::Result<i32> {
+    let display = std::env::var("WAYLAND_DISPLAY").unwrap_or("wayland-0".to_owned());
+    let socket = match Path::new(&display).is_absolute() {
+        true => PathBuf::from(display),
+        false => std::env::var("XDG_RUNTIME_DIR")
+            .map(|dir| Path::new(&dir).join(&display))
+            .map_err(|_| std::io::Error::other("XDG_RUNTIME_DIR isn't set"))?,
+    };
+    let stream = UnixStream::connect(socket)?;
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // Safety: cred and len are valid for writes, and len is cred's size
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    match result {
+        0 if cred.pid > 0 => Ok(cred.pid),
+        0 => Err(std::io::Error::other("Compositor PID unknown")),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// PIDs of the compositor's Wayland clients, found through the kernel
+/// since the compositor won't say
+pub fn wayland_clients() -> std::io::Result<Vec<i32>> {
+    let compositor = compositor_pid()?;
+    Ok(ProcFs::default().clients_of(compositor, &unix_peers()?))
+}
+
+const SOCK_DIAG_BY_FAMILY: u16 = 20;
+const UDIAG_SHOW_PEER: u32 = 0x4;
+const UNIX_DIAG_PEER: u16 = 2;
+// nlmsghdr, and unix_diag_msg after it
+const HEADER_LEN: usize = 16;
+const DIAG_MSG_LEN: usize = 16;
+
+// struct unix_diag_req from linux/unix_diag.h
+#[repr(C)]
+struct UnixDiagReq {
+    sdiag_family: u8,
+    sdiag_protocol: u8,
+    pad: u16,
+    udiag_states: u32,
+    udiag_ino: u32,
+    udiag_show: u32,
+    udiag_cookie: [u32; 2],
+}
+
+#[repr(C)]
+struct DiagRequest {
+    header: libc::nlmsghdr,
+    request: UnixDiagReq,
+}
+
+/// Every connected Unix socket's inode and its peer's, from sock_diag.
+/// Only sockets in our own network namespace are listed
+pub fn unix_peers() -> std::io::Result<HashMap<u64, u64>> {
+    // Safety: socket has no memory safety requirements, and the result is
+    // owned by nothing else once checked
+    let socket = unsafe {
+        let fd = libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_SOCK_DIAG,
+        );
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        OwnedFd::from_raw_fd(fd)
+    };
+    let request = DiagRequest {
+        header: libc::nlmsghdr {
+            nlmsg_len: std::mem::size_of::<DiagRequest>() as u32,
+            nlmsg_type: SOCK_DIAG_BY_FAMILY,
+            nlmsg_flags: (libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16,
+            nlmsg_seq: 1,
+            nlmsg_pid: 0,
+        },
+        request: UnixDiagReq {
+            sdiag_family: libc::AF_UNIX as u8,
+            sdiag_protocol: 0,
+            pad: 0,
+            udiag_states: u32::MAX,
+            udiag_ino: 0,
+            udiag_show: UDIAG_SHOW_PEER,
+            udiag_cookie: [u32::MAX; 2],
+        },
+    };
+    // Safety: request is a valid, fully initialised DiagRequest for the
+    // length given
+    let sent = unsafe {
+        libc::send(
+            socket.as_raw_fd(),
+            &request as *const DiagRequest as *const libc::c_void,
+            std::mem::size_of::<DiagRequest>(),
+            0,
+        )
+    };
+    if sent < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut peers = HashMap::new();
+    let mut buf = vec![0u8; 32 * 1024];
+    loop {
+        // Safety: buf is valid for writes of its whole length
+        let received = unsafe {
+            libc::recv(
+                socket.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        if received < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if parse_diag_messages(&buf[..received as usize], &mut peers)? {
+            return Ok(peers);
+        }
+    }
+}
+
+fn read_u16(buf: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_ne_bytes(buf.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(buf: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(buf.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+// One datagram of replies. True once the dump is done
+fn parse_diag_messages(buf: &[u8], peers: &mut HashMap<u64, u64>) -> std::io::Result<bool> {
+    let truncated = || std::io::Error::other("Truncated sock_diag reply");
+    let mut at = 0;
+    while at + HEADER_LEN <= buf.len() {
+        let len = read_u32(buf, at).ok_or_else(truncated)? as usize;
+        let kind = read_u16(buf, at + 4).ok_or_else(truncated)?;
+        if len < HEADER_LEN || at + len > buf.len() {
+            return Err(truncated());
+        }
+        match kind as i32 {
+            libc::NLMSG_DONE => return Ok(true),
+            libc::NLMSG_ERROR => {
+                let errno = read_u32(buf, at + HEADER_LEN).ok_or_else(truncated)? as i32;
+                return Err(std::io::Error::from_raw_os_error(-errno));
+            }
+            _ => {}
+        }
+        let message = &buf[at + HEADER_LEN..at + len];
+        let inode = read_u32(message, 4).ok_or_else(truncated)?;
+        let mut attr = DIAG_MSG_LEN;
+        while attr + 4 <= message.len() {
+            let attr_len = read_u16(message, attr).ok_or_else(truncated)? as usize;
+            let attr_kind = read_u16(message, attr + 2).ok_or_else(truncated)?;
+            if attr_len < 4 {
+                break;
+            }
+            if attr_kind == UNIX_DIAG_PEER {
+                let peer = read_u32(message, attr + 4).ok_or_else(truncated)?;
+                peers.insert(inode as u64, peer as u64);
+            }
+            attr += align(attr_len);
+        }
+        at += align(len);
+    }
+    Ok(false)
+}
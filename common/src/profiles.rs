@@ -0,0 +1,134 @@
+//! Power profiles, like "power-saver" and "performance".
+//!
+//! A [`PowerProfiles`] lists the profiles the machine has and switches
+//! between them. [`PowerProfilesDaemon`] talks to power-profiles-daemon,
+//! which also answers to its old `net.hadess.PowerProfiles` name and as
+//! tuned's compatibility service.
+
+use std::collections::HashMap;
+use std::io;
+use zbus::blocking::{self, fdo::PropertiesProxy};
+use zbus::names::InterfaceName;
+use zbus::zvariant::{OwnedValue, Value};
+
+// Current name first, then the one used before version 0.20
+const SERVICES: [(&str, &str); 2] = [
+    (
+        "org.freedesktop.UPower.PowerProfiles",
+        "/org/freedesktop/UPower/PowerProfiles",
+    ),
+    ("net.hadess.PowerProfiles", "/net/hadess/PowerProfiles"),
+];
+
+pub trait PowerProfiles {
+    /// Profile names, in the daemon's order, lowest power first.
+    fn profiles(&self) -> io::Result<Vec<String>>;
+
+    fn active(&self) -> io::Result<String>;
+
+    fn set_active(&self, profile: &str) -> io::Result<()>;
+}
+
+/// Talks to power-profiles-daemon.
+pub struct PowerProfilesDaemon {
+    // Properties are read through org.freedesktop.DBus.Properties each
+    // time, so a profile just set reads back straight away
+    properties: PropertiesProxy<'static>,
+    interface: InterfaceName<'static>,
+}
+
+impl PowerProfilesDaemon {
+    /// Connect on the system bus. Fails if the daemon isn't running and
+    /// can't be started.
+    pub fn system() -> zbus::Result<PowerProfilesDaemon> {
+        PowerProfilesDaemon::with_connection(blocking::Connection::system()?)
+    }
+
+    /// A daemon on the bus at `address`, for tests.
+    pub fn at_address(address: &str) -> zbus::Result<PowerProfilesDaemon> {
+        PowerProfilesDaemon::with_connection(
+            blocking::connection::Builder::address(address)?.build()?,
+        )
+    }
+
+    fn with_connection(connection: blocking::Connection) -> zbus::Result<PowerProfilesDaemon> {
+        let mut error = None;
+        for (name, path) in SERVICES {
+            let daemon = PowerProfilesDaemon {
+                properties: PropertiesProxy::builder(&connection)
+                    .destination(name)?
+                    .path(path)?
+                    .build()?,
+                interface: InterfaceName::from_static_str(name)?,
+            };
+            match daemon.read_active() {
+                Ok(_) => return Ok(daemon),
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error.unwrap_or(zbus::Error::InterfaceNotFound))
+    }
+
+    fn read_active(&self) -> zbus::Result<String> {
+        let value = self
+            .properties
+            .get(self.interface.as_ref(), "ActiveProfile")?;
+        Ok(String::try_from(value)?)
+    }
+
+    fn read_profiles(&self) -> zbus::Result<Vec<String>> {
+        let value = self.properties.get(self.interface.as_ref(), "Profiles")?;
+        let profiles = Vec::<HashMap<String, OwnedValue>>::try_from(value)?;
+        Ok(profiles
+            .into_iter()
+            .filter_map(|mut profile| String::try_from(profile.remove("Profile")?).ok())
+            .collect())
+    }
+}
+
+impl PowerProfiles for PowerProfilesDaemon {
+    fn profiles(&self) -> io::Result<Vec<String>> {
+        self.read_profiles().map_err(io::Error::other)
+    }
+
+    fn active(&self) -> io::Result<String> {
+        self.read_active().map_err(io::Error::other)
+    }
+
+    fn set_active(&self, profile: &str) -> io::Result<()> {
+        self.properties
+            .set(
+                self.interface.as_ref(),
+                "ActiveProfile",
+                &Value::from(profile),
+            )
+            .map_err(io::Error::other)
+    }
+}
+
+/// A name for a profile fit to show, like "Power saver".
+pub fn profile_label(profile: &str) -> String {
+    match profile {
+        "power-saver" => "Power saver".to_owned(),
+        "balanced" => "Balanced".to_owned(),
+        "performance" => "Performance".to_owned(),
+        other => {
+            let mut label = other.replace('-', " ");
+            if let Some(first) = label.get_mut(..1) {
+                first.make_ascii_uppercase();
+            }
+            label
+        }
+    }
+}
+
+/// power-profiles-daemon if it's running.
+pub fn system_profiles() -> Option<Box<dyn PowerProfiles + Send>> {
+    match PowerProfilesDaemon::system() {
+        Ok(daemon) => Some(Box::new(daemon)),
+        Err(e) => {
+            println!("power-profiles-daemon unavailable: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Recording toplevel sessions to a file and playing them back.
+//!
+//! A recording is every [`ToplevelEvent`] the Wayland side handled, one
+//! JSON object per line, with the milliseconds since recording started:
+//!
+//! ```text
+//! {"ms":0,"event":"new","id":1}
+//! {"ms":2,"event":"title","id":1,"title":"Steam"}
+//! {"ms":2,"event":"done","id":1}
+//! ```
+//!
+//! Playing one back through a [`ToplevelModel`] gives the same changes the
+//! daemon saw, without needing the compositor or game that caused them.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::model::{ToplevelChange, ToplevelEvent, ToplevelModel};
+
+/// One line of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Milliseconds since recording started
+    pub ms: u64,
+    #[serde(flatten)]
+    pub event: ToplevelEvent,
+}
+
+/// Appends events to a recording as they happen.
+#[derive(Debug)]
+pub struct Recorder {
+    file: File,
+    started: Instant,
+}
+
+impl Recorder {
+    /// Start a new recording, replacing anything already at `path`.
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder {
+            file: File::create(path)?,
+            started: Instant::now(),
+        })
+    }
+
+    /// Write one event. Each line is written straight away, so a crash
+    /// keeps everything up to it.
+    pub fn record(&mut self, event: &ToplevelEvent) -> io::Result<()> {
+        let recorded = RecordedEvent {
+            ms: self.started.elapsed().as_millis() as u64,
+            event: event.clone(),
+        };
+        let mut line = serde_json::to_string(&recorded)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+/// Read a whole recording. Blank lines are skipped, anything else that
+/// doesn't parse is an error naming the line.
+pub fn load(path: &Path) -> io::Result<Vec<RecordedEvent>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut events = vec![];
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", idx + 1, e),
+            )
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Feed a recording through a fresh model, calling `on_change` for
+/// everything it changes. With `realtime` the original gaps between events
+/// are kept, otherwise it runs as fast as possible. Stops early if
+/// `on_change` returns false.
+pub fn replay(
+    events: &[RecordedEvent],
+    realtime: bool,
+    mut on_change: impl FnMut(ToplevelChange) -> bool,
+) -> ToplevelModel {
+    let mut model = ToplevelModel::new();
+    let started = Instant::now();
+    for recorded in events {
+        if realtime {
+            let due = Duration::from_millis(recorded.ms);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        for change in model.handle(recorded.event.clone()) {
+            if !on_change(change) {
+                return model;
+            }
+        }
+    }
+    model
+}
@@ -0,0 +1,89 @@
+//! Window rules, matching windows by app_id and title.
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::ToplevelDetails;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Fullscreen,
+    Maximize,
+    Activate,
+    MinimizeOthers,
+    // Keep the screen from dimming or blanking while the window is open
+    Media,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleApply {
+    // Only when the window first appears
+    #[default]
+    Once,
+    // Every time the window's details change
+    Always,
+}
+
+/// `[[window_rules]]` in shell.toml
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct WindowRuleConfig {
+    /// Regexes, both must match when both are given
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+    pub actions: Vec<RuleAction>,
+    /// Output name for fullscreen, eg "HDMI-A-1". The compositor picks
+    /// when unset or not connected
+    pub output: Option<String>,
+    pub apply: RuleApply,
+}
+
+pub struct WindowRule {
+    app_id: Option<Regex>,
+    title: Option<Regex>,
+    pub actions: Vec<RuleAction>,
+    pub output: Option<String>,
+    pub apply: RuleApply,
+}
+
+impl WindowRule {
+    /// Rules with a broken regex are dropped with a warning, rather than
+    /// refusing to start
+    pub fn compile(config: &WindowRuleConfig) -> Option<WindowRule> {
+        let compile = |pattern: &Option<String>| match pattern {
+            Some(pattern) => match Regex::new(pattern) {
+                Ok(regex) => Ok(Some(regex)),
+                Err(e) => {
+                    println!("Ignoring window rule with bad regex {}: {}", pattern, e);
+                    Err(())
+                }
+            },
+            None => Ok(None),
+        };
+        Some(WindowRule {
+            app_id: compile(&config.app_id).ok()?,
+            title: compile(&config.title).ok()?,
+            actions: config.actions.clone(),
+            output: config.output.clone(),
+            apply: config.apply,
+        })
+    }
+
+    pub fn compile_all(configs: &[WindowRuleConfig]) -> Vec<WindowRule> {
+        configs.iter().filter_map(WindowRule::compile).collect()
+    }
+
+    pub fn matches(&self, details: &ToplevelDetails) -> bool {
+        // A rule matching on nothing would hit every window
+        if self.app_id.is_none() && self.title.is_none() {
+            return false;
+        }
+        let field_matches = |regex: &Option<Regex>, value: &Option<String>| match regex {
+            Some(regex) => value.as_deref().map(|v| regex.is_match(v)).unwrap_or(false),
+            None => true,
+        };
+        field_matches(&self.app_id, &details.app_id) && field_matches(&self.title, &details.title)
+    }
+}
@@ -0,0 +1,80 @@
+use bitflags::bitflags;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_handle_v1::State;
+
+bitflags! {
+    /// The states a toplevel can be in, decoded from the
+    /// `zwlr_foreign_toplevel_handle_v1.state` array.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub struct ToplevelState: u32 {
+        const MAXIMIZED = 1 << 0;
+        const MINIMIZED = 1 << 1;
+        const ACTIVATED = 1 << 2;
+        const FULLSCREEN = 1 << 3;
+    }
+}
+
+const NAMES: [(ToplevelState, &str); 4] = [
+    (ToplevelState::MAXIMIZED, "maximized"),
+    (ToplevelState::MINIMIZED, "minimized"),
+    (ToplevelState::ACTIVATED, "activated"),
+    (ToplevelState::FULLSCREEN, "fullscreen"),
+];
+
+impl ToplevelState {
+    /// Decode the state array sent with the `state` event.
+    ///
+    /// The array holds one u32 per state, in the compositor's byte order,
+    /// which is little-endian on everything we run on. Values this version
+    /// of the protocol doesn't know about are ignored.
+    pub fn from_wire(raw: &[u8]) -> ToplevelState {
+        raw.chunks_exact(4)
+            .filter_map(|chunk| {
+                let value = u32::from_ne_bytes(chunk.try_into().ok()?);
+                match State::try_from(value).ok()? {
+                    State::Maximized => Some(ToplevelState::MAXIMIZED),
+                    State::Minimized => Some(ToplevelState::MINIMIZED),
+                    State::Activated => Some(ToplevelState::ACTIVATED),
+                    State::Fullscreen => Some(ToplevelState::FULLSCREEN),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Lowercase names of the set states, eg `["maximized", "activated"]`.
+    /// This is how states appear in the socket protocol and hook payloads.
+    pub fn names(self) -> Vec<&'static str> {
+        NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+
+    /// The state with the given name, as returned by [`ToplevelState::names`].
+    pub fn parse_name(name: &str) -> Option<ToplevelState> {
+        NAMES
+            .iter()
+            .find(|(_, other)| *other == name)
+            .map(|(flag, _)| *flag)
+    }
+}
+
+impl Serialize for ToplevelState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.names().serialize(serializer)
+    }
+}
+
+// Names we don't know are skipped, so older clients keep working when a
+// newer daemon sends states they've never heard of
+impl<'de> Deserialize<'de> for ToplevelState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        Ok(names
+            .iter()
+            .filter_map(|name| ToplevelState::parse_name(name))
+            .collect())
+    }
+}
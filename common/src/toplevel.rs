@@ -0,0 +1,341 @@
+//! Wayland side of the toplevel list.
+//!
+//! [`StagingData`] binds `zwlr_foreign_toplevel_manager_v1`, the seat and
+//! every output, turns their events into [`ToplevelEvent`]s for a
+//! [`ToplevelModel`], and queues up the resulting changes.
+//!
+//! The owning program keeps a `StagingData` in its own state type and
+//! delegates to it:
+//!
+//! ```ignore
+//! struct Shell {
+//!     toplevels: StagingData,
+//! }
+//!
+//! impl AsMut<StagingData> for Shell {
+//!     fn as_mut(&mut self) -> &mut StagingData {
+//!         &mut self.toplevels
+//!     }
+//! }
+//!
+//! consolation_common::delegate_toplevels!(Shell);
+//! ```
+//!
+//! then calls [`StagingData::take_changes`] after each dispatch. Events can
+//! also be written to a [`Recorder`] for replaying later.
+
+use std::collections::HashMap;
+use wayland_client::{
+    event_created_child,
+    globals::{GlobalList, GlobalListContents},
+    protocol::{
+        wl_output::{self, WlOutput},
+        wl_registry::WlRegistry,
+        wl_seat::{self, WlSeat},
+    },
+    Connection, Dispatch, QueueHandle, WEnum,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1, EVT_TOPLEVEL_OPCODE},
+};
+
+use crate::ipc::{ToplevelAction, ToplevelDetails, ToplevelId};
+use crate::model::{ToplevelChange, ToplevelEvent, ToplevelModel};
+use crate::record::Recorder;
+use crate::ToplevelState;
+
+/// Toplevel handles, the seat and outputs, and the model built from them.
+#[derive(Debug, Default)]
+pub struct StagingData {
+    ids: HashMap<ZwlrForeignToplevelHandleV1, ToplevelId>,
+    next_id: ToplevelId,
+    model: ToplevelModel,
+    seat: Option<WlSeat>,
+    // Output names, once the output has sent one
+    outputs: HashMap<WlOutput, String>,
+    changes: Vec<ToplevelChange>,
+    recorder: Option<Recorder>,
+}
+
+impl StagingData {
+    pub fn new() -> StagingData {
+        StagingData::default()
+    }
+
+    /// Bind the toplevel manager, the seat and every output that has a name
+    /// (wl_output version 4). Fails when the compositor doesn't support
+    /// wlr-foreign-toplevel-management.
+    pub fn bind<D>(globals: &GlobalList, qh: &QueueHandle<D>) -> Result<(), String>
+    where
+        D: Dispatch<ZwlrForeignToplevelManagerV1, ()>
+            + Dispatch<WlSeat, ()>
+            + Dispatch<WlOutput, ()>
+            + 'static,
+    {
+        globals
+            .bind::<ZwlrForeignToplevelManagerV1, D, ()>(qh, 3..=3, ())
+            .map_err(|e| format!("No toplevel manager: {}", e))?;
+        globals
+            .bind::<WlSeat, D, ()>(qh, 1..=1, ())
+            .map_err(|e| format!("No seat: {}", e))?;
+        for global in globals.contents().clone_list() {
+            if global.interface == "wl_output" && global.version >= 4 {
+                let _ = globals
+                    .registry()
+                    .bind::<WlOutput, (), D>(global.name, 4, qh, ());
+            }
+        }
+        Ok(())
+    }
+
+    /// Write every event handled from now on to `recorder`.
+    pub fn record_to(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Changes since the last call, in the order they happened.
+    pub fn take_changes(&mut self) -> Vec<ToplevelChange> {
+        std::mem::take(&mut self.changes)
+    }
+
+    pub fn model(&self) -> &ToplevelModel {
+        &self.model
+    }
+
+    pub fn details(&self, id: ToplevelId) -> Option<&ToplevelDetails> {
+        self.model.get(id)
+    }
+
+    /// The handle for a toplevel id, `None` once it has closed.
+    pub fn handle_for(&self, id: ToplevelId) -> Option<ZwlrForeignToplevelHandleV1> {
+        self.ids
+            .iter()
+            .find(|(_, other)| **other == id)
+            .map(|(handle, _)| handle.clone())
+    }
+
+    pub fn seat(&self) -> Option<&WlSeat> {
+        self.seat.as_ref()
+    }
+
+    /// Every output with a name.
+    pub fn outputs(&self) -> Vec<WlOutput> {
+        self.outputs.keys().cloned().collect()
+    }
+
+    pub fn output_named(&self, wanted: &str) -> Option<WlOutput> {
+        self.outputs
+            .iter()
+            .find(|(_, name)| *name == wanted)
+            .map(|(output, _)| output.clone())
+    }
+
+    /// Send the requests for an action. Returns false if the toplevel has
+    /// closed, or activating with no seat yet.
+    pub fn run_action(&self, action: &ToplevelAction) -> bool {
+        let Some(handle) = self.handle_for(action.id()) else {
+            return false;
+        };
+        match action {
+            ToplevelAction::Activate { .. } => match &self.seat {
+                Some(seat) => handle.activate(seat),
+                None => return false,
+            },
+            ToplevelAction::Close { .. } => handle.close(),
+            ToplevelAction::Minimize { .. } => handle.set_minimized(),
+            ToplevelAction::Unminimize { .. } => handle.unset_minimized(),
+            ToplevelAction::Maximize { .. } => handle.set_maximized(),
+            ToplevelAction::Unmaximize { .. } => handle.unset_maximized(),
+            ToplevelAction::Fullscreen { output, .. } => {
+                let output = output.as_ref().and_then(|name| self.output_named(name));
+                handle.set_fullscreen(output.as_ref());
+            }
+            ToplevelAction::Unfullscreen { .. } => handle.unset_fullscreen(),
+        }
+        true
+    }
+
+    fn handle_event(&mut self, event: ToplevelEvent) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record(&event) {
+                println!("Unable to record event, stopping recording: {}", e);
+                self.recorder = None;
+            }
+        }
+        let changes = self.model.handle(event);
+        self.changes.extend(changes);
+    }
+}
+
+/// Implement `Dispatch` for everything [`StagingData`] binds on a type that
+/// is `AsMut<StagingData>`.
+#[macro_export]
+macro_rules! delegate_toplevels {
+    ($ty:ty) => {
+        $crate::wayland_client::delegate_dispatch!($ty: [
+            $crate::wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1: ()
+        ] => $crate::toplevel::StagingData);
+        $crate::wayland_client::delegate_dispatch!($ty: [
+            $crate::wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1: ()
+        ] => $crate::toplevel::StagingData);
+        $crate::wayland_client::delegate_dispatch!($ty: [
+            $crate::wayland_client::protocol::wl_seat::WlSeat: ()
+        ] => $crate::toplevel::StagingData);
+        $crate::wayland_client::delegate_dispatch!($ty: [
+            $crate::wayland_client::protocol::wl_output::WlOutput: ()
+        ] => $crate::toplevel::StagingData);
+        $crate::wayland_client::delegate_dispatch!($ty: [
+            $crate::wayland_client::protocol::wl_registry::WlRegistry: $crate::wayland_client::globals::GlobalListContents
+        ] => $crate::toplevel::StagingData);
+    };
+}
+
+impl<D> Dispatch<ZwlrForeignToplevelHandleV1, (), D> for StagingData
+where
+    D: Dispatch<ZwlrForeignToplevelHandleV1, ()> + AsMut<StagingData>,
+{
+    fn event(
+        state: &mut D,
+        proxy: &ZwlrForeignToplevelHandleV1,
+        event: <ZwlrForeignToplevelHandleV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<D>,
+    ) {
+        let state = state.as_mut();
+        let Some(id) = state.ids.get(proxy).copied() else {
+            return;
+        };
+        let event = match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
+                ToplevelEvent::Title { id, title }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                ToplevelEvent::AppId { id, app_id }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state } => ToplevelEvent::State {
+                id,
+                state: ToplevelState::from_wire(&state),
+            },
+            zwlr_foreign_toplevel_handle_v1::Event::Parent { parent } => ToplevelEvent::Parent {
+                id,
+                // A parent we haven't been told about yet is as good as none
+                parent: parent.and_then(|parent| state.ids.get(&parent).copied()),
+            },
+            zwlr_foreign_toplevel_handle_v1::Event::Done => ToplevelEvent::Done { id },
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                state.ids.remove(proxy);
+                proxy.destroy();
+                ToplevelEvent::Closed { id }
+            }
+            // TODO. Care about outputs
+            _ => return,
+        };
+        state.handle_event(event);
+    }
+}
+
+impl<D> Dispatch<ZwlrForeignToplevelManagerV1, (), D> for StagingData
+where
+    D: Dispatch<ZwlrForeignToplevelManagerV1, ()>
+        + Dispatch<ZwlrForeignToplevelHandleV1, ()>
+        + AsMut<StagingData>
+        + 'static,
+{
+    event_created_child!(D, ZwlrForeignToplevelManagerV1, [
+        EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ())
+    ]);
+
+    fn event(
+        state: &mut D,
+        _proxy: &ZwlrForeignToplevelManagerV1,
+        event: <ZwlrForeignToplevelManagerV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<D>,
+    ) {
+        let state = state.as_mut();
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                state.next_id += 1;
+                let id = state.next_id;
+                state.ids.insert(toplevel, id);
+                state.handle_event(ToplevelEvent::New { id });
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                println!("Manager handle has gone away. We're done here");
+                state.handle_event(ToplevelEvent::Finished);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<D> Dispatch<WlSeat, (), D> for StagingData
+where
+    D: Dispatch<WlSeat, ()> + AsMut<StagingData>,
+{
+    fn event(
+        state: &mut D,
+        proxy: &WlSeat,
+        event: <WlSeat as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<D>,
+    ) {
+        if let wl_seat::Event::Capabilities { capabilities } = event {
+            let state = state.as_mut();
+            state.seat = Some(proxy.clone());
+            let capabilities = match capabilities {
+                WEnum::Value(capabilities) => capabilities.bits(),
+                WEnum::Unknown(raw) => raw,
+            };
+            state.handle_event(ToplevelEvent::Seat { capabilities });
+        }
+    }
+}
+
+impl<D> Dispatch<WlOutput, (), D> for StagingData
+where
+    D: Dispatch<WlOutput, ()> + AsMut<StagingData>,
+{
+    fn event(
+        state: &mut D,
+        proxy: &WlOutput,
+        event: <WlOutput as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<D>,
+    ) {
+        let state = state.as_mut();
+        match event {
+            wl_output::Event::Name { name } => {
+                state.outputs.insert(proxy.clone(), name);
+            }
+            wl_output::Event::Done => {
+                if let Some(name) = state.outputs.get(proxy).cloned() {
+                    state.handle_event(ToplevelEvent::Output { name });
+                }
+            }
+            // Geometry, modes and scale don't matter to us
+            _ => {}
+        }
+    }
+}
+
+// Globals added later, like a newly plugged in monitor, aren't picked up yet
+impl<D> Dispatch<WlRegistry, GlobalListContents, D> for StagingData
+where
+    D: Dispatch<WlRegistry, GlobalListContents>,
+{
+    fn event(
+        _state: &mut D,
+        _proxy: &WlRegistry,
+        _event: <WlRegistry as wayland_client::Proxy>::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<D>,
+    ) {
+    }
+}
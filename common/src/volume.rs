@@ -0,0 +1,167 @@
+//! Output volume.
+//!
+//! An [`AudioBackend`] reads and sets the default output's volume. The
+//! backends here drive the sound server's own command line tool, so there
+//! is no client library to link and they keep working across sound server
+//! versions: [`WirePlumber`] for PipeWire, and [`PulseAudio`] for
+//! PulseAudio or anything else speaking its protocol.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const WPCTL_SINK: &str = "@DEFAULT_AUDIO_SINK@";
+const PACTL_SINK: &str = "@DEFAULT_SINK@";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Volume {
+    /// 0 to 100. Volumes boosted above 100% read as 100.
+    pub percentage: u8,
+    pub muted: bool,
+}
+
+pub trait AudioBackend {
+    fn volume(&self) -> io::Result<Volume>;
+
+    fn set_volume(&self, percentage: u8) -> io::Result<()>;
+
+    fn set_muted(&self, muted: bool) -> io::Result<()>;
+}
+
+/// Uses `wpctl`.
+#[derive(Debug, Clone)]
+pub struct WirePlumber {
+    program: PathBuf,
+}
+
+impl WirePlumber {
+    /// Run `program` rather than `wpctl` from `PATH`. Tests use a script.
+    pub fn new(program: impl Into<PathBuf>) -> WirePlumber {
+        WirePlumber {
+            program: program.into(),
+        }
+    }
+
+    pub fn system() -> WirePlumber {
+        WirePlumber::new("wpctl")
+    }
+}
+
+impl AudioBackend for WirePlumber {
+    fn volume(&self) -> io::Result<Volume> {
+        let output = run(&self.program, &["get-volume", WPCTL_SINK])?;
+        parse_wpctl(&output).ok_or_else(|| unexpected(&output))
+    }
+
+    fn set_volume(&self, percentage: u8) -> io::Result<()> {
+        let volume = format!("{}%", percentage.min(100));
+        run(&self.program, &["set-volume", WPCTL_SINK, &volume]).map(|_| ())
+    }
+
+    fn set_muted(&self, muted: bool) -> io::Result<()> {
+        let muted = if muted { "1" } else { "0" };
+        run(&self.program, &["set-mute", WPCTL_SINK, muted]).map(|_| ())
+    }
+}
+
+/// Uses `pactl`.
+#[derive(Debug, Clone)]
+pub struct PulseAudio {
+    program: PathBuf,
+}
+
+impl PulseAudio {
+    /// Run `program` rather than `pactl` from `PATH`. Tests use a script.
+    pub fn new(program: impl Into<PathBuf>) -> PulseAudio {
+        PulseAudio {
+            program: program.into(),
+        }
+    }
+
+    pub fn system() -> PulseAudio {
+        PulseAudio::new("pactl")
+    }
+}
+
+impl AudioBackend for PulseAudio {
+    fn volume(&self) -> io::Result<Volume> {
+        let volume = run(&self.program, &["get-sink-volume", PACTL_SINK])?;
+        let mute = run(&self.program, &["get-sink-mute", PACTL_SINK])?;
+        parse_pactl(&volume, &mute).ok_or_else(|| unexpected(&volume))
+    }
+
+    fn set_volume(&self, percentage: u8) -> io::Result<()> {
+        let volume = format!("{}%", percentage.min(100));
+        run(&self.program, &["set-sink-volume", PACTL_SINK, &volume]).map(|_| ())
+    }
+
+    fn set_muted(&self, muted: bool) -> io::Result<()> {
+        let muted = if muted { "1" } else { "0" };
+        run(&self.program, &["set-sink-mute", PACTL_SINK, muted]).map(|_| ())
+    }
+}
+
+/// Reads `wpctl get-volume`, like `Volume: 0.40 [MUTED]`.
+pub fn parse_wpctl(output: &str) -> Option<Volume> {
+    let rest = output.trim().strip_prefix("Volume:")?;
+    let mut words = rest.split_whitespace();
+    let level: f64 = words.next()?.parse().ok()?;
+    Some(Volume {
+        percentage: (level * 100.0).round().clamp(0.0, 100.0) as u8,
+        muted: words.any(|word| word == "[MUTED]"),
+    })
+}
+
+/// Reads `pactl get-sink-volume` and `get-sink-mute`. Channels are
+/// averaged, as a balance setting makes them differ.
+pub fn parse_pactl(volume: &str, mute: &str) -> Option<Volume> {
+    let levels: Vec<u32> = volume
+        .split_whitespace()
+        .filter_map(|word| word.strip_suffix('%')?.parse().ok())
+        .collect();
+    if levels.is_empty() {
+        return None;
+    }
+    let average = levels.iter().sum::<u32>() / levels.len() as u32;
+    Some(Volume {
+        percentage: average.min(100) as u8,
+        muted: mute.trim() == "Mute: yes",
+    })
+}
+
+/// WirePlumber if it's working, PulseAudio otherwise. `None` when neither
+/// is.
+pub fn system_audio() -> Option<Box<dyn AudioBackend + Send>> {
+    let wireplumber = WirePlumber::system();
+    if wireplumber.volume().is_ok() {
+        return Some(Box::new(wireplumber));
+    }
+    let pulseaudio = PulseAudio::system();
+    match pulseaudio.volume() {
+        Ok(_) => Some(Box::new(pulseaudio)),
+        Err(e) => {
+            println!("No sound server to control: {}", e);
+            None
+        }
+    }
+}
+
+fn run(program: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new(program).args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} {} failed: {}",
+            program.display(),
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn unexpected(output: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Unexpected output: {}", output.trim()),
+    )
+}
@@ -0,0 +1,56 @@
+use consolation_common::apps::{AppsAction, AppsSignal};
+use consolation_common::limits::LimitPeriod;
+use consolation_common::process::{ProcessInfo, Signal};
+use consolation_common::ToplevelId;
+
+#[test]
+fn protocol() {
+    let id: ToplevelId = 2;
+    assert_eq!(
+        serde_json::to_string(&AppsSignal::Status {
+            paused: vec![],
+            overdue: vec![id],
+        })
+        .unwrap(),
+        r#"{"type":"status","paused":[],"overdue":[2]}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&AppsSignal::Playtime).unwrap(),
+        r#"{"type":"playtime"}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&AppsSignal::TimeUp {
+            rule: "games".to_owned(),
+            period: LimitPeriod::Week,
+        })
+        .unwrap(),
+        r#"{"type":"time_up","rule":"games","period":"week"}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&AppsSignal::ForceQuit {
+            id,
+            signal: Signal::Term,
+            processes: vec![ProcessInfo {
+                pid: 4242,
+                command: "supertux2 --fullscreen".to_owned(),
+            }],
+            by_name: true,
+        })
+        .unwrap(),
+        r#"{"type":"force_quit","id":2,"signal":"term","processes":[{"pid":4242,"command":"supertux2 --fullscreen"}],"by_name":true}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<AppsAction>(
+            r#"{"action":"grant_time","rule":"games","pin":"1234"}"#
+        )
+        .unwrap(),
+        AppsAction::GrantTime {
+            rule: "games".to_owned(),
+            pin: "1234".to_owned(),
+        }
+    );
+    assert_eq!(
+        serde_json::from_str::<AppsAction>(r#"{"action":"confirm_force_quit","id":2}"#).unwrap(),
+        AppsAction::ConfirmForceQuit { id }
+    );
+}
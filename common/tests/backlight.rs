@@ -0,0 +1,132 @@
+use consolation_common::backlight::{Backlight, Brightness, LogindBacklight, SysfsBacklight};
+use std::sync::{Arc, Mutex};
+use zbus::blocking;
+
+mod support;
+use support::{Bus, TempTree};
+
+// Devices in a fake /sys/class/backlight, each with its own brightness files
+impl TempTree {
+    fn device(&self, name: &str, kind: &str, brightness: u32, max: u32) -> &TempTree {
+        let dir = self.path().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("type"), format!("{}\n", kind)).unwrap();
+        std::fs::write(dir.join("brightness"), format!("{}\n", brightness)).unwrap();
+        std::fs::write(dir.join("actual_brightness"), format!("{}\n", brightness)).unwrap();
+        std::fs::write(dir.join("max_brightness"), format!("{}\n", max)).unwrap();
+        self
+    }
+
+    fn brightness(&self, name: &str) -> String {
+        std::fs::read_to_string(self.path().join(name).join("brightness")).unwrap()
+    }
+}
+
+#[test]
+fn firmware_preferred() {
+    let sysfs = TempTree::new("backlight", "preferred");
+    sysfs
+        .device("amdgpu_bl1", "raw", 100, 255)
+        .device("acpi_video0", "firmware", 40, 100);
+    let backlight = SysfsBacklight::new(sysfs.path());
+    assert_eq!(
+        backlight.brightness().unwrap(),
+        Brightness {
+            device: "acpi_video0".to_owned(),
+            value: 40,
+            max: 100,
+        }
+    );
+}
+
+#[test]
+fn percentages() {
+    let brightness = Brightness {
+        device: "intel_backlight".to_owned(),
+        value: 9600,
+        max: 19200,
+    };
+    assert_eq!(brightness.percentage(), 50);
+    assert_eq!(brightness.value_at(25), 4800);
+    assert_eq!(brightness.value_at(0), 0);
+    assert_eq!(brightness.value_at(150), 19200);
+
+    // 1% of a coarse range still leaves the screen lit
+    let coarse = Brightness {
+        device: "acpi_video0".to_owned(),
+        value: 0,
+        max: 15,
+    };
+    assert_eq!(coarse.value_at(1), 1);
+}
+
+#[test]
+fn sysfs_writes() {
+    let sysfs = TempTree::new("backlight", "writes");
+    sysfs.device("intel_backlight", "raw", 19200, 19200);
+    let backlight = SysfsBacklight::new(sysfs.path());
+    assert!(backlight.is_writable());
+    backlight.set_percentage(30).unwrap();
+    assert_eq!(sysfs.brightness("intel_backlight"), "5760");
+    // Clamped rather than rejected by the kernel
+    backlight.set_brightness(50000).unwrap();
+    assert_eq!(sysfs.brightness("intel_backlight"), "19200");
+}
+
+#[test]
+fn no_backlight() {
+    let sysfs = TempTree::new("backlight", "none");
+    let backlight = SysfsBacklight::new(sysfs.path());
+    assert_eq!(backlight.device(), None);
+    assert!(backlight.brightness().is_err());
+    assert!(SysfsBacklight::new(sysfs.path().join("missing"))
+        .device()
+        .is_none());
+}
+
+// logind's session object, recording calls rather than writing sysfs
+struct FakeSession {
+    calls: Arc<Mutex<Vec<(String, String, u32)>>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Session")]
+impl FakeSession {
+    fn set_brightness(&self, subsystem: String, name: String, brightness: u32) {
+        self.calls
+            .lock()
+            .unwrap()
+            .push((subsystem, name, brightness));
+    }
+}
+
+#[test]
+fn logind_sets() {
+    let sysfs = TempTree::new("backlight", "logind");
+    sysfs.device("intel_backlight", "raw", 19200, 19200);
+    let bus = Bus::start();
+    let calls = Arc::new(Mutex::new(vec![]));
+    let _logind = blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/login1/session/auto",
+            FakeSession {
+                calls: calls.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let backlight =
+        LogindBacklight::at_address(SysfsBacklight::new(sysfs.path()), &bus.address).unwrap();
+    assert_eq!(backlight.brightness().unwrap().value, 19200);
+    backlight.set_percentage(50).unwrap();
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec![("backlight".to_owned(), "intel_backlight".to_owned(), 9600)]
+    );
+    // Left to logind
+    assert_eq!(sysfs.brightness("intel_backlight"), "19200\n");
+}
@@ -0,0 +1,27 @@
+use consolation_common::config::ShellConfig;
+
+#[test]
+fn unknown_profiles_are_dropped_once() {
+    let mut config: ShellConfig = toml::from_str(
+        r#"
+[profiles.gamemode]
+wrapper = ["gamemoderun"]
+
+[launcher.assign]
+"supertux2" = ["gamemode", "mangohud"]
+"foot" = ["tv"]
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.drop_unknown_profiles(),
+        vec![
+            ("foot".to_owned(), "tv".to_owned()),
+            ("supertux2".to_owned(), "mangohud".to_owned()),
+        ]
+    );
+    assert_eq!(config.profiles_for("supertux2").len(), 1);
+    assert!(config.profiles_for("foot").is_empty());
+    assert!(config.drop_unknown_profiles().is_empty());
+}
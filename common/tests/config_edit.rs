@@ -0,0 +1,127 @@
+use consolation_common::config_edit::ConfigEdit;
+use std::path::PathBuf;
+
+// A config directory, removed when dropped
+struct FakeConfig {
+    root: PathBuf,
+}
+
+impl FakeConfig {
+    fn new(name: &str) -> FakeConfig {
+        let root = std::env::temp_dir().join(format!(
+            "consolation-config-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        FakeConfig { root }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.root.join("consolation").join("shell.toml")
+    }
+
+    fn write(&self, contents: &str) -> &FakeConfig {
+        std::fs::create_dir_all(self.path().parent().unwrap()).unwrap();
+        std::fs::write(self.path(), contents).unwrap();
+        self
+    }
+
+    fn read(&self) -> String {
+        std::fs::read_to_string(self.path()).unwrap()
+    }
+}
+
+impl Drop for FakeConfig {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn comments_are_kept() {
+    let config = FakeConfig::new("comments");
+    config.write(
+        r#"# Set up for the living room
+[switcher]
+# Long, the TV is slow
+close_timeout = 10 # seconds
+auto_pause = true
+"#,
+    );
+    let mut edit = ConfigEdit::load(&config.path()).unwrap();
+    edit.change(|edit| edit.set(&["switcher", "close_timeout"], 20))
+        .unwrap();
+    edit.change(|edit| edit.set(&["notifications", "timeout"], 8))
+        .unwrap();
+    let saved = edit.save().unwrap();
+
+    assert_eq!(saved.switcher.close_timeout, 20);
+    assert_eq!(saved.notifications.timeout, 8);
+    assert_eq!(
+        config.read(),
+        r#"# Set up for the living room
+[switcher]
+# Long, the TV is slow
+close_timeout = 20 # seconds
+auto_pause = true
+
+[notifications]
+timeout = 8
+"#
+    );
+}
+
+#[test]
+fn saving_creates_the_directory_and_leaves_no_partial_file() {
+    let config = FakeConfig::new("atomic");
+    let mut edit = ConfigEdit::load(&config.path()).unwrap();
+    edit.change(|edit| edit.set(&["switcher", "auto_pause"], true))
+        .unwrap();
+    edit.save().unwrap();
+    assert_eq!(config.read(), "[switcher]\nauto_pause = true\n");
+
+    // Saving again replaces the file whole
+    edit.change(|edit| edit.set(&["switcher", "auto_pause"], false))
+        .unwrap();
+    edit.save().unwrap();
+    assert_eq!(config.read(), "[switcher]\nauto_pause = false\n");
+    let left: Vec<_> = std::fs::read_dir(config.path().parent().unwrap())
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.file_name())
+        .collect();
+    assert_eq!(left, vec!["shell.toml"]);
+}
+
+#[test]
+fn invalid_changes_are_rolled_back() {
+    let config = FakeConfig::new("invalid");
+    config.write("[switcher]\nclose_timeout = 10\n");
+    let mut edit = ConfigEdit::load(&config.path()).unwrap();
+
+    assert!(edit
+        .change(|edit| edit.set(&["switcher", "close_timeout"], "soon"))
+        .is_err());
+    assert_eq!(
+        edit.get(&["switcher", "close_timeout"])
+            .and_then(|item| item.as_integer()),
+        Some(10)
+    );
+    assert_eq!(edit.config().unwrap().switcher.close_timeout, 10);
+}
+
+#[test]
+fn invalid_config_is_not_saved() {
+    let config = FakeConfig::new("refused");
+    let broken = "[switcher]\nclose_timeout = \"soon\"\n";
+    config.write(broken);
+    // Still TOML, so it opens, but it can't be written back
+    let edit = ConfigEdit::load(&config.path()).unwrap();
+    assert!(edit.config().is_err());
+    assert!(edit.save().is_err());
+    assert_eq!(config.read(), broken);
+
+    config.write("[switcher\n");
+    assert!(ConfigEdit::load(&config.path()).is_err());
+}
@@ -0,0 +1,134 @@
+use consolation_common::launcher::desktop::{exec_to_argv, DesktopFile};
+use std::path::Path;
+
+fn parse(contents: &str) -> DesktopFile {
+    DesktopFile::parse("test", Path::new("/test.desktop"), contents)
+}
+
+// Only a ; that isn't escaped ends a list item
+#[test]
+fn list_escapes() {
+    let file = parse(concat!(
+        "[Desktop Entry]\n",
+        r"Keywords=semi\;colon;back\\;slash\\\;semi;;space\s;",
+    ));
+    assert_eq!(
+        file.list("Keywords"),
+        ["semi;colon", r"back\", r"slash\;semi", "space "]
+    );
+}
+
+const FIREFOX: &str = r#"
+# A comment
+[Desktop Entry]
+Type=Application
+Name=Firefox
+Icon=firefox
+Exec=firefox %u
+Categories=Network;WebBrowser;
+Actions=new-private-window;new-window;profile-manager
+
+[Desktop Action new-window]
+Name=New Window
+Exec=firefox --new-window %u
+
+[Desktop Action new-private-window]
+Name=New Private Window
+Icon=firefox-private
+Exec=firefox --private-window %u
+
+[Desktop Action profile-manager]
+Exec=firefox --ProfileManager
+"#;
+
+#[test]
+fn entry() {
+    let file = parse(FIREFOX);
+    assert!(file.is_visible());
+    assert_eq!(file.get("Name").as_deref(), Some("Firefox"));
+    let entry = file.to_entry().unwrap();
+    assert_eq!(entry.id, "test");
+    assert_eq!(entry.name, "Firefox");
+    assert_eq!(entry.exec, ["firefox"]);
+    assert_eq!(entry.categories, ["Network", "WebBrowser"]);
+}
+
+// In the order Actions lists them, leaving out any without a name
+#[test]
+fn actions() {
+    let actions = parse(FIREFOX).actions();
+    let found: Vec<_> = actions
+        .iter()
+        .map(|action| {
+            (
+                action.name.as_str(),
+                action.icon.as_deref(),
+                action.exec.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (
+                "New Private Window",
+                Some("firefox-private"),
+                vec!["firefox".to_owned(), "--private-window".to_owned()]
+            ),
+            (
+                "New Window",
+                None,
+                vec!["firefox".to_owned(), "--new-window".to_owned()]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn hidden() {
+    for extra in ["NoDisplay=true", "Hidden=true", "Type=Link"] {
+        let file = parse(&format!(
+            "[Desktop Entry]\nType=Application\nName=Tool\nExec=tool\n{}\n",
+            extra
+        ));
+        assert!(!file.is_visible(), "{}", extra);
+    }
+    let file = parse("[Desktop Entry]\nType=Application\nName=Tool\n");
+    assert!(!file.is_visible());
+}
+
+#[test]
+fn string_escapes() {
+    let file = parse(concat!(
+        "[Desktop Entry]\n",
+        r"Comment=one\stwo\nthree\\four",
+    ));
+    assert_eq!(file.get("Comment").as_deref(), Some("one two\nthree\\four"));
+}
+
+#[test]
+fn exec_field_codes() {
+    assert_eq!(
+        exec_to_argv(
+            "app %F --name %c %i --percent 100%%",
+            "App",
+            Some("app-icon")
+        )
+        .unwrap(),
+        [
+            "app",
+            "--name",
+            "App",
+            "--icon",
+            "app-icon",
+            "--percent",
+            "100%"
+        ]
+    );
+    assert_eq!(
+        exec_to_argv(r#"sh -c "echo 'a b'" %U"#, "App", None).unwrap(),
+        ["sh", "-c", "echo 'a b'"]
+    );
+    assert_eq!(exec_to_argv("%f", "App", None), None);
+    assert_eq!(exec_to_argv("app \"unclosed", "App", None), None);
+}
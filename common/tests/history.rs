@@ -0,0 +1,97 @@
+use consolation_common::notifications::history::{
+    HistorySignal, NotificationHistory, HISTORY_LIMIT,
+};
+use consolation_common::notifications::{Notification, NotificationImage};
+use std::path::PathBuf;
+
+fn notification(id: u32, summary: &str) -> Notification {
+    Notification {
+        id,
+        app_name: "Steam".to_owned(),
+        summary: summary.to_owned(),
+        ..Default::default()
+    }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("consolation-{}-{}.json", name, std::process::id()))
+}
+
+#[test]
+fn add_replace_and_remove() {
+    let mut history = NotificationHistory::default();
+    history.add(notification(1, "Download complete"));
+    history.add(notification(2, "Friend online"));
+    assert_eq!(history.unread(), 2);
+
+    // Replacing moves it to the front as unread
+    history.mark_read();
+    history.add(notification(1, "Update complete"));
+    let summaries: Vec<_> = history
+        .entries()
+        .iter()
+        .map(|entry| entry.notification.summary.as_str())
+        .collect();
+    assert_eq!(summaries, ["Update complete", "Friend online"]);
+    assert_eq!(history.unread(), 1);
+
+    assert!(history.remove(2));
+    assert!(!history.remove(2));
+    assert_eq!(history.clear(), vec![1]);
+    assert!(history.entries().is_empty());
+}
+
+#[test]
+fn limit_and_images() {
+    let mut history = NotificationHistory::default();
+    for id in 1..=HISTORY_LIMIT as u32 {
+        let (_, dropped) = history.add(notification(id, "Achievement unlocked"));
+        assert!(dropped.is_empty());
+    }
+    let mut newest = notification(1000, "Achievement unlocked");
+    newest.image = Some(NotificationImage::Pixels {
+        width: 1,
+        height: 1,
+        rgba: vec![0, 0, 0, 255],
+    });
+    let (entry, dropped) = history.add(newest);
+    assert_eq!(dropped, vec![1]);
+    assert_eq!(entry.notification.image, None);
+    assert_eq!(history.entries().len(), HISTORY_LIMIT);
+}
+
+#[test]
+fn persists() {
+    let path = temp_path("history");
+    let mut history = NotificationHistory::load(&path);
+    assert!(history.entries().is_empty());
+    history.add(notification(7, "Battery low"));
+    history.add(notification(8, "Controller connected"));
+    history.mark_read();
+    history.save().unwrap();
+
+    let loaded = NotificationHistory::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.entries(), history.entries());
+    assert_eq!(loaded.unread(), 0);
+    assert_eq!(loaded.get(7).unwrap().notification.summary, "Battery low");
+}
+
+#[test]
+fn broken_file_is_empty() {
+    let path = temp_path("broken");
+    std::fs::write(&path, "[{").unwrap();
+    let history = NotificationHistory::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(history.entries().is_empty());
+}
+
+#[test]
+fn signal_json() {
+    let json = serde_json::to_string(&HistorySignal::Removed { id: 3 }).unwrap();
+    assert_eq!(json, r#"{"type":"removed","id":3}"#);
+    assert_eq!(
+        serde_json::from_str::<HistorySignal>(&json).unwrap(),
+        HistorySignal::Removed { id: 3 }
+    );
+}
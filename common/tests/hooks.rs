@@ -0,0 +1,68 @@
+use consolation_common::hooks::{events_for, HookEvent};
+use consolation_common::{ToplevelDetails, ToplevelState};
+
+fn window(title: &str, state: ToplevelState) -> ToplevelDetails {
+    ToplevelDetails {
+        title: Some(title.to_owned()),
+        app_id: Some("org.supertux.SuperTux".to_owned()),
+        state,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn new_window() {
+    assert_eq!(
+        events_for(None, &window("SuperTux", ToplevelState::empty())),
+        [HookEvent::New]
+    );
+    assert_eq!(
+        events_for(
+            None,
+            &window(
+                "SuperTux",
+                ToplevelState::ACTIVATED | ToplevelState::FULLSCREEN
+            )
+        ),
+        [HookEvent::New, HookEvent::Activated, HookEvent::Fullscreen]
+    );
+}
+
+// Only gaining a state is an event, and nothing changing is none
+#[test]
+fn changes() {
+    let before = window("SuperTux", ToplevelState::ACTIVATED);
+    assert_eq!(events_for(Some(&before), &before), []);
+    assert_eq!(
+        events_for(
+            Some(&before),
+            &window("SuperTux - Level 1", ToplevelState::ACTIVATED)
+        ),
+        [HookEvent::Title]
+    );
+    assert_eq!(
+        events_for(
+            Some(&before),
+            &window(
+                "SuperTux",
+                ToplevelState::ACTIVATED | ToplevelState::FULLSCREEN
+            )
+        ),
+        [HookEvent::Fullscreen]
+    );
+    assert_eq!(
+        events_for(Some(&before), &window("SuperTux", ToplevelState::empty())),
+        []
+    );
+    let fullscreen = window("SuperTux", ToplevelState::FULLSCREEN);
+    assert_eq!(
+        events_for(
+            Some(&fullscreen),
+            &window(
+                "Paused",
+                ToplevelState::ACTIVATED | ToplevelState::FULLSCREEN
+            )
+        ),
+        [HookEvent::Title, HookEvent::Activated]
+    );
+}
@@ -0,0 +1,113 @@
+use consolation_common::idle::gamepad::{ActivityFilter, JsEvent, AXIS_THRESHOLD};
+use consolation_common::idle::{IdleCommand, IdleEvent, IdleStage, IdleState, RESTART_INTERVAL};
+use std::time::{Duration, Instant};
+
+fn js(value: i16, kind: u8, number: u8) -> JsEvent {
+    let mut bytes = [0; 8];
+    bytes[..4].copy_from_slice(&1234u32.to_ne_bytes());
+    bytes[4..6].copy_from_slice(&value.to_ne_bytes());
+    bytes[6] = kind;
+    bytes[7] = number;
+    JsEvent::parse(bytes)
+}
+
+#[test]
+fn dims_then_blanks() {
+    let mut idle = IdleState::new();
+    assert_eq!(
+        idle.handle(IdleEvent::Idled(IdleStage::Dim)),
+        vec![IdleCommand::Dim]
+    );
+    assert!(idle.dimmed());
+    // Said twice is done once
+    assert_eq!(idle.handle(IdleEvent::Idled(IdleStage::Dim)), vec![]);
+    assert_eq!(
+        idle.handle(IdleEvent::Idled(IdleStage::Blank)),
+        vec![IdleCommand::Blank]
+    );
+
+    assert_eq!(
+        idle.handle(IdleEvent::Resumed(IdleStage::Dim)),
+        vec![IdleCommand::Unblank, IdleCommand::Undim]
+    );
+    // The other stage resuming too changes nothing
+    assert_eq!(idle.handle(IdleEvent::Resumed(IdleStage::Blank)), vec![]);
+    assert!(!idle.dimmed());
+    assert!(!idle.blanked());
+}
+
+#[test]
+fn inhibited() {
+    let mut idle = IdleState::new();
+    idle.handle(IdleEvent::Idled(IdleStage::Dim));
+    // A film going fullscreen while dimmed brings the screen back
+    assert_eq!(idle.set_inhibited(true), vec![IdleCommand::Undim]);
+    assert_eq!(idle.set_inhibited(true), vec![]);
+    assert_eq!(idle.handle(IdleEvent::Idled(IdleStage::Dim)), vec![]);
+    assert_eq!(idle.handle(IdleEvent::Idled(IdleStage::Blank)), vec![]);
+    assert_eq!(idle.gamepad(Instant::now()), vec![]);
+
+    // The timers start from when it ends
+    assert_eq!(idle.set_inhibited(false), vec![IdleCommand::Restart]);
+    assert_eq!(
+        idle.handle(IdleEvent::Idled(IdleStage::Dim)),
+        vec![IdleCommand::Dim]
+    );
+}
+
+#[test]
+fn gamepad_input() {
+    let mut idle = IdleState::new();
+    let start = Instant::now();
+    assert_eq!(idle.gamepad(start), vec![IdleCommand::Restart]);
+    // Not again straight away
+    assert_eq!(idle.gamepad(start + Duration::from_secs(1)), vec![]);
+    assert_eq!(
+        idle.gamepad(start + RESTART_INTERVAL),
+        vec![IdleCommand::Restart]
+    );
+
+    // Waking up is never held back
+    idle.handle(IdleEvent::Idled(IdleStage::Dim));
+    idle.handle(IdleEvent::Idled(IdleStage::Blank));
+    assert_eq!(
+        idle.gamepad(start + RESTART_INTERVAL + Duration::from_secs(1)),
+        vec![
+            IdleCommand::Unblank,
+            IdleCommand::Undim,
+            IdleCommand::Restart
+        ]
+    );
+}
+
+#[test]
+fn js_events() {
+    let event = js(-300, 0x82, 5);
+    assert_eq!(event.value, -300);
+    assert_eq!(event.number, 5);
+    assert!(event.is_axis());
+    assert!(event.is_init());
+    assert!(!event.is_button());
+    assert!(js(1, 0x01, 0).is_button());
+}
+
+#[test]
+fn activity() {
+    let mut filter = ActivityFilter::new();
+    // The state sent on opening isn't input
+    assert!(!filter.is_activity(&js(1, 0x81, 0)));
+    assert!(!filter.is_activity(&js(-32767, 0x82, 2)));
+
+    assert!(filter.is_activity(&js(1, 0x01, 0)));
+    assert!(filter.is_activity(&js(0, 0x01, 0)));
+
+    // A trigger resting at -32767 jitters
+    assert!(!filter.is_activity(&js(-32000, 0x02, 2)));
+    assert!(!filter.is_activity(&js(-32767, 0x02, 2)));
+    let pulled = -32767 + AXIS_THRESHOLD as i16;
+    assert!(filter.is_activity(&js(pulled, 0x02, 2)));
+    assert!(!filter.is_activity(&js(pulled + 10, 0x02, 2)));
+
+    // An axis never seen before counts
+    assert!(filter.is_activity(&js(100, 0x02, 7)));
+}
@@ -0,0 +1,289 @@
+use chrono::{DateTime, Local, TimeZone};
+use consolation_common::limits::{
+    Clock, GrantRefused, LimitEvent, LimitPeriod, LimitRule, LimitsConfig, TimeLimits,
+};
+use consolation_common::pin;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+struct Times {
+    wall: DateTime<Local>,
+    boot_id: String,
+    boottime: u64,
+    uptime: u64,
+}
+
+// Clocks the test moves by hand. Clones share the same times
+#[derive(Clone)]
+struct FakeClock(Arc<Mutex<Times>>);
+
+impl FakeClock {
+    fn new(wall: DateTime<Local>) -> FakeClock {
+        FakeClock(Arc::new(Mutex::new(Times {
+            wall,
+            boot_id: "boot-1".to_owned(),
+            boottime: 1000,
+            uptime: 1000,
+        })))
+    }
+
+    // Time passing normally, every clock moves together
+    fn advance(&self, seconds: u64) {
+        let mut times = self.0.lock().unwrap();
+        times.wall += chrono::Duration::seconds(seconds as i64);
+        times.boottime += seconds;
+        times.uptime += seconds;
+    }
+
+    // Someone changing the time, nothing else moves
+    fn set_wall(&self, wall: DateTime<Local>) {
+        self.0.lock().unwrap().wall = wall;
+    }
+
+    fn reboot(&self, wall: DateTime<Local>) {
+        let mut times = self.0.lock().unwrap();
+        times.wall = wall;
+        times.boot_id = format!("{}-again", times.boot_id);
+        times.boottime = 30;
+        times.uptime = 30;
+    }
+}
+
+impl Clock for FakeClock {
+    fn wall(&self) -> DateTime<Local> {
+        self.0.lock().unwrap().wall
+    }
+
+    fn boot_id(&self) -> String {
+        self.0.lock().unwrap().boot_id.clone()
+    }
+
+    fn boottime(&self) -> u64 {
+        self.0.lock().unwrap().boottime
+    }
+
+    fn uptime(&self) -> u64 {
+        self.0.lock().unwrap().uptime
+    }
+}
+
+// A limits.json of its own, removed when dropped
+struct FakeData {
+    path: PathBuf,
+}
+
+impl FakeData {
+    fn new(name: &str) -> FakeData {
+        let path = std::env::temp_dir().join(format!(
+            "consolation-limits-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        FakeData { path }
+    }
+
+    fn limits(&self, config: &LimitsConfig, clock: &FakeClock) -> TimeLimits {
+        TimeLimits::with_clock(
+            config.clone(),
+            Some(self.path.clone()),
+            Box::new(clock.clone()),
+        )
+    }
+}
+
+impl Drop for FakeData {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+    Local
+        .with_ymd_and_hms(year, month, day, hour, minute, 0)
+        .single()
+        .unwrap()
+}
+
+fn config(daily: Option<u64>, weekly: Option<u64>) -> LimitsConfig {
+    let mut config = LimitsConfig {
+        pin_hash: Some(pin::hash("1234")),
+        ..Default::default()
+    };
+    config.rules.insert(
+        "games".to_owned(),
+        LimitRule {
+            daily,
+            weekly,
+            ..Default::default()
+        },
+    );
+    config
+}
+
+fn games() -> Vec<String> {
+    vec!["games".to_owned()]
+}
+
+fn minutes(minutes: u64) -> Option<Duration> {
+    Some(Duration::from_secs(minutes * 60))
+}
+
+// Play for this many minutes, a tick every 10 seconds as the daemon does
+fn play(limits: &mut TimeLimits, clock: &FakeClock, minutes: u64) -> Vec<LimitEvent> {
+    let mut events = vec![];
+    for _ in 0..minutes * 6 {
+        clock.advance(10);
+        events.extend(limits.tick(&games()));
+    }
+    events
+}
+
+#[test]
+fn daily_usage_resets_at_midnight() {
+    let data = FakeData::new("day");
+    // Sunday evening
+    let clock = FakeClock::new(at(2026, 6, 14, 23, 0));
+    let mut limits = data.limits(&config(Some(60), None), &clock);
+
+    play(&mut limits, &clock, 40);
+    assert_eq!(limits.remaining("games"), minutes(20));
+
+    clock.advance(30 * 60);
+    limits.tick(&[]);
+    assert_eq!(limits.now(), at(2026, 6, 15, 0, 10));
+    assert_eq!(limits.remaining("games"), minutes(60));
+}
+
+#[test]
+fn weekly_usage_resets_on_monday_only() {
+    let data = FakeData::new("week");
+    // Saturday
+    let clock = FakeClock::new(at(2026, 6, 13, 12, 0));
+    let mut limits = data.limits(&config(None, Some(120)), &clock);
+
+    play(&mut limits, &clock, 50);
+    // Into Sunday, the same ISO week
+    clock.advance(24 * 60 * 60);
+    limits.tick(&[]);
+    assert_eq!(limits.remaining("games"), minutes(70));
+
+    play(&mut limits, &clock, 20);
+    // Into Monday
+    clock.advance(24 * 60 * 60);
+    limits.tick(&[]);
+    assert_eq!(limits.remaining("games"), minutes(120));
+}
+
+#[test]
+fn setting_the_clock_back_changes_nothing() {
+    let data = FakeData::new("backwards");
+    let clock = FakeClock::new(at(2026, 6, 15, 20, 0));
+    let mut limits = data.limits(&config(Some(60), None), &clock);
+
+    play(&mut limits, &clock, 60);
+    assert_eq!(
+        limits.is_exhausted(&games()),
+        Some(("games".to_owned(), LimitPeriod::Day))
+    );
+
+    // Back a day, then forward past midnight, neither is believed
+    clock.set_wall(at(2026, 6, 14, 20, 0));
+    limits.tick(&[]);
+    assert_eq!(limits.now(), at(2026, 6, 15, 21, 0));
+    clock.set_wall(at(2026, 6, 16, 9, 0));
+    limits.tick(&[]);
+    assert_eq!(limits.now(), at(2026, 6, 15, 21, 0));
+    assert_eq!(limits.remaining("games"), minutes(0));
+
+    // The boot clock still moves the day on
+    clock.advance(3 * 60 * 60);
+    limits.tick(&[]);
+    assert_eq!(limits.remaining("games"), minutes(60));
+}
+
+#[test]
+fn rebooting_never_goes_backwards() {
+    let data = FakeData::new("reboot");
+    let clock = FakeClock::new(at(2026, 6, 15, 20, 0));
+    let mut limits = data.limits(&config(Some(60), None), &clock);
+    play(&mut limits, &clock, 60);
+    drop(limits);
+
+    // Rebooted with the clock set back to yesterday
+    clock.reboot(at(2026, 6, 14, 20, 0));
+    let mut limits = data.limits(&config(Some(60), None), &clock);
+    assert_eq!(limits.now(), at(2026, 6, 15, 21, 0));
+    assert_eq!(limits.remaining("games"), minutes(0));
+    drop(limits);
+
+    // Rebooted the next morning, the wall clock is all there is
+    clock.reboot(at(2026, 6, 16, 8, 0));
+    limits = data.limits(&config(Some(60), None), &clock);
+    assert_eq!(limits.now(), at(2026, 6, 16, 8, 0));
+    assert_eq!(limits.remaining("games"), minutes(60));
+}
+
+#[test]
+fn usage_survives_a_restart_on_the_same_boot() {
+    let data = FakeData::new("restart");
+    let clock = FakeClock::new(at(2026, 6, 15, 10, 0));
+    let mut limits = data.limits(&config(Some(60), None), &clock);
+    play(&mut limits, &clock, 25);
+    drop(limits);
+
+    clock.advance(60);
+    let limits = data.limits(&config(Some(60), None), &clock);
+    assert_eq!(limits.remaining("games"), minutes(35));
+}
+
+#[test]
+fn warnings_name_the_budget_running_out() {
+    let data = FakeData::new("warnings");
+    let clock = FakeClock::new(at(2026, 6, 15, 10, 0));
+    let mut limits = data.limits(&config(Some(120), Some(30)), &clock);
+
+    let events = play(&mut limits, &clock, 30);
+    assert_eq!(
+        events.first(),
+        Some(&LimitEvent::Warning {
+            rule: "games".to_owned(),
+            minutes_left: 15,
+            period: LimitPeriod::Week,
+        })
+    );
+    assert!(events.iter().any(|event| matches!(
+        event,
+        LimitEvent::Expired {
+            period: LimitPeriod::Week,
+            ..
+        }
+    )));
+    assert_eq!(LimitPeriod::Week.describe(), "this week");
+}
+
+#[test]
+fn wrong_pins_hold_off_the_next_attempt() {
+    let data = FakeData::new("grant");
+    let clock = FakeClock::new(at(2026, 6, 15, 10, 0));
+    let mut limits = data.limits(&config(Some(60), None), &clock);
+    play(&mut limits, &clock, 60);
+
+    assert_eq!(
+        limits.grant("games", "0000"),
+        Err(GrantRefused::Wait(pin::WRONG_DELAY))
+    );
+    // Even the right PIN isn't checked until the delay is up
+    assert!(matches!(
+        limits.grant("games", "1234"),
+        Err(GrantRefused::Wait(_))
+    ));
+    assert_eq!(limits.remaining("games"), minutes(0));
+
+    let mut no_pin = config(Some(60), None);
+    no_pin.pin_hash = None;
+    let mut limits = TimeLimits::with_clock(no_pin, None, Box::new(clock.clone()));
+    assert_eq!(limits.grant("games", "1234"), Err(GrantRefused::NoPin));
+}
@@ -0,0 +1,206 @@
+use consolation_common::logind::{is_allowed, Logind, PowerAction, PowerControl};
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+use zbus::blocking;
+use zbus::zvariant::OwnedFd;
+
+mod support;
+use support::Bus;
+
+type Calls = Arc<Mutex<Vec<String>>>;
+
+// logind's manager, recording what was asked of it rather than doing it
+struct FakeManager {
+    calls: Calls,
+    hibernate: &'static str,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Manager")]
+impl FakeManager {
+    fn can_suspend(&self) -> String {
+        "yes".to_owned()
+    }
+
+    fn can_hibernate(&self) -> String {
+        self.hibernate.to_owned()
+    }
+
+    fn can_reboot(&self) -> String {
+        "challenge".to_owned()
+    }
+
+    fn can_power_off(&self) -> String {
+        "yes".to_owned()
+    }
+
+    fn suspend(&self, interactive: bool) {
+        self.record("Suspend", interactive);
+    }
+
+    fn hibernate(&self, interactive: bool) {
+        self.record("Hibernate", interactive);
+    }
+
+    fn reboot(&self, interactive: bool) {
+        self.record("Reboot", interactive);
+    }
+
+    fn power_off(&self, interactive: bool) {
+        self.record("PowerOff", interactive);
+    }
+
+    fn inhibit(&self, what: &str, _who: &str, _why: &str, mode: &str) -> OwnedFd {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("Inhibit {} {}", what, mode));
+        std::os::fd::OwnedFd::from(File::open("/dev/null").unwrap()).into()
+    }
+}
+
+impl FakeManager {
+    fn record(&self, method: &str, interactive: bool) {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", method, interactive));
+    }
+}
+
+struct FakeSession {
+    calls: Calls,
+    locked: bool,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Session")]
+impl FakeSession {
+    fn terminate(&self) {
+        self.calls.lock().unwrap().push("Terminate".to_owned());
+    }
+
+    fn set_locked_hint(&mut self, locked: bool) {
+        self.locked = locked;
+    }
+
+    #[zbus(property)]
+    fn locked_hint(&self) -> bool {
+        self.locked
+    }
+}
+
+fn serve(bus: &Bus, hibernate: &'static str, calls: &Calls) -> blocking::Connection {
+    blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/login1",
+            FakeManager {
+                calls: calls.clone(),
+                hibernate,
+            },
+        )
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/login1/session/auto",
+            FakeSession {
+                calls: calls.clone(),
+                locked: false,
+            },
+        )
+        .unwrap()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn available_actions() {
+    let bus = Bus::start();
+    let calls = Calls::default();
+    let _logind = serve(&bus, "na", &calls);
+
+    let logind = Logind::at_address(&bus.address).unwrap();
+    assert!(logind.can(PowerAction::Reboot).unwrap());
+    assert!(!logind.can(PowerAction::Hibernate).unwrap());
+    assert_eq!(
+        logind.available(),
+        vec![
+            PowerAction::Suspend,
+            PowerAction::Reboot,
+            PowerAction::PowerOff
+        ]
+    );
+}
+
+#[test]
+fn perform_actions() {
+    let bus = Bus::start();
+    let calls = Calls::default();
+    let _logind = serve(&bus, "yes", &calls);
+
+    let logind = Logind::at_address(&bus.address).unwrap();
+    logind.perform(PowerAction::Hibernate).unwrap();
+    logind.perform(PowerAction::PowerOff).unwrap();
+    logind.terminate_session().unwrap();
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec!["Hibernate true", "PowerOff true", "Terminate"]
+    );
+}
+
+#[test]
+fn sleeping() {
+    let bus = Bus::start();
+    let calls = Calls::default();
+    let server = serve(&bus, "yes", &calls);
+
+    let logind = Logind::at_address(&bus.address).unwrap();
+    let mut signals = logind.sleep_signals().unwrap();
+    let _inhibitor = logind.inhibit_sleep("Test", "Testing").unwrap();
+    assert_eq!(*calls.lock().unwrap(), vec!["Inhibit sleep delay"]);
+
+    for start in [true, false] {
+        server
+            .emit_signal(
+                None::<()>,
+                "/org/freedesktop/login1",
+                "org.freedesktop.login1.Manager",
+                "PrepareForSleep",
+                &(start,),
+            )
+            .unwrap();
+    }
+    assert_eq!(signals.next(), Some(true));
+    assert_eq!(signals.next(), Some(false));
+}
+
+#[test]
+fn locked_hint() {
+    let bus = Bus::start();
+    let calls = Calls::default();
+    let _logind = serve(&bus, "yes", &calls);
+
+    let logind = Logind::at_address(&bus.address).unwrap();
+    assert!(!logind.locked_hint().unwrap());
+    logind.set_locked_hint(true).unwrap();
+    assert!(logind.locked_hint().unwrap());
+    logind.set_locked_hint(false).unwrap();
+    assert!(!logind.locked_hint().unwrap());
+}
+
+#[test]
+fn no_logind() {
+    let bus = Bus::start();
+    let logind = Logind::at_address(&bus.address).unwrap();
+    assert!(logind.can(PowerAction::Suspend).is_err());
+    assert!(logind.perform(PowerAction::Suspend).is_err());
+    assert!(logind.available().is_empty());
+}
+
+#[test]
+fn answers() {
+    assert!(is_allowed("yes"));
+    assert!(is_allowed("challenge"));
+    assert!(!is_allowed("no"));
+    assert!(!is_allowed("na"));
+}
@@ -0,0 +1,176 @@
+use consolation_common::{
+    ToplevelChange, ToplevelDetails, ToplevelEvent, ToplevelModel, ToplevelSignal, ToplevelState,
+};
+
+fn wire(values: &[u32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_ne_bytes())
+        .collect()
+}
+
+fn open(model: &mut ToplevelModel, id: u64, app_id: &str, state: ToplevelState) {
+    model.handle(ToplevelEvent::New { id });
+    model.handle(ToplevelEvent::AppId {
+        id,
+        app_id: app_id.to_owned(),
+    });
+    model.handle(ToplevelEvent::State { id, state });
+    model.handle(ToplevelEvent::Done { id });
+}
+
+#[test]
+fn state_from_wire() {
+    // maximized = 0, minimized = 1, activated = 2, fullscreen = 3
+    assert_eq!(ToplevelState::from_wire(&[]), ToplevelState::empty());
+    assert_eq!(
+        ToplevelState::from_wire(&wire(&[0])),
+        ToplevelState::MAXIMIZED
+    );
+    assert_eq!(
+        ToplevelState::from_wire(&wire(&[2, 3])),
+        ToplevelState::ACTIVATED | ToplevelState::FULLSCREEN
+    );
+    // Unknown values and trailing bytes are ignored
+    let mut raw = wire(&[1, 99]);
+    raw.push(2);
+    assert_eq!(ToplevelState::from_wire(&raw), ToplevelState::MINIMIZED);
+}
+
+#[test]
+fn state_json() {
+    let state = ToplevelState::MAXIMIZED | ToplevelState::ACTIVATED;
+    let json = serde_json::to_string(&state).unwrap();
+    assert_eq!(json, r#"["maximized","activated"]"#);
+    assert_eq!(serde_json::from_str::<ToplevelState>(&json).unwrap(), state);
+    assert_eq!(
+        serde_json::from_str::<ToplevelState>(r#"["fullscreen","tiled"]"#).unwrap(),
+        ToplevelState::FULLSCREEN
+    );
+}
+
+#[test]
+fn nothing_until_done() {
+    let mut model = ToplevelModel::new();
+    assert!(model.handle(ToplevelEvent::New { id: 1 }).is_empty());
+    assert!(model
+        .handle(ToplevelEvent::Title {
+            id: 1,
+            title: "Steam".to_owned()
+        })
+        .is_empty());
+    assert!(model.get(1).is_none());
+
+    let changes = model.handle(ToplevelEvent::Done { id: 1 });
+    let details = ToplevelDetails {
+        title: Some("Steam".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(
+        changes,
+        vec![
+            ToplevelChange::Updated {
+                id: 1,
+                previous: None,
+                details: details.clone(),
+            },
+            ToplevelChange::Mru(vec![1]),
+        ]
+    );
+    assert_eq!(model.get(1), Some(&details));
+}
+
+#[test]
+fn activation_moves_to_front() {
+    let mut model = ToplevelModel::new();
+    open(&mut model, 1, "steam", ToplevelState::ACTIVATED);
+    open(&mut model, 2, "firefox", ToplevelState::empty());
+    assert_eq!(model.mru(), &[1, 2]);
+
+    model.handle(ToplevelEvent::State {
+        id: 2,
+        state: ToplevelState::ACTIVATED,
+    });
+    let changes = model.handle(ToplevelEvent::Done { id: 2 });
+    assert_eq!(changes.last(), Some(&ToplevelChange::Mru(vec![2, 1])));
+
+    // Staying activated doesn't reorder anything
+    model.handle(ToplevelEvent::Title {
+        id: 2,
+        title: "New Tab".to_owned(),
+    });
+    let changes = model.handle(ToplevelEvent::Done { id: 2 });
+    assert_eq!(changes.len(), 1);
+    let ToplevelChange::Updated { previous, .. } = &changes[0] else {
+        panic!("expected an update, got {:?}", changes);
+    };
+    assert_eq!(previous.as_ref().and_then(|p| p.title.clone()), None);
+}
+
+#[test]
+fn closed_is_removed() {
+    let mut model = ToplevelModel::new();
+    open(&mut model, 1, "steam", ToplevelState::empty());
+    open(&mut model, 2, "firefox", ToplevelState::ACTIVATED);
+
+    let changes = model.handle(ToplevelEvent::Closed { id: 2 });
+    assert!(matches!(
+        changes.as_slice(),
+        [ToplevelChange::Removed { id: 2, .. }]
+    ));
+    assert_eq!(model.mru(), &[1]);
+    assert_eq!(model.toplevels().count(), 1);
+
+    // Closing before the first done has nothing to report
+    model.handle(ToplevelEvent::New { id: 3 });
+    assert!(model.handle(ToplevelEvent::Closed { id: 3 }).is_empty());
+}
+
+#[test]
+fn parents_are_cleared_when_they_close() {
+    let mut model = ToplevelModel::new();
+    open(&mut model, 1, "steam", ToplevelState::empty());
+    model.handle(ToplevelEvent::New { id: 2 });
+    model.handle(ToplevelEvent::Parent {
+        id: 2,
+        parent: Some(1),
+    });
+    model.handle(ToplevelEvent::Done { id: 2 });
+    assert_eq!(model.get(2).and_then(|details| details.parent), Some(1));
+
+    let changes = model.handle(ToplevelEvent::Closed { id: 1 });
+    assert_eq!(changes.len(), 2);
+    assert!(matches!(
+        &changes[1],
+        ToplevelChange::Updated { id: 2, details, .. } if details.parent.is_none()
+    ));
+    assert_eq!(model.get(2).and_then(|details| details.parent), None);
+}
+
+#[test]
+fn snapshot_round_trip() {
+    let mut model = ToplevelModel::new();
+    open(&mut model, 1, "steam", ToplevelState::MAXIMIZED);
+    model.handle(ToplevelEvent::Output {
+        name: "HDMI-A-1".to_owned(),
+    });
+
+    let snapshot = model.snapshot();
+    let json = serde_json::to_string(&snapshot).unwrap();
+    assert_eq!(
+        serde_json::from_str::<ToplevelSignal>(&json).unwrap(),
+        snapshot
+    );
+    let ToplevelSignal::Snapshot {
+        toplevels,
+        mru,
+        outputs,
+    } = snapshot
+    else {
+        panic!("expected a snapshot");
+    };
+    assert_eq!(toplevels.len(), 1);
+    assert_eq!(toplevels[0].1.state, ToplevelState::MAXIMIZED);
+    assert_eq!(mru, vec![1]);
+    assert_eq!(outputs, vec!["HDMI-A-1".to_owned()]);
+}
@@ -0,0 +1,150 @@
+use consolation_common::nightlight::{
+    gamma_ramps, sun_times, temperature, whitepoint, NightLightAction, Schedule, SunTimes, NEUTRAL,
+};
+
+// 2024-06-21 and 2024-12-21, in days since the Unix epoch
+const MIDSUMMER: i64 = 19895;
+const MIDWINTER: i64 = 20078;
+
+fn at(day: i64, hours: i64, minutes: i64) -> i64 {
+    day * 86400 + hours * 3600 + minutes * 60
+}
+
+fn close(a: f64, b: f64, within: f64) -> bool {
+    (a - b).abs() <= within
+}
+
+#[test]
+fn whitepoints() {
+    assert_eq!(whitepoint(NEUTRAL), [1.0, 1.0, 1.0]);
+    // Cooler is left alone
+    assert_eq!(whitepoint(9000), [1.0, 1.0, 1.0]);
+
+    let warm = whitepoint(3400);
+    assert_eq!(warm[0], 1.0);
+    assert!(warm[1] < 1.0);
+    assert!(warm[2] < warm[1]);
+    let warmer = whitepoint(2000);
+    assert!(warmer[1] < warm[1]);
+    assert!(warmer[2] < warm[2]);
+}
+
+#[test]
+fn ramps() {
+    let neutral = gamma_ramps(256, NEUTRAL);
+    assert_eq!(neutral.len(), 768);
+    for channel in neutral.chunks(256) {
+        assert_eq!(channel[0], 0);
+        assert_eq!(channel[128], 32896);
+        assert_eq!(channel[255], 65535);
+    }
+
+    let warm = gamma_ramps(1024, 3000);
+    let (red, rest) = warm.split_at(1024);
+    let (green, blue) = rest.split_at(1024);
+    assert_eq!(red[1023], 65535);
+    assert!(green[1023] < red[1023]);
+    assert!(blue[1023] < green[1023]);
+    assert!(blue.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    // Sizes a broken compositor might send
+    assert_eq!(gamma_ramps(1, 3000).len(), 3);
+    assert!(gamma_ramps(0, 3000).is_empty());
+}
+
+#[test]
+fn london_sun() {
+    // Sunrise 03:43 and sunset 20:21 UTC, half a minute earlier in solar
+    // time west of Greenwich
+    let SunTimes::Rises { sunrise, sunset } = sun_times(MIDSUMMER, 51.5072, -0.1276) else {
+        panic!("The sun rises in London");
+    };
+    assert!(close(sunrise, 222.5, 5.0), "sunrise {}", sunrise);
+    assert!(close(sunset, 1220.5, 5.0), "sunset {}", sunset);
+
+    let SunTimes::Rises { sunrise, sunset } = sun_times(MIDWINTER, 51.5072, -0.1276) else {
+        panic!("The sun rises in London");
+    };
+    assert!(close(sunrise, 483.5, 5.0), "sunrise {}", sunrise);
+    assert!(close(sunset, 953.5, 5.0), "sunset {}", sunset);
+}
+
+#[test]
+fn polar_sun() {
+    assert_eq!(sun_times(MIDSUMMER, 69.6492, 18.9553), SunTimes::AlwaysDay);
+    assert_eq!(
+        sun_times(MIDWINTER, 69.6492, 18.9553),
+        SunTimes::AlwaysNight
+    );
+    assert_eq!(sun_times(MIDWINTER, -77.85, 166.67), SunTimes::AlwaysDay);
+
+    let tromso = Schedule::Sun {
+        latitude: 69.6492,
+        longitude: 18.9553,
+    };
+    assert_eq!(tromso.night_level(at(MIDSUMMER, 23, 0), 0, 60.0), 0.0);
+    assert_eq!(tromso.night_level(at(MIDWINTER, 12, 0), 0, 60.0), 1.0);
+}
+
+#[test]
+fn sun_schedule() {
+    let london = Schedule::Sun {
+        latitude: 51.5072,
+        longitude: -0.1276,
+    };
+    assert_eq!(london.night_level(at(MIDSUMMER, 12, 0), 0, 60.0), 0.0);
+    assert_eq!(london.night_level(at(MIDSUMMER, 23, 30), 0, 60.0), 1.0);
+    assert_eq!(london.night_level(at(MIDSUMMER, 2, 0), 0, 60.0), 1.0);
+    let dusk = london.night_level(at(MIDSUMMER, 20, 21), 0, 60.0);
+    assert!(close(dusk, 0.5, 0.1), "dusk {}", dusk);
+    // Time zones make no difference
+    assert_eq!(london.night_level(at(MIDSUMMER, 12, 0), 3600, 60.0), 0.0);
+}
+
+#[test]
+fn fixed_schedule() {
+    let evenings = Schedule::Fixed {
+        dusk: 21 * 60,
+        dawn: 7 * 60,
+    };
+    assert_eq!(evenings.night_level(at(MIDSUMMER, 12, 0), 0, 60.0), 0.0);
+    assert_eq!(evenings.night_level(at(MIDSUMMER, 23, 0), 0, 60.0), 1.0);
+    assert_eq!(evenings.night_level(at(MIDSUMMER, 3, 0), 0, 60.0), 1.0);
+    assert_eq!(evenings.night_level(at(MIDSUMMER, 21, 0), 0, 60.0), 0.5);
+    assert_eq!(evenings.night_level(at(MIDSUMMER, 20, 45), 0, 60.0), 0.25);
+    assert_eq!(evenings.night_level(at(MIDSUMMER, 7, 15), 0, 60.0), 0.25);
+    // 20:00 UTC is 22:00 two hours east
+    assert_eq!(evenings.night_level(at(MIDSUMMER, 20, 0), 7200, 60.0), 1.0);
+
+    // Fading in across midnight
+    let late = Schedule::Fixed {
+        dusk: 23 * 60 + 50,
+        dawn: 6 * 60,
+    };
+    assert_eq!(late.night_level(at(MIDSUMMER, 0, 5), 0, 60.0), 0.75);
+
+    // Night in the middle of the day's clock, for night shifts
+    let shift = Schedule::Fixed {
+        dusk: 60,
+        dawn: 5 * 60,
+    };
+    assert_eq!(shift.night_level(at(MIDSUMMER, 3, 0), 0, 30.0), 1.0);
+    assert_eq!(shift.night_level(at(MIDSUMMER, 12, 0), 0, 30.0), 0.0);
+    assert_eq!(shift.night_level(at(MIDSUMMER, 23, 50), 0, 30.0), 0.0);
+}
+
+#[test]
+fn temperatures() {
+    assert_eq!(temperature(0.0, 6500, 4000), 6500);
+    assert_eq!(temperature(1.0, 6500, 4000), 4000);
+    assert_eq!(temperature(0.5, 6500, 4500), 5500);
+    assert_eq!(temperature(2.0, 6500, 4000), 4000);
+}
+
+#[test]
+fn protocol() {
+    assert_eq!(
+        serde_json::to_string(&NightLightAction::SetEnabled { enabled: true }).unwrap(),
+        r#"{"action":"set_enabled","enabled":true}"#
+    );
+}
@@ -0,0 +1,440 @@
+use consolation_common::notifications::{
+    CloseReason, Notification, NotificationAction, NotificationEvent, NotificationImage,
+    NotificationServer, ServerOptions, Urgency,
+};
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+use zbus::blocking;
+use zbus::zvariant::Value;
+
+mod support;
+use support::Bus;
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, &Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    fn close_notification(&self, id: u32) -> zbus::Result<()>;
+
+    fn get_capabilities(&self) -> zbus::Result<Vec<String>>;
+
+    #[zbus(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn action_invoked(&self, id: u32, action_key: String) -> zbus::Result<()>;
+}
+
+impl Bus {
+    fn serve(
+        &self,
+        default_timeout: Duration,
+    ) -> (NotificationServer, Receiver<NotificationEvent>) {
+        let (sender, events) = channel();
+        let options = ServerOptions {
+            default_timeout,
+            persistence: false,
+        };
+        let server = NotificationServer::at_address(&self.address, sender, options).unwrap();
+        (server, events)
+    }
+
+    fn client(&self) -> NotificationsProxyBlocking<'static> {
+        let connection = blocking::connection::Builder::address(self.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        NotificationsProxyBlocking::new(&connection).unwrap()
+    }
+}
+
+fn next(events: &Receiver<NotificationEvent>) -> NotificationEvent {
+    events.recv_timeout(Duration::from_secs(5)).unwrap()
+}
+
+const LONG: Duration = Duration::from_secs(60);
+
+#[test]
+fn notify_and_close() {
+    let bus = Bus::start();
+    let (_server, events) = bus.serve(LONG);
+    let client = bus.client();
+    let mut closed = client.receive_notification_closed().unwrap();
+
+    let urgency = Value::from(2u8);
+    let hints = HashMap::from([("urgency", &urgency)]);
+    let id = client
+        .notify(
+            "Steam",
+            0,
+            "steam",
+            "Download complete",
+            "Hades is ready to play",
+            &["default", "Play", "later", "Later"],
+            hints,
+            -1,
+        )
+        .unwrap();
+
+    let NotificationEvent::Show(notification) = next(&events) else {
+        panic!("expected a notification");
+    };
+    assert_eq!(notification.id, id);
+    assert_eq!(notification.summary, "Download complete");
+    assert_eq!(notification.urgency, Urgency::Critical);
+    assert_eq!(notification.actions.len(), 2);
+    assert_eq!(notification.default_action().unwrap().label, "Play");
+    // Critical notifications wait for the user
+    assert_eq!(notification.timeout, None);
+
+    client.close_notification(id).unwrap();
+    assert_eq!(
+        next(&events),
+        NotificationEvent::Closed {
+            id,
+            reason: CloseReason::Closed
+        }
+    );
+    let signal = closed.next().unwrap();
+    let args = signal.args().unwrap();
+    assert_eq!((args.id, args.reason), (id, CloseReason::Closed as u32));
+}
+
+#[test]
+fn replaces_id() {
+    let bus = Bus::start();
+    let (server, events) = bus.serve(LONG);
+    let client = bus.client();
+
+    let first = client
+        .notify("Volume", 0, "", "Volume 40%", "", &[], HashMap::new(), -1)
+        .unwrap();
+    let second = client
+        .notify(
+            "Volume",
+            first,
+            "",
+            "Volume 50%",
+            "",
+            &[],
+            HashMap::new(),
+            -1,
+        )
+        .unwrap();
+    assert_eq!(first, second);
+    next(&events);
+    let NotificationEvent::Show(replaced) = next(&events) else {
+        panic!("expected a notification");
+    };
+    assert_eq!(replaced.summary, "Volume 50%");
+    assert_eq!(server.open().len(), 1);
+
+    // Once closed, replacing it gets a new id
+    server.dismiss(first);
+    next(&events);
+    let third = client
+        .notify(
+            "Volume",
+            first,
+            "",
+            "Volume 60%",
+            "",
+            &[],
+            HashMap::new(),
+            -1,
+        )
+        .unwrap();
+    assert_ne!(third, first);
+}
+
+#[test]
+fn expiry() {
+    let bus = Bus::start();
+    let (server, events) = bus.serve(Duration::from_millis(100));
+    let client = bus.client();
+    let mut closed = client.receive_notification_closed().unwrap();
+
+    let never = client
+        .notify("Clock", 0, "", "Alarm", "", &[], HashMap::new(), 0)
+        .unwrap();
+    let id = client
+        .notify("Clock", 0, "", "Tick", "", &[], HashMap::new(), -1)
+        .unwrap();
+    next(&events);
+    next(&events);
+    assert_eq!(
+        next(&events),
+        NotificationEvent::Closed {
+            id,
+            reason: CloseReason::Expired
+        }
+    );
+    let signal = closed.next().unwrap();
+    assert_eq!(signal.args().unwrap().reason, CloseReason::Expired as u32);
+    assert_eq!(server.open().len(), 1);
+    assert_eq!(server.open()[0].id, never);
+}
+
+#[test]
+fn replacing_restarts_expiry() {
+    let bus = Bus::start();
+    let (server, events) = bus.serve(LONG);
+    let client = bus.client();
+
+    let id = client
+        .notify("Build", 0, "", "Building", "", &[], HashMap::new(), 200)
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    client
+        .notify(
+            "Build",
+            id,
+            "",
+            "Still building",
+            "",
+            &[],
+            HashMap::new(),
+            0,
+        )
+        .unwrap();
+    next(&events);
+    next(&events);
+    // The first timer has gone off by now, but was for the old contents
+    assert!(events.recv_timeout(Duration::from_millis(300)).is_err());
+    assert_eq!(server.open()[0].summary, "Still building");
+}
+
+#[test]
+fn actions() {
+    let bus = Bus::start();
+    let (server, events) = bus.serve(LONG);
+    let client = bus.client();
+    let mut invoked = client.receive_action_invoked().unwrap();
+    let mut closed = client.receive_notification_closed().unwrap();
+
+    let resident = Value::from(true);
+    let id = client
+        .notify(
+            "Music",
+            0,
+            "",
+            "Now playing",
+            "",
+            &["skip", "Skip"],
+            HashMap::from([("resident", &resident)]),
+            0,
+        )
+        .unwrap();
+    next(&events);
+
+    server.invoke_action(id, "skip");
+    let signal = invoked.next().unwrap();
+    let args = signal.args().unwrap();
+    assert_eq!((args.id, args.action_key.as_str()), (id, "skip"));
+    // Resident notifications stay until dismissed
+    assert_eq!(server.open().len(), 1);
+
+    server.dismiss(id);
+    assert_eq!(
+        next(&events),
+        NotificationEvent::Closed {
+            id,
+            reason: CloseReason::Dismissed
+        }
+    );
+    let signal = closed.next().unwrap();
+    assert_eq!(signal.args().unwrap().reason, CloseReason::Dismissed as u32);
+}
+
+#[test]
+fn image_hints() {
+    let bus = Bus::start();
+    let (_server, events) = bus.serve(LONG);
+    let client = bus.client();
+
+    // 2x1 RGB with a padded row
+    let data = Value::from((
+        2i32,
+        1i32,
+        8i32,
+        false,
+        8i32,
+        3i32,
+        vec![1u8, 2, 3, 4, 5, 6, 0, 0],
+    ));
+    let path = Value::from("/usr/share/icons/steam.png");
+    client
+        .notify(
+            "Steam",
+            0,
+            "",
+            "Friend online",
+            "",
+            &[],
+            HashMap::from([("image-data", &data), ("image-path", &path)]),
+            -1,
+        )
+        .unwrap();
+    let NotificationEvent::Show(notification) = next(&events) else {
+        panic!("expected a notification");
+    };
+    assert_eq!(
+        notification.image,
+        Some(NotificationImage::Pixels {
+            width: 2,
+            height: 1,
+            rgba: vec![1, 2, 3, 255, 4, 5, 6, 255],
+        })
+    );
+
+    client
+        .notify(
+            "Steam",
+            0,
+            "",
+            "Friend online",
+            "",
+            &[],
+            HashMap::from([("image-path", &path)]),
+            -1,
+        )
+        .unwrap();
+    let NotificationEvent::Show(notification) = next(&events) else {
+        panic!("expected a notification");
+    };
+    assert_eq!(
+        notification.image,
+        Some(NotificationImage::Name {
+            name: "/usr/share/icons/steam.png".to_owned()
+        })
+    );
+}
+
+#[test]
+fn name_already_owned() {
+    let bus = Bus::start();
+    let (_server, _events) = bus.serve(LONG);
+    let (sender, _) = channel();
+    let options = ServerOptions {
+        default_timeout: LONG,
+        persistence: false,
+    };
+    assert!(NotificationServer::at_address(&bus.address, sender, options).is_err());
+
+    let capabilities = bus.client().get_capabilities().unwrap();
+    assert!(capabilities.contains(&"actions".to_owned()));
+}
+
+#[test]
+fn persistence() {
+    let bus = Bus::start();
+    let (sender, events) = channel();
+    let options = ServerOptions {
+        default_timeout: Duration::from_millis(100),
+        persistence: true,
+    };
+    let server = NotificationServer::at_address(&bus.address, sender, options).unwrap();
+    let client = bus.client();
+    assert!(client
+        .get_capabilities()
+        .unwrap()
+        .contains(&"persistence".to_owned()));
+
+    let transient = Value::from(true);
+    let kept = client
+        .notify("Mail", 0, "", "New mail", "", &[], HashMap::new(), -1)
+        .unwrap();
+    let id = client
+        .notify(
+            "Volume",
+            0,
+            "",
+            "Volume 40%",
+            "",
+            &[],
+            HashMap::from([("transient", &transient)]),
+            -1,
+        )
+        .unwrap();
+    next(&events);
+    let NotificationEvent::Show(notification) = next(&events) else {
+        panic!("expected a notification");
+    };
+    assert!(notification.transient);
+    // Only the transient one expires
+    assert_eq!(
+        next(&events),
+        NotificationEvent::Closed {
+            id,
+            reason: CloseReason::Expired
+        }
+    );
+    assert!(events.recv_timeout(Duration::from_millis(200)).is_err());
+    assert_eq!(server.open()[0].id, kept);
+}
+
+#[test]
+fn restore() {
+    let bus = Bus::start();
+    let (server, _events) = bus.serve(LONG);
+    let client = bus.client();
+    let mut invoked = client.receive_action_invoked().unwrap();
+
+    // As if the daemon restarted with an old notification in its history
+    server.restore([Notification {
+        id: 41,
+        app_name: "Mail".to_owned(),
+        summary: "Old mail".to_owned(),
+        actions: vec![NotificationAction {
+            key: "open".to_owned(),
+            label: "Open".to_owned(),
+        }],
+        ..Default::default()
+    }]);
+    let id = client
+        .notify("Mail", 0, "", "New mail", "", &[], HashMap::new(), 0)
+        .unwrap();
+    assert_eq!(id, 42);
+
+    assert!(server.invoke_action(41, "open"));
+    assert_eq!(invoked.next().unwrap().args().unwrap().id, 41);
+    assert!(!server.invoke_action(41, "open"));
+}
+
+#[test]
+fn image_conversion() {
+    // Too short for the size it claims
+    assert_eq!(
+        NotificationImage::from_raw(2, 2, 8, true, 8, &[0; 12]),
+        None
+    );
+    // Only 8 bits per sample
+    assert_eq!(
+        NotificationImage::from_raw(1, 1, 8, true, 16, &[0; 8]),
+        None
+    );
+    assert_eq!(
+        NotificationImage::from_raw(1, 2, 4, true, 8, &[1, 2, 3, 4, 5, 6, 7, 8]),
+        Some(NotificationImage::Pixels {
+            width: 1,
+            height: 2,
+            rgba: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        })
+    );
+}
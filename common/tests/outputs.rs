@@ -0,0 +1,244 @@
+use consolation_common::outputs::{
+    Confirmation, HeadPlan, Mode, OutputEvent, OutputModel, PlannedMode, Transform,
+};
+use std::time::{Duration, Instant};
+
+// Sends what a compositor would for a head, with modes numbered from
+// `first_mode`
+fn head(
+    model: &mut OutputModel,
+    id: u32,
+    name: &str,
+    modes: &[(i32, i32, i32)],
+    first_mode: u32,
+    current: Option<usize>,
+) {
+    model.handle(OutputEvent::Head { head: id });
+    model.handle(OutputEvent::Name {
+        head: id,
+        name: name.to_owned(),
+    });
+    model.handle(OutputEvent::Description {
+        head: id,
+        description: format!("{} display", name),
+    });
+    for (idx, (width, height, refresh)) in modes.iter().enumerate() {
+        let mode = first_mode + idx as u32;
+        model.handle(OutputEvent::Mode { head: id, mode });
+        model.handle(OutputEvent::ModeSize {
+            mode,
+            width: *width,
+            height: *height,
+        });
+        model.handle(OutputEvent::ModeRefresh {
+            mode,
+            refresh: *refresh,
+        });
+        if idx == 0 {
+            model.handle(OutputEvent::ModePreferred { mode });
+        }
+    }
+    model.handle(OutputEvent::Enabled {
+        head: id,
+        enabled: current.is_some(),
+    });
+    if let Some(current) = current {
+        model.handle(OutputEvent::CurrentMode {
+            head: id,
+            mode: first_mode + current as u32,
+        });
+        model.handle(OutputEvent::Position {
+            head: id,
+            x: 0,
+            y: 0,
+        });
+        model.handle(OutputEvent::Transform {
+            head: id,
+            transform: Transform::Normal,
+        });
+        model.handle(OutputEvent::Scale {
+            head: id,
+            scale: 1.0,
+        });
+    }
+}
+
+// A handheld's built in screen, on its side, with a TV plugged in but off
+fn docked() -> OutputModel {
+    let mut model = OutputModel::new();
+    head(
+        &mut model,
+        1,
+        "eDP-1",
+        &[(800, 1280, 60000), (800, 1280, 40000)],
+        10,
+        Some(0),
+    );
+    model.handle(OutputEvent::Transform {
+        head: 1,
+        transform: Transform::Rotate270,
+    });
+    head(
+        &mut model,
+        2,
+        "HDMI-A-1",
+        &[
+            (3840, 2160, 60000),
+            (1920, 1080, 60000),
+            (1920, 1080, 119880),
+            (3840, 2160, 30000),
+        ],
+        20,
+        None,
+    );
+    model.handle(OutputEvent::Done { serial: 7 });
+    model
+}
+
+#[test]
+fn heads_listed() {
+    let model = docked();
+    assert_eq!(model.serial(), Some(7));
+    let heads = model.heads();
+    assert_eq!(heads.len(), 2);
+
+    let screen = &heads[0];
+    assert_eq!(screen.name, "eDP-1");
+    assert!(screen.enabled);
+    assert_eq!(screen.transform, Transform::Rotate270);
+    assert_eq!(screen.current_mode.map(|mode| mode.refresh), Some(60000));
+    assert_eq!(
+        screen.config().logical_size(),
+        Some((1280, 800)),
+        "rotated on its side"
+    );
+
+    let tv = &heads[1];
+    assert!(!tv.enabled);
+    assert_eq!(tv.current_mode, None);
+    assert_eq!(tv.resolutions(), vec![(3840, 2160), (1920, 1080)]);
+    let rates: Vec<_> = tv
+        .modes_sized(1920, 1080)
+        .iter()
+        .map(|mode| mode.refresh_label())
+        .collect();
+    assert_eq!(rates, vec!["119.88 Hz", "60 Hz"]);
+    assert_eq!(tv.preferred_mode().map(|mode| mode.width), Some(3840));
+}
+
+#[test]
+fn unplugged_heads_go() {
+    let mut model = docked();
+    model.handle(OutputEvent::HeadFinished { head: 2 });
+    model.handle(OutputEvent::Done { serial: 8 });
+    let names: Vec<_> = model.heads().into_iter().map(|head| head.name).collect();
+    assert_eq!(names, vec!["eDP-1"]);
+    assert_eq!(model.serial(), Some(8));
+}
+
+#[test]
+fn plan_docking() {
+    let model = docked();
+    let heads = model.heads();
+
+    // TV on at 1080p120, to the right of the screen, screen scaled up
+    let mut screen = heads[0].config();
+    screen.scale = 1.5;
+    let mut tv = heads[1].config();
+    tv.enabled = true;
+    tv.mode = Some(Mode {
+        width: 1920,
+        height: 1080,
+        refresh: 119880,
+        preferred: false,
+    });
+    tv.position = (853, 0);
+
+    let plan = model.plan(&[screen, tv]).unwrap();
+    assert_eq!(
+        plan,
+        vec![
+            HeadPlan::Enable {
+                head: 1,
+                mode: Some(PlannedMode::Existing(10)),
+                position: (0, 0),
+                transform: Transform::Rotate270,
+                scale: 1.5,
+            },
+            HeadPlan::Enable {
+                head: 2,
+                mode: Some(PlannedMode::Existing(22)),
+                position: (853, 0),
+                transform: Transform::Normal,
+                scale: 1.0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn plan_keeps_other_heads() {
+    let model = docked();
+    let mut screen = model.heads()[0].config();
+    // Not something the screen lists
+    let custom = Mode {
+        width: 800,
+        height: 1280,
+        refresh: 50000,
+        preferred: false,
+    };
+    screen.mode = Some(custom);
+
+    let plan = model.plan(&[screen]).unwrap();
+    assert_eq!(plan.len(), 2);
+    assert!(matches!(
+        plan[0],
+        HeadPlan::Enable {
+            mode: Some(PlannedMode::Custom(mode)),
+            ..
+        } if mode == custom
+    ));
+    assert_eq!(plan[1], HeadPlan::Disable { head: 2 });
+}
+
+#[test]
+fn plan_refuses() {
+    let model = docked();
+    let mut screen = model.heads()[0].config();
+    screen.enabled = false;
+    assert_eq!(
+        model.plan(&[screen.clone()]),
+        Err("At least one display has to stay on".to_owned())
+    );
+
+    screen.name = "DP-3".to_owned();
+    assert_eq!(
+        model.plan(&[screen]),
+        Err("DP-3 isn't connected".to_owned())
+    );
+}
+
+#[test]
+fn confirmation_counts_down() {
+    let now = Instant::now();
+    let previous = docked().heads().iter().map(|head| head.config()).collect();
+    let confirmation = Confirmation::new(previous, Duration::from_secs(15), now);
+    assert_eq!(confirmation.previous().len(), 2);
+    assert_eq!(confirmation.seconds_left(now), 15);
+    assert_eq!(
+        confirmation.seconds_left(now + Duration::from_millis(14100)),
+        1
+    );
+    assert!(!confirmation.expired(now + Duration::from_millis(14900)));
+    assert!(confirmation.expired(now + Duration::from_secs(15)));
+    assert_eq!(confirmation.seconds_left(now + Duration::from_secs(20)), 0);
+}
+
+#[test]
+fn transforms_on_the_wire() {
+    for transform in Transform::ALL {
+        assert_eq!(Transform::from_wire(transform.to_wire()), transform);
+    }
+    assert_eq!(Transform::from_wire(3), Transform::Rotate270);
+    assert_eq!(Transform::from_wire(99), Transform::Normal);
+}
@@ -0,0 +1,33 @@
+use consolation_common::pin::{self, Backoff, MAX_DELAY, WRONG_DELAY};
+use std::time::{Duration, Instant};
+
+#[test]
+fn hashed_pins_verify() {
+    let stored = pin::hash("2468");
+    assert!(stored.starts_with("sha256:"));
+    assert!(pin::verify(&stored, "2468"));
+    assert!(!pin::verify(&stored, "1357"));
+    assert!(!pin::verify("plain:2468", "2468"));
+}
+
+#[test]
+fn backoff_doubles_up_to_the_limit() {
+    let start = Instant::now();
+    let mut backoff = Backoff::default();
+    assert_eq!(backoff.wait(start), None);
+
+    assert_eq!(backoff.failed(start), WRONG_DELAY);
+    assert_eq!(backoff.wait(start), Some(WRONG_DELAY));
+    assert_eq!(backoff.wait(start + WRONG_DELAY), None);
+
+    assert_eq!(backoff.failed(start), WRONG_DELAY * 2);
+    assert_eq!(backoff.failed(start), WRONG_DELAY * 4);
+    for _ in 0..20 {
+        backoff.failed(start);
+    }
+    assert_eq!(backoff.wait(start), Some(MAX_DELAY));
+
+    backoff.succeeded();
+    assert_eq!(backoff.wait(start), None);
+    assert_eq!(backoff.failed(start + Duration::from_secs(1)), WRONG_DELAY);
+}
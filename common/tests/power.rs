@@ -0,0 +1,401 @@
+use consolation_common::power::{
+    battery_icon, BatteryLevel, BatteryStatus, CapacityLevel, ControllerMonitor, ControllerWarning,
+    PowerMonitor, PowerSource, PowerStatus, PowerThresholds, PowerWarning, SupplyKind, SysfsPower,
+};
+
+mod support;
+use support::TempTree;
+
+// Supplies in a fake /sys/class/power_supply, one file per attribute
+impl TempTree {
+    fn supply(&self, name: &str, attributes: &[(&str, &str)]) -> &TempTree {
+        let dir = self.path().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (attribute, value) in attributes {
+            // The kernel ends every value with a newline
+            std::fs::write(dir.join(attribute), format!("{}\n", value)).unwrap();
+        }
+        self
+    }
+
+    // The HID device a supply belongs to
+    fn hid_device(&self, name: &str, hid_name: &str) -> &TempTree {
+        let dir = self.path().join(name).join("device");
+        std::fs::create_dir_all(&dir).unwrap();
+        let uevent = format!(
+            "DRIVER=playstation\nHID_ID=0005:0000054C:00000CE6\nHID_NAME={}\n",
+            hid_name
+        );
+        std::fs::write(dir.join("uevent"), uevent).unwrap();
+        self
+    }
+}
+
+fn battery(percentage: u8, status: BatteryStatus) -> Option<BatteryLevel> {
+    Some(BatteryLevel {
+        percentage,
+        status,
+        charge_start: None,
+        charge_end: None,
+    })
+}
+
+fn discharging(percentage: u8) -> PowerStatus {
+    PowerStatus {
+        ac_online: Some(false),
+        battery: battery(percentage, BatteryStatus::Discharging),
+    }
+}
+
+fn charging(percentage: u8) -> PowerStatus {
+    PowerStatus {
+        ac_online: Some(true),
+        battery: battery(percentage, BatteryStatus::Charging),
+    }
+}
+
+#[test]
+fn laptop() {
+    let sysfs = TempTree::new("power", "laptop");
+    sysfs
+        .supply("AC", &[("type", "Mains"), ("online", "0")])
+        .supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("present", "1"),
+                ("status", "Discharging"),
+                ("capacity", "42"),
+                ("model_name", "5B10W13975"),
+            ],
+        );
+    let power = SysfsPower::new(sysfs.path());
+
+    let supplies = power.supplies().unwrap();
+    assert_eq!(supplies.len(), 2);
+    assert_eq!(supplies[0].kind, SupplyKind::Mains);
+    assert_eq!(supplies[1].name, "BAT0");
+    assert_eq!(supplies[1].model_name.as_deref(), Some("5B10W13975"));
+
+    let status = power.status().unwrap();
+    assert_eq!(status, discharging(42));
+    assert!(status.on_battery());
+}
+
+#[test]
+fn batteries_combine_by_size() {
+    // A small internal battery nearly empty, a big external one full. The
+    // controller's battery doesn't count
+    let sysfs = TempTree::new("power", "combine");
+    sysfs
+        .supply("AC", &[("type", "Mains"), ("online", "1")])
+        .supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("status", "Charging"),
+                ("capacity", "10"),
+                ("energy_now", "2000000"),
+                ("energy_full", "20000000"),
+            ],
+        )
+        .supply(
+            "BAT1",
+            &[
+                ("type", "Battery"),
+                ("status", "Full"),
+                ("capacity", "100"),
+                ("energy_now", "60000000"),
+                ("energy_full", "60000000"),
+            ],
+        )
+        .supply(
+            "ps-controller-battery-a0:ab:51:00:00:01",
+            &[
+                ("type", "Battery"),
+                ("scope", "Device"),
+                ("status", "Discharging"),
+                ("capacity", "3"),
+            ],
+        );
+
+    let status = SysfsPower::new(sysfs.path()).status().unwrap();
+    assert_eq!(status, charging(77));
+}
+
+#[test]
+fn desktop() {
+    let sysfs = TempTree::new("power", "desktop");
+    sysfs.supply(
+        "hidpp_battery_0",
+        &[("type", "Battery"), ("scope", "Device"), ("capacity", "50")],
+    );
+
+    let status = SysfsPower::new(sysfs.path()).status().unwrap();
+    assert_eq!(status, PowerStatus::default());
+    assert!(!status.on_battery());
+    assert!(SysfsPower::new(sysfs.path().join("missing"))
+        .status()
+        .is_err());
+}
+
+#[test]
+fn low_warnings_once_each() {
+    let mut monitor = PowerMonitor::new(PowerThresholds {
+        low: vec![20, 10],
+        critical: 5,
+    });
+    let mut warnings = vec![];
+    for percentage in [25, 21, 20, 19, 15, 11, 10, 9, 6, 5, 4, 3] {
+        warnings.extend(monitor.update(&discharging(percentage)));
+    }
+    assert_eq!(
+        warnings,
+        [
+            PowerWarning::Low { percentage: 20 },
+            PowerWarning::Low { percentage: 10 },
+            PowerWarning::Critical { percentage: 5 },
+        ]
+    );
+
+    // Plugging in re-arms them
+    assert_eq!(monitor.update(&charging(6)), []);
+    assert_eq!(
+        monitor.update(&discharging(5)),
+        [PowerWarning::Critical { percentage: 5 }]
+    );
+}
+
+#[test]
+fn starting_low_warns_for_the_lowest() {
+    let mut monitor = PowerMonitor::new(PowerThresholds::default());
+    assert_eq!(
+        monitor.update(&discharging(8)),
+        [PowerWarning::Low { percentage: 8 }]
+    );
+    assert_eq!(monitor.update(&discharging(7)), []);
+
+    let mut monitor = PowerMonitor::new(PowerThresholds::default());
+    assert_eq!(
+        monitor.update(&discharging(2)),
+        [PowerWarning::Critical { percentage: 2 }]
+    );
+    assert_eq!(monitor.update(&discharging(1)), []);
+}
+
+#[test]
+fn plugged_in_not_charging() {
+    let mut monitor = PowerMonitor::new(PowerThresholds::default());
+    let not_charging = |percentage, status| PowerStatus {
+        ac_online: Some(true),
+        battery: battery(percentage, status),
+    };
+
+    assert_eq!(monitor.update(&charging(50)), []);
+    assert_eq!(
+        monitor.update(&not_charging(50, BatteryStatus::NotCharging)),
+        [PowerWarning::NotCharging { percentage: 50 }]
+    );
+    assert_eq!(
+        monitor.update(&not_charging(49, BatteryStatus::NotCharging)),
+        []
+    );
+    // An adapter too weak to keep up is still draining the battery
+    assert_eq!(monitor.update(&charging(49)), []);
+    assert_eq!(
+        monitor.update(&not_charging(48, BatteryStatus::Discharging)),
+        [PowerWarning::NotCharging { percentage: 48 }]
+    );
+
+    // Sitting just under full, or under the firmware's charge limit, is fine
+    let mut monitor = PowerMonitor::new(PowerThresholds::default());
+    assert_eq!(
+        monitor.update(&not_charging(98, BatteryStatus::NotCharging)),
+        []
+    );
+    let limited = PowerStatus {
+        ac_online: Some(true),
+        battery: Some(BatteryLevel {
+            percentage: 78,
+            status: BatteryStatus::NotCharging,
+            charge_start: Some(75),
+            charge_end: Some(80),
+        }),
+    };
+    assert_eq!(monitor.update(&limited), []);
+}
+
+#[test]
+fn end_threshold_alone() {
+    let sysfs = TempTree::new("power", "end");
+    sysfs
+        .supply("ADP1", &[("type", "Mains"), ("online", "1")])
+        .supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("status", "Not charging"),
+                ("capacity", "77"),
+                ("charge_control_end_threshold", "80"),
+            ],
+        );
+
+    // Not mistaken for a start threshold
+    let status = SysfsPower::new(sysfs.path()).status().unwrap();
+    let battery = status.battery.unwrap();
+    assert_eq!(battery.charge_start, None);
+    assert_eq!(battery.charge_end, Some(80));
+    // Drifting a little under the end is normal
+    let mut monitor = PowerMonitor::new(PowerThresholds::default());
+    assert_eq!(monitor.update(&status), []);
+
+    // Well under it isn't
+    let low = PowerStatus {
+        ac_online: Some(true),
+        battery: Some(BatteryLevel {
+            percentage: 60,
+            ..battery
+        }),
+    };
+    assert_eq!(
+        monitor.update(&low),
+        [PowerWarning::NotCharging { percentage: 60 }]
+    );
+}
+
+#[test]
+fn charge_limit_from_sysfs() {
+    let sysfs = TempTree::new("power", "limit");
+    sysfs
+        .supply("ADP1", &[("type", "Mains"), ("online", "1")])
+        .supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("status", "Not charging"),
+                ("capacity", "80"),
+                ("charge_control_start_threshold", "75"),
+                ("charge_control_end_threshold", "80"),
+            ],
+        );
+
+    let status = SysfsPower::new(sysfs.path()).status().unwrap();
+    assert_eq!(
+        status.battery,
+        Some(BatteryLevel {
+            percentage: 80,
+            status: BatteryStatus::NotCharging,
+            charge_start: Some(75),
+            charge_end: Some(80),
+        })
+    );
+    let mut monitor = PowerMonitor::new(PowerThresholds::default());
+    assert_eq!(monitor.update(&status), []);
+}
+
+fn controllers() -> TempTree {
+    let sysfs = TempTree::new("power", "controllers");
+    sysfs
+        .supply("BAT0", &[("type", "Battery"), ("capacity", "80")])
+        .supply(
+            "ps-controller-battery-a0:ab:51:00:00:01",
+            &[
+                ("type", "Battery"),
+                ("scope", "Device"),
+                ("status", "Discharging"),
+                ("capacity", "45"),
+            ],
+        )
+        .hid_device(
+            "ps-controller-battery-a0:ab:51:00:00:01",
+            "Sony Interactive Entertainment Wireless Controller",
+        )
+        // xpadneo only knows roughly how full it is
+        .supply(
+            "xpadneo_batt_98:7a:14:00:00:02_0",
+            &[
+                ("type", "Battery"),
+                ("scope", "Device"),
+                ("status", "Charging"),
+                ("capacity_level", "Low"),
+                ("model_name", "Xbox Wireless Controller"),
+            ],
+        )
+        // A mouse isn't a controller
+        .supply(
+            "hidpp_battery_0",
+            &[
+                ("type", "Battery"),
+                ("scope", "Device"),
+                ("status", "Discharging"),
+                ("capacity", "5"),
+            ],
+        );
+    sysfs
+}
+
+#[test]
+fn controllers_listed() {
+    let sysfs = controllers();
+    let controllers = SysfsPower::new(sysfs.path()).controllers().unwrap();
+    let labels: Vec<_> = controllers.iter().map(|c| c.label()).collect();
+    assert_eq!(
+        labels,
+        [
+            "Sony Interactive Entertainment Wireless Controller",
+            "Xbox Wireless Controller",
+        ]
+    );
+    assert_eq!(controllers[0].capacity, Some(45));
+    assert!(!controllers[0].is_charging());
+    assert_eq!(controllers[1].capacity, None);
+    assert_eq!(controllers[1].capacity_level, Some(CapacityLevel::Low));
+    assert!(controllers[1].is_charging());
+    assert_eq!(controllers[0].icon(), "battery-level-50-symbolic");
+    assert_eq!(controllers[1].icon(), "battery-level-20-charging-symbolic");
+}
+
+#[test]
+fn controller_warnings() {
+    let sysfs = controllers();
+    let power = SysfsPower::new(sysfs.path());
+    let mut monitor = ControllerMonitor::new(50);
+
+    let warning = ControllerWarning {
+        name: "ps-controller-battery-a0:ab:51:00:00:01".to_owned(),
+        label: "Sony Interactive Entertainment Wireless Controller".to_owned(),
+        percentage: Some(45),
+    };
+    assert_eq!(
+        monitor.update(&power.controllers().unwrap()),
+        std::slice::from_ref(&warning)
+    );
+    assert_eq!(monitor.update(&power.controllers().unwrap()), []);
+
+    // Unplugging the Xbox controller's cable leaves it low
+    sysfs.supply(
+        "xpadneo_batt_98:7a:14:00:00:02_0",
+        &[("status", "Discharging")],
+    );
+    let warnings = monitor.update(&power.controllers().unwrap());
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].label, "Xbox Wireless Controller");
+    assert_eq!(warnings[0].percentage, None);
+
+    // Reconnecting warns again
+    assert_eq!(monitor.update(&[]), []);
+    let warnings = monitor.update(&power.controllers().unwrap());
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0], warning);
+}
+
+#[test]
+fn battery_icons() {
+    assert_eq!(battery_icon(0, false), "battery-level-0-symbolic");
+    assert_eq!(battery_icon(44, false), "battery-level-40-symbolic");
+    assert_eq!(battery_icon(45, true), "battery-level-50-charging-symbolic");
+    assert_eq!(
+        battery_icon(100, true),
+        "battery-level-100-charging-symbolic"
+    );
+}
@@ -0,0 +1,252 @@
+use consolation_common::process::{self, ProcFs, ProcessInfo};
+use std::collections::HashMap;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+// A fake /proc, removed when dropped
+struct FakeProc {
+    root: PathBuf,
+}
+
+impl FakeProc {
+    fn new(name: &str) -> FakeProc {
+        let root =
+            std::env::temp_dir().join(format!("consolation-proc-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        FakeProc { root }
+    }
+
+    fn process(&self, pid: i32, ppid: i32, comm: &str, argv: &[&str]) -> &FakeProc {
+        self.process_with_env(pid, ppid, comm, argv, &[])
+    }
+
+    fn process_with_env(
+        &self,
+        pid: i32,
+        ppid: i32,
+        comm: &str,
+        argv: &[&str],
+        env: &[&str],
+    ) -> &FakeProc {
+        let dir = self.root.join(pid.to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("comm"), format!("{}\n", comm)).unwrap();
+        let stat = format!("{} ({}) S {} {} {} 0 -1", pid, comm, ppid, pid, pid);
+        std::fs::write(dir.join("stat"), stat).unwrap();
+        std::fs::write(dir.join("cmdline"), nul_separated(argv)).unwrap();
+        std::fs::write(dir.join("environ"), nul_separated(env)).unwrap();
+        self
+    }
+
+    // A socket:[<inode>] link in the process's fd directory
+    fn socket(&self, pid: i32, fd: i32, inode: u64) -> &FakeProc {
+        let dir = self.root.join(pid.to_string()).join("fd");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::os::unix::fs::symlink(format!("socket:[{}]", inode), dir.join(fd.to_string()))
+            .unwrap();
+        self
+    }
+
+    fn procfs(&self) -> ProcFs {
+        ProcFs::new(&self.root)
+    }
+}
+
+impl Drop for FakeProc {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+fn nul_separated(values: &[&str]) -> Vec<u8> {
+    let mut raw = vec![];
+    for value in values {
+        raw.extend_from_slice(value.as_bytes());
+        raw.push(0);
+    }
+    raw
+}
+
+fn sorted<T: Ord>(mut values: Vec<T>) -> Vec<T> {
+    values.sort();
+    values
+}
+
+#[test]
+fn stat_is_parsed_from_the_last_bracket() {
+    let proc = FakeProc::new("stat");
+    proc.process(100, 1, "Web Content (x)", &["firefox"]);
+    let dir = proc.root.join("101");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("stat"), "101 (game) Z 100 101 101 0 -1").unwrap();
+    let procfs = proc.procfs();
+
+    assert_eq!(procfs.ppid(100), Some(1));
+    assert_eq!(procfs.state(100), Some('S'));
+    assert_eq!(procfs.comm(100).as_deref(), Some("Web Content (x)"));
+    assert!(procfs.is_alive(100));
+    assert_eq!(procfs.ppid(101), Some(100));
+    assert_eq!(procfs.state(101), Some('Z'));
+    assert!(!procfs.is_alive(101));
+    assert_eq!(procfs.ppid(102), None);
+    assert!(!procfs.is_alive(102));
+}
+
+#[test]
+fn matches_by_comm_argv0_and_flatpak_id() {
+    let proc = FakeProc::new("names");
+    proc.process(10, 1, "foot", &["foot"])
+        .process(
+            11,
+            1,
+            "wrapper",
+            &["/usr/lib/supertux/supertux2", "--fullscreen"],
+        )
+        .process_with_env(12, 1, "bwrap", &["bwrap"], &["FLATPAK_ID=org.example.Game"]);
+    let procfs = proc.procfs();
+
+    assert!(procfs.matches_app_id(10, "foot"));
+    assert!(procfs.matches_app_id(10, "Foot"));
+    assert!(procfs.matches_app_id(11, "supertux2"));
+    assert!(procfs.matches_app_id(12, "org.example.Game"));
+    assert!(!procfs.matches_app_id(10, "supertux2"));
+    assert!(!procfs.matches_app_id(12, "org.example.Other"));
+    // Only exact names count, org.example.Foot isn't foot
+    assert!(!procfs.matches_app_id(10, "org.example.Foot"));
+}
+
+#[test]
+fn describe_prefers_the_command_line() {
+    let proc = FakeProc::new("describe");
+    proc.process(15, 1, "supertux2", &["/usr/bin/supertux2", "--fullscreen"])
+        .process(16, 1, "kworker", &[]);
+    let procfs = proc.procfs();
+
+    assert_eq!(
+        procfs.describe(15),
+        ProcessInfo {
+            pid: 15,
+            command: "/usr/bin/supertux2 --fullscreen".to_owned(),
+        }
+    );
+    assert_eq!(procfs.describe(16).command, "kworker");
+}
+
+#[test]
+fn steam_games_match_by_steam_app_id() {
+    let proc = FakeProc::new("steam");
+    proc.process_with_env(20, 1, "game.exe", &["game.exe"], &["SteamAppId=620"])
+        .process_with_env(21, 1, "other.exe", &["other.exe"], &["SteamAppId=400"])
+        .process(22, 1, "steam_app_620", &["steam_app_620"]);
+    let procfs = proc.procfs();
+
+    assert!(procfs.matches_app_id(20, "steam_app_620"));
+    assert!(!procfs.matches_app_id(21, "steam_app_620"));
+    // Only the environment counts for Steam, not the name
+    assert!(!procfs.matches_app_id(22, "steam_app_620"));
+    assert_eq!(procfs.find_by_app_id("steam_app_620"), vec![20]);
+}
+
+#[test]
+fn find_by_app_id_keeps_only_the_top_of_each_tree() {
+    let proc = FakeProc::new("find");
+    proc.process(30, 1, "supertux2", &["supertux2"])
+        .process(31, 30, "supertux2", &["supertux2"])
+        .process(32, 1, "supertux2", &["supertux2"])
+        .process(33, 1, "foot", &["foot"]);
+    let procfs = proc.procfs();
+
+    assert_eq!(sorted(procfs.find_by_app_id("supertux2")), vec![30, 32]);
+    assert!(procfs.find_by_app_id("missing").is_empty());
+}
+
+#[test]
+fn app_tree_includes_every_descendant() {
+    let proc = FakeProc::new("tree");
+    proc.process(40, 1, "launcher", &["launcher"])
+        .process(41, 40, "game", &["game"])
+        .process(42, 41, "helper", &["helper"])
+        .process(43, 41, "crashpad", &["crashpad"])
+        .process(44, 1, "unrelated", &["unrelated"]);
+    let procfs = proc.procfs();
+
+    assert_eq!(sorted(procfs.tree(41)), vec![41, 42, 43]);
+    let tree = procfs.app_tree("game");
+    assert_eq!(tree[0], 41);
+    assert_eq!(sorted(tree), vec![41, 42, 43]);
+    assert!(procfs.app_tree("missing").is_empty());
+}
+
+#[test]
+fn clients_are_the_peers_of_the_servers_sockets() {
+    let proc = FakeProc::new("clients");
+    proc.process(50, 1, "sway", &["sway"])
+        .socket(50, 3, 1000)
+        .socket(50, 4, 1001)
+        .socket(50, 5, 1002)
+        .process(51, 1, "foot", &["foot"])
+        .socket(51, 3, 2000)
+        .process(52, 1, "game", &["game"])
+        .socket(52, 3, 2001)
+        .process(53, 1, "pipewire", &["pipewire"])
+        .socket(53, 3, 3000);
+    // 1002 is a listening socket, and pipewire's peer belongs to nobody
+    let peers = HashMap::from([
+        (1000, 2000),
+        (2000, 1000),
+        (1001, 2001),
+        (2001, 1001),
+        (3000, 4000),
+    ]);
+    let procfs = proc.procfs();
+
+    assert_eq!(sorted(procfs.socket_inodes(50)), vec![1000, 1001, 1002]);
+    assert_eq!(procfs.clients_of(50, &peers), vec![51, 52]);
+    assert!(procfs.clients_of(53, &peers).is_empty());
+}
+
+#[test]
+fn owners_come_from_clients_before_names() {
+    let proc = FakeProc::new("owners");
+    // A client whose short name matches, and a stranger whose full name does
+    proc.process(60, 1, "game", &["/app/bin/game"])
+        .process(61, 60, "game", &["/app/bin/game", "--child"])
+        .process(62, 1, "game", &["game"])
+        .process(63, 1, "org.example.game", &["org.example.game"])
+        .process(64, 1, "foot", &["foot"]);
+    let procfs = proc.procfs();
+
+    let owners = procfs.owners("org.example.Game", &[60, 61, 64]);
+    assert_eq!(owners.pids, vec![60]);
+    assert!(!owners.by_name);
+
+    // Not one of the clients, so only the exact name will do. The short
+    // name would have found 60 and 62 as well
+    let owners = procfs.owners("org.example.Game", &[64]);
+    assert_eq!(owners.pids, vec![63]);
+    assert!(owners.by_name);
+
+    let owners = procfs.owners("missing", &[60, 64]);
+    assert!(owners.pids.is_empty());
+    assert!(owners.by_name);
+}
+
+#[test]
+fn unix_peers_pairs_connected_sockets() {
+    let (a, b) = UnixStream::pair().unwrap();
+    let inode = |stream: &UnixStream| -> u64 {
+        let link = std::fs::read_link(format!("/proc/self/fd/{}", stream.as_raw_fd())).unwrap();
+        let link = link.to_str().unwrap();
+        link.strip_prefix("socket:[")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    let peers = process::unix_peers().unwrap();
+
+    assert_eq!(peers.get(&inode(&a)), Some(&inode(&b)));
+    assert_eq!(peers.get(&inode(&b)), Some(&inode(&a)));
+}
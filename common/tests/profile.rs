@@ -0,0 +1,158 @@
+use consolation_common::config::ShellConfig;
+use consolation_common::launcher::profile::{steam_root, LaunchCommand, LaunchProfile};
+use consolation_common::launcher::{run_prompt_command, LauncherEntry, LauncherSource};
+use std::collections::BTreeMap;
+
+fn argv(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+fn env(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn no_profiles() {
+    let command = LaunchCommand::with_profiles(argv(&["supertux2", "--fullscreen"]), &[]);
+    assert_eq!(
+        command,
+        LaunchCommand {
+            env: BTreeMap::new(),
+            argv: argv(&["supertux2", "--fullscreen"]),
+        }
+    );
+}
+
+// The first profile's wrapper is outermost, and later profiles win on env
+#[test]
+fn profiles_in_order() {
+    let gamescope = LaunchProfile {
+        env: env(&[("DXVK_HUD", "0")]),
+        wrapper: argv(&["gamescope", "-f", "--"]),
+        args: argv(&["-windowed"]),
+        ..Default::default()
+    };
+    let mangohud = LaunchProfile {
+        env: env(&[("DXVK_HUD", "fps"), ("MANGOHUD", "1")]),
+        wrapper: argv(&["mangohud"]),
+        ..Default::default()
+    };
+    let command =
+        LaunchCommand::with_profiles(argv(&["game", "--level", "2"]), &[&gamescope, &mangohud]);
+    assert_eq!(
+        command,
+        LaunchCommand {
+            env: env(&[("DXVK_HUD", "fps"), ("MANGOHUD", "1")]),
+            argv: argv(&[
+                "gamescope",
+                "-f",
+                "--",
+                "mangohud",
+                "game",
+                "--level",
+                "2",
+                "-windowed"
+            ]),
+        }
+    );
+    assert_eq!(
+        command.preview(),
+        "DXVK_HUD=fps MANGOHUD=1 gamescope -f -- mangohud game --level 2 -windowed"
+    );
+}
+
+// Proton goes inside the wrappers, with its prefix and Steam's install
+#[test]
+fn proton() {
+    let wrapper = LaunchProfile {
+        wrapper: argv(&["gamemoderun"]),
+        ..Default::default()
+    };
+    let proton = LaunchProfile {
+        proton: Some("/opt/proton/GE-Proton9/proton".to_owned()),
+        proton_prefix: Some("/games/prefixes/setup".to_owned()),
+        ..Default::default()
+    };
+    let command =
+        LaunchCommand::with_profiles(argv(&["/games/Setup Wizard.exe"]), &[&wrapper, &proton]);
+    assert_eq!(
+        command.argv,
+        argv(&[
+            "gamemoderun",
+            "/opt/proton/GE-Proton9/proton",
+            "run",
+            "/games/Setup Wizard.exe"
+        ])
+    );
+    let install = steam_root().to_string_lossy().into_owned();
+    assert_eq!(
+        command.env,
+        env(&[
+            ("STEAM_COMPAT_CLIENT_INSTALL_PATH", &install),
+            ("STEAM_COMPAT_DATA_PATH", "/games/prefixes/setup"),
+        ])
+    );
+    assert!(command
+        .preview()
+        .ends_with("/opt/proton/GE-Proton9/proton run '/games/Setup Wizard.exe'"));
+}
+
+// A profile's own env is kept over what Proton would set
+#[test]
+fn proton_env_kept() {
+    let proton = LaunchProfile {
+        env: env(&[("STEAM_COMPAT_DATA_PATH", "/elsewhere")]),
+        proton: Some("/opt/proton/GE-Proton9/proton".to_owned()),
+        proton_prefix: Some("/games/prefixes/setup".to_owned()),
+        ..Default::default()
+    };
+    let command = LaunchCommand::with_profiles(argv(&["setup.exe"]), &[&proton]);
+    assert_eq!(command.env["STEAM_COMPAT_DATA_PATH"], "/elsewhere");
+}
+
+// Entries are matched on their ID, then on the program's name
+#[test]
+fn assigned_profiles() {
+    let config: ShellConfig = toml::from_str(
+        r#"
+[profiles.gamemode]
+wrapper = ["gamemoderun"]
+
+[profiles.hud]
+env = { MANGOHUD = "1" }
+
+[launcher.assign]
+"org.supertux.SuperTux.desktop" = ["gamemode"]
+"supertux2" = ["hud"]
+"foot" = ["hud"]
+"#,
+    )
+    .unwrap();
+    let entry = |id: &str, exec: &[&str]| LauncherEntry {
+        id: id.to_owned(),
+        name: id.to_owned(),
+        icon: None,
+        categories: vec![],
+        exec: argv(exec),
+        source: LauncherSource::Desktop,
+    };
+    assert_eq!(
+        entry("org.supertux.SuperTux.desktop", &["/usr/bin/supertux2"])
+            .command(&config)
+            .argv,
+        argv(&["gamemoderun", "/usr/bin/supertux2"])
+    );
+    assert_eq!(
+        entry("supertux-nightly", &["/usr/bin/supertux2"])
+            .command(&config)
+            .env,
+        env(&[("MANGOHUD", "1")])
+    );
+    let typed = run_prompt_command("/usr/bin/foot -e 'htop -d 5'", &config).unwrap();
+    assert_eq!(typed.argv, argv(&["/usr/bin/foot", "-e", "htop -d 5"]));
+    assert_eq!(typed.env, env(&[("MANGOHUD", "1")]));
+    assert!(run_prompt_command("foot 'unclosed", &config).is_none());
+}
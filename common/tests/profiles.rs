@@ -0,0 +1,129 @@
+use consolation_common::profiles::{profile_label, PowerProfiles, PowerProfilesDaemon};
+use std::collections::HashMap;
+use zbus::blocking;
+use zbus::zvariant::{OwnedValue, Str};
+
+mod support;
+use support::Bus;
+
+// power-profiles-daemon, without the profiles doing anything
+struct FakeDaemon {
+    active: String,
+    profiles: Vec<&'static str>,
+}
+
+impl FakeDaemon {
+    fn new(profiles: &[&'static str]) -> FakeDaemon {
+        FakeDaemon {
+            active: "balanced".to_owned(),
+            profiles: profiles.to_vec(),
+        }
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.UPower.PowerProfiles")]
+impl FakeDaemon {
+    #[zbus(property)]
+    fn active_profile(&self) -> String {
+        self.active.clone()
+    }
+
+    #[zbus(property)]
+    fn set_active_profile(&mut self, profile: String) -> zbus::fdo::Result<()> {
+        if !self.profiles.contains(&profile.as_str()) {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "Invalid profile name '{}'",
+                profile
+            )));
+        }
+        self.active = profile;
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn profiles(&self) -> Vec<HashMap<String, OwnedValue>> {
+        self.profiles
+            .iter()
+            .map(|profile| {
+                HashMap::from([
+                    ("Profile".to_owned(), OwnedValue::from(Str::from(*profile))),
+                    (
+                        "Driver".to_owned(),
+                        OwnedValue::from(Str::from("placeholder")),
+                    ),
+                ])
+            })
+            .collect()
+    }
+}
+
+// Only differs in name, as served by versions before 0.20
+struct LegacyDaemon(FakeDaemon);
+
+#[zbus::interface(name = "net.hadess.PowerProfiles")]
+impl LegacyDaemon {
+    #[zbus(property)]
+    fn active_profile(&self) -> String {
+        self.0.active.clone()
+    }
+}
+
+#[test]
+fn switch_profiles() {
+    let bus = Bus::start();
+    let _daemon = blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name("org.freedesktop.UPower.PowerProfiles")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/UPower/PowerProfiles",
+            FakeDaemon::new(&["power-saver", "balanced", "performance"]),
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let profiles = PowerProfilesDaemon::at_address(&bus.address).unwrap();
+    assert_eq!(
+        profiles.profiles().unwrap(),
+        vec!["power-saver", "balanced", "performance"]
+    );
+    assert_eq!(profiles.active().unwrap(), "balanced");
+    profiles.set_active("performance").unwrap();
+    assert_eq!(profiles.active().unwrap(), "performance");
+    assert!(profiles.set_active("turbo").is_err());
+    assert_eq!(profiles.active().unwrap(), "performance");
+}
+
+#[test]
+fn legacy_name() {
+    let bus = Bus::start();
+    let _daemon = blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name("net.hadess.PowerProfiles")
+        .unwrap()
+        .serve_at(
+            "/net/hadess/PowerProfiles",
+            LegacyDaemon(FakeDaemon::new(&["balanced"])),
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let profiles = PowerProfilesDaemon::at_address(&bus.address).unwrap();
+    assert_eq!(profiles.active().unwrap(), "balanced");
+}
+
+#[test]
+fn no_daemon() {
+    let bus = Bus::start();
+    assert!(PowerProfilesDaemon::at_address(&bus.address).is_err());
+}
+
+#[test]
+fn labels() {
+    assert_eq!(profile_label("power-saver"), "Power saver");
+    assert_eq!(profile_label("performance"), "Performance");
+    assert_eq!(profile_label("low-latency"), "Low latency");
+    assert_eq!(profile_label(""), "");
+}
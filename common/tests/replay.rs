@@ -0,0 +1,92 @@
+use consolation_common::record::{self, Recorder};
+use consolation_common::{ToplevelChange, ToplevelEvent, ToplevelState};
+use std::path::{Path, PathBuf};
+
+fn session(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/sessions")
+        .join(name)
+}
+
+#[test]
+fn replay_steam_launch() {
+    let events = record::load(&session("steam-launch.jsonl")).unwrap();
+    assert_eq!(events.len(), 22);
+
+    let mut changes = vec![];
+    let model = record::replay(&events, false, |change| {
+        changes.push(change);
+        true
+    });
+
+    // The game took focus, then the player went back to Steam and quit it
+    let mru: Vec<_> = changes
+        .iter()
+        .filter_map(|change| match change {
+            ToplevelChange::Mru(order) => Some(order.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(mru, vec![vec![1], vec![1, 2], vec![2, 1], vec![1, 2]]);
+    assert!(changes.contains(&ToplevelChange::Output("HDMI-A-1".to_owned())));
+    let removed = changes.iter().find_map(|change| match change {
+        ToplevelChange::Removed { id, details } => Some((*id, details.title.clone())),
+        _ => None,
+    });
+    assert_eq!(removed, Some((2, Some("Hades".to_owned()))));
+    assert_eq!(changes.last(), Some(&ToplevelChange::Finished));
+
+    let steam = model.get(1).unwrap();
+    assert_eq!(steam.app_id.as_deref(), Some("steam"));
+    assert_eq!(steam.state, ToplevelState::ACTIVATED);
+    assert!(model.get(2).is_none());
+    assert_eq!(model.mru(), &[1]);
+    assert!(model.is_finished());
+}
+
+#[test]
+fn replay_stops_early() {
+    let events = record::load(&session("steam-launch.jsonl")).unwrap();
+    let mut seen = 0;
+    let model = record::replay(&events, false, |_| {
+        seen += 1;
+        seen < 2
+    });
+    assert_eq!(seen, 2);
+    assert_eq!(model.toplevels().count(), 1);
+    assert!(!model.is_finished());
+}
+
+#[test]
+fn record_round_trip() {
+    let path =
+        std::env::temp_dir().join(format!("consolation-record-{}.jsonl", std::process::id()));
+    let events = vec![
+        ToplevelEvent::New { id: 1 },
+        ToplevelEvent::State {
+            id: 1,
+            state: ToplevelState::MAXIMIZED,
+        },
+        ToplevelEvent::Done { id: 1 },
+        ToplevelEvent::Finished,
+    ];
+    let mut recorder = Recorder::create(&path).unwrap();
+    for event in &events {
+        recorder.record(event).unwrap();
+    }
+    drop(recorder);
+
+    let loaded = record::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let loaded: Vec<_> = loaded.into_iter().map(|recorded| recorded.event).collect();
+    assert_eq!(loaded, events);
+}
+
+#[test]
+fn load_names_bad_line() {
+    let path = std::env::temp_dir().join(format!("consolation-bad-{}.jsonl", std::process::id()));
+    std::fs::write(&path, "{\"ms\":0,\"event\":\"finished\"}\n{\"ms\":1}\n").unwrap();
+    let err = record::load(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(err.to_string().starts_with("line 2:"), "{}", err);
+}
@@ -0,0 +1,248 @@
+use consolation_common::launcher::retroarch::{
+    load_entries, parse_playlist, thumbnail_name, PlaylistItem, RetroArchConfig, RomRule,
+};
+use std::path::PathBuf;
+
+mod support;
+use support::TempTree;
+
+fn snes_rule() -> RomRule {
+    RomRule {
+        extensions: vec!["sfc".to_owned()],
+        command: Some(vec!["snes9x".to_owned(), "{rom}".to_owned()]),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn symlink_loops_scanned_once() {
+    let tree = TempTree::new("retroarch", "loop");
+    let roms = tree.path().join("roms");
+    std::fs::create_dir_all(&roms).unwrap();
+    std::fs::write(roms.join("Chrono Trigger.sfc"), "").unwrap();
+    std::os::unix::fs::symlink(tree.path(), roms.join("all")).unwrap();
+    let config = RetroArchConfig {
+        playlists: false,
+        rom_dirs: vec![roms.to_string_lossy().into_owned()],
+        rules: vec![snes_rule()],
+        ..Default::default()
+    };
+    let entries = load_entries(&config);
+    let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["Chrono Trigger"]);
+}
+
+// A # only ends the file's path after a real archive
+#[test]
+fn hashes_in_playlist_paths() {
+    let tree = TempTree::new("retroarch", "hashes");
+    let roms = tree.path().join("roms");
+    std::fs::create_dir_all(&roms).unwrap();
+    std::fs::write(roms.join("Mario.zip"), "").unwrap();
+    let playlist = serde_json::json!({
+        "items": [
+            {
+                "path": roms.join("#1 Hits.sfc"),
+                "core_path": "/cores/snes9x_libretro.so",
+            },
+            {
+                "path": format!("{}#Mario.sfc", roms.join("Mario.zip").display()),
+                "core_path": "/cores/snes9x_libretro.so",
+            },
+        ],
+    });
+    std::fs::write(tree.path().join("SNES.lpl"), playlist.to_string()).unwrap();
+    let config = RetroArchConfig {
+        playlist_dir: Some(tree.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    let entries = load_entries(&config);
+    let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["#1 Hits", "Mario"]);
+}
+
+#[test]
+fn json_playlist() {
+    let items = parse_playlist(
+        r#"{
+  "version": "1.5",
+  "default_core_path": "/cores/snes9x_libretro.so",
+  "items": [
+    {
+      "path": "/roms/Super Metroid (USA).sfc",
+      "label": "Super Metroid (USA)",
+      "core_path": "DETECT",
+      "db_name": "Nintendo - Super Nintendo Entertainment System.lpl"
+    },
+    {
+      "path": "/roms/F-Zero (USA).sfc",
+      "label": "F-Zero (USA)",
+      "core_path": "/cores/bsnes_libretro.so",
+      "db_name": "Nintendo - Super Nintendo Entertainment System.lpl"
+    }
+  ]
+}"#,
+    );
+    assert_eq!(
+        items,
+        [
+            PlaylistItem {
+                path: "/roms/Super Metroid (USA).sfc".to_owned(),
+                label: "Super Metroid (USA)".to_owned(),
+                core_path: "/cores/snes9x_libretro.so".to_owned(),
+                db_name: "Nintendo - Super Nintendo Entertainment System.lpl".to_owned(),
+            },
+            PlaylistItem {
+                path: "/roms/F-Zero (USA).sfc".to_owned(),
+                label: "F-Zero (USA)".to_owned(),
+                core_path: "/cores/bsnes_libretro.so".to_owned(),
+                db_name: "Nintendo - Super Nintendo Entertainment System.lpl".to_owned(),
+            },
+        ]
+    );
+}
+
+// Six lines a game, and a game cut short by the end of the file is dropped
+#[test]
+fn old_playlist() {
+    let items = parse_playlist(
+        "/roms/Sonic.md\n\
+         Sonic the Hedgehog\n\
+         /cores/genesis_plus_gx_libretro.so\n\
+         Genesis Plus GX\n\
+         DETECT\n\
+         Sega - Mega Drive - Genesis.lpl\n\
+         /roms/Ecco.md\n\
+         Ecco\n",
+    );
+    assert_eq!(
+        items,
+        [PlaylistItem {
+            path: "/roms/Sonic.md".to_owned(),
+            label: "Sonic the Hedgehog".to_owned(),
+            core_path: "/cores/genesis_plus_gx_libretro.so".to_owned(),
+            db_name: "Sega - Mega Drive - Genesis.lpl".to_owned(),
+        }]
+    );
+}
+
+#[test]
+fn broken_playlist_is_empty() {
+    assert_eq!(parse_playlist("{ \"items\": ["), []);
+}
+
+#[test]
+fn thumbnail_names() {
+    assert_eq!(
+        thumbnail_name("Sonic & Knuckles: Part 1/2 <Beta>?"),
+        "Sonic _ Knuckles_ Part 1_2 _Beta__"
+    );
+    assert_eq!(thumbnail_name("Super Metroid (USA)"), "Super Metroid (USA)");
+}
+
+#[test]
+fn core_paths() {
+    let tree = TempTree::new("retroarch", "cores");
+    std::fs::write(tree.path().join("snes9x_libretro.so"), "").unwrap();
+    let config = RetroArchConfig {
+        cores_dir: Some(tree.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    let found = tree.path().join("snes9x_libretro.so");
+    assert_eq!(config.core_path("snes9x"), found);
+    assert_eq!(config.core_path("snes9x_libretro.so"), found);
+    // Not installed anywhere, so where it would be in the cores directory
+    assert_eq!(
+        config.core_path("consolation_test"),
+        tree.path().join("consolation_test_libretro.so")
+    );
+    assert_eq!(
+        config.core_path("/opt/cores/mgba_libretro.so"),
+        PathBuf::from("/opt/cores/mgba_libretro.so")
+    );
+}
+
+#[test]
+fn core_commands() {
+    let config = RetroArchConfig {
+        command: vec![
+            "flatpak".to_owned(),
+            "run".to_owned(),
+            "org.libretro.RetroArch".to_owned(),
+        ],
+        ..Default::default()
+    };
+    assert_eq!(
+        config.core_command(
+            &PathBuf::from("/cores/snes9x_libretro.so"),
+            &PathBuf::from("/roms/Game.zip#Game.sfc")
+        ),
+        [
+            "flatpak",
+            "run",
+            "org.libretro.RetroArch",
+            "-L",
+            "/cores/snes9x_libretro.so",
+            "/roms/Game.zip#Game.sfc",
+        ]
+    );
+}
+
+// ROMs from a directory are run with their rule's command. Ones a playlist
+// already lists aren't added twice
+#[test]
+fn scanned_roms() {
+    let tree = TempTree::new("retroarch", "scan");
+    let roms = tree.path().join("roms");
+    std::fs::create_dir_all(roms.join("snes")).unwrap();
+    std::fs::write(roms.join("snes").join("Super Metroid.SFC"), "").unwrap();
+    std::fs::write(roms.join("snes").join("F-Zero.sfc"), "").unwrap();
+    std::fs::write(roms.join("readme.txt"), "").unwrap();
+    let playlists = tree.path().join("playlists");
+    std::fs::create_dir_all(&playlists).unwrap();
+    let playlist = serde_json::json!({
+        "items": [{
+            "path": roms.join("snes").join("F-Zero.sfc"),
+            "label": "F-Zero (USA)",
+            "core_path": "/cores/bsnes_libretro.so",
+        }],
+    });
+    std::fs::write(playlists.join("SNES.lpl"), playlist.to_string()).unwrap();
+    let config = RetroArchConfig {
+        playlist_dir: Some(playlists.to_string_lossy().into_owned()),
+        rom_dirs: vec![roms.to_string_lossy().into_owned()],
+        rules: vec![RomRule {
+            system: Some("Nintendo - Super Nintendo Entertainment System".to_owned()),
+            ..snes_rule()
+        }],
+        ..Default::default()
+    };
+    let entries = load_entries(&config);
+    let found: Vec<_> = entries
+        .iter()
+        .map(|entry| (entry.name.as_str(), entry.exec.clone()))
+        .collect();
+    let metroid = roms.join("snes").join("Super Metroid.SFC");
+    assert_eq!(
+        found,
+        [
+            (
+                "F-Zero (USA)",
+                vec![
+                    "retroarch".to_owned(),
+                    "-L".to_owned(),
+                    "/cores/bsnes_libretro.so".to_owned(),
+                    roms.join("snes").join("F-Zero.sfc").display().to_string(),
+                ]
+            ),
+            (
+                "Super Metroid",
+                vec!["snes9x".to_owned(), metroid.display().to_string()]
+            ),
+        ]
+    );
+    assert_eq!(
+        entries[1].categories,
+        ["Game", "Nintendo - Super Nintendo Entertainment System"]
+    );
+}
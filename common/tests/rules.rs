@@ -0,0 +1,79 @@
+use consolation_common::rules::{RuleAction, WindowRule, WindowRuleConfig};
+use consolation_common::ToplevelDetails;
+
+fn rule(app_id: Option<&str>, title: Option<&str>) -> Option<WindowRule> {
+    WindowRule::compile(&WindowRuleConfig {
+        app_id: app_id.map(|app_id| app_id.to_owned()),
+        title: title.map(|title| title.to_owned()),
+        actions: vec![RuleAction::Fullscreen],
+        ..Default::default()
+    })
+}
+
+fn window(app_id: Option<&str>, title: Option<&str>) -> ToplevelDetails {
+    ToplevelDetails {
+        app_id: app_id.map(|app_id| app_id.to_owned()),
+        title: title.map(|title| title.to_owned()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn app_id_only() {
+    let rule = rule(Some("^steam_app_\\d+$"), None).unwrap();
+    assert!(rule.matches(&window(Some("steam_app_620"), Some("Portal 2"))));
+    assert!(rule.matches(&window(Some("steam_app_620"), None)));
+    assert!(!rule.matches(&window(Some("steam_app_"), Some("Portal 2"))));
+    assert!(!rule.matches(&window(Some("steam"), Some("Steam"))));
+    assert!(!rule.matches(&window(None, Some("Portal 2"))));
+}
+
+// Both have to match when both are given
+#[test]
+fn app_id_and_title() {
+    let rule = rule(Some("^firefox$"), Some("YouTube")).unwrap();
+    assert!(rule.matches(&window(
+        Some("firefox"),
+        Some("Music - YouTube — Mozilla Firefox")
+    )));
+    assert!(!rule.matches(&window(Some("firefox"), Some("Mozilla Firefox"))));
+    assert!(!rule.matches(&window(Some("chromium"), Some("YouTube"))));
+    assert!(!rule.matches(&window(Some("firefox"), None)));
+}
+
+// Unanchored, so a title rule matches part of the title
+#[test]
+fn title_only() {
+    let rule = rule(None, Some("(?i)trailer")).unwrap();
+    assert!(rule.matches(&window(Some("mpv"), Some("Game Trailer.mkv"))));
+    assert!(rule.matches(&window(None, Some("TRAILER"))));
+    assert!(!rule.matches(&window(Some("trailer"), Some("mpv"))));
+}
+
+// Matching on nothing would hit every window
+#[test]
+fn empty_rule_matches_nothing() {
+    let rule = rule(None, None).unwrap();
+    assert!(!rule.matches(&window(Some("firefox"), Some("Firefox"))));
+    assert!(!rule.matches(&window(None, None)));
+}
+
+#[test]
+fn bad_regex_dropped() {
+    assert!(rule(Some("steam_app_("), None).is_none());
+    assert!(rule(Some("steam"), Some("[")).is_none());
+    let configs = [
+        WindowRuleConfig {
+            app_id: Some("(".to_owned()),
+            ..Default::default()
+        },
+        WindowRuleConfig {
+            app_id: Some("mpv".to_owned()),
+            actions: vec![RuleAction::Media],
+            ..Default::default()
+        },
+    ];
+    let rules = WindowRule::compile_all(&configs);
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].actions, [RuleAction::Media]);
+}
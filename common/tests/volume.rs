@@ -0,0 +1,133 @@
+use consolation_common::volume::{
+    parse_pactl, parse_wpctl, AudioBackend, PulseAudio, Volume, WirePlumber,
+};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+// A stand in for wpctl or pactl. It prints `output` and logs the
+// arguments it was run with
+struct FakeTool {
+    dir: PathBuf,
+}
+
+impl FakeTool {
+    fn new(name: &str, output: &str) -> FakeTool {
+        let dir = std::env::temp_dir().join(format!(
+            "consolation-volume-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("output"), output).unwrap();
+        let script = format!(
+            "#!/bin/sh\necho \"$@\" >> {0}/log\ncat {0}/output\n",
+            dir.display()
+        );
+        let program = dir.join("tool");
+        std::fs::write(&program, script).unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        FakeTool { dir }
+    }
+
+    fn program(&self) -> PathBuf {
+        self.dir.join("tool")
+    }
+
+    fn log(&self) -> Vec<String> {
+        std::fs::read_to_string(self.dir.join("log"))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.to_owned())
+            .collect()
+    }
+}
+
+impl Drop for FakeTool {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn wpctl_output() {
+    assert_eq!(
+        parse_wpctl("Volume: 0.40\n"),
+        Some(Volume {
+            percentage: 40,
+            muted: false,
+        })
+    );
+    assert_eq!(
+        parse_wpctl("Volume: 0.73 [MUTED]\n"),
+        Some(Volume {
+            percentage: 73,
+            muted: true,
+        })
+    );
+    // Boosted
+    assert_eq!(parse_wpctl("Volume: 1.50").map(|v| v.percentage), Some(100));
+    assert_eq!(parse_wpctl("Translate ID failed"), None);
+}
+
+#[test]
+fn pactl_output() {
+    let volume = "Volume: front-left: 26214 /  40% / -23.88 dB,   front-right: 32768 /  50% / -18.06 dB\n        balance 0.20\n";
+    assert_eq!(
+        parse_pactl(volume, "Mute: yes\n"),
+        Some(Volume {
+            percentage: 45,
+            muted: true,
+        })
+    );
+    assert_eq!(parse_pactl("", "Mute: no"), None);
+}
+
+#[test]
+fn wireplumber_commands() {
+    let tool = FakeTool::new("wpctl", "Volume: 0.55\n");
+    let backend = WirePlumber::new(tool.program());
+    assert_eq!(backend.volume().unwrap().percentage, 55);
+    backend.set_volume(60).unwrap();
+    backend.set_volume(120).unwrap();
+    backend.set_muted(true).unwrap();
+    assert_eq!(
+        tool.log(),
+        vec![
+            "get-volume @DEFAULT_AUDIO_SINK@",
+            "set-volume @DEFAULT_AUDIO_SINK@ 60%",
+            "set-volume @DEFAULT_AUDIO_SINK@ 100%",
+            "set-mute @DEFAULT_AUDIO_SINK@ 1",
+        ]
+    );
+}
+
+#[test]
+fn pulseaudio_commands() {
+    let tool = FakeTool::new("pactl", "Volume: mono: 65536 / 100% / 0.00 dB\n");
+    let backend = PulseAudio::new(tool.program());
+    assert_eq!(
+        backend.volume().unwrap(),
+        Volume {
+            percentage: 100,
+            muted: false,
+        }
+    );
+    backend.set_volume(20).unwrap();
+    backend.set_muted(false).unwrap();
+    assert_eq!(
+        tool.log(),
+        vec![
+            "get-sink-volume @DEFAULT_SINK@",
+            "get-sink-mute @DEFAULT_SINK@",
+            "set-sink-volume @DEFAULT_SINK@ 20%",
+            "set-sink-mute @DEFAULT_SINK@ 0",
+        ]
+    );
+}
+
+#[test]
+fn failing_tool() {
+    let backend = WirePlumber::new("/nonexistent/wpctl");
+    assert!(backend.volume().is_err());
+}
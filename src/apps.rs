@@ -0,0 +1,434 @@
+use consolation_common::apps::{AppsAction, AppsSignal};
+use consolation_common::config::ShellConfig;
+use consolation_common::ipc;
+use consolation_common::launcher::desktop;
+use consolation_common::limits::{GrantRefused, LimitAction, LimitEvent, LimitPeriod, TimeLimits};
+use consolation_common::playtime::PlaytimeTracker;
+use consolation_common::process::{self, ProcFs, Signal};
+use consolation_common::{ToplevelId, ToplevelModel, ToplevelState};
+use std::collections::HashMap;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::notify;
+
+// How much playtime a crash or power loss can cost
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+const LIMITS_INTERVAL: Duration = Duration::from_secs(10);
+
+struct PendingClose {
+    requested: Instant,
+    signalled: Option<(Signal, Instant)>,
+}
+
+// Windows we have SIGSTOPped and the PIDs that were stopped, shared with
+// clients. The PIDs are also written to a file, so anything a crash left
+// stopped is continued when the daemon starts again. Foreground time is
+// counted here too, time limits enforced and windows that won't close
+// tracked, whether or not the switcher is running
+pub struct Apps {
+    paused: HashMap<ToplevelId, Vec<i32>>,
+    clients: Vec<UnixStream>,
+    paused_path: Option<PathBuf>,
+    playtime: PlaytimeTracker,
+    checkpointed: Instant,
+    limits: TimeLimits,
+    limits_ticked: Instant,
+    // Desktop file categories per app_id, looked up once
+    categories: HashMap<String, Vec<String>>,
+    // Rule whose time has run out, until extra time is granted
+    time_up: Option<String>,
+    // Windows asked to close, by a client or for running out of time
+    pending_close: HashMap<ToplevelId, PendingClose>,
+    close_timeout: Duration,
+    // Overdue windows as clients were last told
+    overdue: Vec<ToplevelId>,
+    // Signals offered for force quitting, sent once confirmed
+    force_quit: HashMap<ToplevelId, (Signal, Vec<i32>)>,
+}
+
+impl Apps {
+    pub fn new(config: &ShellConfig) -> Apps {
+        let paused_path = ipc::runtime_socket("paused");
+        if let Some(path) = &paused_path {
+            resume_left_over(path);
+        }
+        Apps {
+            paused: HashMap::new(),
+            clients: vec![],
+            paused_path,
+            playtime: PlaytimeTracker::load(),
+            checkpointed: Instant::now(),
+            limits: TimeLimits::load(config.limits.clone()),
+            limits_ticked: Instant::now(),
+            categories: HashMap::new(),
+            time_up: None,
+            pending_close: HashMap::new(),
+            close_timeout: Duration::from_secs(config.switcher.close_timeout),
+            overdue: vec![],
+            force_quit: HashMap::new(),
+        }
+    }
+
+    // Run after every change to the window list
+    pub fn set_foreground(&mut self, model: &ToplevelModel) {
+        let foreground = model
+            .toplevels()
+            .find(|(_, details)| details.state.contains(ToplevelState::ACTIVATED))
+            .and_then(|(_, details)| details.app_id.as_deref());
+        if self.playtime.current_app_id() != foreground {
+            self.playtime.set_foreground(foreground);
+            self.checkpointed = Instant::now();
+            self.broadcast(&AppsSignal::Playtime);
+        }
+    }
+
+    // Run after every dispatch. Returns windows to close for running out
+    // of time
+    pub fn dispatched(&mut self, model: &ToplevelModel) -> Vec<ToplevelId> {
+        if self.overdue() != self.overdue {
+            self.changed();
+        }
+        if self.checkpointed.elapsed() >= CHECKPOINT_INTERVAL {
+            self.checkpointed = Instant::now();
+            if self.playtime.current_app_id().is_some() {
+                self.playtime.checkpoint();
+                self.broadcast(&AppsSignal::Playtime);
+            }
+        }
+        if self.limits.is_enabled() && self.limits_ticked.elapsed() >= LIMITS_INTERVAL {
+            self.limits_ticked = Instant::now();
+            return self.tick_limits(model);
+        }
+        vec![]
+    }
+
+    fn tick_limits(&mut self, model: &ToplevelModel) -> Vec<ToplevelId> {
+        let foreground = model
+            .toplevels()
+            .find(|(_, details)| details.state.contains(ToplevelState::ACTIVATED))
+            .map(|(id, _)| id);
+        let rules = foreground
+            .map(|id| self.rules_for(id, model))
+            .unwrap_or_default();
+        let mut to_close = vec![];
+        for event in self.limits.tick(&rules) {
+            match event {
+                LimitEvent::Warning {
+                    rule,
+                    minutes_left,
+                    period,
+                } => notify::send(
+                    "Time limit",
+                    &format!(
+                        "{} minutes left for {} {}",
+                        minutes_left,
+                        rule,
+                        period.describe()
+                    ),
+                    notify::Urgency::Normal,
+                ),
+                LimitEvent::Expired {
+                    rule,
+                    action,
+                    period,
+                } => to_close.extend(self.enforce_limit(&rule, action, period, model)),
+            }
+        }
+        to_close
+    }
+
+    // Pause or close every window under a rule that has run out. Runs on
+    // every tick so windows brought back some other way are caught again
+    fn enforce_limit(
+        &mut self,
+        rule: &str,
+        action: LimitAction,
+        period: LimitPeriod,
+        model: &ToplevelModel,
+    ) -> Vec<ToplevelId> {
+        let ids: Vec<ToplevelId> = model
+            .toplevels()
+            .map(|(id, _)| id)
+            .filter(|id| self.rules_for(*id, model).iter().any(|r| r == rule))
+            .collect();
+        let mut to_close = vec![];
+        for id in ids {
+            match action {
+                LimitAction::Pause => self.pause(id, model),
+                LimitAction::Close => {
+                    if !self.pending_close.contains_key(&id) {
+                        self.closing(id);
+                        to_close.push(id);
+                    }
+                }
+            }
+        }
+        self.time_up(rule, period);
+        to_close
+    }
+
+    // Activating a window whose time is up is refused, and clients told
+    // why. True if it was refused
+    pub fn refuse_activate(&mut self, id: ToplevelId, model: &ToplevelModel) -> bool {
+        let rules = self.rules_for(id, model);
+        match self.limits.is_exhausted(&rules) {
+            Some((rule, period)) => {
+                self.time_up(&rule, period);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn time_up(&mut self, rule: &str, period: LimitPeriod) {
+        if self.time_up.as_deref() != Some(rule) {
+            notify::send(
+                "Time's up",
+                &format!("No time left for {} {}", rule, period.describe()),
+                notify::Urgency::Critical,
+            );
+            self.time_up = Some(rule.to_owned());
+        }
+        self.broadcast(&AppsSignal::TimeUp {
+            rule: rule.to_owned(),
+            period,
+        });
+    }
+
+    fn grant(&mut self, rule: String, pin: &str) {
+        match self.limits.grant(&rule, pin) {
+            Ok(()) => {
+                self.time_up = None;
+                self.broadcast(&AppsSignal::Granted { rule });
+            }
+            Err(GrantRefused::Wait(wait)) => {
+                let retry_after = wait.as_secs_f64().ceil() as u64;
+                self.broadcast(&AppsSignal::WrongPin { retry_after });
+            }
+            Err(GrantRefused::NoPin) => println!("No PIN is set, unable to grant time"),
+        }
+    }
+
+    // Time limit rules a window falls under
+    fn rules_for(&mut self, id: ToplevelId, model: &ToplevelModel) -> Vec<String> {
+        if !self.limits.is_enabled() {
+            return vec![];
+        }
+        let Some(app_id) = model.get(id).and_then(|details| details.app_id.clone()) else {
+            return vec![];
+        };
+        let categories = self
+            .categories
+            .entry(app_id.clone())
+            .or_insert_with(|| desktop::categories_for_app_id(&app_id));
+        self.limits.rules_for(&app_id, categories)
+    }
+
+    pub fn add_client(&mut self, client: UnixStream) {
+        let _ = client.set_write_timeout(Some(Duration::from_secs(1)));
+        if ipc::send(&client, &self.status()).is_ok() {
+            self.clients.push(client);
+        }
+    }
+
+    pub fn handle(&mut self, action: AppsAction, model: &ToplevelModel) {
+        match action {
+            AppsAction::Pause { id } => self.pause(id, model),
+            AppsAction::Resume { id } => self.resume(id),
+            AppsAction::GrantTime { rule, pin } => self.grant(rule, &pin),
+            AppsAction::ForceQuit { id } => self.offer_force_quit(id, model),
+            AppsAction::ConfirmForceQuit { id } => self.force_quit(id),
+        }
+    }
+
+    // Run for every close, so windows that ignore it can be force quit
+    pub fn closing(&mut self, id: ToplevelId) {
+        self.pending_close.entry(id).or_insert(PendingClose {
+            requested: Instant::now(),
+            signalled: None,
+        });
+    }
+
+    // Windows that have ignored a close, or a signal, for longer than
+    // we're willing to wait
+    fn overdue(&self) -> Vec<ToplevelId> {
+        let mut overdue: Vec<ToplevelId> = self
+            .pending_close
+            .iter()
+            .filter(|(_, pending)| {
+                let since = match pending.signalled {
+                    Some((Signal::Kill, _)) => return false,
+                    Some((_, at)) => at,
+                    None => pending.requested,
+                };
+                since.elapsed() >= self.close_timeout
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        overdue.sort();
+        overdue
+    }
+
+    // SIGTERM first. SIGKILL can't be caught to clean up children, so
+    // once that's needed take the whole tree down with it
+    fn offer_force_quit(&mut self, id: ToplevelId, model: &ToplevelModel) {
+        let Some(app_id) = model.get(id).and_then(|details| details.app_id.clone()) else {
+            return;
+        };
+        let signal = match self.pending_close.get(&id).and_then(|p| p.signalled) {
+            Some(_) => Signal::Kill,
+            None => Signal::Term,
+        };
+        let procfs = ProcFs::default();
+        let owners = procfs.owners(&app_id, &clients());
+        let pids = match signal {
+            Signal::Kill => procfs.trees(&owners.pids),
+            _ => owners.pids,
+        };
+        let processes = pids.iter().map(|pid| procfs.describe(*pid)).collect();
+        self.force_quit.insert(id, (signal, pids));
+        self.broadcast(&AppsSignal::ForceQuit {
+            id,
+            signal,
+            processes,
+            by_name: owners.by_name,
+        });
+    }
+
+    fn force_quit(&mut self, id: ToplevelId) {
+        let Some((signal, pids)) = self.force_quit.remove(&id) else {
+            return;
+        };
+        process::signal_all(&pids, signal);
+        self.closing(id);
+        if let Some(pending) = self.pending_close.get_mut(&id) {
+            pending.signalled = Some((signal, Instant::now()));
+        }
+        // A stopped process won't act on SIGTERM until it is continued
+        self.resume(id);
+        self.changed();
+    }
+
+    // SIGSTOP every process belonging to the window
+    pub fn pause(&mut self, id: ToplevelId, model: &ToplevelModel) {
+        if self.paused.contains_key(&id) {
+            return;
+        }
+        let Some(app_id) = model.get(id).and_then(|details| details.app_id.clone()) else {
+            return;
+        };
+        let procfs = ProcFs::default();
+        let owners = procfs.owners(&app_id, &clients());
+        // Stopping the wrong process is only a nuisance, but say so
+        if owners.by_name && !owners.pids.is_empty() {
+            println!("Pausing {} by name: {:?}", app_id, owners.pids);
+        }
+        let pids = procfs.trees(&owners.pids);
+        if pids.is_empty() {
+            println!("Unable to find a process to pause for {}", app_id);
+            return;
+        }
+        process::signal_all(&pids, Signal::Stop);
+        self.paused.insert(id, pids);
+        self.changed();
+    }
+
+    pub fn resume(&mut self, id: ToplevelId) {
+        if let Some(pids) = self.paused.remove(&id) {
+            process::signal_all(&pids, Signal::Cont);
+            self.changed();
+        }
+    }
+
+    // A window going away doesn't mean its processes have, they may be
+    // stopped halfway through exiting
+    pub fn removed(&mut self, id: ToplevelId) {
+        self.resume(id);
+        self.force_quit.remove(&id);
+        if self.pending_close.remove(&id).is_some() {
+            self.changed();
+        }
+    }
+
+    // Run on the way out. Nothing else would continue paused processes,
+    // or write the running session
+    pub fn exiting(&mut self) {
+        let ids: Vec<ToplevelId> = self.paused.keys().copied().collect();
+        for id in ids {
+            self.resume(id);
+        }
+        self.playtime.set_foreground(None);
+    }
+
+    fn changed(&mut self) {
+        self.save_paused();
+        self.overdue = self.overdue();
+        self.broadcast(&self.status());
+    }
+
+    // Clients that can't keep up, or have gone away, are dropped
+    fn broadcast(&mut self, signal: &AppsSignal) {
+        self.clients
+            .retain(|client| ipc::send(client, signal).is_ok());
+    }
+
+    fn status(&self) -> AppsSignal {
+        let mut paused: Vec<ToplevelId> = self.paused.keys().copied().collect();
+        paused.sort();
+        AppsSignal::Status {
+            paused,
+            overdue: self.overdue.clone(),
+        }
+    }
+
+    fn save_paused(&self) {
+        let Some(path) = &self.paused_path else {
+            return;
+        };
+        let pids: Vec<String> = self
+            .paused
+            .values()
+            .flatten()
+            .map(|pid| pid.to_string())
+            .collect();
+        let written = match pids.is_empty() {
+            true => std::fs::remove_file(path).or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            }),
+            false => std::fs::write(path, pids.join("\n")),
+        };
+        if let Err(e) = written {
+            println!(
+                "Unable to save paused processes to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+// SIGCONT to a process that isn't stopped does nothing, so it doesn't
+// matter if some of these PIDs have since been reused
+fn resume_left_over(path: &Path) {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return;
+    };
+    let pids: Vec<i32> = contents
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect();
+    println!("Continuing {} processes left paused", pids.len());
+    process::signal_all(&pids, Signal::Cont);
+    let _ = std::fs::remove_file(path);
+}
+
+// Unknown clients only mean falling back to names, so don't give up
+fn clients() -> Vec<i32> {
+    process::wayland_clients().unwrap_or_else(|e| {
+        println!("Unable to list the compositor's clients: {}", e);
+        vec![]
+    })
+}
@@ -0,0 +1,574 @@
+use consolation_common::config::{
+    expand_home, BackgroundConfig, DesktopConfig, ImageFit, ShellConfig,
+};
+use consolation_common::{ipc, ToplevelDetails, ToplevelId, ToplevelSignal, ToplevelState};
+use cosmic::app::{Command, Core};
+use cosmic::cctk::wayland_client::protocol::wl_output::WlOutput;
+use cosmic::iced::event::wayland::{self, OutputEvent};
+use cosmic::iced::event::PlatformSpecific;
+use cosmic::iced::gradient::Linear;
+use cosmic::iced::wayland::layer_surface::{
+    destroy_layer_surface, get_layer_surface, Anchor, KeyboardInteractivity, Layer,
+};
+use cosmic::iced::{self, window, Background, Color, ContentFit, Degrees, Length, Subscription};
+use cosmic::iced_runtime::command::platform_specific::wayland::layer_surface::{
+    IcedOutput, SctkLayerSurfaceSettings,
+};
+use cosmic::widget::{self, list::container, text};
+use cosmic::Application;
+use image::RgbaImage;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod hero;
+
+use hero::HeroCache;
+
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "webp"];
+
+// Waits between attempts to reach consolation-shelld double up to this
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+
+struct UiFlags {
+    config: DesktopConfig,
+    focus_recv: Receiver<Message>,
+}
+
+fn main() {
+    let config = ShellConfig::load().desktop;
+
+    let (focus_sender, focus_recv) = channel();
+    if config.app_themed {
+        let config = config.clone();
+        std::thread::spawn(move || watch_focus(config, focus_sender));
+    }
+    let input = UiFlags { config, focus_recv };
+
+    // Backgrounds are layer surfaces, one per output, there's no main window
+    let mut settings = cosmic::app::Settings::default();
+    settings = settings.no_main_window(true);
+    settings = settings.transparent(true);
+    cosmic::app::run::<ConsolationDesktopApp>(settings, input).expect("Unable to start App");
+    exit(0);
+}
+
+// consolation-shelld may start after us, or be restarted, so it's
+// connected to again until the app goes away
+fn watch_focus(config: DesktopConfig, sender: Sender<Message>) {
+    let mut heroes = HeroCache::default();
+    let mut wait = Duration::from_secs(1);
+    loop {
+        match ipc::connect() {
+            Ok(shell) => {
+                wait = Duration::from_secs(1);
+                if !follow_focus(shell, &config, &mut heroes, &sender) {
+                    return;
+                }
+                println!("Lost consolation-shelld, reconnecting");
+            }
+            Err(e) => println!(
+                "Unable to connect to consolation-shelld, retrying in {}s: {}",
+                wait.as_secs(),
+                e
+            ),
+        }
+        std::thread::sleep(wait);
+        wait = (wait * 2).min(RECONNECT_MAX);
+    }
+}
+
+// Follows the activated window, sending its app's image whenever it
+// changes. False once there's nobody to send to
+fn follow_focus(
+    shell: UnixStream,
+    config: &DesktopConfig,
+    heroes: &mut HeroCache,
+    sender: &Sender<Message>,
+) -> bool {
+    let mut toplevels: HashMap<ToplevelId, ToplevelDetails> = HashMap::new();
+    let mut focused = None;
+    for signal in ipc::read_messages(shell) {
+        match signal {
+            ToplevelSignal::Snapshot {
+                toplevels: list, ..
+            } => toplevels = list.into_iter().collect(),
+            ToplevelSignal::AddUpdateToplevel { id, details } => {
+                toplevels.insert(id, details);
+            }
+            ToplevelSignal::RemoveToplevel { id } => {
+                toplevels.remove(&id);
+            }
+            _ => continue,
+        }
+        let app_id = toplevels
+            .values()
+            .find(|details| details.state.contains(ToplevelState::ACTIVATED))
+            .and_then(|details| details.app_id.clone());
+        // Opening the switcher keeps the game's image behind it
+        if app_id == focused || app_id.as_deref() == Some(ipc::SWITCHER_APP_ID) {
+            continue;
+        }
+        let hero = app_id
+            .as_deref()
+            .and_then(|app_id| heroes.get(app_id, config));
+        focused = app_id;
+        if sender.send(Message::Hero(hero)).is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+// The background on one output
+struct Surface {
+    output: WlOutput,
+    name: Option<String>,
+    surface: window::Id,
+    // Above the background, showing the focused app's image
+    hero_surface: window::Id,
+    background: BackgroundConfig,
+    // A slideshow's images, and the one showing
+    slides: Vec<PathBuf>,
+    slide: usize,
+}
+
+impl Surface {
+    fn set_background(&mut self, background: BackgroundConfig) {
+        let colours = match &background {
+            BackgroundConfig::Colour { colour } => vec![colour],
+            BackgroundConfig::Gradient { from, to, .. } => vec![from, to],
+            _ => vec![],
+        };
+        for colour in colours {
+            if parse_colour(colour).is_none() {
+                println!("Unable to parse colour {}, using black", colour);
+            }
+        }
+        self.slides = match &background {
+            BackgroundConfig::Slideshow { directory, .. } => list_images(&expand_home(directory)),
+            _ => vec![],
+        };
+        self.slide = 0;
+        self.background = background;
+    }
+
+    // Directories are read again on the way round, picking up new images
+    fn next_slide(&mut self) {
+        let BackgroundConfig::Slideshow { directory, .. } = &self.background else {
+            return;
+        };
+        self.slide += 1;
+        if self.slide >= self.slides.len() {
+            self.slides = list_images(&expand_home(directory));
+            self.slide = 0;
+        }
+    }
+}
+
+// Part way from one app's image to another's. The frames are worked out
+// in the background, until then the old image stays. None is no image,
+// showing the background
+struct Fade {
+    // Which change of image this is, so late frames for an earlier one
+    // are dropped
+    generation: u64,
+    frames: Vec<Option<widget::image::Handle>>,
+    started: Option<Instant>,
+}
+
+struct ConsolationDesktopApp {
+    core: Core,
+    config: DesktopConfig,
+    focus_recv: RefCell<Option<Receiver<Message>>>,
+    surfaces: Vec<Surface>,
+    hero: Option<Arc<RgbaImage>>,
+    fade: Option<Fade>,
+    fades_started: u64,
+    // What the hero surfaces show right now
+    hero_frame: Option<widget::image::Handle>,
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    // Messages from the compositor
+    OutputAdded(WlOutput, Option<String>),
+    OutputChanged(WlOutput, Option<String>),
+    OutputRemoved(WlOutput),
+
+    // The focused app's image, or None to show the background
+    Hero(Option<Arc<RgbaImage>>),
+
+    // Slideshows with this interval move on
+    NextSlide(u64),
+    FadeReady(u64, Vec<Option<widget::image::Handle>>),
+    FadeTick(),
+}
+
+impl Application for ConsolationDesktopApp {
+    type Executor = cosmic::executor::Default;
+    type Flags = UiFlags;
+    type Message = Message;
+
+    const APP_ID: &'static str = "Consolation Desktop";
+
+    fn core(&self) -> &cosmic::app::Core {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut cosmic::app::Core {
+        &mut self.core
+    }
+
+    fn init(core: Core, flags: Self::Flags) -> (Self, Command<Self::Message>) {
+        (
+            ConsolationDesktopApp {
+                core,
+                config: flags.config,
+                focus_recv: RefCell::new(Some(flags.focus_recv)),
+                surfaces: vec![],
+                hero: None,
+                fade: None,
+                fades_started: 0,
+                hero_frame: None,
+            },
+            Command::none(),
+        )
+    }
+
+    fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+        match message {
+            Message::OutputAdded(output, name) => {
+                if self.surfaces.iter().any(|s| s.output == output) {
+                    return Command::none();
+                }
+                let surface = window::Id::unique();
+                let hero_surface = window::Id::unique();
+                let mut background = Surface {
+                    output: output.clone(),
+                    name: None,
+                    surface,
+                    hero_surface,
+                    background: BackgroundConfig::default(),
+                    slides: vec![],
+                    slide: 0,
+                };
+                background.set_background(self.config.background_for(name.as_deref()).clone());
+                background.name = name;
+                self.surfaces.push(background);
+                // Below every window, and ignored when placing them. The
+                // app's image goes on the next layer up, always above
+                return Command::batch([
+                    get_layer_surface(fullscreen_layer(surface, Layer::Background, &output)),
+                    get_layer_surface(fullscreen_layer(hero_surface, Layer::Bottom, &output)),
+                ]);
+            }
+            Message::OutputChanged(output, name) => {
+                // The name can arrive after the output, pick up its own
+                // background then
+                let config = &self.config;
+                if let Some(surface) = self.surfaces.iter_mut().find(|s| s.output == output) {
+                    if surface.name != name {
+                        surface.set_background(config.background_for(name.as_deref()).clone());
+                        surface.name = name;
+                    }
+                }
+            }
+            Message::OutputRemoved(output) => {
+                if let Some(idx) = self.surfaces.iter().position(|s| s.output == output) {
+                    let surface = self.surfaces.remove(idx);
+                    return Command::batch([
+                        destroy_layer_surface(surface.surface),
+                        destroy_layer_surface(surface.hero_surface),
+                    ]);
+                }
+            }
+            Message::Hero(hero) => {
+                let unchanged = match (&self.hero, &hero) {
+                    (Some(current), Some(new)) => Arc::ptr_eq(current, new),
+                    (current, new) => current.is_none() && new.is_none(),
+                };
+                if unchanged {
+                    return Command::none();
+                }
+                // A fade already under way jumps to its end
+                let from = self.hero.take();
+                self.hero = hero.clone();
+                self.fades_started += 1;
+                let generation = self.fades_started;
+                self.fade = Some(Fade {
+                    generation,
+                    frames: vec![],
+                    started: None,
+                });
+                let count = match self.config.fade {
+                    0 => 1,
+                    _ => hero::FADE_FRAMES,
+                };
+                return Command::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || {
+                            hero::fade_frames(from.as_deref(), hero.as_deref(), count)
+                                .into_iter()
+                                .map(|frame| {
+                                    frame.map(|frame| {
+                                        let (width, height) = frame.dimensions();
+                                        widget::image::Handle::from_pixels(
+                                            width,
+                                            height,
+                                            frame.into_raw(),
+                                        )
+                                    })
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                        .await
+                        .unwrap_or_default()
+                    },
+                    move |frames| cosmic::app::Message::App(Message::FadeReady(generation, frames)),
+                );
+            }
+            Message::FadeReady(generation, frames) => {
+                if let Some(fade) = &mut self.fade {
+                    if fade.generation == generation {
+                        fade.frames = frames;
+                        fade.started = Some(Instant::now());
+                        self.fade_step();
+                    }
+                }
+            }
+            Message::FadeTick() => {
+                self.fade_step();
+            }
+            Message::NextSlide(interval) => {
+                for surface in &mut self.surfaces {
+                    if let BackgroundConfig::Slideshow { interval: i, .. } = surface.background {
+                        if i.max(1) == interval {
+                            surface.next_slide();
+                        }
+                    }
+                }
+            }
+        }
+        Command::none()
+    }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        let mut subscriptions = vec![cosmic::iced::event::listen_with(|event, _| {
+            let iced::Event::PlatformSpecific(PlatformSpecific::Wayland(wayland::Event::Output(
+                event,
+                output,
+            ))) = event
+            else {
+                return None;
+            };
+            match event {
+                OutputEvent::Created(info) => Some(Message::OutputAdded(
+                    output,
+                    info.and_then(|info| info.name),
+                )),
+                OutputEvent::InfoUpdate(info) => Some(Message::OutputChanged(output, info.name)),
+                OutputEvent::Removed => Some(Message::OutputRemoved(output)),
+            }
+        })];
+        subscriptions.push(iced::subscription::unfold(
+            "focused app",
+            self.focus_recv.take(),
+            move |mut recvr| async move {
+                let message = match recvr.as_mut().unwrap().recv() {
+                    Ok(message) => message,
+                    // Not following focus, or consolation-shelld went away
+                    Err(_) => futures::future::pending().await,
+                };
+                (message, recvr)
+            },
+        ));
+        if self
+            .fade
+            .as_ref()
+            .is_some_and(|fade| fade.started.is_some())
+        {
+            let interval = Duration::from_millis(self.config.fade / hero::FADE_FRAMES as u64);
+            subscriptions.push(
+                iced::time::every(interval.max(Duration::from_millis(16)))
+                    .map(|_| Message::FadeTick()),
+            );
+        }
+        // One timer per interval in use, outputs sharing one change together
+        let mut intervals: Vec<u64> = self
+            .surfaces
+            .iter()
+            .filter_map(|surface| match surface.background {
+                BackgroundConfig::Slideshow { interval, .. } => Some(interval.max(1)),
+                _ => None,
+            })
+            .collect();
+        intervals.sort();
+        intervals.dedup();
+        for interval in intervals {
+            subscriptions.push(
+                iced::time::every(Duration::from_secs(interval))
+                    .map(move |_| Message::NextSlide(interval)),
+            );
+        }
+        Subscription::batch(subscriptions)
+    }
+
+    fn view(&self) -> cosmic::Element<Self::Message> {
+        text("").into()
+    }
+
+    fn view_window(&self, id: window::Id) -> cosmic::Element<Self::Message> {
+        if let Some(surface) = self.surfaces.iter().find(|surface| surface.surface == id) {
+            return view_background(surface);
+        }
+        match &self.hero_frame {
+            Some(frame) if self.surfaces.iter().any(|s| s.hero_surface == id) => {
+                widget::image(frame.clone())
+                    .content_fit(ContentFit::Cover)
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .into()
+            }
+            _ => text("").into(),
+        }
+    }
+}
+
+impl ConsolationDesktopApp {
+    // Show the frame for how far through the fade we are. Only swaps
+    // handles, the frames are already worked out
+    fn fade_step(&mut self) {
+        let Some(fade) = &self.fade else {
+            return;
+        };
+        let Some(started) = fade.started else {
+            return;
+        };
+        let duration = Duration::from_millis(self.config.fade);
+        let progress = match duration.is_zero() {
+            true => 1.0,
+            false => started.elapsed().as_secs_f32() / duration.as_secs_f32(),
+        };
+        let last = fade.frames.len().saturating_sub(1);
+        let idx = ((progress * fade.frames.len() as f32) as usize).min(last);
+        self.hero_frame = fade.frames.get(idx).cloned().flatten();
+        if progress >= 1.0 {
+            self.fade = None;
+        }
+    }
+}
+
+fn fullscreen_layer(id: window::Id, layer: Layer, output: &WlOutput) -> SctkLayerSurfaceSettings {
+    SctkLayerSurfaceSettings {
+        id,
+        layer,
+        keyboard_interactivity: KeyboardInteractivity::None,
+        anchor: Anchor::TOP | Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT,
+        exclusive_zone: -1,
+        output: IcedOutput::Output(output.clone()),
+        namespace: "consolation-desktop".to_owned(),
+        size: Some((None, None)),
+        ..Default::default()
+    }
+}
+
+fn view_background(surface: &Surface) -> cosmic::Element<'static, Message> {
+    match &surface.background {
+        BackgroundConfig::Colour { colour } => fill(Background::Color(colour_or_black(colour))),
+        BackgroundConfig::Gradient { from, to, angle } => {
+            let gradient = Linear::new(Degrees(*angle))
+                .add_stop(0.0, colour_or_black(from))
+                .add_stop(1.0, colour_or_black(to));
+            fill(Background::Gradient(gradient.into()))
+        }
+        BackgroundConfig::Image { path, fit } => image(expand_home(path), *fit),
+        BackgroundConfig::Slideshow { fit, .. } => match surface.slides.get(surface.slide) {
+            Some(path) => image(path.clone(), *fit),
+            None => fill(Background::Color(Color::BLACK)),
+        },
+    }
+}
+
+fn fill(background: Background) -> cosmic::Element<'static, Message> {
+    container(widget::Space::new(Length::Fill, Length::Fill))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .style(cosmic::theme::Container::custom(move |_| {
+            cosmic::iced_style::container::Appearance {
+                background: Some(background),
+                ..Default::default()
+            }
+        }))
+        .into()
+}
+
+// On black, which shows around scaled images and while loading
+fn image(path: PathBuf, fit: ImageFit) -> cosmic::Element<'static, Message> {
+    let fit = match fit {
+        ImageFit::Crop => ContentFit::Cover,
+        ImageFit::Scale => ContentFit::Contain,
+        ImageFit::Stretch => ContentFit::Fill,
+    };
+    let image = widget::image(widget::image::Handle::from_path(path))
+        .content_fit(fit)
+        .width(Length::Fill)
+        .height(Length::Fill);
+    container(image)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .center_x()
+        .center_y()
+        .style(cosmic::theme::Container::custom(|_| {
+            cosmic::iced_style::container::Appearance {
+                background: Some(Background::Color(Color::BLACK)),
+                ..Default::default()
+            }
+        }))
+        .into()
+}
+
+// "#rrggbb"
+fn parse_colour(colour: &str) -> Option<Color> {
+    let rgb = colour
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())?;
+    Some(Color::from_rgb8(
+        (rgb >> 16) as u8,
+        (rgb >> 8) as u8,
+        rgb as u8,
+    ))
+}
+
+fn colour_or_black(colour: &str) -> Color {
+    parse_colour(colour).unwrap_or(Color::BLACK)
+}
+
+// Images in a directory, in name order
+fn list_images(directory: &Path) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Unable to read {}: {}", directory.display(), e);
+            return vec![];
+        }
+    };
+    let mut images: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+                })
+        })
+        .collect();
+    images.sort();
+    images
+}
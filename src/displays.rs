@@ -0,0 +1,343 @@
+use consolation_common::outputs::{
+    wayland::OutputManager, Confirmation, Head, HeadConfig, Transform, REVERT_AFTER,
+};
+use std::time::Instant;
+
+// Scales offered, smaller steps than this are rarely useful on a TV
+const SCALE_STEP: f64 = 0.25;
+const SCALE_MIN: f64 = 0.5;
+const SCALE_MAX: f64 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayField {
+    Enabled,
+    Resolution,
+    Refresh,
+    Scale,
+    Rotation,
+    Position,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayRow {
+    // A setting of the head at this index
+    Field(usize, DisplayField),
+    Apply,
+    Keep,
+    Revert,
+}
+
+// Where a head can go, next to another enabled head
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Placement {
+    RightOf(usize),
+    LeftOf(usize),
+    Above(usize),
+    Below(usize),
+    Mirror(usize),
+}
+
+// The displays page: the heads as they are, the changes waiting to be
+// applied, and the countdown once they have been
+pub struct Displays {
+    manager: OutputManager,
+    heads: Vec<Head>,
+    // One per head, in the same order
+    wanted: Vec<HeadConfig>,
+    confirmation: Option<Confirmation>,
+}
+
+impl Displays {
+    pub fn open() -> Result<Displays, String> {
+        let manager = OutputManager::connect()?;
+        let heads = manager.heads();
+        let wanted = heads.iter().map(Head::config).collect();
+        Ok(Displays {
+            manager,
+            heads,
+            wanted,
+            confirmation: None,
+        })
+    }
+
+    // Start again from the displays as they are now, dropping unapplied
+    // changes
+    pub fn reload(&mut self) -> Result<(), String> {
+        let result = self.manager.refresh();
+        self.heads = self.manager.heads();
+        self.wanted = self.heads.iter().map(Head::config).collect();
+        result
+    }
+
+    pub fn rows(&self) -> Vec<DisplayRow> {
+        if self.confirmation.is_some() {
+            return vec![DisplayRow::Keep, DisplayRow::Revert];
+        }
+        let enabled = self.wanted.iter().filter(|config| config.enabled).count();
+        let mut rows = vec![];
+        for (idx, (head, config)) in self.heads.iter().zip(&self.wanted).enumerate() {
+            rows.push(DisplayRow::Field(idx, DisplayField::Enabled));
+            if !config.enabled {
+                continue;
+            }
+            if !head.modes.is_empty() {
+                rows.push(DisplayRow::Field(idx, DisplayField::Resolution));
+                rows.push(DisplayRow::Field(idx, DisplayField::Refresh));
+            }
+            rows.push(DisplayRow::Field(idx, DisplayField::Scale));
+            rows.push(DisplayRow::Field(idx, DisplayField::Rotation));
+            if enabled > 1 {
+                rows.push(DisplayRow::Field(idx, DisplayField::Position));
+            }
+        }
+        rows.push(DisplayRow::Apply);
+        rows
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.heads
+            .iter()
+            .zip(&self.wanted)
+            .any(|(head, config)| head.config() != *config)
+    }
+
+    pub fn seconds_left(&self, now: Instant) -> Option<u64> {
+        self.confirmation
+            .as_ref()
+            .map(|confirmation| confirmation.seconds_left(now))
+    }
+
+    pub fn label(&self, idx: usize, field: DisplayField) -> String {
+        let Some(head) = self.heads.get(idx) else {
+            return String::new();
+        };
+        match field {
+            DisplayField::Enabled => match head.description.is_empty() {
+                true => head.name.clone(),
+                false => format!("{} ({})", head.description, head.name),
+            },
+            DisplayField::Resolution => "Resolution".to_owned(),
+            DisplayField::Refresh => "Refresh rate".to_owned(),
+            DisplayField::Scale => "Scale".to_owned(),
+            DisplayField::Rotation => "Rotation".to_owned(),
+            DisplayField::Position => "Position".to_owned(),
+        }
+    }
+
+    pub fn value(&self, idx: usize, field: DisplayField) -> String {
+        let Some(config) = self.wanted.get(idx) else {
+            return String::new();
+        };
+        match field {
+            DisplayField::Enabled => match config.enabled {
+                true => "On".to_owned(),
+                false => "Off".to_owned(),
+            },
+            DisplayField::Resolution => config
+                .mode
+                .map_or("Automatic".to_owned(), |mode| mode.resolution_label()),
+            DisplayField::Refresh => config
+                .mode
+                .map_or("Automatic".to_owned(), |mode| mode.refresh_label()),
+            DisplayField::Scale => format!("{}×", config.scale),
+            DisplayField::Rotation => config.transform.label().to_owned(),
+            DisplayField::Position => {
+                let placement = self
+                    .placements(idx)
+                    .into_iter()
+                    .find(|(_, position)| *position == config.position);
+                match placement {
+                    Some((Placement::RightOf(other), _)) => {
+                        format!("Right of {}", self.heads[other].name)
+                    }
+                    Some((Placement::LeftOf(other), _)) => {
+                        format!("Left of {}", self.heads[other].name)
+                    }
+                    Some((Placement::Above(other), _)) => {
+                        format!("Above {}", self.heads[other].name)
+                    }
+                    Some((Placement::Below(other), _)) => {
+                        format!("Below {}", self.heads[other].name)
+                    }
+                    Some((Placement::Mirror(other), _)) => {
+                        format!("Same as {}", self.heads[other].name)
+                    }
+                    None => format!("{}, {}", config.position.0, config.position.1),
+                }
+            }
+        }
+    }
+
+    // Left (-1) or right (1) on a setting
+    pub fn step(&mut self, idx: usize, field: DisplayField, direction: i64) {
+        let Some(head) = self.heads.get(idx).cloned() else {
+            return;
+        };
+        let placements = self.placements(idx);
+        let right_edge = self.right_edge(idx);
+        let config = &mut self.wanted[idx];
+        match field {
+            DisplayField::Enabled => {
+                config.enabled = !config.enabled;
+                if config.enabled {
+                    if config.mode.is_none() {
+                        config.mode = head.preferred_mode();
+                    }
+                    // Turned on displays go on the end rather than on top
+                    // of another
+                    config.position = (right_edge, 0);
+                }
+            }
+            DisplayField::Resolution => {
+                let resolutions = head.resolutions();
+                let current = config.mode.map(|mode| (mode.width, mode.height));
+                if let Some((width, height)) = cycle(&resolutions, current, direction) {
+                    // Keep the refresh rate if the new size has it
+                    let modes = head.modes_sized(width, height);
+                    let refresh = config.mode.map(|mode| mode.refresh);
+                    config.mode = modes
+                        .iter()
+                        .find(|mode| Some(mode.refresh) == refresh)
+                        .or(modes.first())
+                        .copied();
+                }
+            }
+            DisplayField::Refresh => {
+                let Some(current) = config.mode else {
+                    return;
+                };
+                let modes = head.modes_sized(current.width, current.height);
+                let current = modes.iter().copied().find(|mode| mode.same_as(&current));
+                config.mode = cycle(&modes, current, direction).or(config.mode);
+            }
+            DisplayField::Scale => {
+                let scale = config.scale + direction as f64 * SCALE_STEP;
+                // Back onto the steps if the compositor picked something
+                // in between
+                config.scale =
+                    ((scale / SCALE_STEP).round() * SCALE_STEP).clamp(SCALE_MIN, SCALE_MAX);
+            }
+            DisplayField::Rotation => {
+                config.transform =
+                    cycle(&Transform::ALL, Some(config.transform), direction).unwrap_or_default();
+            }
+            DisplayField::Position => {
+                let positions: Vec<_> = placements.iter().map(|(_, position)| *position).collect();
+                if let Some(position) = cycle(&positions, Some(config.position), direction) {
+                    config.position = position;
+                }
+            }
+        }
+    }
+
+    // The first free x position to the right of every other enabled head
+    fn right_edge(&self, idx: usize) -> i32 {
+        self.wanted
+            .iter()
+            .enumerate()
+            .filter(|(other, config)| *other != idx && config.enabled)
+            .filter_map(|(_, config)| {
+                let (width, _) = config.logical_size()?;
+                Some(config.position.0 + width)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Positions touching each other enabled head, lined up with its top or
+    // left edge
+    fn placements(&self, idx: usize) -> Vec<(Placement, (i32, i32))> {
+        let Some((width, height)) = self.wanted.get(idx).and_then(HeadConfig::logical_size) else {
+            return vec![];
+        };
+        let mut placements = vec![];
+        for (other, config) in self.wanted.iter().enumerate() {
+            if other == idx || !config.enabled {
+                continue;
+            }
+            let Some((other_width, other_height)) = config.logical_size() else {
+                continue;
+            };
+            let (x, y) = config.position;
+            placements.push((Placement::RightOf(other), (x + other_width, y)));
+            placements.push((Placement::LeftOf(other), (x - width, y)));
+            placements.push((Placement::Above(other), (x, y - height)));
+            placements.push((Placement::Below(other), (x, y + other_height)));
+            placements.push((Placement::Mirror(other), (x, y)));
+        }
+        placements
+    }
+
+    // Apply the changes, then give the user REVERT_AFTER to keep them
+    pub fn apply(&mut self, now: Instant) -> Result<(), String> {
+        let previous = self.heads.iter().map(Head::config).collect();
+        let result = self.manager.apply(&normalised(&self.wanted));
+        match result {
+            Ok(()) => {
+                self.confirmation = Some(Confirmation::new(previous, REVERT_AFTER, now));
+                self.reload()
+            }
+            Err(e) => {
+                // The compositor may have let the changes through to some
+                // heads before failing, show what's really there
+                let _ = self.reload();
+                Err(e)
+            }
+        }
+    }
+
+    pub fn keep(&mut self) {
+        self.confirmation = None;
+    }
+
+    pub fn revert(&mut self) -> Result<(), String> {
+        let Some(confirmation) = self.confirmation.take() else {
+            return Ok(());
+        };
+        let result = self.manager.apply(confirmation.previous());
+        let _ = self.reload();
+        result
+    }
+
+    // Revert once the countdown runs out. None while it hasn't, otherwise
+    // how the revert went
+    pub fn tick(&mut self, now: Instant) -> Option<Result<(), String>> {
+        match &self.confirmation {
+            Some(confirmation) if confirmation.expired(now) => Some(self.revert()),
+            _ => None,
+        }
+    }
+}
+
+// Moved so the layout starts at 0,0, which some compositors need
+fn normalised(wanted: &[HeadConfig]) -> Vec<HeadConfig> {
+    let enabled = wanted.iter().filter(|config| config.enabled);
+    let min_x = enabled.clone().map(|config| config.position.0).min();
+    let min_y = enabled.map(|config| config.position.1).min();
+    let (Some(min_x), Some(min_y)) = (min_x, min_y) else {
+        return wanted.to_vec();
+    };
+    wanted
+        .iter()
+        .cloned()
+        .map(|mut config| {
+            if config.enabled {
+                config.position = (config.position.0 - min_x, config.position.1 - min_y);
+            }
+            config
+        })
+        .collect()
+}
+
+// The choice before or after `current`, wrapping round. Starts at the first
+// when `current` isn't one of them
+fn cycle<T: Copy + PartialEq>(choices: &[T], current: Option<T>, direction: i64) -> Option<T> {
+    if choices.is_empty() {
+        return None;
+    }
+    let len = choices.len() as i64;
+    let idx = current
+        .and_then(|current| choices.iter().position(|choice| *choice == current))
+        .map_or(0, |idx| (idx as i64 + direction).rem_euclid(len));
+    Some(choices[idx as usize])
+}
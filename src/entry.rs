@@ -0,0 +1,93 @@
+// Typing without a keyboard. A grid of keys picked with the arrows and
+// Select, for settings that take text
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryKey {
+    Char(char),
+    Shift,
+    Space,
+    Delete,
+    Done,
+}
+
+impl EntryKey {
+    pub fn label(self, shifted: bool) -> String {
+        match self {
+            EntryKey::Char(c) if shifted => c.to_uppercase().to_string(),
+            EntryKey::Char(c) => c.to_string(),
+            EntryKey::Shift => "Shift".to_owned(),
+            EntryKey::Space => "Space".to_owned(),
+            EntryKey::Delete => "Delete".to_owned(),
+            EntryKey::Done => "Done".to_owned(),
+        }
+    }
+}
+
+pub const ENTRY_COLUMNS: usize = 10;
+
+pub struct Entry {
+    // The field on the open page being typed into
+    pub field: usize,
+    pub text: String,
+    keys: Vec<EntryKey>,
+    pub selected: usize,
+    pub shifted: bool,
+}
+
+impl Entry {
+    // Only `chars` can be typed, so a colour can't be given letters past f.
+    // Shift is only offered when there are letters to shift
+    pub fn new(field: usize, text: String, chars: &str, space: bool) -> Entry {
+        let mut keys: Vec<EntryKey> = chars.chars().map(EntryKey::Char).collect();
+        if chars.chars().any(|c| c.is_lowercase()) {
+            keys.push(EntryKey::Shift);
+        }
+        if space {
+            keys.push(EntryKey::Space);
+        }
+        keys.push(EntryKey::Delete);
+        keys.push(EntryKey::Done);
+        Entry {
+            field,
+            text,
+            // Done, so an unchanged value is one press away
+            selected: keys.len() - 1,
+            keys,
+            shifted: false,
+        }
+    }
+
+    pub fn keys(&self) -> &[EntryKey] {
+        &self.keys
+    }
+
+    // True when Done was pressed
+    pub fn press(&mut self, idx: usize) -> bool {
+        let Some(key) = self.keys.get(idx).copied() else {
+            return false;
+        };
+        self.selected = idx;
+        match key {
+            EntryKey::Char(c) if self.shifted => self.text.extend(c.to_uppercase()),
+            EntryKey::Char(c) => self.text.push(c),
+            EntryKey::Shift => self.shifted = !self.shifted,
+            EntryKey::Space => self.text.push(' '),
+            EntryKey::Delete => {
+                self.text.pop();
+            }
+            EntryKey::Done => return true,
+        }
+        false
+    }
+
+    // Moves stop at the grid's edges
+    pub fn move_selection(&mut self, right: i64, down: i64) {
+        let last = self.keys.len() as i64 - 1;
+        let col = (self.selected % ENTRY_COLUMNS) as i64 + right;
+        let row = (self.selected / ENTRY_COLUMNS) as i64 + down;
+        let col = col.clamp(0, ENTRY_COLUMNS as i64 - 1);
+        let rows = last / ENTRY_COLUMNS as i64;
+        let row = row.clamp(0, rows);
+        self.selected = (row * ENTRY_COLUMNS as i64 + col).min(last) as usize;
+    }
+}
@@ -0,0 +1,119 @@
+use consolation_common::config::{expand_home, DesktopConfig};
+use consolation_common::launcher::profile::steam_root;
+use consolation_common::playtime::find_steam_app_id;
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Steam's heroes are 3840 wide, more than needed and slow to crossfade
+const MAX_WIDTH: u32 = 1920;
+
+// Frames worked out for each crossfade. Each is a full size image, so
+// this is kept low
+pub const FADE_FRAMES: u32 = 10;
+
+// The image shown behind an app: the user's own, then Steam's library hero
+pub fn find_image(app_id: &str, config: &DesktopConfig) -> Option<PathBuf> {
+    if let Some(path) = config.app_images.get(app_id) {
+        return Some(expand_home(path));
+    }
+    let steam_app_id = find_steam_app_id(app_id)?;
+    let cache = steam_root().join("appcache").join("librarycache");
+    // Newer Steam clients keep each game's art in a directory of its own
+    [
+        cache.join(format!("{}_library_hero.jpg", steam_app_id)),
+        cache.join(&steam_app_id).join("library_hero.jpg"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+pub fn load(path: &Path) -> Option<RgbaImage> {
+    let image = match image::open(path) {
+        Ok(image) => image.to_rgba8(),
+        Err(e) => {
+            println!("Unable to load {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    if image.width() <= MAX_WIDTH {
+        return Some(image);
+    }
+    let height = image.height() * MAX_WIDTH / image.width();
+    Some(imageops::resize(
+        &image,
+        MAX_WIDTH,
+        height,
+        FilterType::Triangle,
+    ))
+}
+
+// Loads each app's image once. Apps without one are looked up again each
+// time, as Steam may have downloaded its art since
+#[derive(Default)]
+pub struct HeroCache {
+    images: HashMap<String, Arc<RgbaImage>>,
+}
+
+impl HeroCache {
+    pub fn get(&mut self, app_id: &str, config: &DesktopConfig) -> Option<Arc<RgbaImage>> {
+        if let Some(image) = self.images.get(app_id) {
+            return Some(image.clone());
+        }
+        let image = Arc::new(load(&find_image(app_id, config)?)?);
+        self.images.insert(app_id.to_owned(), image.clone());
+        Some(image)
+    }
+}
+
+// Scaled to the size of another image, so the two can be crossfaded
+fn resized_like(image: &RgbaImage, like: &RgbaImage) -> RgbaImage {
+    let (width, height) = like.dimensions();
+    match image.dimensions() == (width, height) {
+        true => image.clone(),
+        false => imageops::resize(image, width, height, FilterType::Triangle),
+    }
+}
+
+// Every frame of a crossfade, evenly spaced and ending on `to`. Slow, so
+// not for the UI thread
+pub fn fade_frames(
+    from: Option<&RgbaImage>,
+    to: Option<&RgbaImage>,
+    count: u32,
+) -> Vec<Option<RgbaImage>> {
+    let from = match (from, to) {
+        (Some(from), Some(to)) => Some(resized_like(from, to)),
+        (from, _) => from.cloned(),
+    };
+    let mut frames: Vec<Option<RgbaImage>> = (1..count.max(1))
+        .map(|frame| crossfade(from.as_ref(), to, frame as f32 / count as f32))
+        .collect();
+    frames.push(to.cloned());
+    frames
+}
+
+// `progress` of the way from one image to another of the same size, where
+// no image is transparent
+fn crossfade(from: Option<&RgbaImage>, to: Option<&RgbaImage>, progress: f32) -> Option<RgbaImage> {
+    let (width, height) = to.or(from).map(|image| image.dimensions())?;
+    let progress = progress.clamp(0.0, 1.0);
+    let mut out = RgbaImage::new(width, height);
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let from = from.map(|from| from.get_pixel(x, y).0);
+        let to = to.map(|to| to.get_pixel(x, y).0);
+        pixel.0 = match (from, to) {
+            (Some(from), Some(to)) => std::array::from_fn(|channel| {
+                (from[channel] as f32 * (1.0 - progress) + to[channel] as f32 * progress).round()
+                    as u8
+            }),
+            // Fading in or out keeps the colour and changes the alpha
+            (None, Some([r, g, b, a])) => [r, g, b, (a as f32 * progress).round() as u8],
+            (Some([r, g, b, a]), None) => [r, g, b, (a as f32 * (1.0 - progress)).round() as u8],
+            (None, None) => [0; 4],
+        };
+    }
+    Some(out)
+}
@@ -0,0 +1,105 @@
+use consolation_common::backlight::{self, Backlight};
+use consolation_common::config::IdleConfig;
+use consolation_common::idle::{wayland::IdleNotifier, IdleCommand, IdleState};
+use consolation_common::StagingData;
+use std::time::Instant;
+use wayland_client::QueueHandle;
+
+use crate::Shell;
+
+// Dims through the backlight and blanks through the compositor, when the
+// seat has been idle long enough and nothing is keeping the screen on
+pub struct Idle {
+    pub notifier: IdleNotifier,
+    state: IdleState,
+    backlight: Option<Box<dyn Backlight + Send>>,
+    dim_level: u8,
+    // Brightness from before dimming, in the device's units
+    undimmed: Option<u32>,
+}
+
+impl Idle {
+    pub fn new(config: &IdleConfig) -> Idle {
+        Idle {
+            notifier: IdleNotifier::new(config.dim_after(), config.blank_after()),
+            state: IdleState::new(),
+            backlight: backlight::system_backlight(),
+            // 0% turns some panels off, which is what blanking is for
+            dim_level: config.dim_level.clamp(1, 100),
+            undimmed: None,
+        }
+    }
+
+    // Run after every dispatch. The timers start once the seat is known
+    pub fn dispatched(&mut self, toplevels: &StagingData, qh: &QueueHandle<Shell>) {
+        if !self.notifier.started() {
+            self.run(vec![IdleCommand::Restart], toplevels, qh);
+        }
+        for event in self.notifier.take_events() {
+            let commands = self.state.handle(event);
+            self.run(commands, toplevels, qh);
+        }
+    }
+
+    pub fn set_inhibited(
+        &mut self,
+        inhibited: bool,
+        toplevels: &StagingData,
+        qh: &QueueHandle<Shell>,
+    ) {
+        let commands = self.state.set_inhibited(inhibited);
+        self.run(commands, toplevels, qh);
+    }
+
+    pub fn gamepad(&mut self, toplevels: &StagingData, qh: &QueueHandle<Shell>) {
+        let commands = self.state.gamepad(Instant::now());
+        self.run(commands, toplevels, qh);
+    }
+
+    fn run(
+        &mut self,
+        commands: Vec<IdleCommand>,
+        toplevels: &StagingData,
+        qh: &QueueHandle<Shell>,
+    ) {
+        for command in commands {
+            match command {
+                IdleCommand::Dim => self.dim(),
+                IdleCommand::Undim => self.undim(),
+                IdleCommand::Blank => self.notifier.set_blanked(Some(toplevels.outputs()), qh),
+                IdleCommand::Unblank => self.notifier.set_blanked(None, qh),
+                IdleCommand::Restart => {
+                    if let Some(seat) = toplevels.seat() {
+                        self.notifier.restart(seat, qh);
+                    }
+                }
+            }
+        }
+    }
+
+    fn dim(&mut self) {
+        let Some(backlight) = &self.backlight else {
+            return;
+        };
+        match backlight.brightness() {
+            // Already darker is left alone
+            Ok(brightness) if brightness.percentage() > self.dim_level => {
+                match backlight.set_percentage(self.dim_level) {
+                    Ok(()) => self.undimmed = Some(brightness.value),
+                    Err(e) => println!("Unable to dim the screen: {}", e),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => println!("Unable to read brightness: {}", e),
+        }
+    }
+
+    fn undim(&mut self) {
+        let (Some(backlight), Some(value)) = (&self.backlight, self.undimmed.take()) else {
+            return;
+        };
+        if let Err(e) = backlight.set_brightness(value) {
+            println!("Unable to restore brightness: {}", e);
+        }
+    }
+}
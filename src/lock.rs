@@ -0,0 +1,432 @@
+use consolation_common::config::{LockConfig, ShellConfig};
+use consolation_common::logind::Logind;
+use consolation_common::pin;
+use consolation_common::power::{self, BatteryStatus, PowerSource, PowerStatus};
+use cosmic::app::{Command, Core};
+use cosmic::cctk::wayland_client::protocol::wl_output::WlOutput;
+use cosmic::iced::event::wayland::{self, OutputEvent, SessionLockEvent};
+use cosmic::iced::event::PlatformSpecific;
+use cosmic::iced::wayland::session_lock::{destroy_lock_surface, get_lock_surface, lock, unlock};
+use cosmic::iced::{
+    self,
+    keyboard::{self, key},
+    window, Alignment, Length, Subscription,
+};
+use cosmic::widget::{button, column, icon, list::container, row, text};
+use cosmic::Application;
+use std::process::exit;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+mod pam;
+
+// Long enough for any PIN, short enough to keep the dots on one line
+const MAX_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PadKey {
+    Digit(u8),
+    Delete,
+    Enter,
+}
+
+impl PadKey {
+    fn label(self) -> String {
+        match self {
+            PadKey::Digit(digit) => digit.to_string(),
+            PadKey::Delete => "Delete".to_owned(),
+            PadKey::Enter => "OK".to_owned(),
+        }
+    }
+}
+
+// A phone's keypad, three across
+const PAD_COLUMNS: usize = 3;
+const PAD: [PadKey; 12] = [
+    PadKey::Digit(1),
+    PadKey::Digit(2),
+    PadKey::Digit(3),
+    PadKey::Digit(4),
+    PadKey::Digit(5),
+    PadKey::Digit(6),
+    PadKey::Digit(7),
+    PadKey::Digit(8),
+    PadKey::Digit(9),
+    PadKey::Delete,
+    PadKey::Digit(0),
+    PadKey::Enter,
+];
+
+fn main() {
+    let config = ShellConfig::load().lock;
+
+    // Lock surfaces, one per output, there's no main window
+    let mut settings = cosmic::app::Settings::default();
+    settings = settings.no_main_window(true);
+    cosmic::app::run::<ConsolationLockApp>(settings, config).expect("Unable to start App");
+    exit(0);
+}
+
+struct ConsolationLockApp {
+    core: Core,
+    config: LockConfig,
+    // Every output and its lock surface, created once locked
+    outputs: Vec<(WlOutput, window::Id)>,
+    locked: bool,
+    entered: String,
+    selected: usize,
+    checking: bool,
+    wrong: bool,
+    // Wrong PINs hold off the next attempt, for PAM as well as config
+    backoff: pin::Backoff,
+    // Both connect on first use, on a blocking thread. Locking can't wait
+    // on them, as consolation-shelld only holds off suspend for so long
+    logind: Arc<OnceLock<Option<Logind>>>,
+    power: Arc<Mutex<Option<Box<dyn PowerSource + Send>>>>,
+    power_status: Option<PowerStatus>,
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    // Messages from the compositor
+    OutputAdded(WlOutput),
+    OutputRemoved(WlOutput),
+    SessionLock(SessionLockEvent),
+
+    // Messages from the keyboard, or a controller mapped to one
+    ArrowUp(),
+    ArrowDown(),
+    ArrowLeft(),
+    ArrowRight(),
+    Select(),
+    Back(),
+    Typed(String),
+
+    Press(PadKey),
+    Checked(bool),
+    LockedHintSet(bool),
+    Tick(),
+    PowerTick(),
+    PowerRead(Option<PowerStatus>),
+}
+
+impl Application for ConsolationLockApp {
+    type Executor = cosmic::executor::Default;
+    type Flags = LockConfig;
+    type Message = Message;
+
+    const APP_ID: &'static str = "Consolation Lock";
+
+    fn core(&self) -> &cosmic::app::Core {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut cosmic::app::Core {
+        &mut self.core
+    }
+
+    fn init(core: Core, flags: Self::Flags) -> (Self, Command<Self::Message>) {
+        let app = ConsolationLockApp {
+            core,
+            config: flags,
+            outputs: vec![],
+            locked: false,
+            entered: String::new(),
+            selected: 0,
+            checking: false,
+            wrong: false,
+            backoff: pin::Backoff::default(),
+            logind: Arc::new(OnceLock::new()),
+            power: Arc::new(Mutex::new(None)),
+            power_status: None,
+        };
+        let read_power = app.read_power();
+        (app, Command::batch([lock(), read_power]))
+    }
+
+    fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+        match message {
+            Message::OutputAdded(output) => {
+                if self.outputs.iter().any(|(known, _)| *known == output) {
+                    return Command::none();
+                }
+                let id = window::Id::unique();
+                self.outputs.push((output.clone(), id));
+                if self.locked {
+                    return get_lock_surface(id, output);
+                }
+            }
+            Message::OutputRemoved(output) => {
+                if let Some(idx) = self.outputs.iter().position(|(known, _)| *known == output) {
+                    let (_, id) = self.outputs.remove(idx);
+                    if self.locked {
+                        return destroy_lock_surface(id);
+                    }
+                }
+            }
+            Message::SessionLock(event) => return self.session_lock(event),
+            Message::ArrowUp() => self.move_selection(0, -1),
+            Message::ArrowDown() => self.move_selection(0, 1),
+            Message::ArrowLeft() => self.move_selection(-1, 0),
+            Message::ArrowRight() => self.move_selection(1, 0),
+            Message::Select() => return self.update(Message::Press(PAD[self.selected])),
+            Message::Back() => return self.update(Message::Press(PadKey::Delete)),
+            Message::Typed(typed) => {
+                if self.checking {
+                    return Command::none();
+                }
+                // Keyboards can type a whole password. Enter then submits
+                // it rather than pressing whichever key was selected
+                self.wrong = false;
+                for c in typed.chars().filter(|c| !c.is_control()) {
+                    if self.entered.chars().count() < MAX_LENGTH {
+                        self.entered.push(c);
+                    }
+                }
+                self.selected = PAD.len() - 1;
+            }
+            Message::Press(key) => {
+                if self.checking {
+                    return Command::none();
+                }
+                self.wrong = false;
+                match key {
+                    PadKey::Digit(digit) => {
+                        if self.entered.chars().count() < MAX_LENGTH {
+                            self.entered.push(char::from(b'0' + digit));
+                        }
+                    }
+                    PadKey::Delete => {
+                        self.entered.pop();
+                    }
+                    PadKey::Enter => {
+                        if self.entered.is_empty() || self.backoff.wait(Instant::now()).is_some() {
+                            return Command::none();
+                        }
+                        self.checking = true;
+                        let config = self.config.clone();
+                        let entered = std::mem::take(&mut self.entered);
+                        return Command::perform(
+                            async move {
+                                tokio::task::spawn_blocking(move || check(&config, &entered))
+                                    .await
+                                    .unwrap_or(false)
+                            },
+                            |correct| cosmic::app::Message::App(Message::Checked(correct)),
+                        );
+                    }
+                }
+            }
+            Message::Checked(correct) => {
+                self.checking = false;
+                if !correct {
+                    self.wrong = true;
+                    self.backoff.failed(Instant::now());
+                    return Command::none();
+                }
+                return self.set_locked_hint(false);
+            }
+            // Unlocking waits for the hint, as we exit once unlocked
+            Message::LockedHintSet(locked) => {
+                if !locked {
+                    return unlock();
+                }
+            }
+            // Only here to redraw the clock
+            Message::Tick() => {}
+            Message::PowerTick() => return self.read_power(),
+            Message::PowerRead(status) => self.power_status = status,
+        }
+        Command::none()
+    }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        Subscription::batch([
+            cosmic::iced::event::listen_with(|event, _| {
+                let iced::Event::PlatformSpecific(PlatformSpecific::Wayland(event)) = event else {
+                    return None;
+                };
+                match event {
+                    wayland::Event::Output(OutputEvent::Created(_), output) => {
+                        Some(Message::OutputAdded(output))
+                    }
+                    wayland::Event::Output(OutputEvent::Removed, output) => {
+                        Some(Message::OutputRemoved(output))
+                    }
+                    wayland::Event::SessionLock(event) => Some(Message::SessionLock(event)),
+                    _ => None,
+                }
+            }),
+            keyboard::on_key_press(|key, _modifiers| match key {
+                keyboard::Key::Named(key::Named::ArrowUp) => Some(Message::ArrowUp()),
+                keyboard::Key::Named(key::Named::ArrowDown) => Some(Message::ArrowDown()),
+                keyboard::Key::Named(key::Named::ArrowLeft) => Some(Message::ArrowLeft()),
+                keyboard::Key::Named(key::Named::ArrowRight) => Some(Message::ArrowRight()),
+                keyboard::Key::Named(key::Named::Enter) => Some(Message::Select()),
+                keyboard::Key::Named(key::Named::Escape | key::Named::Backspace) => {
+                    Some(Message::Back())
+                }
+                keyboard::Key::Character(typed) => Some(Message::Typed(typed.to_string())),
+                _ => None,
+            }),
+            iced::time::every(Duration::from_secs(1)).map(|_| Message::Tick()),
+            iced::time::every(Duration::from_secs(30)).map(|_| Message::PowerTick()),
+        ])
+    }
+
+    fn view(&self) -> cosmic::Element<Self::Message> {
+        text("").into()
+    }
+
+    // Every output shows the same thing. Whichever the compositor gives
+    // keyboard focus to takes the input
+    fn view_window(&self, _id: window::Id) -> cosmic::Element<Self::Message> {
+        let now = chrono::Local::now();
+        let mut c = column().spacing(16).align_items(Alignment::Center);
+        c = c.push(text::title1(now.format("%H:%M").to_string()));
+        c = c.push(text(now.format("%A %-d %B").to_string()));
+        if let Some(battery) = self.power_status.and_then(|status| status.battery) {
+            c = c.push(
+                row()
+                    .spacing(8)
+                    .push(icon::from_name(power::battery_icon(
+                        battery.percentage,
+                        battery.status == BatteryStatus::Charging,
+                    )))
+                    .push(text(format!("{}%", battery.percentage))),
+            );
+        }
+
+        let prompt = if self.checking {
+            "Checking…".to_owned()
+        } else if self.entered.is_empty() {
+            "Enter PIN".to_owned()
+        } else {
+            "●".repeat(self.entered.chars().count())
+        };
+        c = c.push(text::title3(prompt));
+        // Redrawn every second by the clock's tick
+        if let Some(wait) = self.backoff.wait(Instant::now()) {
+            c = c.push(text::caption(format!(
+                "Wrong PIN, try again in {} seconds",
+                wait.as_secs_f64().ceil()
+            )));
+        } else if self.wrong {
+            c = c.push(text::caption("Wrong PIN"));
+        }
+
+        let mut pad = column().spacing(8);
+        for (row_idx, keys) in PAD.chunks(PAD_COLUMNS).enumerate() {
+            let mut r = row().spacing(8);
+            for (col_idx, key) in keys.iter().enumerate() {
+                let mut key_button = button(text::title3(key.label()))
+                    .width(Length::Fixed(120.0))
+                    .on_press(Message::Press(*key));
+                if row_idx * PAD_COLUMNS + col_idx == self.selected {
+                    key_button = key_button.style(cosmic::theme::Button::Suggested);
+                }
+                r = r.push(key_button);
+            }
+            pad = pad.push(r);
+        }
+        c = c.push(pad);
+
+        let content = container(c)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .center_x()
+            .center_y();
+        content.style(cosmic::theme::Container::Background).into()
+    }
+}
+
+impl ConsolationLockApp {
+    fn set_locked_hint(&self, locked: bool) -> Command<Message> {
+        let logind = self.logind.clone();
+        Command::perform(
+            async move {
+                let _ = tokio::task::spawn_blocking(move || {
+                    let logind = logind.get_or_init(|| {
+                        Logind::system()
+                            .map_err(|e| println!("Unable to reach logind: {}", e))
+                            .ok()
+                    });
+                    if let Some(logind) = logind {
+                        if let Err(e) = logind.set_locked_hint(locked) {
+                            println!("Unable to set the locked hint to {}: {}", locked, e);
+                        }
+                    }
+                })
+                .await;
+                locked
+            },
+            |locked| cosmic::app::Message::App(Message::LockedHintSet(locked)),
+        )
+    }
+
+    fn read_power(&self) -> Command<Message> {
+        let power = self.power.clone();
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let mut power = power.lock().unwrap();
+                    power.get_or_insert_with(power::system_source).status().ok()
+                })
+                .await
+                .ok()
+                .flatten()
+            },
+            |status| cosmic::app::Message::App(Message::PowerRead(status)),
+        )
+    }
+
+    fn session_lock(&mut self, event: SessionLockEvent) -> Command<Message> {
+        match event {
+            SessionLockEvent::Locked => {
+                self.locked = true;
+                let surfaces = self
+                    .outputs
+                    .iter()
+                    .map(|(output, id)| get_lock_surface(*id, output.clone()));
+                return Command::batch(surfaces.chain([self.set_locked_hint(true)]));
+            }
+            SessionLockEvent::Unlocked => exit(0),
+            SessionLockEvent::NotSupported => {
+                println!("The compositor doesn't support ext-session-lock");
+                exit(1);
+            }
+            // Another locker has the session, or the compositor took the
+            // lock away
+            SessionLockEvent::Finished => {
+                println!("The session lock was refused or ended");
+                exit(1);
+            }
+            _ => {}
+        }
+        Command::none()
+    }
+
+    // Moves stop at the pad's edges
+    fn move_selection(&mut self, right: i64, down: i64) {
+        let rows = (PAD.len() / PAD_COLUMNS) as i64;
+        let col = (self.selected % PAD_COLUMNS) as i64 + right;
+        let row = (self.selected / PAD_COLUMNS) as i64 + down;
+        let col = col.clamp(0, PAD_COLUMNS as i64 - 1);
+        let row = row.clamp(0, rows - 1);
+        self.selected = (row * PAD_COLUMNS as i64 + col) as usize;
+    }
+}
+
+// Runs on a blocking thread, PAM can take seconds to say no
+fn check(config: &LockConfig, entered: &str) -> bool {
+    match &config.pin_hash {
+        Some(hash) => pin::verify(hash, entered),
+        None => match pam::authenticate(&config.pam_service, entered) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("Unable to unlock: {}", e);
+                false
+            }
+        },
+    }
+}
@@ -0,0 +1,126 @@
+use consolation_common::config::NightLightConfig;
+use consolation_common::ipc;
+use consolation_common::nightlight::{
+    self, wayland::GammaControl, NightLightAction, NightLightSignal, Schedule, NEUTRAL,
+};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// How often the schedule is looked at again, often enough for the fade to
+// look smooth
+const INTERVAL: Duration = Duration::from_secs(30);
+
+// Whether the night light should be showing, shared with clients. Gamma is
+// set from a thread with its own Wayland connection, told only whether
+// it's wanted
+pub struct NightLight {
+    enabled: bool,
+    suspended: bool,
+    pause_for_games: bool,
+    clients: Vec<UnixStream>,
+    sender: Sender<bool>,
+}
+
+impl NightLight {
+    pub fn start(config: &NightLightConfig) -> NightLight {
+        let (sender, receiver) = mpsc::channel();
+        let schedule = config.schedule();
+        let night = config.temperature.min(NEUTRAL);
+        let transition = config.transition as f64;
+        std::thread::spawn(move || {
+            let mut gamma = match GammaControl::connect() {
+                Ok(gamma) => gamma,
+                Err(e) => {
+                    println!("Night light unavailable: {}", e);
+                    return;
+                }
+            };
+            let mut wanted = false;
+            loop {
+                match receiver.recv_timeout(INTERVAL) {
+                    Ok(active) => wanted = active,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+                // Only the latest matters
+                wanted = receiver.try_iter().last().unwrap_or(wanted);
+                let kelvin = match wanted {
+                    true => temperature_now(&schedule, night, transition),
+                    false => None,
+                };
+                if let Err(e) = gamma.set_temperature(kelvin) {
+                    println!("Unable to set gamma, stopping night light: {}", e);
+                    return;
+                }
+            }
+        });
+        let night_light = NightLight {
+            enabled: config.enabled,
+            suspended: false,
+            pause_for_games: config.pause_for_games,
+            clients: vec![],
+            sender,
+        };
+        night_light.send_wanted();
+        night_light
+    }
+
+    pub fn add_client(&mut self, client: UnixStream) {
+        let _ = client.set_write_timeout(Some(Duration::from_secs(1)));
+        if ipc::send(&client, &self.status()).is_ok() {
+            self.clients.push(client);
+        }
+    }
+
+    pub fn handle(&mut self, action: NightLightAction) {
+        match action {
+            NightLightAction::SetEnabled { enabled } => {
+                if self.enabled != enabled {
+                    self.enabled = enabled;
+                    self.changed();
+                }
+            }
+        }
+    }
+
+    // Colour accurate games are left alone while they're in front
+    pub fn set_game_in_front(&mut self, in_front: bool) {
+        let suspended = in_front && self.pause_for_games;
+        if self.suspended != suspended {
+            self.suspended = suspended;
+            self.changed();
+        }
+    }
+
+    fn changed(&mut self) {
+        self.send_wanted();
+        let status = self.status();
+        self.clients
+            .retain(|client| ipc::send(client, &status).is_ok());
+    }
+
+    fn send_wanted(&self) {
+        // Fails once the thread has given up, which it has said why
+        let _ = self.sender.send(self.enabled && !self.suspended);
+    }
+
+    fn status(&self) -> NightLightSignal {
+        NightLightSignal::Status {
+            enabled: self.enabled,
+            suspended: self.suspended,
+        }
+    }
+}
+
+// None by day, leaving the compositor's own gamma alone
+fn temperature_now(schedule: &Schedule, night: u32, transition: f64) -> Option<u32> {
+    let unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() as i64)
+        .unwrap_or_default();
+    let utc_offset = chrono::Local::now().offset().local_minus_utc() as i64;
+    let level = schedule.night_level(unix, utc_offset, transition);
+    let kelvin = nightlight::temperature(level, NEUTRAL, night);
+    (kelvin < NEUTRAL).then_some(kelvin)
+}
@@ -0,0 +1,23 @@
+use std::process::{Command, Stdio};
+
+pub use consolation_common::notifications::Urgency;
+
+// Hand a notification to whichever notification daemon is running, via
+// notify-send so we don't need our own D-Bus connection for it
+pub fn send(summary: &str, body: &str, urgency: Urgency) {
+    let child = Command::new("notify-send")
+        .arg("--app-name=Consolation")
+        .arg(format!("--urgency={}", urgency.as_str()))
+        .arg(summary)
+        .arg(body)
+        .stdin(Stdio::null())
+        .spawn();
+    match child {
+        Ok(mut child) => {
+            std::thread::spawn(move || {
+                let _ = child.wait();
+            });
+        }
+        Err(e) => println!("Unable to send notification {}: {}", summary, e),
+    }
+}
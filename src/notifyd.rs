@@ -0,0 +1,467 @@
+use consolation_common::config::{NotificationsConfig, ShellConfig};
+use consolation_common::ipc;
+use consolation_common::notifications::history::{
+    self, HistoryAction, HistorySignal, NotificationHistory,
+};
+use consolation_common::notifications::{
+    CloseReason, Notification, NotificationEvent, NotificationImage, NotificationServer,
+    ServerOptions, Urgency, BUS_NAME,
+};
+use cosmic::app::{Command, Core};
+use cosmic::iced::wayland::layer_surface::{
+    destroy_layer_surface, get_layer_surface, Anchor, KeyboardInteractivity, Layer,
+};
+use cosmic::iced::{self, window, Length, Subscription};
+use cosmic::iced_runtime::command::platform_specific::wayland::layer_surface::{
+    IcedMargin, SctkLayerSurfaceSettings,
+};
+use cosmic::widget::{self, button, column, icon, list::container, row, text};
+use cosmic::Application;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::exit;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Toasts are sized to be read from across the room
+const TOAST_HEIGHT: u32 = 200;
+const TOAST_GAP: i32 = 24;
+const IMAGE_SIZE: u16 = 96;
+
+struct UiFlags {
+    server: NotificationServer,
+    events: Receiver<NotificationEvent>,
+    history: NotificationHistory,
+    client_recv: Receiver<Message>,
+    config: NotificationsConfig,
+}
+
+fn main() {
+    let config = ShellConfig::load().notifications;
+    let (sender, events) = channel();
+    // Notifications stay open in the history, where the switcher shows
+    // them, until the user dismisses them
+    let options = ServerOptions {
+        default_timeout: Duration::from_secs(config.timeout),
+        persistence: true,
+    };
+    let server = match NotificationServer::session(sender, options) {
+        Ok(server) => server,
+        Err(e) => {
+            println!("Unable to own {}: {}", BUS_NAME, e);
+            exit(1);
+        }
+    };
+    let history = match NotificationHistory::default_path() {
+        Some(path) => NotificationHistory::load(&path),
+        None => NotificationHistory::default(),
+    };
+    server.restore(
+        history
+            .entries()
+            .iter()
+            .map(|entry| entry.notification.clone()),
+    );
+
+    let Some(socket_path) = history::socket_path() else {
+        println!("XDG_RUNTIME_DIR is not set, nowhere to put the socket");
+        exit(1);
+    };
+    let listener = match ipc::bind(&socket_path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("Unable to listen on {}: {}", socket_path.display(), e);
+            exit(1);
+        }
+    };
+    let (client_sender, client_recv) = channel();
+    std::thread::spawn(move || {
+        let action_sender = client_sender.clone();
+        ipc::serve(
+            listener,
+            |client| {
+                client_sender
+                    .send(Message::ClientConnected(Arc::new(client)))
+                    .is_ok()
+            },
+            move |action| action_sender.send(Message::History(action)).is_ok(),
+        )
+    });
+
+    let input = UiFlags {
+        server,
+        events,
+        history,
+        client_recv,
+        config,
+    };
+
+    // Toasts are layer surfaces, there's no main window
+    let mut settings = cosmic::app::Settings::default();
+    settings = settings.no_main_window(true);
+    settings = settings.transparent(true);
+    cosmic::app::run::<ConsolationNotifyApp>(settings, input).expect("Unable to start App");
+    let _ = std::fs::remove_file(&socket_path);
+    exit(0);
+}
+
+// A notification on screen, in one of the max_toasts spots down the right
+// hand side
+struct Toast {
+    notification: Notification,
+    surface: window::Id,
+    slot: usize,
+    // When to hide it. It stays in the history afterwards
+    hide_at: Option<Instant>,
+}
+
+struct ConsolationNotifyApp {
+    core: Core,
+    events: RefCell<Option<Receiver<NotificationEvent>>>,
+    client_recv: RefCell<Option<Receiver<Message>>>,
+    server: NotificationServer,
+    history: NotificationHistory,
+    clients: Vec<Arc<UnixStream>>,
+    config: NotificationsConfig,
+    toasts: Vec<Toast>,
+    // Waiting for a free spot, oldest first
+    queue: VecDeque<Notification>,
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    // Messages from the server
+    Show(Notification),
+    Closed(u32, CloseReason),
+    // Messages from history clients
+    ClientConnected(Arc<UnixStream>),
+    History(HistoryAction),
+    // Messages from user
+    Invoke(u32, String),
+    Dismiss(u32),
+
+    Tick(),
+}
+
+impl Application for ConsolationNotifyApp {
+    type Executor = cosmic::executor::Default;
+    type Flags = UiFlags;
+    type Message = Message;
+
+    const APP_ID: &'static str = "Consolation Notifications";
+
+    fn core(&self) -> &cosmic::app::Core {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut cosmic::app::Core {
+        &mut self.core
+    }
+
+    fn init(core: Core, flags: Self::Flags) -> (Self, Command<Self::Message>) {
+        (
+            ConsolationNotifyApp {
+                core,
+                events: RefCell::new(Some(flags.events)),
+                client_recv: RefCell::new(Some(flags.client_recv)),
+                server: flags.server,
+                history: flags.history,
+                clients: vec![],
+                config: flags.config,
+                toasts: vec![],
+                queue: VecDeque::new(),
+            },
+            Command::none(),
+        )
+    }
+
+    fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+        match message {
+            Message::Show(notification) => {
+                if !notification.transient {
+                    let (entry, dropped) = self.history.add(notification.clone());
+                    self.broadcast(&HistorySignal::Added {
+                        notification: entry,
+                    });
+                    // Too old to keep, let their senders know they're gone
+                    for id in dropped {
+                        self.server.dismiss(id);
+                    }
+                    self.save_history();
+                }
+                // Replacements update in place, wherever they are
+                let id = notification.id;
+                if let Some(toast) = self.toasts.iter_mut().find(|t| t.notification.id == id) {
+                    toast.hide_at = notification.timeout.map(|timeout| Instant::now() + timeout);
+                    toast.notification = notification;
+                    return Command::none();
+                }
+                if let Some(queued) = self.queue.iter_mut().find(|n| n.id == id) {
+                    *queued = notification;
+                    return Command::none();
+                }
+                if let Some(slot) = self.free_slot() {
+                    return self.show(notification, slot);
+                }
+                // Critical notifications are next in line
+                if notification.urgency == Urgency::Critical {
+                    self.queue.push_front(notification);
+                } else {
+                    self.queue.push_back(notification);
+                }
+            }
+            Message::Closed(id, reason) => {
+                // Expired notifications were transient, and never in the
+                // history. Anything else is gone for good
+                if reason != CloseReason::Expired {
+                    self.remove_from_history(id);
+                }
+                self.queue.retain(|n| n.id != id);
+                return self.hide(id);
+            }
+            Message::ClientConnected(client) => {
+                let _ = client.set_write_timeout(Some(Duration::from_secs(1)));
+                let snapshot = HistorySignal::Snapshot {
+                    notifications: self.history.entries().to_vec(),
+                };
+                if ipc::send(&client, &snapshot).is_ok() {
+                    self.clients.push(client);
+                }
+            }
+            Message::History(HistoryAction::Invoke { id, key }) => {
+                // Anything the server has forgotten can only be dismissed
+                if !self.server.invoke_action(id, &key) {
+                    self.remove_from_history(id);
+                }
+            }
+            Message::History(HistoryAction::Dismiss { id }) => {
+                if !self.server.dismiss(id) {
+                    self.remove_from_history(id);
+                }
+            }
+            Message::History(HistoryAction::DismissAll) => {
+                let ids: Vec<u32> = self
+                    .history
+                    .entries()
+                    .iter()
+                    .map(|entry| entry.notification.id)
+                    .collect();
+                for id in ids {
+                    if !self.server.dismiss(id) {
+                        self.remove_from_history(id);
+                    }
+                }
+            }
+            Message::History(HistoryAction::MarkRead) => {
+                if self.history.mark_read() {
+                    self.broadcast(&HistorySignal::Read);
+                    self.save_history();
+                }
+            }
+            Message::Invoke(id, key) => {
+                self.server.invoke_action(id, &key);
+            }
+            Message::Dismiss(id) => {
+                self.server.dismiss(id);
+            }
+            Message::Tick() => {
+                let now = Instant::now();
+                let due: Vec<u32> = self
+                    .toasts
+                    .iter()
+                    .filter(|t| t.hide_at.is_some_and(|at| at <= now))
+                    .map(|t| t.notification.id)
+                    .collect();
+                return Command::batch(due.into_iter().map(|id| self.hide(id)));
+            }
+        }
+        Command::none()
+    }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        let mut subscriptions = vec![
+            iced::subscription::unfold(
+                "notification events",
+                self.events.take(),
+                move |mut recvr| async move {
+                    let event = recvr.as_mut().unwrap().recv().unwrap();
+                    (
+                        match event {
+                            NotificationEvent::Show(notification) => Message::Show(notification),
+                            NotificationEvent::Closed { id, reason } => Message::Closed(id, reason),
+                        },
+                        recvr,
+                    )
+                },
+            ),
+            iced::subscription::unfold(
+                "history clients",
+                self.client_recv.take(),
+                move |mut recvr| async move {
+                    let message = recvr.as_mut().unwrap().recv().unwrap();
+                    (message, recvr)
+                },
+            ),
+        ];
+        if self.toasts.iter().any(|toast| toast.hide_at.is_some()) {
+            subscriptions.push(iced::time::every(Duration::from_secs(1)).map(|_| Message::Tick()));
+        }
+        Subscription::batch(subscriptions)
+    }
+
+    fn view(&self) -> cosmic::Element<Self::Message> {
+        text("").into()
+    }
+
+    fn view_window(&self, id: window::Id) -> cosmic::Element<Self::Message> {
+        match self.toasts.iter().find(|toast| toast.surface == id) {
+            Some(toast) => self.view_toast(&toast.notification),
+            None => text("").into(),
+        }
+    }
+}
+
+impl ConsolationNotifyApp {
+    fn free_slot(&self) -> Option<usize> {
+        (0..self.config.max_toasts).find(|slot| self.toasts.iter().all(|t| t.slot != *slot))
+    }
+
+    // Clients that can't keep up, or have gone away, are dropped
+    fn broadcast(&mut self, signal: &HistorySignal) {
+        self.clients
+            .retain(|client| ipc::send(client, signal).is_ok());
+    }
+
+    fn save_history(&self) {
+        if let Err(e) = self.history.save() {
+            println!("Unable to save notification history: {}", e);
+        }
+    }
+
+    fn remove_from_history(&mut self, id: u32) {
+        if self.history.remove(id) {
+            self.broadcast(&HistorySignal::Removed { id });
+            self.save_history();
+        }
+    }
+
+    // Take a toast off screen, moving the next queued one into its spot
+    fn hide(&mut self, id: u32) -> Command<Message> {
+        let Some(idx) = self.toasts.iter().position(|t| t.notification.id == id) else {
+            return Command::none();
+        };
+        let toast = self.toasts.remove(idx);
+        let mut commands = vec![destroy_layer_surface(toast.surface)];
+        if let Some(next) = self.queue.pop_front() {
+            commands.push(self.show(next, toast.slot));
+        }
+        Command::batch(commands)
+    }
+
+    fn show(&mut self, notification: Notification, slot: usize) -> Command<Message> {
+        let surface = window::Id::unique();
+        let top = TOAST_GAP + slot as i32 * (TOAST_HEIGHT as i32 + TOAST_GAP);
+        self.toasts.push(Toast {
+            hide_at: notification.timeout.map(|timeout| Instant::now() + timeout),
+            notification,
+            surface,
+            slot,
+        });
+        // Never take keyboard focus, the game keeps its controls
+        get_layer_surface(SctkLayerSurfaceSettings {
+            id: surface,
+            layer: Layer::Overlay,
+            keyboard_interactivity: KeyboardInteractivity::None,
+            anchor: Anchor::TOP | Anchor::RIGHT,
+            namespace: "consolation-notification".to_owned(),
+            margin: IcedMargin {
+                top,
+                right: TOAST_GAP,
+                bottom: 0,
+                left: 0,
+            },
+            size: Some((Some(self.config.width), Some(TOAST_HEIGHT))),
+            ..Default::default()
+        })
+    }
+
+    fn view_toast(&self, notification: &Notification) -> cosmic::Element<Message> {
+        let mut content = row().spacing(24);
+        if notification.urgency == Urgency::Critical {
+            content = content.push(icon::from_name("dialog-warning").size(IMAGE_SIZE));
+        }
+        if let Some(image) = image_for(notification) {
+            content = content.push(image);
+        }
+
+        let mut c = column().spacing(8).width(Length::Fill);
+        c = c.push(text::caption(notification.app_name.clone()));
+        c = c.push(text::title3(notification.summary.clone()));
+        if !notification.body.is_empty() {
+            c = c.push(text(notification.body.clone()).size(24));
+        }
+        let mut actions = row().spacing(12);
+        for action in &notification.actions {
+            if action.key == "default" {
+                continue;
+            }
+            actions = actions.push(
+                button(text(action.label.clone()).size(24))
+                    .on_press(Message::Invoke(notification.id, action.key.clone())),
+            );
+        }
+        c = c.push(actions);
+        content = content.push(c);
+
+        let close = button(icon::from_name("window-close").size(32))
+            .on_press(Message::Dismiss(notification.id));
+        content = content.push(close);
+
+        // Clicking the toast itself runs its default action, or dismisses it
+        let on_press = match notification.default_action() {
+            Some(action) => Message::Invoke(notification.id, action.key.clone()),
+            None => Message::Dismiss(notification.id),
+        };
+        let mut container = container(content)
+            .padding(24)
+            .width(Length::Fill)
+            .height(Length::Fill);
+        container = container.style(cosmic::theme::Container::Background);
+        widget::mouse_area(container).on_press(on_press).into()
+    }
+}
+
+// image-data pixels, then image-path, then the app's own icon
+fn image_for(notification: &Notification) -> Option<cosmic::Element<'static, Message>> {
+    let name = match &notification.image {
+        Some(NotificationImage::Pixels {
+            width,
+            height,
+            rgba,
+        }) => {
+            let handle = widget::image::Handle::from_pixels(*width, *height, rgba.clone());
+            return Some(
+                widget::image(handle)
+                    .width(Length::Fixed(IMAGE_SIZE as f32))
+                    .height(Length::Fixed(IMAGE_SIZE as f32))
+                    .into(),
+            );
+        }
+        Some(NotificationImage::Name { name }) => name,
+        None if !notification.app_icon.is_empty() => &notification.app_icon,
+        None => return None,
+    };
+    let path = name.strip_prefix("file://").unwrap_or(name);
+    if path.starts_with('/') {
+        Some(
+            icon::icon(icon::from_path(PathBuf::from(path)))
+                .size(IMAGE_SIZE)
+                .into(),
+        )
+    } else {
+        Some(icon::from_name(name.clone()).size(IMAGE_SIZE).into())
+    }
+}
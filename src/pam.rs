@@ -0,0 +1,111 @@
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+// Just enough of libpam to check a password. Every prompt PAM sends is
+// answered with the secret, which covers pam_unix and PIN modules alike
+const PAM_SUCCESS: c_int = 0;
+const PAM_BUF_ERR: c_int = 5;
+const PAM_PROMPT_ECHO_OFF: c_int = 1;
+const PAM_PROMPT_ECHO_ON: c_int = 2;
+
+#[repr(C)]
+struct PamMessage {
+    msg_style: c_int,
+    msg: *const c_char,
+}
+
+#[repr(C)]
+struct PamResponse {
+    resp: *mut c_char,
+    resp_retcode: c_int,
+}
+
+#[repr(C)]
+struct PamConv {
+    conv: extern "C" fn(c_int, *mut *const PamMessage, *mut *mut PamResponse, *mut c_void) -> c_int,
+    appdata_ptr: *mut c_void,
+}
+
+#[link(name = "pam")]
+extern "C" {
+    fn pam_start(
+        service: *const c_char,
+        user: *const c_char,
+        conv: *const PamConv,
+        handle: *mut *mut c_void,
+    ) -> c_int;
+    fn pam_authenticate(handle: *mut c_void, flags: c_int) -> c_int;
+    fn pam_end(handle: *mut c_void, status: c_int) -> c_int;
+    fn pam_strerror(handle: *mut c_void, errnum: c_int) -> *const c_char;
+}
+
+// Check `secret` for the user running us
+pub fn authenticate(service: &str, secret: &str) -> Result<(), String> {
+    let user = current_user().ok_or("Unable to find the current user")?;
+    let service = CString::new(service).map_err(|e| e.to_string())?;
+    let secret = CString::new(secret).map_err(|e| e.to_string())?;
+    let conv = PamConv {
+        conv: conversation,
+        appdata_ptr: secret.as_ptr() as *mut c_void,
+    };
+    let mut handle = ptr::null_mut();
+    // Safety: the strings are NUL terminated, and conv and secret outlive
+    // the handle, which is ended below
+    let status = unsafe { pam_start(service.as_ptr(), user.as_ptr(), &conv, &mut handle) };
+    if status != PAM_SUCCESS {
+        return Err(format!("Unable to start PAM: {}", status));
+    }
+    // Safety: handle came from a successful pam_start and isn't ended yet
+    let status = unsafe { pam_authenticate(handle, 0) };
+    let result = match status {
+        PAM_SUCCESS => Ok(()),
+        // Safety: pam_strerror returns a static, NUL terminated string
+        _ => Err(unsafe { CStr::from_ptr(pam_strerror(handle, status)) }
+            .to_string_lossy()
+            .into_owned()),
+    };
+    // Safety: handle is ended once, and not used after
+    unsafe { pam_end(handle, status) };
+    result
+}
+
+fn current_user() -> Option<CString> {
+    // Safety: getpwuid's result is checked for null, and only read before
+    // anything else could call it. pw_name is NUL terminated
+    unsafe {
+        let passwd = libc::getpwuid(libc::getuid());
+        if passwd.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr((*passwd).pw_name).to_owned())
+    }
+}
+
+// PAM frees the responses, so they're allocated with malloc
+extern "C" fn conversation(
+    count: c_int,
+    messages: *mut *const PamMessage,
+    responses: *mut *mut PamResponse,
+    secret: *mut c_void,
+) -> c_int {
+    let count = count.max(0) as usize;
+    // Safety: PAM passes count messages and somewhere to put the responses.
+    // secret is the NUL terminated string given to pam_start, still alive
+    // while authenticate waits on PAM. replies is zeroed, so prompts that
+    // aren't answered have a null resp
+    unsafe {
+        let replies = libc::calloc(count, std::mem::size_of::<PamResponse>()) as *mut PamResponse;
+        if replies.is_null() {
+            return PAM_BUF_ERR;
+        }
+        for idx in 0..count {
+            let message = *messages.add(idx);
+            if let PAM_PROMPT_ECHO_OFF | PAM_PROMPT_ECHO_ON = (*message).msg_style {
+                (*replies.add(idx)).resp = libc::strdup(secret as *const c_char);
+            }
+        }
+        *responses = replies;
+    }
+    PAM_SUCCESS
+}
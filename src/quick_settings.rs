@@ -0,0 +1,393 @@
+use consolation_common::backlight::{self, Backlight};
+use consolation_common::ipc;
+use consolation_common::nightlight::{self, NightLightAction, NightLightSignal};
+use consolation_common::profiles::{self, PowerProfiles};
+use consolation_common::volume::{self, AudioBackend, Volume};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// How far left and right move a slider
+const STEP: i64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuickSetting {
+    Brightness,
+    Volume,
+    Mute,
+    PowerProfile,
+    NightLight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NightLightStatus {
+    pub enabled: bool,
+    // Held off by consolation-shelld while a fullscreen game is in front
+    pub suspended: bool,
+}
+
+struct Backends {
+    backlight: Option<Box<dyn Backlight + Send>>,
+    audio: Option<Box<dyn AudioBackend + Send>>,
+    profiles: Option<Box<dyn PowerProfiles + Send>>,
+}
+
+impl Backends {
+    fn system() -> Backends {
+        Backends {
+            backlight: backlight::system_backlight(),
+            audio: volume::system_audio(),
+            profiles: profiles::system_profiles(),
+        }
+    }
+
+    fn write(&self, writes: &Writes) {
+        if let (Some(backlight), Some(percentage)) = (&self.backlight, writes.brightness) {
+            if let Err(e) = backlight.set_percentage(percentage) {
+                println!("Unable to set brightness: {}", e);
+            }
+        }
+        if let (Some(audio), Some(percentage)) = (&self.audio, writes.volume) {
+            if let Err(e) = audio.set_volume(percentage) {
+                println!("Unable to set volume: {}", e);
+            }
+        }
+        if let (Some(audio), Some(muted)) = (&self.audio, writes.muted) {
+            if let Err(e) = audio.set_muted(muted) {
+                println!("Unable to mute: {}", e);
+            }
+        }
+        if let (Some(profiles), Some(profile)) = (&self.profiles, &writes.profile) {
+            if let Err(e) = profiles.set_active(profile) {
+                println!("Unable to set power profile {}: {}", profile, e);
+            }
+        }
+    }
+
+    fn read(&self) -> Readings {
+        let brightness = self.backlight.as_ref().and_then(|backlight| {
+            backlight
+                .brightness()
+                .map(|brightness| brightness.percentage())
+                .map_err(|e| println!("Unable to read brightness: {}", e))
+                .ok()
+        });
+        let volume = self.audio.as_ref().and_then(|audio| {
+            audio
+                .volume()
+                .map_err(|e| println!("Unable to read volume: {}", e))
+                .ok()
+        });
+        let (profile, profile_choices) = match &self.profiles {
+            Some(profiles) => (
+                profiles
+                    .active()
+                    .map_err(|e| println!("Unable to read power profile: {}", e))
+                    .ok(),
+                profiles.profiles().unwrap_or_default(),
+            ),
+            None => (None, vec![]),
+        };
+        Readings {
+            changes: 0,
+            brightness,
+            volume,
+            profile,
+            profile_choices,
+            night_light: None,
+        }
+    }
+}
+
+// Changes made on screen and not written yet. They're all taken by the next
+// write, so a slider moved several steps meanwhile is only written where it
+// stopped
+#[derive(Default)]
+struct Writes {
+    brightness: Option<u8>,
+    volume: Option<u8>,
+    muted: Option<bool>,
+    profile: Option<String>,
+    night_light: Option<bool>,
+}
+
+impl Writes {
+    fn is_empty(&self) -> bool {
+        self.brightness.is_none()
+            && self.volume.is_none()
+            && self.muted.is_none()
+            && self.profile.is_none()
+            && self.night_light.is_none()
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    // How many changes have been made on screen, written or not
+    changes: u64,
+    writes: Writes,
+}
+
+// Everything read back in one go, to hand from the reading thread to the UI
+#[derive(Debug, Clone, Default)]
+pub struct Readings {
+    // The changes written before reading, so readings that miss later ones
+    // don't undo them on screen
+    changes: u64,
+    brightness: Option<u8>,
+    volume: Option<Volume>,
+    profile: Option<String>,
+    profile_choices: Vec<String>,
+    night_light: Option<NightLightStatus>,
+}
+
+// Brightness, volume, power profile and night light, each only when the
+// machine has a way to change it. Changes show straight away and are
+// written off the UI thread by `writer`
+pub struct QuickSettings {
+    // Found on the first read or write rather than here, as finding them
+    // runs wpctl and connects to D-Bus, which the UI thread shouldn't wait on
+    backends: Arc<Mutex<Option<Backends>>>,
+    pending: Arc<Mutex<Pending>>,
+    changes: u64,
+    pub brightness: Option<u8>,
+    pub volume: Option<Volume>,
+    pub profile: Option<String>,
+    pub profile_choices: Vec<String>,
+    // None when consolation-shelld's night light can't be reached
+    pub night_light: Option<NightLightStatus>,
+}
+
+impl QuickSettings {
+    // Empty until the first read comes back
+    pub fn system() -> QuickSettings {
+        QuickSettings {
+            backends: Arc::new(Mutex::new(None)),
+            pending: Arc::new(Mutex::new(Pending::default())),
+            changes: 0,
+            brightness: None,
+            volume: None,
+            profile: None,
+            profile_choices: vec![],
+            night_light: None,
+        }
+    }
+
+    // Read everything again, as brightness keys and other programs change
+    // these too. Slow enough to be run off the UI thread, with what it
+    // returns given to `apply`
+    pub fn reader(&self) -> impl FnOnce() -> Readings + Send + 'static {
+        let backends = self.backends.clone();
+        let pending = self.pending.clone();
+        move || sync(&backends, &pending, true).unwrap_or_default()
+    }
+
+    // Write the changes made since the last write, then read back. None
+    // when an earlier write already took them
+    pub fn writer(&self) -> impl FnOnce() -> Option<Readings> + Send + 'static {
+        let backends = self.backends.clone();
+        let pending = self.pending.clone();
+        move || sync(&backends, &pending, false)
+    }
+
+    pub fn apply(&mut self, readings: Readings) {
+        let Readings {
+            changes,
+            brightness,
+            volume,
+            profile,
+            profile_choices,
+            night_light,
+        } = readings;
+        if changes < self.changes {
+            return;
+        }
+        self.brightness = brightness;
+        self.volume = volume;
+        self.profile = profile;
+        self.profile_choices = profile_choices;
+        self.night_light = night_light;
+    }
+
+    pub fn rows(&self) -> Vec<QuickSetting> {
+        let mut rows = vec![];
+        if self.brightness.is_some() {
+            rows.push(QuickSetting::Brightness);
+        }
+        if self.volume.is_some() {
+            rows.push(QuickSetting::Volume);
+            rows.push(QuickSetting::Mute);
+        }
+        if self.profile.is_some() && !self.profile_choices.is_empty() {
+            rows.push(QuickSetting::PowerProfile);
+        }
+        if self.night_light.is_some() {
+            rows.push(QuickSetting::NightLight);
+        }
+        rows
+    }
+
+    fn change(&mut self, change: impl FnOnce(&mut Writes)) {
+        self.changes += 1;
+        let mut pending = self.pending.lock().unwrap();
+        pending.changes = self.changes;
+        change(&mut pending.writes);
+    }
+
+    pub fn set_brightness(&mut self, percentage: u8) {
+        if self.brightness.is_none() {
+            return;
+        }
+        // Turning the screen off from here would leave no way back
+        let percentage = percentage.clamp(1, 100);
+        self.brightness = Some(percentage);
+        self.change(|writes| writes.brightness = Some(percentage));
+    }
+
+    pub fn set_volume(&mut self, percentage: u8) {
+        let Some(volume) = &mut self.volume else {
+            return;
+        };
+        let percentage = percentage.min(100);
+        volume.percentage = percentage;
+        self.change(|writes| writes.volume = Some(percentage));
+    }
+
+    pub fn toggle_mute(&mut self) {
+        let Some(volume) = &mut self.volume else {
+            return;
+        };
+        volume.muted = !volume.muted;
+        let muted = volume.muted;
+        self.change(|writes| writes.muted = Some(muted));
+    }
+
+    pub fn set_profile(&mut self, profile: &str) {
+        if self.profile.is_none() {
+            return;
+        }
+        self.profile = Some(profile.to_owned());
+        let profile = profile.to_owned();
+        self.change(|writes| writes.profile = Some(profile));
+    }
+
+    pub fn toggle_night_light(&mut self) {
+        let Some(status) = &mut self.night_light else {
+            return;
+        };
+        status.enabled = !status.enabled;
+        let enabled = status.enabled;
+        self.change(|writes| writes.night_light = Some(enabled));
+    }
+
+    // Left (-1) or right (1) on a setting
+    pub fn step(&mut self, setting: QuickSetting, direction: i64) {
+        match setting {
+            QuickSetting::Brightness => {
+                if let Some(brightness) = self.brightness {
+                    self.set_brightness(stepped(brightness, direction));
+                }
+            }
+            QuickSetting::Volume => {
+                if let Some(volume) = self.volume {
+                    self.set_volume(stepped(volume.percentage, direction));
+                }
+            }
+            QuickSetting::Mute => self.toggle_mute(),
+            QuickSetting::NightLight => self.toggle_night_light(),
+            QuickSetting::PowerProfile => {
+                let Some(current) = &self.profile else {
+                    return;
+                };
+                let len = self.profile_choices.len() as i64;
+                let idx = self
+                    .profile_choices
+                    .iter()
+                    .position(|choice| choice == current)
+                    .map_or(0, |idx| (idx as i64 + direction).clamp(0, len - 1));
+                if let Some(profile) = self.profile_choices.get(idx as usize).cloned() {
+                    if Some(&profile) != self.profile.as_ref() {
+                        self.set_profile(&profile);
+                    }
+                }
+            }
+        }
+    }
+
+    // Select on a setting, where sliders have nothing to do
+    pub fn select(&mut self, setting: QuickSetting) {
+        match setting {
+            QuickSetting::Mute => self.toggle_mute(),
+            QuickSetting::NightLight => self.toggle_night_light(),
+            QuickSetting::PowerProfile => {
+                if let Some(next) = self.next_profile() {
+                    self.set_profile(&next);
+                }
+            }
+            QuickSetting::Brightness | QuickSetting::Volume => {}
+        }
+    }
+
+    // The profile after the active one, going round to the first
+    pub fn next_profile(&self) -> Option<String> {
+        let current = self.profile.as_ref()?;
+        let idx = self
+            .profile_choices
+            .iter()
+            .position(|choice| choice == current)
+            .map_or(0, |idx| (idx + 1) % self.profile_choices.len());
+        self.profile_choices.get(idx).cloned()
+    }
+}
+
+// Snapped to the steps, so a value set by a slider lines up again
+fn stepped(percentage: u8, direction: i64) -> u8 {
+    let snapped = (percentage as i64 + STEP / 2) / STEP * STEP;
+    (snapped + direction * STEP).clamp(0, 100) as u8
+}
+
+// Taking the writes while holding the backends keeps them in order when
+// two writers run at once
+fn sync(
+    backends: &Mutex<Option<Backends>>,
+    pending: &Mutex<Pending>,
+    read_anyway: bool,
+) -> Option<Readings> {
+    let mut backends = backends.lock().unwrap();
+    let (changes, writes) = {
+        let mut pending = pending.lock().unwrap();
+        (pending.changes, std::mem::take(&mut pending.writes))
+    };
+    if writes.is_empty() && !read_anyway {
+        return None;
+    }
+    let backends = backends.get_or_insert_with(Backends::system);
+    backends.write(&writes);
+    let mut readings = backends.read();
+    readings.changes = changes;
+    readings.night_light = night_light_request(
+        writes
+            .night_light
+            .map(|enabled| NightLightAction::SetEnabled { enabled }),
+    );
+    Some(readings)
+}
+
+// consolation-shelld sends the night light's status on connecting, and again
+// after anything changes it
+fn night_light_request(action: Option<NightLightAction>) -> Option<NightLightStatus> {
+    let stream = nightlight::connect().ok()?;
+    stream
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .ok()?;
+    let mut messages = ipc::read_messages::<NightLightSignal>(stream.try_clone().ok()?);
+    let mut signal = messages.next()?;
+    if let Some(action) = action {
+        if let Err(e) = ipc::send(&stream, &action) {
+            println!("Unable to reach the night light: {}", e);
+        }
+        // Nothing is sent back when it was already as asked
+        signal = messages.next().unwrap_or(signal);
+    }
+    let NightLightSignal::Status { enabled, suspended } = signal;
+    Some(NightLightStatus { enabled, suspended })
+}
@@ -0,0 +1,1158 @@
+use consolation_common::config::{BackgroundConfig, ImageFit, ShellConfig};
+use consolation_common::config_edit::ConfigEdit;
+use cosmic::app::{Command, Core};
+use cosmic::iced::{
+    self,
+    keyboard::{self, key},
+    Length, Subscription,
+};
+use cosmic::widget::{self, button, column, icon, list::container, row, text};
+use cosmic::Application;
+use std::collections::HashMap;
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+mod displays;
+mod entry;
+
+use displays::{DisplayField, DisplayRow, Displays};
+use entry::{Entry, ENTRY_COLUMNS};
+
+// Offered by left and right on colour settings, so they can be changed
+// without a keyboard
+const PALETTE: [&str; 8] = [
+    "#000000", "#1e1e2e", "#303446", "#2e3440", "#1a5fb4", "#26a269", "#a51d2d", "#ffffff",
+];
+
+fn main() {
+    let Some(path) = ShellConfig::write_path() else {
+        println!("Unable to find a config directory");
+        exit(1);
+    };
+    let edit = match ConfigEdit::load(&path) {
+        Ok(edit) => edit,
+        Err(e) => {
+            println!("{}", e);
+            exit(1);
+        }
+    };
+
+    let mut settings = cosmic::app::Settings::default();
+    settings = settings.client_decorations(false);
+    cosmic::app::run::<ConsolationSettingsApp>(settings, edit).expect("Unable to start App");
+    exit(0);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldKind {
+    Toggle,
+    Number { min: i64, max: i64, step: i64 },
+    Choice(&'static [&'static str]),
+    Text,
+    // "#rrggbb"
+    Colour,
+    // Comma separated
+    TextList,
+    NumberList { min: i64, max: i64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FieldValue {
+    Bool(bool),
+    Number(i64),
+    Text(String),
+    TextList(Vec<String>),
+    NumberList(Vec<i64>),
+}
+
+impl FieldValue {
+    fn describe(&self) -> String {
+        match self {
+            FieldValue::Bool(true) => "On".to_owned(),
+            FieldValue::Bool(false) => "Off".to_owned(),
+            FieldValue::Number(number) => number.to_string(),
+            FieldValue::Text(text) => text.clone(),
+            FieldValue::TextList(list) => list.join(", "),
+            FieldValue::NumberList(list) => list
+                .iter()
+                .map(|number| number.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+
+    fn to_toml(&self) -> toml_edit::Value {
+        match self {
+            FieldValue::Bool(value) => (*value).into(),
+            FieldValue::Number(number) => (*number).into(),
+            FieldValue::Text(text) => text.as_str().into(),
+            FieldValue::TextList(list) => toml_edit::Array::from_iter(list).into(),
+            FieldValue::NumberList(list) => {
+                toml_edit::Array::from_iter(list.iter().copied()).into()
+            }
+        }
+    }
+}
+
+// One setting. What's shown comes from the typed config, so settings the
+// file leaves out show their defaults
+struct Field {
+    label: &'static str,
+    key: &'static [&'static str],
+    kind: FieldKind,
+    get: fn(&ShellConfig) -> FieldValue,
+    // Hidden when it doesn't apply, like image settings on a colour
+    // background
+    shown: fn(&ShellConfig) -> bool,
+    // Settings next to this one that a choice needs, with their defaults
+    requires: fn(&str) -> &'static [(&'static str, &'static str)],
+}
+
+fn field(
+    label: &'static str,
+    key: &'static [&'static str],
+    kind: FieldKind,
+    get: fn(&ShellConfig) -> FieldValue,
+) -> Field {
+    Field {
+        label,
+        key,
+        kind,
+        get,
+        shown: |_| true,
+        requires: |_| &[],
+    }
+}
+
+impl Field {
+    fn shown(mut self, shown: fn(&ShellConfig) -> bool) -> Field {
+        self.shown = shown;
+        self
+    }
+
+    fn requires(mut self, requires: fn(&str) -> &'static [(&'static str, &'static str)]) -> Field {
+        self.requires = requires;
+        self
+    }
+
+    // The value after pressing left (-1) or right (1)
+    fn step(&self, value: &FieldValue, direction: i64) -> Option<FieldValue> {
+        match (self.kind, value) {
+            (FieldKind::Toggle, FieldValue::Bool(value)) => Some(FieldValue::Bool(!value)),
+            (FieldKind::Number { min, max, step }, FieldValue::Number(number)) => Some(
+                FieldValue::Number((number + direction * step).clamp(min, max)),
+            ),
+            (FieldKind::Choice(choices), FieldValue::Text(current)) => Some(FieldValue::Text(
+                cycle(choices, current, direction).to_owned(),
+            )),
+            (FieldKind::Colour, FieldValue::Text(current)) => Some(FieldValue::Text(
+                cycle(&PALETTE, current, direction).to_owned(),
+            )),
+            _ => None,
+        }
+    }
+
+    // Typed text, checked before it goes anywhere near the file
+    fn parse(&self, input: &str) -> Result<FieldValue, String> {
+        match self.kind {
+            FieldKind::Text => Ok(FieldValue::Text(input.trim().to_owned())),
+            FieldKind::Colour => {
+                let colour = input.trim();
+                let valid = colour
+                    .strip_prefix('#')
+                    .is_some_and(|hex| hex.len() == 6 && u32::from_str_radix(hex, 16).is_ok());
+                match valid {
+                    true => Ok(FieldValue::Text(colour.to_lowercase())),
+                    false => Err(format!("{} isn't a colour like #1e1e2e", colour)),
+                }
+            }
+            FieldKind::TextList => Ok(FieldValue::TextList(
+                input
+                    .split(',')
+                    .map(|item| item.trim().to_owned())
+                    .filter(|item| !item.is_empty())
+                    .collect(),
+            )),
+            FieldKind::NumberList { min, max } => input
+                .split(',')
+                .map(|item| item.trim())
+                .filter(|item| !item.is_empty())
+                .map(|item| match item.parse::<i64>() {
+                    Ok(number) if (min..=max).contains(&number) => Ok(number),
+                    _ => Err(format!("{} isn't a number from {} to {}", item, min, max)),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(FieldValue::NumberList),
+            FieldKind::Number { min, max, .. } => match input.trim().parse::<i64>() {
+                Ok(number) if (min..=max).contains(&number) => Ok(FieldValue::Number(number)),
+                _ => Err(format!("{} isn't a number from {} to {}", input, min, max)),
+            },
+            FieldKind::Toggle | FieldKind::Choice(_) => Err("Use left and right".to_owned()),
+        }
+    }
+
+    // What the on-screen entry offers for this field, and whether it has
+    // a space key
+    fn entry_chars(&self) -> (&'static str, bool) {
+        match self.kind {
+            FieldKind::Colour => ("#0123456789abcdef", false),
+            FieldKind::NumberList { .. } => ("0123456789,", false),
+            _ => ("1234567890qwertyuiopasdfghjkl~zxcvbnm/._-,", true),
+        }
+    }
+
+    fn is_typed(&self) -> bool {
+        matches!(
+            self.kind,
+            FieldKind::Text
+                | FieldKind::Colour
+                | FieldKind::TextList
+                | FieldKind::NumberList { .. }
+        )
+    }
+}
+
+// The choice before or after `current`, wrapping round
+fn cycle<'a>(choices: &[&'a str], current: &str, direction: i64) -> &'a str {
+    let len = choices.len() as i64;
+    let idx = choices
+        .iter()
+        .position(|choice| *choice == current)
+        .map_or(0, |idx| (idx as i64 + direction).rem_euclid(len));
+    choices[idx as usize]
+}
+
+struct Page {
+    title: &'static str,
+    icon: &'static str,
+    fields: Vec<Field>,
+    // Shown under the settings, for what can only be changed in the file
+    hint: &'static str,
+}
+
+fn pages() -> Vec<Page> {
+    vec![
+        Page {
+            title: "Switcher",
+            icon: "preferences-system-windows",
+            fields: vec![
+                field(
+                    "Seconds before offering to force quit",
+                    &["switcher", "close_timeout"],
+                    FieldKind::Number {
+                        min: 1,
+                        max: 60,
+                        step: 1,
+                    },
+                    |c| FieldValue::Number(c.switcher.close_timeout as i64),
+                ),
+                field(
+                    "Pause games when switching away",
+                    &["switcher", "auto_pause"],
+                    FieldKind::Toggle,
+                    |c| FieldValue::Bool(c.switcher.auto_pause),
+                ),
+            ],
+            hint: "",
+        },
+        Page {
+            title: "Launcher",
+            icon: "view-app-grid",
+            fields: vec![
+                field(
+                    "Applications from .desktop files",
+                    &["launcher", "desktop_entries"],
+                    FieldKind::Toggle,
+                    |c| FieldValue::Bool(c.launcher.desktop_entries),
+                ),
+                field(
+                    "RetroArch playlists",
+                    &["launcher", "retroarch", "playlists"],
+                    FieldKind::Toggle,
+                    |c| {
+                        FieldValue::Bool(c.launcher.retroarch.as_ref().is_some_and(|r| r.playlists))
+                    },
+                )
+                .shown(|c| c.launcher.retroarch.is_some()),
+                field(
+                    "RetroArch config directory",
+                    &["launcher", "retroarch", "config_dir"],
+                    FieldKind::Text,
+                    |c| {
+                        FieldValue::Text(
+                            c.launcher
+                                .retroarch
+                                .as_ref()
+                                .map(|r| r.config_dir.clone())
+                                .unwrap_or_default(),
+                        )
+                    },
+                )
+                .shown(|c| c.launcher.retroarch.is_some()),
+                field(
+                    "ROM directories",
+                    &["launcher", "retroarch", "rom_dirs"],
+                    FieldKind::TextList,
+                    |c| {
+                        FieldValue::TextList(
+                            c.launcher
+                                .retroarch
+                                .as_ref()
+                                .map(|r| r.rom_dirs.clone())
+                                .unwrap_or_default(),
+                        )
+                    },
+                )
+                .shown(|c| c.launcher.retroarch.is_some()),
+            ],
+            hint: "RetroArch, launch profiles and categories are set up in shell.toml",
+        },
+        Page {
+            title: "Notifications",
+            icon: "preferences-system-notifications",
+            fields: vec![
+                field(
+                    "Seconds notifications stay on screen",
+                    &["notifications", "timeout"],
+                    FieldKind::Number {
+                        min: 1,
+                        max: 60,
+                        step: 1,
+                    },
+                    |c| FieldValue::Number(c.notifications.timeout as i64),
+                ),
+                field(
+                    "Notifications on screen at once",
+                    &["notifications", "max_toasts"],
+                    FieldKind::Number {
+                        min: 1,
+                        max: 6,
+                        step: 1,
+                    },
+                    |c| FieldValue::Number(c.notifications.max_toasts as i64),
+                ),
+                field(
+                    "Notification width",
+                    &["notifications", "width"],
+                    FieldKind::Number {
+                        min: 400,
+                        max: 1600,
+                        step: 40,
+                    },
+                    |c| FieldValue::Number(c.notifications.width as i64),
+                ),
+            ],
+            hint: "",
+        },
+        Page {
+            title: "Power",
+            icon: "battery-good-symbolic",
+            fields: vec![
+                field(
+                    "Battery and charger warnings",
+                    &["power", "warnings"],
+                    FieldKind::Toggle,
+                    |c| FieldValue::Bool(c.power.warnings),
+                ),
+                field(
+                    "Warn at battery percentages",
+                    &["power", "low"],
+                    FieldKind::NumberList { min: 1, max: 100 },
+                    |c| FieldValue::NumberList(c.power.low.iter().map(|p| *p as i64).collect()),
+                )
+                .shown(|c| c.power.warnings),
+                field(
+                    "Critical warning at percentage",
+                    &["power", "critical"],
+                    FieldKind::Number {
+                        min: 1,
+                        max: 50,
+                        step: 1,
+                    },
+                    |c| FieldValue::Number(c.power.critical as i64),
+                )
+                .shown(|c| c.power.warnings),
+                field(
+                    "Warn about controllers at percentage",
+                    &["power", "controller_low"],
+                    FieldKind::Number {
+                        min: 5,
+                        max: 50,
+                        step: 5,
+                    },
+                    |c| FieldValue::Number(c.power.controller_low as i64),
+                )
+                .shown(|c| c.power.warnings),
+                field(
+                    "Seconds between checks",
+                    &["power", "interval"],
+                    FieldKind::Number {
+                        min: 5,
+                        max: 600,
+                        step: 5,
+                    },
+                    |c| FieldValue::Number(c.power.interval as i64),
+                )
+                .shown(|c| c.power.warnings),
+            ],
+            hint: "",
+        },
+        Page {
+            title: "Background",
+            icon: "preferences-desktop-wallpaper",
+            fields: background_fields(),
+            hint: "",
+        },
+    ]
+}
+
+// The background used on outputs without one of their own
+fn background_fields() -> Vec<Field> {
+    vec![
+        field(
+            "Background",
+            &["desktop", "background", "type"],
+            FieldKind::Choice(&["colour", "gradient", "image", "slideshow"]),
+            |c| {
+                FieldValue::Text(
+                    match c.desktop.background {
+                        BackgroundConfig::Colour { .. } => "colour",
+                        BackgroundConfig::Gradient { .. } => "gradient",
+                        BackgroundConfig::Image { .. } => "image",
+                        BackgroundConfig::Slideshow { .. } => "slideshow",
+                    }
+                    .to_owned(),
+                )
+            },
+        )
+        .requires(|choice| match choice {
+            "colour" => &[("colour", "#000000")],
+            "gradient" => &[("from", "#000000"), ("to", "#1e1e2e")],
+            "image" => &[("path", "")],
+            "slideshow" => &[("directory", "~/Pictures")],
+            _ => &[],
+        }),
+        field(
+            "Colour",
+            &["desktop", "background", "colour"],
+            FieldKind::Colour,
+            |c| match &c.desktop.background {
+                BackgroundConfig::Colour { colour } => FieldValue::Text(colour.clone()),
+                _ => FieldValue::Text(String::new()),
+            },
+        )
+        .shown(|c| matches!(c.desktop.background, BackgroundConfig::Colour { .. })),
+        field(
+            "From",
+            &["desktop", "background", "from"],
+            FieldKind::Colour,
+            |c| match &c.desktop.background {
+                BackgroundConfig::Gradient { from, .. } => FieldValue::Text(from.clone()),
+                _ => FieldValue::Text(String::new()),
+            },
+        )
+        .shown(|c| matches!(c.desktop.background, BackgroundConfig::Gradient { .. })),
+        field(
+            "To",
+            &["desktop", "background", "to"],
+            FieldKind::Colour,
+            |c| match &c.desktop.background {
+                BackgroundConfig::Gradient { to, .. } => FieldValue::Text(to.clone()),
+                _ => FieldValue::Text(String::new()),
+            },
+        )
+        .shown(|c| matches!(c.desktop.background, BackgroundConfig::Gradient { .. })),
+        field(
+            "Angle",
+            &["desktop", "background", "angle"],
+            FieldKind::Number {
+                min: 0,
+                max: 345,
+                step: 15,
+            },
+            |c| match c.desktop.background {
+                BackgroundConfig::Gradient { angle, .. } => {
+                    FieldValue::Number(angle.round() as i64)
+                }
+                _ => FieldValue::Number(0),
+            },
+        )
+        .shown(|c| matches!(c.desktop.background, BackgroundConfig::Gradient { .. })),
+        field(
+            "Image",
+            &["desktop", "background", "path"],
+            FieldKind::Text,
+            |c| match &c.desktop.background {
+                BackgroundConfig::Image { path, .. } => FieldValue::Text(path.clone()),
+                _ => FieldValue::Text(String::new()),
+            },
+        )
+        .shown(|c| matches!(c.desktop.background, BackgroundConfig::Image { .. })),
+        field(
+            "Directory",
+            &["desktop", "background", "directory"],
+            FieldKind::Text,
+            |c| match &c.desktop.background {
+                BackgroundConfig::Slideshow { directory, .. } => {
+                    FieldValue::Text(directory.clone())
+                }
+                _ => FieldValue::Text(String::new()),
+            },
+        )
+        .shown(|c| matches!(c.desktop.background, BackgroundConfig::Slideshow { .. })),
+        field(
+            "Seconds per image",
+            &["desktop", "background", "interval"],
+            FieldKind::Number {
+                min: 10,
+                max: 3600,
+                step: 10,
+            },
+            |c| match c.desktop.background {
+                BackgroundConfig::Slideshow { interval, .. } => FieldValue::Number(interval as i64),
+                _ => FieldValue::Number(0),
+            },
+        )
+        .shown(|c| matches!(c.desktop.background, BackgroundConfig::Slideshow { .. })),
+        field(
+            "Fit",
+            &["desktop", "background", "fit"],
+            FieldKind::Choice(&["crop", "scale", "stretch"]),
+            |c| {
+                let fit = match c.desktop.background {
+                    BackgroundConfig::Image { fit, .. }
+                    | BackgroundConfig::Slideshow { fit, .. } => fit,
+                    _ => ImageFit::default(),
+                };
+                FieldValue::Text(
+                    match fit {
+                        ImageFit::Crop => "crop",
+                        ImageFit::Scale => "scale",
+                        ImageFit::Stretch => "stretch",
+                    }
+                    .to_owned(),
+                )
+            },
+        )
+        .shown(|c| {
+            matches!(
+                c.desktop.background,
+                BackgroundConfig::Image { .. } | BackgroundConfig::Slideshow { .. }
+            )
+        }),
+        field(
+            "Show the focused game's image",
+            &["desktop", "app_themed"],
+            FieldKind::Toggle,
+            |c| FieldValue::Bool(c.desktop.app_themed),
+        ),
+        field(
+            "Crossfade milliseconds",
+            &["desktop", "fade"],
+            FieldKind::Number {
+                min: 0,
+                max: 2000,
+                step: 100,
+            },
+            |c| FieldValue::Number(c.desktop.fade as i64),
+        )
+        .shown(|c| c.desktop.app_themed),
+    ]
+}
+
+// Everything that can be selected, top to bottom
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Row {
+    Page(usize),
+    Field(usize),
+    Displays,
+    Display(DisplayRow),
+    Back,
+    Save,
+}
+
+struct ConsolationSettingsApp {
+    core: Core,
+    edit: ConfigEdit,
+    // What the file loads as right now
+    config: ShellConfig,
+    pages: Vec<Page>,
+    page: Option<usize>,
+    // Open instead of a page. Displays aren't in shell.toml, they're
+    // changed straight away through the compositor
+    displays: Option<Displays>,
+    selection: usize,
+    // Text being typed into a field, by field index on the open page
+    drafts: HashMap<usize, String>,
+    // Open over the page while typing into a field without a keyboard
+    entry: Option<Entry>,
+    unsaved: bool,
+    status: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    OpenPage(usize),
+    Step(usize, i64),
+    Input(usize, String),
+    Submit(usize),
+    OpenEntry(usize),
+    EntryPress(usize),
+    Save(),
+    OpenDisplays(),
+    DisplayStep(usize, DisplayField, i64),
+    DisplayApply(),
+    DisplayKeep(),
+    DisplayRevert(),
+    DisplayTick(),
+
+    // Messages from keyboard or controller
+    ArrowUp(),
+    ArrowDown(),
+    ArrowLeft(),
+    ArrowRight(),
+    Select(),
+    Back(),
+}
+
+impl Application for ConsolationSettingsApp {
+    type Executor = cosmic::executor::Default;
+    type Flags = ConfigEdit;
+    type Message = Message;
+
+    const APP_ID: &'static str = "Consolation Settings";
+
+    fn core(&self) -> &cosmic::app::Core {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut cosmic::app::Core {
+        &mut self.core
+    }
+
+    fn init(core: Core, flags: Self::Flags) -> (Self, Command<Self::Message>) {
+        // A file that parses as TOML but not as our config still opens, it
+        // just can't be saved until it's fixed
+        let (config, status) = match flags.config() {
+            Ok(config) => (config, None),
+            Err(e) => (
+                ShellConfig::default(),
+                Some(format!("{} has a problem: {}", flags.path().display(), e)),
+            ),
+        };
+        (
+            ConsolationSettingsApp {
+                core,
+                edit: flags,
+                config,
+                pages: pages(),
+                page: None,
+                displays: None,
+                selection: 0,
+                drafts: HashMap::new(),
+                entry: None,
+                unsaved: false,
+                status,
+            },
+            Command::none(),
+        )
+    }
+
+    fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+        match message {
+            Message::OpenPage(idx) => {
+                self.page = Some(idx);
+                self.drafts.clear();
+                // Start on the first setting rather than Back
+                self.selection = 1;
+                self.clamp_selection();
+            }
+            Message::Step(idx, direction) => {
+                let Some(field) = self.field(idx) else {
+                    return Command::none();
+                };
+                if let Some(value) = field.step(&(field.get)(&self.config), direction) {
+                    self.apply(idx, value);
+                }
+            }
+            Message::Input(idx, input) => {
+                self.drafts.insert(idx, input);
+            }
+            Message::Submit(idx) => {
+                let Some(input) = self.drafts.get(&idx) else {
+                    return Command::none();
+                };
+                let Some(field) = self.field(idx) else {
+                    return Command::none();
+                };
+                match field.parse(input) {
+                    Ok(value) => {
+                        self.drafts.remove(&idx);
+                        self.apply(idx, value);
+                    }
+                    Err(e) => self.status = Some(e),
+                }
+            }
+            Message::OpenEntry(idx) => {
+                let Some(field) = self.field(idx) else {
+                    return Command::none();
+                };
+                let text = match self.drafts.remove(&idx) {
+                    Some(draft) => draft,
+                    None => (field.get)(&self.config).describe(),
+                };
+                let (chars, space) = field.entry_chars();
+                self.entry = Some(Entry::new(idx, text, chars, space));
+            }
+            Message::EntryPress(key) => {
+                let Some(entry) = &mut self.entry else {
+                    return Command::none();
+                };
+                if !entry.press(key) {
+                    return Command::none();
+                }
+                let (idx, typed) = (entry.field, entry.text.clone());
+                let Some(field) = self.field(idx) else {
+                    return Command::none();
+                };
+                // A bad value stays open to be fixed
+                match field.parse(&typed) {
+                    Ok(value) => {
+                        self.entry = None;
+                        self.apply(idx, value);
+                    }
+                    Err(e) => self.status = Some(e),
+                }
+            }
+            Message::Save() => match self.edit.save() {
+                Ok(config) => {
+                    self.config = config;
+                    self.unsaved = false;
+                    self.status = Some(format!(
+                        "Saved to {}. Restart the shell for everything to pick it up",
+                        self.edit.path().display()
+                    ));
+                }
+                Err(e) => self.status = Some(e),
+            },
+            Message::OpenDisplays() => match Displays::open() {
+                Ok(displays) => {
+                    self.displays = Some(displays);
+                    self.status = None;
+                    self.selection = 1;
+                    self.clamp_selection();
+                }
+                Err(e) => self.status = Some(format!("Unable to list displays: {}", e)),
+            },
+            Message::DisplayStep(idx, field, direction) => {
+                if let Some(displays) = &mut self.displays {
+                    displays.step(idx, field, direction);
+                }
+                self.clamp_selection();
+            }
+            Message::DisplayApply() => {
+                let Some(displays) = &mut self.displays else {
+                    return Command::none();
+                };
+                if !displays.is_changed() {
+                    return Command::none();
+                }
+                match displays.apply(Instant::now()) {
+                    Ok(()) => {
+                        self.status = None;
+                        // Keep is selected, so one press is enough if the
+                        // picture is fine
+                        self.selection = 0;
+                    }
+                    Err(e) => self.status = Some(e),
+                }
+                self.clamp_selection();
+            }
+            Message::DisplayKeep() => {
+                if let Some(displays) = &mut self.displays {
+                    displays.keep();
+                }
+                self.status = None;
+                self.clamp_selection();
+            }
+            Message::DisplayRevert() => {
+                if let Some(displays) = &mut self.displays {
+                    self.status = displays.revert().err();
+                }
+                self.clamp_selection();
+            }
+            Message::DisplayTick() => {
+                let Some(displays) = &mut self.displays else {
+                    return Command::none();
+                };
+                if let Some(result) = displays.tick(Instant::now()) {
+                    self.status = Some(match result {
+                        Ok(()) => {
+                            "Changes weren't kept, the displays are back as they were".to_owned()
+                        }
+                        Err(e) => e,
+                    });
+                    self.clamp_selection();
+                }
+            }
+
+            Message::ArrowUp()
+            | Message::ArrowDown()
+            | Message::ArrowLeft()
+            | Message::ArrowRight()
+                if self.entry.is_some() =>
+            {
+                let (right, down) = match message {
+                    Message::ArrowUp() => (0, -1),
+                    Message::ArrowDown() => (0, 1),
+                    Message::ArrowLeft() => (-1, 0),
+                    _ => (1, 0),
+                };
+                if let Some(entry) = &mut self.entry {
+                    entry.move_selection(right, down);
+                }
+            }
+            Message::Select() if self.entry.is_some() => {
+                let selected = self.entry.as_ref().map_or(0, |entry| entry.selected);
+                return self.update(Message::EntryPress(selected));
+            }
+            Message::Back() if self.entry.is_some() => {
+                self.entry = None;
+                self.status = None;
+            }
+            Message::ArrowUp() => {
+                self.selection = self.selection.saturating_sub(1);
+            }
+            Message::ArrowDown() => {
+                self.selection += 1;
+                self.clamp_selection();
+            }
+            Message::ArrowLeft() | Message::ArrowRight() => {
+                let direction = match message {
+                    Message::ArrowLeft() => -1,
+                    _ => 1,
+                };
+                match self.selected() {
+                    Some(Row::Field(idx)) => return self.update(Message::Step(idx, direction)),
+                    Some(Row::Display(DisplayRow::Field(idx, field))) => {
+                        return self.update(Message::DisplayStep(idx, field, direction))
+                    }
+                    _ => {}
+                }
+            }
+            Message::Select() => match self.selected() {
+                Some(Row::Page(idx)) => return self.update(Message::OpenPage(idx)),
+                Some(Row::Field(idx)) => {
+                    let typed = self.field(idx).is_some_and(|field| field.is_typed());
+                    match typed {
+                        // Enter in the text box submits what was typed.
+                        // Without a keyboard there's nothing typed yet
+                        true if self.drafts.contains_key(&idx) => {
+                            return self.update(Message::Submit(idx))
+                        }
+                        true => return self.update(Message::OpenEntry(idx)),
+                        // Toggles flip, choices move on
+                        false => return self.update(Message::Step(idx, 1)),
+                    }
+                }
+                Some(Row::Displays) => return self.update(Message::OpenDisplays()),
+                Some(Row::Display(DisplayRow::Field(idx, field))) => {
+                    return self.update(Message::DisplayStep(idx, field, 1))
+                }
+                Some(Row::Display(DisplayRow::Apply)) => {
+                    return self.update(Message::DisplayApply())
+                }
+                Some(Row::Display(DisplayRow::Keep)) => return self.update(Message::DisplayKeep()),
+                Some(Row::Display(DisplayRow::Revert)) => {
+                    return self.update(Message::DisplayRevert())
+                }
+                Some(Row::Back) => return self.update(Message::Back()),
+                Some(Row::Save) => return self.update(Message::Save()),
+                None => {}
+            },
+            Message::Back() => {
+                if let Some(mut displays) = self.displays.take() {
+                    // Leaving without keeping is the same as not keeping
+                    if let Err(e) = displays.revert() {
+                        self.status = Some(e);
+                    }
+                    self.selection = self.pages.len();
+                    return Command::none();
+                }
+                match self.page.take() {
+                    Some(idx) => {
+                        self.drafts.clear();
+                        self.selection = idx;
+                    }
+                    None => exit(0),
+                }
+            }
+        }
+        Command::none()
+    }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        let mut subscriptions = vec![keyboard::on_key_press(|key, modifiers| {
+            let keyboard::Key::Named(key) = key else {
+                return None;
+            };
+
+            match (key, modifiers) {
+                (key::Named::ArrowUp, _) => Some(Message::ArrowUp()),
+                (key::Named::ArrowDown, _) => Some(Message::ArrowDown()),
+                (key::Named::ArrowLeft, _) => Some(Message::ArrowLeft()),
+                (key::Named::ArrowRight, _) => Some(Message::ArrowRight()),
+                (key::Named::Enter, _) => Some(Message::Select()),
+                (key::Named::Escape, _) => Some(Message::Back()),
+                _ => None,
+            }
+        })];
+        let counting_down = self
+            .displays
+            .as_ref()
+            .is_some_and(|displays| displays.seconds_left(Instant::now()).is_some());
+        if counting_down {
+            subscriptions.push(
+                iced::time::every(Duration::from_millis(250)).map(|_| Message::DisplayTick()),
+            );
+        }
+        Subscription::batch(subscriptions)
+    }
+
+    fn view(&self) -> cosmic::Element<Self::Message> {
+        if let Some(entry) = &self.entry {
+            return self.view_entry(entry);
+        }
+        let rows = self.rows();
+        let mut c = column().spacing(8);
+        match self.page {
+            Some(idx) => c = c.push(text::title3(self.pages[idx].title)),
+            None if self.displays.is_some() => c = c.push(text::title3("Displays")),
+            None => c = c.push(text::title3("Settings")),
+        }
+
+        let mut list = column().spacing(4);
+        for (row_idx, entry) in rows.iter().enumerate() {
+            let element = match *entry {
+                Row::Page(idx) => {
+                    let page = &self.pages[idx];
+                    button(
+                        row()
+                            .push(icon::from_name(page.icon))
+                            .push(text(page.title)),
+                    )
+                    .on_press(Message::OpenPage(idx))
+                    .into()
+                }
+                Row::Field(idx) => self.view_field(idx),
+                Row::Displays => button(
+                    row()
+                        .push(icon::from_name("preferences-desktop-display"))
+                        .push(text("Displays")),
+                )
+                .on_press(Message::OpenDisplays())
+                .into(),
+                Row::Display(display_row) => self.view_display(display_row),
+                Row::Back => button(
+                    row()
+                        .push(icon::from_name("go-previous"))
+                        .push(text("Back")),
+                )
+                .on_press(Message::Back())
+                .into(),
+                Row::Save => {
+                    let label = match self.unsaved {
+                        true => "Save",
+                        false => "Saved",
+                    };
+                    button(text(label)).on_press(Message::Save()).into()
+                }
+            };
+            let mut selectable = container(element).width(Length::Fill);
+            selectable = selectable.style(match row_idx == self.selection {
+                true => cosmic::theme::Container::Background,
+                false => cosmic::theme::Container::Transparent,
+            });
+            list = list.push(selectable);
+        }
+        if let Some(idx) = self.page.filter(|idx| !self.pages[*idx].hint.is_empty()) {
+            list = list.push(text::caption(self.pages[idx].hint));
+        }
+        c = c.push(widget::scrollable(list));
+        if let Some(status) = &self.status {
+            c = c.push(text::caption(status.clone()));
+        }
+        c.into()
+    }
+}
+
+impl ConsolationSettingsApp {
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = vec![];
+        if let Some(displays) = &self.displays {
+            rows.push(Row::Back);
+            rows.extend(displays.rows().into_iter().map(Row::Display));
+            return rows;
+        }
+        match self.page {
+            Some(idx) => {
+                rows.push(Row::Back);
+                for (field_idx, field) in self.pages[idx].fields.iter().enumerate() {
+                    if (field.shown)(&self.config) {
+                        rows.push(Row::Field(field_idx));
+                    }
+                }
+            }
+            None => {
+                rows.extend((0..self.pages.len()).map(Row::Page));
+                rows.push(Row::Displays);
+            }
+        }
+        rows.push(Row::Save);
+        rows
+    }
+
+    fn selected(&self) -> Option<Row> {
+        self.rows().get(self.selection).copied()
+    }
+
+    // Fields come and go, keep the selection on something that exists
+    fn clamp_selection(&mut self) {
+        let len = self.rows().len();
+        self.selection = self.selection.min(len.saturating_sub(1));
+    }
+
+    fn field(&self, idx: usize) -> Option<&Field> {
+        self.pages.get(self.page?)?.fields.get(idx)
+    }
+
+    // Change the document, keeping the change only if it still loads
+    fn apply(&mut self, idx: usize, value: FieldValue) {
+        let Some(page) = self.page else {
+            return;
+        };
+        let field = &self.pages[page].fields[idx];
+        let result = self.edit.change(|edit| {
+            edit.set(field.key, value.to_toml());
+            if let FieldValue::Text(choice) = &value {
+                let table = &field.key[..field.key.len() - 1];
+                for (name, default) in (field.requires)(choice) {
+                    let mut key = table.to_vec();
+                    key.push(*name);
+                    if edit.get(&key).is_none() {
+                        edit.set(&key, *default);
+                    }
+                }
+            }
+        });
+        match result {
+            Ok(config) => {
+                self.config = config;
+                self.unsaved = true;
+                self.status = None;
+            }
+            Err(e) => self.status = Some(e),
+        }
+        self.clamp_selection();
+    }
+
+    fn view_field(&self, idx: usize) -> cosmic::Element<Message> {
+        let Some(field) = self.field(idx) else {
+            return text("").into();
+        };
+        let value = (field.get)(&self.config);
+        let mut r = row().spacing(12);
+        r = r.push(text(field.label));
+        r = r.push(widget::Space::with_width(Length::Fill));
+        if field.is_typed() {
+            let current = match self.drafts.get(&idx) {
+                Some(draft) => draft.clone(),
+                None => value.describe(),
+            };
+            if matches!(field.kind, FieldKind::Colour) {
+                r = r.push(button(text("<")).on_press(Message::Step(idx, -1)));
+            }
+            r = r.push(
+                widget::text_input(field.label, current)
+                    .on_input(move |input| Message::Input(idx, input))
+                    .on_submit(Message::Submit(idx))
+                    .width(Length::Fixed(320.0)),
+            );
+            if matches!(field.kind, FieldKind::Colour) {
+                r = r.push(button(text(">")).on_press(Message::Step(idx, 1)));
+            }
+            r = r.push(button(icon::from_name("input-keyboard")).on_press(Message::OpenEntry(idx)));
+        } else {
+            r = r.push(button(text("<")).on_press(Message::Step(idx, -1)));
+            r = r.push(text(value.describe()));
+            r = r.push(button(text(">")).on_press(Message::Step(idx, 1)));
+        }
+        r.into()
+    }
+
+    fn view_entry(&self, entry: &Entry) -> cosmic::Element<Message> {
+        let mut c = column().spacing(8);
+        if let Some(field) = self.field(entry.field) {
+            c = c.push(text::title3(field.label));
+        }
+        c = c.push(text::title4(format!("{}_", entry.text)));
+        let mut grid = column().spacing(4);
+        for (row_idx, keys) in entry.keys().chunks(ENTRY_COLUMNS).enumerate() {
+            let mut r = row().spacing(4);
+            for (col_idx, key) in keys.iter().enumerate() {
+                let idx = row_idx * ENTRY_COLUMNS + col_idx;
+                let mut key_button = button(text(key.label(entry.shifted)))
+                    .width(Length::Fixed(72.0))
+                    .on_press(Message::EntryPress(idx));
+                if idx == entry.selected {
+                    key_button = key_button.style(cosmic::theme::Button::Suggested);
+                }
+                r = r.push(key_button);
+            }
+            grid = grid.push(r);
+        }
+        c = c.push(grid);
+        c = c.push(button(text("Cancel")).on_press(Message::Back()));
+        if let Some(status) = &self.status {
+            c = c.push(text::caption(status.clone()));
+        }
+        c.into()
+    }
+
+    fn view_display(&self, display_row: DisplayRow) -> cosmic::Element<Message> {
+        let Some(displays) = &self.displays else {
+            return text("").into();
+        };
+        match display_row {
+            DisplayRow::Field(idx, field) => {
+                let mut r = row().spacing(12);
+                let label = displays.label(idx, field);
+                r = r.push(match field {
+                    DisplayField::Enabled => text::title4(label),
+                    _ => text(label),
+                });
+                r = r.push(widget::Space::with_width(Length::Fill));
+                r = r.push(button(text("<")).on_press(Message::DisplayStep(idx, field, -1)));
+                r = r.push(text(displays.value(idx, field)));
+                r = r.push(button(text(">")).on_press(Message::DisplayStep(idx, field, 1)));
+                r.into()
+            }
+            DisplayRow::Apply => {
+                let label = match displays.is_changed() {
+                    true => "Apply",
+                    false => "No changes",
+                };
+                button(text(label)).on_press(Message::DisplayApply()).into()
+            }
+            DisplayRow::Keep => {
+                let seconds = displays.seconds_left(Instant::now()).unwrap_or_default();
+                button(text(format!("Keep changes? Reverting in {}", seconds)))
+                    .on_press(Message::DisplayKeep())
+                    .into()
+            }
+            DisplayRow::Revert => button(text("Revert"))
+                .on_press(Message::DisplayRevert())
+                .into(),
+        }
+    }
+}
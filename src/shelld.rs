@@ -0,0 +1,597 @@
+use calloop::{channel::channel, EventLoop};
+use calloop_wayland_source::WaylandSource;
+use consolation_common::apps::AppsAction;
+use consolation_common::config::{PowerConfig, ShellConfig, SwitcherConfig};
+use consolation_common::hooks;
+use consolation_common::logind::Logind;
+use consolation_common::power::{
+    self, ControllerMonitor, ControllerWarning, PowerMonitor, PowerThresholds, PowerWarning,
+    SysfsPower,
+};
+use consolation_common::rules::{RuleAction, RuleApply, WindowRule};
+use consolation_common::{
+    delegate_idle, delegate_toplevels,
+    idle::{gamepad, wayland::IdleNotifier},
+    ipc,
+    nightlight::NightLightAction,
+    record::Recorder,
+    StagingData, ToplevelAction, ToplevelChange, ToplevelId, ToplevelSignal, ToplevelState,
+};
+use std::collections::HashMap;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::{exit, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use wayland_client::{globals::registry_queue_init, Connection, QueueHandle};
+
+mod apps;
+mod idle;
+mod nightlight;
+mod notify;
+
+use apps::Apps;
+use idle::Idle;
+use nightlight::NightLight;
+
+// How long consolation-lock has to lock the screen before sleep goes ahead
+// anyway
+const LOCK_WAIT: Duration = Duration::from_secs(3);
+
+// Set by SIGTERM and SIGINT, so paused games are continued and playtime
+// written on the way out
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+// Sent from the socket and controller threads to the Wayland thread
+enum ClientEvent {
+    Connected(UnixStream),
+    Action(ToplevelAction),
+    NightLightConnected(UnixStream),
+    NightLight(NightLightAction),
+    AppsConnected(UnixStream),
+    Apps(AppsAction),
+    GamepadInput,
+}
+
+struct Shell {
+    exit: bool,
+    toplevels: StagingData,
+    hooks: Vec<hooks::Hook>,
+    window_rules: Vec<WindowRule>,
+    clients: Vec<UnixStream>,
+    night_light: NightLight,
+    apps: Apps,
+    // Which app_ids are games, looked up once each from desktop files
+    switcher: SwitcherConfig,
+    games: HashMap<String, bool>,
+    idle: Idle,
+    qh: QueueHandle<Shell>,
+}
+
+impl AsMut<StagingData> for Shell {
+    fn as_mut(&mut self) -> &mut StagingData {
+        &mut self.toplevels
+    }
+}
+
+delegate_toplevels!(Shell);
+
+impl AsMut<IdleNotifier> for Shell {
+    fn as_mut(&mut self) -> &mut IdleNotifier {
+        &mut self.idle.notifier
+    }
+}
+
+delegate_idle!(Shell);
+
+impl Shell {
+    // Clients that can't keep up, or have gone away, are dropped
+    fn broadcast(&mut self, signal: &ToplevelSignal) {
+        self.clients
+            .retain(|client| ipc::send(client, signal).is_ok());
+    }
+
+    fn add_client(&mut self, client: UnixStream) {
+        let _ = client.set_write_timeout(Some(Duration::from_secs(1)));
+        if ipc::send(&client, &self.toplevels.model().snapshot()).is_ok() {
+            self.clients.push(client);
+        }
+    }
+
+    // Run hooks and rules for whatever the last dispatch changed, and pass
+    // it on to clients
+    fn process_changes(&mut self) {
+        let changes = self.toplevels.take_changes();
+        if changes.is_empty() {
+            return;
+        }
+        for change in changes {
+            match &change {
+                ToplevelChange::Updated {
+                    id,
+                    previous,
+                    details,
+                } => {
+                    for event in hooks::events_for(previous.as_ref(), details) {
+                        // Activated some other way than through us. A window
+                        // stopped because its time is up stays stopped
+                        if event == hooks::HookEvent::Activated
+                            && !self.apps.refuse_activate(*id, self.toplevels.model())
+                        {
+                            self.apps.resume(*id);
+                        }
+                        hooks::run(&self.hooks, event, details);
+                    }
+                    self.apply_window_rules(*id, previous.is_none());
+                }
+                ToplevelChange::Removed { id, details } => {
+                    hooks::run(&self.hooks, hooks::HookEvent::Closed, details);
+                    self.apps.removed(*id);
+                }
+                ToplevelChange::Finished => self.exit = true,
+                ToplevelChange::Mru(_) | ToplevelChange::Output(_) => {}
+            }
+            self.broadcast(&change.signal());
+        }
+        self.apps.set_foreground(self.toplevels.model());
+        let in_front = self.game_in_front();
+        self.night_light.set_game_in_front(in_front);
+        let inhibited = self.idle_inhibited();
+        self.idle
+            .set_inhibited(inhibited, &self.toplevels, &self.qh);
+    }
+
+    // Fullscreen windows in front and "media" rule matches keep the
+    // screen on, whether or not anyone touches anything
+    fn idle_inhibited(&self) -> bool {
+        let fullscreen = ToplevelState::ACTIVATED | ToplevelState::FULLSCREEN;
+        let media: Vec<&WindowRule> = self
+            .window_rules
+            .iter()
+            .filter(|rule| rule.actions.contains(&RuleAction::Media))
+            .collect();
+        self.toplevels.model().toplevels().any(|(_, details)| {
+            details.state.contains(fullscreen) || media.iter().any(|rule| rule.matches(details))
+        })
+    }
+
+    fn game_in_front(&mut self) -> bool {
+        let fullscreen = ToplevelState::ACTIVATED | ToplevelState::FULLSCREEN;
+        let app_ids: Vec<String> = self
+            .toplevels
+            .model()
+            .toplevels()
+            .filter(|(_, details)| details.state.contains(fullscreen))
+            .filter_map(|(_, details)| details.app_id.clone())
+            .collect();
+        app_ids.into_iter().any(|app_id| self.is_game(app_id))
+    }
+
+    fn is_game(&mut self, app_id: String) -> bool {
+        *self
+            .games
+            .entry(app_id)
+            .or_insert_with_key(|app_id| self.switcher.is_game(app_id))
+    }
+
+    fn run_action(&mut self, action: ToplevelAction) {
+        match action {
+            ToplevelAction::Activate { id } => {
+                if self.apps.refuse_activate(id, self.toplevels.model()) {
+                    return;
+                }
+                self.activating(id);
+            }
+            ToplevelAction::Close { id } => self.apps.closing(id),
+            _ => {}
+        }
+        self.toplevels.run_action(&action);
+    }
+
+    // Bringing a stopped game to the front would just show a frozen
+    // window. With auto_pause, the game it replaces is stopped instead
+    fn activating(&mut self, id: ToplevelId) {
+        self.apps.resume(id);
+        let model = self.toplevels.model();
+        let is_switcher = |id| {
+            model.get(id).and_then(|details| details.app_id.as_deref())
+                == Some(ipc::SWITCHER_APP_ID)
+        };
+        // The switcher brings itself back to say time is up
+        if !self.switcher.auto_pause || is_switcher(id) {
+            return;
+        }
+        // Windows are usually picked from the switcher, which is in front
+        // by then
+        let previous = model.mru().iter().find_map(|other| {
+            let app_id = model.get(*other)?.app_id.as_ref()?;
+            (!is_switcher(*other)).then(|| (*other, app_id.clone()))
+        });
+        if let Some((previous, app_id)) = previous {
+            if previous != id && self.is_game(app_id) {
+                self.apps.pause(previous, self.toplevels.model());
+            }
+        }
+    }
+
+    // Run any matching window rules. Each action is skipped when the
+    // window is already in that state, so "always" rules don't fight
+    // the compositor on every update
+    fn apply_window_rules(&self, id: ToplevelId, is_new: bool) {
+        let (Some(handle), Some(details)) =
+            (self.toplevels.handle_for(id), self.toplevels.details(id))
+        else {
+            return;
+        };
+        for rule in self.window_rules.iter() {
+            if (rule.apply == RuleApply::Once && !is_new) || !rule.matches(details) {
+                continue;
+            }
+            for action in rule.actions.iter() {
+                match action {
+                    RuleAction::Fullscreen => {
+                        if !details.state.contains(ToplevelState::FULLSCREEN) {
+                            let output = rule
+                                .output
+                                .as_ref()
+                                .and_then(|wanted| self.toplevels.output_named(wanted));
+                            handle.set_fullscreen(output.as_ref());
+                        }
+                    }
+                    RuleAction::Maximize => {
+                        if !details.state.contains(ToplevelState::MAXIMIZED) {
+                            handle.set_maximized();
+                        }
+                    }
+                    RuleAction::Activate => {
+                        if !details.state.contains(ToplevelState::ACTIVATED) {
+                            if let Some(seat) = self.toplevels.seat() {
+                                handle.activate(seat);
+                            }
+                        }
+                    }
+                    RuleAction::MinimizeOthers => {
+                        for (other, other_details) in self.toplevels.model().toplevels() {
+                            if other != id
+                                && !other_details.state.contains(ToplevelState::MINIMIZED)
+                            {
+                                self.toplevels
+                                    .run_action(&ToplevelAction::Minimize { id: other });
+                            }
+                        }
+                    }
+                    // Looked at by idle_inhibited instead
+                    RuleAction::Media => {}
+                }
+            }
+        }
+    }
+}
+
+// Polls in a thread of its own, it has nothing to do with Wayland
+fn watch_power(config: &PowerConfig) {
+    let thresholds = PowerThresholds {
+        low: config.low.clone(),
+        critical: config.critical,
+    };
+    let interval = Duration::from_secs(config.interval.max(1));
+    let controller_low = config.controller_low;
+    std::thread::spawn(move || {
+        let source = power::system_source();
+        let mut monitor = PowerMonitor::new(thresholds);
+        // Controllers only show up in sysfs, UPower or not
+        let sysfs = SysfsPower::system();
+        let mut controller_monitor = ControllerMonitor::new(controller_low);
+        loop {
+            match source.status() {
+                Ok(status) => {
+                    for warning in monitor.update(&status) {
+                        warn_power(warning);
+                    }
+                }
+                Err(e) => println!("Unable to read power status: {}", e),
+            }
+            match sysfs.controllers() {
+                Ok(controllers) => {
+                    for warning in controller_monitor.update(&controllers) {
+                        warn_controller(warning);
+                    }
+                }
+                Err(e) => println!("Unable to read controller batteries: {}", e),
+            }
+            std::thread::sleep(interval);
+        }
+    });
+}
+
+// Locks the screen before sleeping, so it's locked on waking. A delay
+// inhibitor holds sleep off until it is
+fn lock_on_sleep() {
+    std::thread::spawn(|| {
+        let logind = match Logind::system() {
+            Ok(logind) => logind,
+            Err(e) => {
+                println!("Unable to reach logind, not locking on suspend: {}", e);
+                return;
+            }
+        };
+        let signals = match logind.sleep_signals() {
+            Ok(signals) => signals,
+            Err(e) => {
+                println!("Unable to watch for suspend: {}", e);
+                return;
+            }
+        };
+        let inhibit = || {
+            logind
+                .inhibit_sleep("Consolation", "Locking the screen")
+                .map_err(|e| println!("Unable to delay suspend: {}", e))
+                .ok()
+        };
+        let mut inhibitor = inhibit();
+        for sleeping in signals {
+            // Taken again on waking, for next time
+            if !sleeping {
+                inhibitor = inhibit();
+                continue;
+            }
+            if !logind.locked_hint().unwrap_or(false) {
+                lock_and_wait(&logind);
+            }
+            // Sleep goes ahead once this is closed
+            drop(inhibitor.take());
+        }
+    });
+}
+
+// consolation-lock sets the session's locked hint once it has the lock
+fn lock_and_wait(logind: &Logind) {
+    let mut child = match Command::new("consolation-lock").spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            println!("Unable to start consolation-lock: {}", e);
+            return;
+        }
+    };
+    let started = Instant::now();
+    while started.elapsed() < LOCK_WAIT {
+        // Exiting this early means it couldn't lock
+        if logind.locked_hint().unwrap_or(false) || !matches!(child.try_wait(), Ok(None)) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+}
+
+fn warn_power(warning: PowerWarning) {
+    match warning {
+        PowerWarning::Low { percentage } => notify::send(
+            "Battery low",
+            &format!("{}% left", percentage),
+            notify::Urgency::Normal,
+        ),
+        PowerWarning::Critical { percentage } => notify::send(
+            "Battery critically low",
+            &format!("{}% left. Plug in now, or save and quit", percentage),
+            notify::Urgency::Critical,
+        ),
+        PowerWarning::NotCharging { percentage } => notify::send(
+            "Plugged in, not charging",
+            &format!(
+                "The battery is at {}%. The charger may be too weak, or not plugged in properly",
+                percentage
+            ),
+            notify::Urgency::Normal,
+        ),
+    }
+}
+
+fn warn_controller(warning: ControllerWarning) {
+    let body = match warning.percentage {
+        Some(percentage) => format!("{}% left", percentage),
+        None => "Charge it soon".to_owned(),
+    };
+    notify::send(
+        &format!("{} battery low", warning.label),
+        &body,
+        notify::Urgency::Normal,
+    );
+}
+
+fn main() {
+    // Every event the compositor sends us can be saved, to replay with
+    // `consolation-switcher --replay` when reporting a bug
+    let args: Vec<String> = std::env::args().collect();
+    let recorder = match args.iter().position(|arg| arg == "--record") {
+        Some(idx) => {
+            let Some(path) = args.get(idx + 1) else {
+                println!("Usage: consolation-shelld --record <file>");
+                exit(1);
+            };
+            match Recorder::create(Path::new(path)) {
+                Ok(recorder) => Some(recorder),
+                Err(e) => {
+                    println!("Unable to record to {}: {}", path, e);
+                    exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
+    let Some(socket_path) = ipc::socket_path() else {
+        println!("XDG_RUNTIME_DIR is not set, nowhere to put the socket");
+        exit(1);
+    };
+    let listener = match ipc::bind(&socket_path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("Unable to listen on {}: {}", socket_path.display(), e);
+            exit(1);
+        }
+    };
+
+    let conn = Connection::connect_to_env().unwrap();
+    let _display = conn.display();
+
+    let event_queue = conn.new_event_queue();
+    let qh = event_queue.handle();
+    let (globals, _queue) = registry_queue_init::<Shell>(&conn).unwrap();
+    if let Err(e) = StagingData::bind(&globals, &qh) {
+        println!("{}", e);
+        exit(1);
+    }
+
+    let config = ShellConfig::load();
+    let mut idle = Idle::new(&config.idle);
+    let idle_bound = config.idle.enabled
+        && idle
+            .notifier
+            .bind(&globals, &qh)
+            .map_err(|e| println!("Not dimming or blanking when idle: {}", e))
+            .is_ok();
+
+    let mut event_loop: EventLoop<Shell> = EventLoop::try_new().unwrap();
+    let loop_handle = event_loop.handle();
+
+    let (client_sender, client_recv) = channel::<ClientEvent>();
+    loop_handle
+        .insert_source(client_recv, |event, _meta, state| {
+            if let calloop::channel::Event::Msg(msg) = event {
+                match msg {
+                    ClientEvent::Connected(client) => state.add_client(client),
+                    ClientEvent::Action(action) => state.run_action(action),
+                    ClientEvent::NightLightConnected(client) => {
+                        state.night_light.add_client(client)
+                    }
+                    ClientEvent::NightLight(action) => state.night_light.handle(action),
+                    ClientEvent::AppsConnected(client) => state.apps.add_client(client),
+                    ClientEvent::Apps(action) => state.apps.handle(action, state.toplevels.model()),
+                    ClientEvent::GamepadInput => state.idle.gamepad(&state.toplevels, &state.qh),
+                }
+            }
+        })
+        .expect("Unable to register channel");
+    // The night light has a socket of its own, so toplevel clients don't
+    // have to know about it
+    let night_light_path = consolation_common::nightlight::socket_path();
+    match night_light_path.as_deref().map(ipc::bind) {
+        Some(Ok(night_light_listener)) => {
+            let connect_sender = client_sender.clone();
+            let action_sender = client_sender.clone();
+            std::thread::spawn(move || {
+                ipc::serve(
+                    night_light_listener,
+                    |client| {
+                        connect_sender
+                            .send(ClientEvent::NightLightConnected(client))
+                            .is_ok()
+                    },
+                    move |action| action_sender.send(ClientEvent::NightLight(action)).is_ok(),
+                )
+            });
+        }
+        Some(Err(e)) => println!("Unable to listen for night light clients: {}", e),
+        None => {}
+    }
+    let apps_path = consolation_common::apps::socket_path();
+    match apps_path.as_deref().map(ipc::bind) {
+        Some(Ok(apps_listener)) => {
+            let connect_sender = client_sender.clone();
+            let action_sender = client_sender.clone();
+            std::thread::spawn(move || {
+                ipc::serve(
+                    apps_listener,
+                    |client| {
+                        connect_sender
+                            .send(ClientEvent::AppsConnected(client))
+                            .is_ok()
+                    },
+                    move |action| action_sender.send(ClientEvent::Apps(action)).is_ok(),
+                )
+            });
+        }
+        Some(Err(e)) => println!("Unable to listen for apps clients: {}", e),
+        None => {}
+    }
+    // The compositor never sees controllers that games read themselves
+    if idle_bound {
+        let gamepad_sender = client_sender.clone();
+        gamepad::watch(move || gamepad_sender.send(ClientEvent::GamepadInput).is_ok());
+    }
+    // A thread per client reads its requests
+    std::thread::spawn(move || {
+        let action_sender = client_sender.clone();
+        ipc::serve(
+            listener,
+            |client| client_sender.send(ClientEvent::Connected(client)).is_ok(),
+            move |action| action_sender.send(ClientEvent::Action(action)).is_ok(),
+        )
+    });
+
+    WaylandSource::new(conn, event_queue)
+        .insert(loop_handle)
+        .expect("Unable to register wayland");
+
+    if config.power.warnings {
+        watch_power(&config.power);
+    }
+    if config.lock.on_suspend {
+        lock_on_sleep();
+    }
+    let apps = Apps::new(&config);
+    let mut state = Shell {
+        exit: false,
+        toplevels: StagingData::new(),
+        hooks: config.hooks,
+        window_rules: WindowRule::compile_all(&config.window_rules),
+        clients: vec![],
+        night_light: NightLight::start(&config.night_light),
+        apps,
+        switcher: config.switcher.clone(),
+        games: HashMap::new(),
+        idle,
+        qh,
+    };
+    if let Some(recorder) = recorder {
+        state.toplevels.record_to(recorder);
+    }
+    handle_termination();
+
+    while event_loop
+        .dispatch(Some(Duration::from_millis(100)), &mut state)
+        .is_ok()
+    {
+        state.process_changes();
+        state.idle.dispatched(&state.toplevels, &state.qh);
+        for id in state.apps.dispatched(state.toplevels.model()) {
+            state.toplevels.run_action(&ToplevelAction::Close { id });
+        }
+        if state.exit || TERMINATED.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+    state.apps.exiting();
+    let _ = std::fs::remove_file(&socket_path);
+    for path in [night_light_path, apps_path].into_iter().flatten() {
+        let _ = std::fs::remove_file(path);
+    }
+    exit(0);
+}
+
+extern "C" fn terminate(_: libc::c_int) {
+    TERMINATED.store(true, Ordering::Relaxed);
+}
+
+fn handle_termination() {
+    let handler = terminate as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // Safety: the handler only stores to an atomic, which is async signal
+    // safe
+    unsafe {
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+}
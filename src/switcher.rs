@@ -1,8 +1,26 @@
-use calloop::{
-    channel::{sync_channel, Channel, SyncSender},
-    EventLoop,
+use calloop::channel::{sync_channel, Channel};
+use consolation_common::apps::{self, AppsAction, AppsSignal};
+use consolation_common::config::ShellConfig;
+use consolation_common::launcher::{
+    self,
+    desktop::{self, DesktopAction},
+    profile::LaunchCommand,
+    LauncherEntry, LauncherIcon,
+};
+use consolation_common::limits::LimitPeriod;
+use consolation_common::logind::{Logind, PowerAction, PowerControl};
+use consolation_common::notifications::history::{
+    self, HistoryAction, HistorySignal, StoredNotification,
+};
+use consolation_common::notifications::NotificationImage;
+use consolation_common::playtime::{self, PlaytimeTracker};
+use consolation_common::power::{PowerSupply, SysfsPower};
+use consolation_common::process::{self, ProcessInfo, Signal};
+use consolation_common::profiles;
+use consolation_common::{
+    ipc, pin, record, ToplevelAction, ToplevelDetails, ToplevelId, ToplevelModel, ToplevelSignal,
+    ToplevelState,
 };
-use calloop_wayland_source::WaylandSource;
 use cosmic::{
     app::{Command, Core},
     iced::{
@@ -14,302 +32,392 @@ use cosmic::{
     ApplicationExt,
 };
 use cosmic::{widget::text, Application};
-use smithay::reexports::wayland_protocols_wlr::foreign_toplevel::v1::client::{
-    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
-    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1, EVT_TOPLEVEL_OPCODE},
-};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
 use std::process::exit;
 use std::{
     cell::RefCell,
-    collections::HashMap,
-    sync::{Arc, Mutex},
-    time::Duration,
-};
-use wayland_client::{
-    event_created_child,
-    globals::{registry_queue_init, GlobalListContents},
-    protocol::{
-        wl_registry::WlRegistry,
-        wl_seat::{self, WlSeat},
-    },
-    Connection, Dispatch, QueueHandle,
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
 };
 
-// Message sent from toplevel manager thread to gui thread
-#[derive(Clone, Debug)]
-enum ToplevelSignal {
-    AddUpdateToplevel((ZwlrForeignToplevelHandleV1, ToplevelDetails)),
-    RemoveToplevel(ZwlrForeignToplevelHandleV1),
-    SeatChanged(WlSeat),
-    //OutputChanged(WlOutput),  // Not using fullscreeb currently
-    Closed,
-}
+mod quick_settings;
 
-// Details about toplevel handles
-#[derive(Default, Debug, Clone)]
-struct ToplevelDetails {
-    title: Option<String>,
-    app_id: Option<String>,
-    state: Vec<u8>,
-    parent: Option<usize>,
-}
-
-// Potential actions the gui thread can send back
-enum ToplevelAction {
-    Refresh(),
-    Exit(),
-}
+use quick_settings::{QuickSetting, QuickSettings, Readings};
 
 struct UiFlags {
     toplevel_recv: Channel<ToplevelSignal>,
-    action_sender: SyncSender<ToplevelAction>,
+    shell: UnixStream,
+    notification_recv: Channel<HistorySignal>,
+    notifications: Option<UnixStream>,
+    apps_recv: Channel<AppsSignal>,
+    apps: Option<UnixStream>,
 }
 
-struct StagingData {
-    exit: bool,
-    hash: HashMap<ZwlrForeignToplevelHandleV1, ToplevelDetails>,
-    sender: Arc<Mutex<SyncSender<ToplevelSignal>>>,
-}
-impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for StagingData {
-    fn event(
-        state: &mut Self,
-        proxy: &ZwlrForeignToplevelHandleV1,
-        event: <ZwlrForeignToplevelHandleV1 as wayland_client::Proxy>::Event,
-        _data: &(),
-        _conn: &Connection,
-        _qhandle: &QueueHandle<Self>,
-    ) {
-        if let Some(details) = state.hash.get_mut(proxy) {
-            match event {
-                zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
-                    details.title = Some(title);
-                }
-                zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
-                    details.app_id = Some(app_id);
-                }
-                zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output: _ } => {
-                    // TODO. Care about oututs
-                }
-                zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output: _ } => {
-                    // TODO. Care about outputs
-                }
-                zwlr_foreign_toplevel_handle_v1::Event::State { state } => {
-                    details.state = state.clone();
-                }
-                zwlr_foreign_toplevel_handle_v1::Event::Done => {
-                    state
-                        .sender
-                        .lock()
-                        .unwrap()
-                        .try_send(ToplevelSignal::AddUpdateToplevel((
-                            proxy.clone(),
-                            details.clone(),
-                        )))
-                        .unwrap();
-                }
-                zwlr_foreign_toplevel_handle_v1::Event::Closed => {
-                    state
-                        .sender
-                        .lock()
-                        .unwrap()
-                        .try_send(ToplevelSignal::RemoveToplevel(proxy.clone()))
-                        .unwrap();
-                }
-                zwlr_foreign_toplevel_handle_v1::Event::Parent { parent: _ } => {
-                    //details.parent = parent;
-                }
-                _ => todo!(),
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(idx) = args.iter().position(|arg| arg == "--export-playtime") {
+        let tracker = PlaytimeTracker::load();
+        match args.get(idx + 1).map(|s| s.as_str()) {
+            Some("json") => println!("{}", tracker.export_json()),
+            Some("csv") | None => print!("{}", tracker.export_csv()),
+            Some(other) => {
+                println!("Unknown export format {}, expected csv or json", other);
+                exit(1);
             }
-        } else {
-            unreachable!();
         }
+        exit(0);
+    }
+    if let Some(idx) = args.iter().position(|arg| arg == "--hash-pin") {
+        let Some(pin) = args.get(idx + 1) else {
+            println!("Usage: consolation-switcher --hash-pin <pin>");
+            exit(1);
+        };
+        println!("{}", pin::hash(pin));
+        exit(0);
     }
-}
 
-impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for StagingData {
-    event_created_child!(StagingData, ZwlrForeignToplevelManagerV1, [
-        EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ())
-    ]);
-
-    fn event(
-        state: &mut Self,
-        _proxy: &ZwlrForeignToplevelManagerV1,
-        event: <ZwlrForeignToplevelManagerV1 as wayland_client::Proxy>::Event,
-        _data: &(),
-        _conn: &Connection,
-        _qhandle: &QueueHandle<Self>,
-    ) {
-        match event {
-            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
-                state.hash.insert(
-                    toplevel,
-                    ToplevelDetails {
-                        title: None,
-                        app_id: None,
-                        state: vec![],
-                        parent: None,
-                    },
-                );
+    // Toplevels are tracked by consolation-shelld, which already has the
+    // whole list by the time we connect. A recording can stand in for it
+    let shell = if let Some(idx) = args.iter().position(|arg| arg == "--replay") {
+        let Some(path) = args.get(idx + 1) else {
+            println!("Usage: consolation-switcher --replay <file>");
+            exit(1);
+        };
+        replay_session(Path::new(path))
+    } else {
+        match ipc::connect() {
+            Ok(shell) => shell,
+            Err(e) => {
+                println!("Unable to connect to consolation-shelld: {}", e);
+                exit(1);
             }
-            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
-                println!("Manager handle has gone away. We're done here");
-                state.exit = true;
+        }
+    };
+    let reader = shell.try_clone().expect("Unable to clone socket");
+    let (toplevel_sender, toplevel_recv) = sync_channel::<ToplevelSignal>(50);
+    std::thread::spawn(move || {
+        for signal in ipc::read_messages(reader) {
+            if toplevel_sender.send(signal).is_err() {
+                return;
             }
-            _ => todo!(),
         }
-    }
-}
+        let _ = toplevel_sender.send(ToplevelSignal::Closed);
+    });
 
-impl Dispatch<wl_seat::WlSeat, ()> for StagingData {
-    fn event(
-        state: &mut Self,
-        proxy: &wl_seat::WlSeat,
-        event: <wl_seat::WlSeat as wayland_client::Proxy>::Event,
-        _data: &(),
-        _conn: &Connection,
-        _qhandle: &QueueHandle<Self>,
-    ) {
-        match event {
-            wl_seat::Event::Capabilities { capabilities: _ } => {
-                let _ = state
-                    .sender
-                    .lock()
-                    .unwrap()
-                    .try_send(ToplevelSignal::SeatChanged(proxy.clone()));
-            }
-            wl_seat::Event::Name { name: _ } => {}
-            _ => todo!(),
+    // Notification history is kept by consolation-notifyd. Without it the
+    // notifications page stays empty
+    let (notification_sender, notification_recv) = sync_channel::<HistorySignal>(50);
+    let notifications = match history::connect() {
+        Ok(stream) => {
+            let reader = stream.try_clone().expect("Unable to clone socket");
+            std::thread::spawn(move || {
+                for signal in ipc::read_messages(reader) {
+                    if notification_sender.send(signal).is_err() {
+                        return;
+                    }
+                }
+            });
+            Some(stream)
         }
-    }
-}
-
-impl Dispatch<WlRegistry, GlobalListContents> for StagingData {
-    fn event(
-        _state: &mut Self,
-        _proxy: &WlRegistry,
-        _event: <WlRegistry as wayland_client::Proxy>::Event,
-        _data: &GlobalListContents,
-        _conn: &Connection,
-        _qhandle: &QueueHandle<Self>,
-    ) {
-        todo!();
-    }
-}
-
-#[tokio::main]
-async fn main() {
-    let (toplevel_sender, toplevel_recv) = sync_channel::<ToplevelSignal>(50);
-    let (action_sender, action_recv) = sync_channel::<ToplevelAction>(10);
-
-    // Start a thread for wayland-client
-    // I know this is stupid but I couldn't find a way to
-    // hook into the system in cosmic, and their ext-shell-wrapper
-    // is in an unusable state for me currently
-    let join = {
-        tokio::spawn(async {
-            let conn = Connection::connect_to_env().unwrap();
-            let _display = conn.display();
-
-            let event_queue = conn.new_event_queue();
-            let qh = event_queue.handle();
-            let (globals, _queue) = registry_queue_init::<StagingData>(&conn).unwrap();
-            let _ = globals
-                .bind::<ZwlrForeignToplevelManagerV1, StagingData, ()>(&qh, 3..=3, ())
-                .unwrap();
-
-            let _ = globals
-                .bind::<wl_seat::WlSeat, StagingData, ()>(&qh, 1..=1, ())
-                .unwrap();
-
-            let mut event_loop: EventLoop<StagingData> = EventLoop::try_new().unwrap();
-            let loop_handle = event_loop.handle();
-            loop_handle
-                .insert_source(action_recv, |event, _meta, state| match event {
-                    calloop::channel::Event::Msg(msg) => match msg {
-                        ToplevelAction::Refresh() => {}
-                        ToplevelAction::Exit() => state.exit = true,
-                    },
-                    calloop::channel::Event::Closed => {
-                        println!("Channel close. Ending");
-                        state.exit = true;
-                    }
-                })
-                .expect("Unable to register channel");
-
-            WaylandSource::new(conn, event_queue)
-                .insert(loop_handle)
-                .expect("Unable to register wayland");
-
-            let toplevel_sender2 = toplevel_sender.clone();
-            let mut state = StagingData {
-                exit: false,
-                hash: HashMap::new(),
-                sender: Arc::new(Mutex::new(toplevel_sender)),
-            };
+        Err(e) => {
+            println!("Unable to connect to consolation-notifyd: {}", e);
+            None
+        }
+    };
 
-            while let Ok(_) = event_loop.dispatch(Some(Duration::from_millis(100)), &mut state) {
-                if state.exit {
-                    break;
+    // Pausing is consolation-shelld's, so paused games are continued even
+    // if we go away. Without it nothing can be paused
+    let (apps_sender, apps_recv) = sync_channel::<AppsSignal>(50);
+    let apps = match apps::connect() {
+        Ok(stream) => {
+            let reader = stream.try_clone().expect("Unable to clone socket");
+            std::thread::spawn(move || {
+                for signal in ipc::read_messages(reader) {
+                    if apps_sender.send(signal).is_err() {
+                        return;
+                    }
                 }
-            }
-            let _ = toplevel_sender2.try_send(ToplevelSignal::Closed);
-        })
+            });
+            Some(stream)
+        }
+        Err(e) => {
+            println!("Unable to connect to consolation-shelld for pausing: {}", e);
+            None
+        }
     };
 
     let input = UiFlags {
-        toplevel_recv: toplevel_recv,
-        action_sender,
+        toplevel_recv,
+        shell,
+        notification_recv,
+        notifications,
+        apps_recv,
+        apps,
     };
 
     let mut settings = cosmic::app::Settings::default();
     settings = settings.transparent(true);
     settings = settings.client_decorations(false);
     cosmic::app::run::<ConsolationSwitcherApp>(settings, input).expect("Unable to start App");
-    let _ = join.await;
     exit(0);
 }
 
+// Play a recording from consolation-shelld --record down one end of a
+// socket pair, as the daemon would, and return the other end. Actions are
+// printed rather than run since there's no compositor to run them on
+fn replay_session(path: &Path) -> UnixStream {
+    let events = match record::load(path) {
+        Ok(events) => events,
+        Err(e) => {
+            println!("Unable to load recording {}: {}", path.display(), e);
+            exit(1);
+        }
+    };
+    let (shell, daemon) = UnixStream::pair().expect("Unable to create socket pair");
+    let actions = daemon.try_clone().expect("Unable to clone socket");
+    std::thread::spawn(move || {
+        for action in ipc::read_messages::<ToplevelAction>(actions) {
+            println!("Replay: ignoring {:?}", action);
+        }
+    });
+    std::thread::spawn(move || {
+        if ipc::send(&daemon, &ToplevelModel::new().snapshot()).is_err() {
+            return;
+        }
+        record::replay(&events, true, |change| {
+            ipc::send(&daemon, &change.signal()).is_ok()
+        });
+        // The action thread's clone keeps the socket open afterwards, so
+        // the switcher stays up unless the recording finished the manager
+        println!("Replay finished");
+    });
+    shell
+}
+
 struct ConsolationSwitcherApp {
     core: Core,
     toplevel_recv: RefCell<Option<Channel<ToplevelSignal>>>,
-    action_sender: SyncSender<ToplevelAction>,
-    applist: HashMap<ZwlrForeignToplevelHandleV1, ToplevelDetails>,
-    seat: Option<WlSeat>,
-    //    output: Option<WlOutput>,
+    shell: UnixStream,
+    applist: HashMap<ToplevelId, ToplevelDetails>,
+    // Most recently activated first, as tracked by consolation-shelld
+    mru: Vec<ToplevelId>,
     selection: ConsolationSelection,
+    page: ConsolationPage,
+    config: ShellConfig,
+    // With the command each runs, launch profiles included
+    launcher_entries: Vec<(LauncherEntry, LaunchCommand)>,
+    run_input: String,
+    context_menu: Option<ContextMenu>,
+    // When Enter went down, and whether holding it has opened the menu.
+    // Select waits for the release to know it wasn't a long press
+    select_held: Option<(Instant, bool)>,
+    // Windows that ignored a close for too long, as consolation-shelld sees it
+    overdue: HashSet<ToplevelId>,
+    force_quit: Option<ForceQuit>,
+    // Windows consolation-shelld has SIGSTOPped
+    paused: HashSet<ToplevelId>,
+    apps_recv: RefCell<Option<Channel<AppsSignal>>>,
+    apps: Option<UnixStream>,
+    // Written by consolation-shelld, loaded again whenever it has
+    playtime: PlaytimeTracker,
+    // Where it waits once loaded again off the UI thread
+    loaded_playtime: Arc<Mutex<Option<PlaytimeTracker>>>,
+    // Rule whose time has run out, as consolation-shelld enforces it,
+    // shown until extra time is granted
+    time_up: Option<(String, LimitPeriod)>,
+    pin_input: String,
+    // Seconds until another PIN will be checked, after a wrong one
+    pin_error: Option<u64>,
+    notification_recv: RefCell<Option<Channel<HistorySignal>>>,
+    notifications: Option<UnixStream>,
+    // Newest first, as kept by consolation-notifyd
+    history: Vec<StoredNotification>,
+    // Connected controllers' batteries, shown above the window list
+    controllers: Vec<PowerSupply>,
+    // Backends are looked for the first time the page is opened
+    quick: Option<QuickSettings>,
+    // Reached the first time the power menu is opened
+    logind: Arc<OnceLock<Option<Logind>>>,
+    power_actions: Vec<SessionEnd>,
+    // Shown on the confirmation page
+    power_confirm: Option<SessionEnd>,
+    // Carried out once every window has closed, or CLOSE_TIMEOUT after
+    // asking them to
+    closing_for: Option<(SessionEnd, Instant)>,
+}
+
+#[derive(Debug, PartialEq)]
+enum ConsolationPage {
+    Windows,
+    Launcher,
+    ContextMenu,
+    ForceQuit,
+    TimeUp,
+    Notifications,
+    QuickSettings,
+    PowerMenu,
+    PowerConfirm,
+}
+
+// Everything the power menu can do. Logging out isn't logind's to do, the
+// compositor has to exit. Locking is consolation-lock's
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SessionEnd {
+    Lock,
+    Power(PowerAction),
+    LogOut,
+}
+
+impl SessionEnd {
+    fn label(self) -> &'static str {
+        match self {
+            SessionEnd::Lock => "Lock",
+            SessionEnd::Power(action) => action.label(),
+            SessionEnd::LogOut => "Log out",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            SessionEnd::Lock => "system-lock-screen",
+            SessionEnd::Power(action) => action.icon(),
+            SessionEnd::LogOut => "system-log-out",
+        }
+    }
+
+    fn ends_session(self) -> bool {
+        match self {
+            SessionEnd::Lock => false,
+            SessionEnd::Power(action) => action.ends_session(),
+            SessionEnd::LogOut => true,
+        }
+    }
 }
 
-#[derive(Debug)]
+// Confirmation for sending a signal to a window's processes
+struct ForceQuit {
+    app: ToplevelId,
+    title: String,
+    processes: Vec<ProcessInfo>,
+    by_name: bool,
+    signal: Signal,
+}
+
+// Holding Enter this long opens the context menu instead. Controllers
+// rarely have a menu key to map, but always have the button for Enter
+const LONG_PRESS: Duration = Duration::from_millis(600);
+
+// Windows that stay open this long after being asked to close, such as
+// ones waiting on a save prompt nobody answers, don't hold up the power action
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Secondary actions for a single window, shown in place of the window list
+struct ContextMenu {
+    app: ToplevelId,
+    desktop_id: Option<String>,
+    actions: Vec<DesktopAction>,
+}
+
+#[derive(Debug, PartialEq)]
 enum ConsolationSelection {
     WindowActivate(usize),
     WindowMaxToggle(usize),
     WindowClose(usize),
     RunButton,
+    LauncherEntry(usize),
+    NotificationsButton,
+    QuickSettingsButton,
+    PowerButton,
+    DismissAllButton,
+    // A notification and which of its buttons, its actions then dismiss
+    Notification(usize, usize),
+    QuickSetting(usize),
+    PowerAction(usize),
+    // A button on the power confirmation page
+    PowerConfirm(usize),
 }
 
 #[derive(Debug, Clone)]
 enum Message {
     // Messages from channel
-    UpdateApplication(ZwlrForeignToplevelHandleV1, ToplevelDetails),
-    RemoveApplication(ZwlrForeignToplevelHandleV1),
-    NewSeat(WlSeat),
-    //NewOutput(WlOutput),
+    Snapshot(Vec<(ToplevelId, ToplevelDetails)>, Vec<ToplevelId>),
+    UpdateApplication(ToplevelId, ToplevelDetails),
+    RemoveApplication(ToplevelId),
+    Mru(Vec<ToplevelId>),
     // Messages from user
-    ActivateApplication(ZwlrForeignToplevelHandleV1),
-    MinApplication(ZwlrForeignToplevelHandleV1),
-    MaxApplication(ZwlrForeignToplevelHandleV1),
-    UnMaxApplication(ZwlrForeignToplevelHandleV1),
-    CloseApplication(ZwlrForeignToplevelHandleV1),
+    ActivateApplication(ToplevelId),
+    MinApplication(ToplevelId),
+    MaxApplication(ToplevelId),
+    UnMaxApplication(ToplevelId),
+    UnMinApplication(ToplevelId),
+    FullscreenApplication(ToplevelId),
+    UnFullscreenApplication(ToplevelId),
+    CloseApplication(ToplevelId),
+    OpenContextMenu(ToplevelId),
+    ForceQuitApplication(ToplevelId),
+    ForceQuitOffered(ToplevelId, Signal, Vec<ProcessInfo>, bool),
+    ConfirmForceQuit(),
+    PauseApplication(ToplevelId),
+    ResumeApplication(ToplevelId),
+    AppsStatus(Vec<ToplevelId>, Vec<ToplevelId>),
+    DesktopActionsFound(ToplevelId, Option<(String, Vec<DesktopAction>)>),
+    RunDesktopAction(usize),
+    LauncherLoaded(Vec<(LauncherEntry, LaunchCommand)>),
+    ShowLauncher(),
+    ShowWindows(),
+    Launch(usize),
+    RunInput(String),
+    RunSubmit(),
     // Message from user keyboard
     ArrowUp(),
     ArrowDown(),
     ArrowLeft(),
     ArrowRight(),
+    SelectPressed(),
+    SelectHeld(),
+    SelectReleased(),
     Select(),
     Back(),
+    Menu(),
 
     NoOp(),
+    PlaytimeSaved(),
+    PlaytimeLoaded(),
+    TimeUp(String, LimitPeriod),
+    Granted(String),
+    WrongPin(u64),
+    ControllersTick(),
+    PinInput(String),
+    PinSubmit(),
     Finish(),
+
+    // Messages from consolation-notifyd
+    NotificationSnapshot(Vec<StoredNotification>),
+    NotificationAdded(StoredNotification),
+    NotificationRemoved(u32),
+    NotificationsRead(),
+    // Messages from user
+    ShowNotifications(),
+    InvokeNotification(u32, String),
+    DismissNotification(u32),
+    DismissAllNotifications(),
+    ShowQuickSettings(),
+    SetBrightness(u8),
+    SetVolume(u8),
+    ToggleMute(),
+    SetPowerProfile(String),
+    ToggleNightLight(),
+    QuickSettingsTick(),
+    QuickSettingsRead(Option<Readings>),
+    ShowPowerMenu(),
+    PowerActionsFound(Vec<PowerAction>),
+    ChooseSessionEnd(SessionEnd),
+    CloseAllThen(SessionEnd),
+    ClosingTick(),
+    EndSession(SessionEnd),
 }
 
 impl Application for ConsolationSwitcherApp {
@@ -317,7 +425,7 @@ impl Application for ConsolationSwitcherApp {
     type Flags = UiFlags;
     type Message = Message;
 
-    const APP_ID: &'static str = "Consolation Switcher";
+    const APP_ID: &'static str = ipc::SWITCHER_APP_ID;
 
     fn core(&self) -> &cosmic::app::Core {
         &self.core
@@ -328,59 +436,466 @@ impl Application for ConsolationSwitcherApp {
     }
 
     fn init(core: Core, flags: Self::Flags) -> (Self, Command<Self::Message>) {
+        let config = ShellConfig::load();
+        let launcher = load_launcher(config.clone());
         (
             ConsolationSwitcherApp {
                 core,
                 toplevel_recv: RefCell::new(Some(flags.toplevel_recv)),
-                action_sender: flags.action_sender,
+                shell: flags.shell,
                 applist: HashMap::new(),
-                seat: None,
+                mru: vec![],
                 selection: ConsolationSelection::WindowActivate(0),
+                page: ConsolationPage::Windows,
+                launcher_entries: vec![],
+                config,
+                run_input: String::new(),
+                context_menu: None,
+                select_held: None,
+                overdue: HashSet::new(),
+                force_quit: None,
+                paused: HashSet::new(),
+                apps_recv: RefCell::new(Some(flags.apps_recv)),
+                apps: flags.apps,
+                playtime: PlaytimeTracker::load(),
+                loaded_playtime: Arc::new(Mutex::new(None)),
+                time_up: None,
+                pin_input: String::new(),
+                pin_error: None,
+                notification_recv: RefCell::new(Some(flags.notification_recv)),
+                notifications: flags.notifications,
+                history: vec![],
+                controllers: read_controllers(),
+                quick: None,
+                logind: Arc::new(OnceLock::new()),
+                power_actions: vec![],
+                power_confirm: None,
+                closing_for: None,
             },
-            Command::none(),
+            launcher,
         )
     }
 
     fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+        // Picking a window action from the context menu returns to the list
+        if let Message::ActivateApplication(_)
+        | Message::MinApplication(_)
+        | Message::UnMinApplication(_)
+        | Message::MaxApplication(_)
+        | Message::UnMaxApplication(_)
+        | Message::FullscreenApplication(_)
+        | Message::UnFullscreenApplication(_)
+        | Message::CloseApplication(_)
+        | Message::PauseApplication(_)
+        | Message::ResumeApplication(_) = message
+        {
+            if self.page == ConsolationPage::ContextMenu {
+                self.context_menu = None;
+                self.page = ConsolationPage::Windows;
+            }
+        }
         match message {
+            Message::Snapshot(toplevels, mru) => {
+                self.applist = toplevels.into_iter().collect();
+                self.mru = mru;
+                self.check_all_closed();
+            }
             Message::UpdateApplication(k, v) => {
                 self.applist.insert(k, v);
             }
+            Message::Mru(order) => {
+                self.mru = order;
+            }
             Message::RemoveApplication(k) => {
                 self.applist.remove(&k);
+                if self.force_quit.as_ref().map(|quit| &quit.app) == Some(&k) {
+                    self.force_quit = None;
+                    self.page = ConsolationPage::Windows;
+                }
+                if self.context_menu.as_ref().map(|menu| &menu.app) == Some(&k) {
+                    self.context_menu = None;
+                    self.page = ConsolationPage::Windows;
+                }
+                self.check_all_closed();
             }
             Message::ActivateApplication(app) => {
-                if let Some(seat) = self.seat.clone() {
-                    app.activate(&seat);
-                }
-                let _ = self.action_sender.try_send(ToplevelAction::Refresh());
+                // consolation-shelld resumes the window, and pauses the
+                // game it replaces with auto_pause. If its time is up, it
+                // refuses and sends TimeUp instead
+                self.send_action(ToplevelAction::Activate { id: app });
                 return self.minimize();
-                //let _ = self.action_sender.try_send(ToplevelAction::Exit());
             }
             Message::MinApplication(app) => {
-                app.set_minimized();
-                let _ = self.action_sender.try_send(ToplevelAction::Refresh());
+                self.send_action(ToplevelAction::Minimize { id: app });
             }
             Message::MaxApplication(app) => {
-                app.set_maximized();
-                let _ = self.action_sender.try_send(ToplevelAction::Refresh());
+                self.send_action(ToplevelAction::Maximize { id: app });
             }
             Message::UnMaxApplication(app) => {
-                app.unset_maximized();
-                let _ = self.action_sender.try_send(ToplevelAction::Refresh());
+                self.send_action(ToplevelAction::Unmaximize { id: app });
+            }
+            Message::UnMinApplication(app) => {
+                self.send_action(ToplevelAction::Unminimize { id: app });
             }
+            Message::FullscreenApplication(app) => {
+                // No output, let the compositor pick
+                self.send_action(ToplevelAction::Fullscreen {
+                    id: app,
+                    output: None,
+                });
+            }
+            Message::UnFullscreenApplication(app) => {
+                self.send_action(ToplevelAction::Unfullscreen { id: app });
+            }
+            // consolation-shelld keeps track of windows that ignore this
             Message::CloseApplication(app) => {
-                app.close();
-                // Wake other thread up to process this
-                let _ = self.action_sender.try_send(ToplevelAction::Refresh());
+                self.send_action(ToplevelAction::Close { id: app });
+            }
+            // consolation-shelld finds the processes and picks the signal,
+            // we ask before sending it
+            Message::ForceQuitApplication(app) => {
+                self.send_apps_action(AppsAction::ForceQuit { id: app });
+            }
+            Message::ForceQuitOffered(app, signal, processes, by_name) => {
+                let Some(details) = self.applist.get(&app) else {
+                    return Command::none();
+                };
+                self.force_quit = Some(ForceQuit {
+                    title: details.title.clone().unwrap_or("No title".to_owned()),
+                    app,
+                    processes,
+                    by_name,
+                    signal,
+                });
+                self.page = ConsolationPage::ForceQuit;
+            }
+            Message::ConfirmForceQuit() => {
+                if let Some(quit) = self.force_quit.take() {
+                    self.send_apps_action(AppsAction::ConfirmForceQuit { id: quit.app });
+                }
+                self.page = ConsolationPage::Windows;
+            }
+            Message::PauseApplication(app) => {
+                self.send_apps_action(AppsAction::Pause { id: app });
+            }
+            Message::ResumeApplication(app) => {
+                self.send_apps_action(AppsAction::Resume { id: app });
+            }
+            Message::AppsStatus(paused, overdue) => {
+                self.paused = paused.into_iter().collect();
+                self.overdue = overdue.into_iter().collect();
+            }
+            Message::PlaytimeSaved() => {
+                // Loading while holding the lock means whichever load
+                // finishes last read the newest log
+                let loaded = self.loaded_playtime.clone();
+                return Command::perform(
+                    async move {
+                        let _ = tokio::task::spawn_blocking(move || {
+                            let mut loaded = loaded.lock().unwrap();
+                            *loaded = Some(PlaytimeTracker::load());
+                        })
+                        .await;
+                    },
+                    |_| cosmic::app::Message::App(Message::PlaytimeLoaded()),
+                );
+            }
+            Message::PlaytimeLoaded() => {
+                if let Some(playtime) = self.loaded_playtime.lock().unwrap().take() {
+                    self.playtime = playtime;
+                }
+            }
+            Message::ControllersTick() => {
+                self.controllers = read_controllers();
+            }
+            Message::TimeUp(rule, period) => {
+                return self.show_time_up(rule, period);
+            }
+            Message::Granted(rule) => {
+                if self.time_up.as_ref().map(|(r, _)| r) == Some(&rule) {
+                    self.time_up = None;
+                    self.pin_error = None;
+                    if self.page == ConsolationPage::TimeUp {
+                        self.page = ConsolationPage::Windows;
+                    }
+                }
+            }
+            Message::WrongPin(retry_after) => {
+                self.pin_error = Some(retry_after);
+            }
+            Message::PinInput(pin) => {
+                self.pin_input = pin;
+                self.pin_error = None;
+            }
+            Message::PinSubmit() => {
+                if let Some((rule, _)) = &self.time_up {
+                    self.send_apps_action(AppsAction::GrantTime {
+                        rule: rule.clone(),
+                        pin: std::mem::take(&mut self.pin_input),
+                    });
+                }
+                self.pin_input.clear();
+            }
+            Message::OpenContextMenu(app) => {
+                self.context_menu = Some(ContextMenu {
+                    app,
+                    desktop_id: None,
+                    actions: vec![],
+                });
+                self.page = ConsolationPage::ContextMenu;
+                // Finding the desktop file reads every applications
+                // directory, so its actions are added when it's found
+                let app_id = self
+                    .applist
+                    .get(&app)
+                    .and_then(|details| details.app_id.clone());
+                if let Some(app_id) = app_id {
+                    return Command::perform(
+                        async move {
+                            let found = tokio::task::spawn_blocking(move || {
+                                let file = desktop::find_for_app_id(&app_id)?;
+                                Some((file.id.clone(), file.actions()))
+                            })
+                            .await
+                            .ok()
+                            .flatten();
+                            (app, found)
+                        },
+                        |(app, found)| {
+                            cosmic::app::Message::App(Message::DesktopActionsFound(app, found))
+                        },
+                    );
+                }
+            }
+            Message::DesktopActionsFound(app, found) => {
+                let menu = self.context_menu.as_mut().filter(|menu| menu.app == app);
+                if let (Some(menu), Some((desktop_id, actions))) = (menu, found) {
+                    menu.desktop_id = Some(desktop_id);
+                    menu.actions = actions;
+                }
+            }
+            Message::RunDesktopAction(idx) => {
+                if let Some(menu) = self.context_menu.take() {
+                    if let Some(action) = menu.actions.get(idx) {
+                        let profiles = menu
+                            .desktop_id
+                            .map(|id| self.config.profiles_for(&id))
+                            .unwrap_or_default();
+                        let command = LaunchCommand::with_profiles(action.exec.clone(), &profiles);
+                        if let Err(e) = command.spawn() {
+                            println!("Unable to run {}: {}", action.name, e);
+                        }
+                    }
+                }
+                self.page = ConsolationPage::Windows;
+                return self.minimize();
+            }
+            Message::LauncherLoaded(entries) => {
+                self.launcher_entries = entries;
+            }
+            Message::ShowLauncher() => {
+                self.page = ConsolationPage::Launcher;
+                self.selection = ConsolationSelection::LauncherEntry(0);
+            }
+            Message::ShowWindows() => {
+                self.context_menu = None;
+                self.force_quit = None;
+                if self.page == ConsolationPage::Launcher {
+                    self.selection = ConsolationSelection::RunButton;
+                }
+                if self.page == ConsolationPage::Notifications {
+                    self.selection = ConsolationSelection::NotificationsButton;
+                }
+                if self.page == ConsolationPage::QuickSettings {
+                    self.selection = ConsolationSelection::QuickSettingsButton;
+                }
+                if let ConsolationPage::PowerMenu | ConsolationPage::PowerConfirm = self.page {
+                    self.selection = ConsolationSelection::PowerButton;
+                    self.power_confirm = None;
+                    self.closing_for = None;
+                }
+                self.page = ConsolationPage::Windows;
+            }
+            Message::Launch(idx) => {
+                if let Some((entry, command)) = self.launcher_entries.get(idx) {
+                    if let Err(e) = command.spawn() {
+                        println!("Unable to launch {}: {}", entry.name, e);
+                    }
+                    self.page = ConsolationPage::Windows;
+                    self.selection = ConsolationSelection::RunButton;
+                    return self.minimize();
+                }
+            }
+            Message::RunInput(input) => {
+                self.run_input = input;
+            }
+            Message::RunSubmit() => {
+                if let Some(command) = launcher::run_prompt_command(&self.run_input, &self.config) {
+                    if let Err(e) = command.spawn() {
+                        println!("Unable to run {}: {}", self.run_input, e);
+                    }
+                    self.run_input.clear();
+                    self.page = ConsolationPage::Windows;
+                    self.selection = ConsolationSelection::RunButton;
+                    return self.minimize();
+                }
+            }
+            Message::NotificationSnapshot(notifications) => {
+                self.history = notifications;
+                self.clamp_notification_selection();
+            }
+            Message::NotificationAdded(notification) => {
+                let id = notification.notification.id;
+                self.history.retain(|entry| entry.notification.id != id);
+                self.history.insert(0, notification);
+            }
+            Message::NotificationRemoved(id) => {
+                self.history.retain(|entry| entry.notification.id != id);
+                self.clamp_notification_selection();
+            }
+            Message::NotificationsRead() => {
+                for entry in self.history.iter_mut() {
+                    entry.read = true;
+                }
+            }
+            Message::ShowNotifications() => {
+                self.page = ConsolationPage::Notifications;
+                self.selection = match self.history.is_empty() {
+                    true => ConsolationSelection::DismissAllButton,
+                    false => ConsolationSelection::Notification(0, 0),
+                };
+                if self.history.iter().any(|entry| !entry.read) {
+                    self.send_history_action(HistoryAction::MarkRead);
+                }
+            }
+            Message::InvokeNotification(id, key) => {
+                // Whatever the action opens should be in front
+                self.send_history_action(HistoryAction::Invoke { id, key });
+                self.page = ConsolationPage::Windows;
+                self.selection = ConsolationSelection::NotificationsButton;
+                return self.minimize();
+            }
+            Message::DismissNotification(id) => {
+                self.send_history_action(HistoryAction::Dismiss { id });
+            }
+            Message::DismissAllNotifications() => {
+                self.send_history_action(HistoryAction::DismissAll);
+            }
+            Message::ShowQuickSettings() => {
+                self.page = ConsolationPage::QuickSettings;
+                self.selection = ConsolationSelection::QuickSetting(0);
+                return self.update(Message::QuickSettingsTick());
+            }
+            Message::SetBrightness(percentage) => {
+                if let Some(quick) = &mut self.quick {
+                    quick.set_brightness(percentage);
+                }
+                return self.write_quick_settings();
+            }
+            Message::SetVolume(percentage) => {
+                if let Some(quick) = &mut self.quick {
+                    quick.set_volume(percentage);
+                }
+                return self.write_quick_settings();
+            }
+            Message::ToggleMute() => {
+                if let Some(quick) = &mut self.quick {
+                    quick.toggle_mute();
+                }
+                return self.write_quick_settings();
+            }
+            Message::SetPowerProfile(profile) => {
+                if let Some(quick) = &mut self.quick {
+                    quick.set_profile(&profile);
+                }
+                return self.write_quick_settings();
+            }
+            Message::ToggleNightLight() => {
+                if let Some(quick) = &mut self.quick {
+                    quick.toggle_night_light();
+                }
+                return self.write_quick_settings();
+            }
+            Message::QuickSettingsTick() => {
+                let read = self
+                    .quick
+                    .get_or_insert_with(QuickSettings::system)
+                    .reader();
+                return Command::perform(
+                    async move { tokio::task::spawn_blocking(read).await.ok() },
+                    |readings| cosmic::app::Message::App(Message::QuickSettingsRead(readings)),
+                );
+            }
+            Message::QuickSettingsRead(readings) => {
+                if let (Some(quick), Some(readings)) = (&mut self.quick, readings) {
+                    quick.apply(readings);
+                }
+            }
+            Message::ShowPowerMenu() => {
+                self.set_power_actions(vec![]);
+                self.power_confirm = None;
+                self.closing_for = None;
+                self.page = ConsolationPage::PowerMenu;
+                self.selection = ConsolationSelection::PowerAction(0);
+                let logind = self.logind.clone();
+                return Command::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || {
+                            system_logind(&logind)
+                                .map(|logind| logind.available())
+                                .unwrap_or_default()
+                        })
+                        .await
+                        .unwrap_or_default()
+                    },
+                    |actions| cosmic::app::Message::App(Message::PowerActionsFound(actions)),
+                );
+            }
+            Message::PowerActionsFound(actions) => {
+                // Stay on whatever was selected while logind was asked
+                let selected = match self.selection {
+                    ConsolationSelection::PowerAction(idx) => self.power_actions.get(idx).copied(),
+                    _ => None,
+                };
+                self.set_power_actions(actions);
+                if let Some(idx) = selected
+                    .and_then(|end| self.power_actions.iter().position(|other| *other == end))
+                {
+                    self.selection = ConsolationSelection::PowerAction(idx);
+                }
+            }
+            // Locking is easily undone, so isn't confirmed
+            Message::ChooseSessionEnd(SessionEnd::Lock) => {
+                self.end_session(SessionEnd::Lock);
+            }
+            Message::ChooseSessionEnd(end) => {
+                self.power_confirm = Some(end);
+                self.page = ConsolationPage::PowerConfirm;
+                self.selection = ConsolationSelection::PowerConfirm(0);
+            }
+            Message::CloseAllThen(end) => {
+                self.closing_for = Some((end, Instant::now()));
+                self.selection = ConsolationSelection::PowerConfirm(0);
+                for app in self.others() {
+                    let _ = self.update(Message::CloseApplication(app));
+                }
+                self.check_all_closed();
+            }
+            Message::ClosingTick() => {
+                if let Some((end, since)) = self.closing_for {
+                    if since.elapsed() >= CLOSE_TIMEOUT {
+                        println!("Windows still open, going ahead anyway");
+                        self.end_session(end);
+                    }
+                }
+            }
+            Message::EndSession(end) => {
+                self.end_session(end);
             }
             Message::NoOp() => {}
             Message::Finish() => {
                 exit(0);
             }
-            Message::NewSeat(seat) => {
-                self.seat = Some(seat);
-            }
 
             Message::ArrowUp() => match self.selection {
                 ConsolationSelection::WindowActivate(idx) => {
@@ -404,6 +919,25 @@ impl Application for ConsolationSwitcherApp {
                         self.selection = ConsolationSelection::WindowClose(idx - 1);
                     }
                 }
+                ConsolationSelection::Notification(idx, _) => {
+                    if idx == 0 {
+                        self.selection = ConsolationSelection::DismissAllButton;
+                    } else {
+                        self.selection = ConsolationSelection::Notification(idx - 1, 0);
+                    }
+                }
+                ConsolationSelection::QuickSetting(idx) if idx > 0 => {
+                    self.selection = ConsolationSelection::QuickSetting(idx - 1);
+                }
+                ConsolationSelection::PowerAction(idx) if idx > 0 => {
+                    self.selection = ConsolationSelection::PowerAction(idx - 1);
+                }
+                ConsolationSelection::PowerConfirm(idx) if idx > 0 => {
+                    self.selection = ConsolationSelection::PowerConfirm(idx - 1);
+                }
+                ConsolationSelection::LauncherEntry(idx) if idx > 0 => {
+                    self.selection = ConsolationSelection::LauncherEntry(idx - 1);
+                }
                 _ => {}
             },
             Message::ArrowDown() => match self.selection {
@@ -422,11 +956,62 @@ impl Application for ConsolationSwitcherApp {
                         self.selection = ConsolationSelection::WindowClose(idx + 1);
                     }
                 }
-                ConsolationSelection::RunButton => {
+                ConsolationSelection::RunButton
+                | ConsolationSelection::NotificationsButton
+                | ConsolationSelection::QuickSettingsButton
+                | ConsolationSelection::PowerButton => {
                     self.selection = ConsolationSelection::WindowActivate(0);
                 }
+                ConsolationSelection::DismissAllButton => {
+                    if !self.history.is_empty() {
+                        self.selection = ConsolationSelection::Notification(0, 0);
+                    }
+                }
+                ConsolationSelection::Notification(idx, _) => {
+                    if idx + 1 < self.history.len() {
+                        self.selection = ConsolationSelection::Notification(idx + 1, 0);
+                    }
+                }
+                ConsolationSelection::QuickSetting(idx) => {
+                    if idx + 1 < self.quick_settings_rows().len() {
+                        self.selection = ConsolationSelection::QuickSetting(idx + 1);
+                    }
+                }
+                ConsolationSelection::PowerAction(idx) => {
+                    if idx + 1 < self.power_actions.len() {
+                        self.selection = ConsolationSelection::PowerAction(idx + 1);
+                    }
+                }
+                ConsolationSelection::PowerConfirm(idx) => {
+                    if idx + 1 < self.power_confirm_buttons().len() {
+                        self.selection = ConsolationSelection::PowerConfirm(idx + 1);
+                    }
+                }
+                ConsolationSelection::LauncherEntry(idx) => {
+                    if idx + 1 < self.launcher_entries.len() {
+                        self.selection = ConsolationSelection::LauncherEntry(idx + 1);
+                    }
+                }
             },
             Message::ArrowLeft() => {
+                match self.selection {
+                    ConsolationSelection::NotificationsButton => {
+                        self.selection = ConsolationSelection::RunButton;
+                    }
+                    ConsolationSelection::QuickSettingsButton => {
+                        self.selection = ConsolationSelection::NotificationsButton;
+                    }
+                    ConsolationSelection::PowerButton => {
+                        self.selection = ConsolationSelection::QuickSettingsButton;
+                    }
+                    ConsolationSelection::Notification(idx, button) if button > 0 => {
+                        self.selection = ConsolationSelection::Notification(idx, button - 1);
+                    }
+                    ConsolationSelection::QuickSetting(idx) => {
+                        return self.step_quick_setting(idx, -1);
+                    }
+                    _ => {}
+                }
                 if let ConsolationSelection::WindowMaxToggle(idx) = self.selection {
                     self.selection = ConsolationSelection::WindowActivate(idx);
                 }
@@ -435,6 +1020,26 @@ impl Application for ConsolationSwitcherApp {
                 }
             }
             Message::ArrowRight() => {
+                match self.selection {
+                    ConsolationSelection::RunButton => {
+                        self.selection = ConsolationSelection::NotificationsButton;
+                    }
+                    ConsolationSelection::NotificationsButton => {
+                        self.selection = ConsolationSelection::QuickSettingsButton;
+                    }
+                    ConsolationSelection::QuickSettingsButton => {
+                        self.selection = ConsolationSelection::PowerButton;
+                    }
+                    ConsolationSelection::Notification(idx, button) => {
+                        if button + 1 < self.notification_buttons(idx).len() {
+                            self.selection = ConsolationSelection::Notification(idx, button + 1);
+                        }
+                    }
+                    ConsolationSelection::QuickSetting(idx) => {
+                        return self.step_quick_setting(idx, 1);
+                    }
+                    _ => {}
+                }
                 if let ConsolationSelection::WindowMaxToggle(idx) = self.selection {
                     self.selection = ConsolationSelection::WindowClose(idx);
                 }
@@ -442,14 +1047,131 @@ impl Application for ConsolationSwitcherApp {
                     self.selection = ConsolationSelection::WindowMaxToggle(idx);
                 }
             }
-            Message::Select() => todo!(),
-            Message::Back() => todo!(),
+            Message::Select() => match self.selection {
+                // Window buttons do what clicking them would
+                ConsolationSelection::WindowActivate(_) => {
+                    if let Some(app) = self.selected_app() {
+                        return self.update(Message::ActivateApplication(app));
+                    }
+                }
+                ConsolationSelection::WindowMaxToggle(_) => {
+                    let Some(app) = self.selected_app() else {
+                        return Command::none();
+                    };
+                    let maximized = self
+                        .applist
+                        .get(&app)
+                        .is_some_and(|details| details.state.contains(ToplevelState::MAXIMIZED));
+                    return self.update(match maximized {
+                        true => Message::UnMaxApplication(app),
+                        false => Message::MaxApplication(app),
+                    });
+                }
+                ConsolationSelection::WindowClose(_) => {
+                    let Some(app) = self.selected_app() else {
+                        return Command::none();
+                    };
+                    return self.update(match self.overdue.contains(&app) {
+                        true => Message::ForceQuitApplication(app),
+                        false => Message::CloseApplication(app),
+                    });
+                }
+                ConsolationSelection::RunButton => {
+                    return self.update(Message::ShowLauncher());
+                }
+                ConsolationSelection::LauncherEntry(idx) => {
+                    return self.update(Message::Launch(idx));
+                }
+                ConsolationSelection::NotificationsButton => {
+                    return self.update(Message::ShowNotifications());
+                }
+                ConsolationSelection::QuickSettingsButton => {
+                    return self.update(Message::ShowQuickSettings());
+                }
+                ConsolationSelection::PowerButton => {
+                    return self.update(Message::ShowPowerMenu());
+                }
+                ConsolationSelection::PowerAction(idx) => {
+                    if let Some(end) = self.power_actions.get(idx) {
+                        return self.update(Message::ChooseSessionEnd(*end));
+                    }
+                }
+                ConsolationSelection::PowerConfirm(idx) => {
+                    if let Some((_, message)) = self.power_confirm_buttons().into_iter().nth(idx) {
+                        return self.update(message);
+                    }
+                }
+                ConsolationSelection::QuickSetting(idx) => {
+                    let setting = self.quick_settings_rows().get(idx).copied();
+                    if let (Some(quick), Some(setting)) = (&mut self.quick, setting) {
+                        quick.select(setting);
+                    }
+                    return self.write_quick_settings();
+                }
+                ConsolationSelection::DismissAllButton => {
+                    return self.update(Message::DismissAllNotifications());
+                }
+                ConsolationSelection::Notification(idx, button) => {
+                    if let Some((_, message)) =
+                        self.notification_buttons(idx).into_iter().nth(button)
+                    {
+                        return self.update(message);
+                    }
+                }
+            },
+            Message::Back() => {
+                // Back from confirming only goes as far as the menu
+                if self.page == ConsolationPage::PowerConfirm {
+                    return self.update(Message::ShowPowerMenu());
+                }
+                if self.page == ConsolationPage::PowerMenu {
+                    self.selection = ConsolationSelection::PowerButton;
+                }
+                if self.page == ConsolationPage::Notifications {
+                    self.selection = ConsolationSelection::NotificationsButton;
+                }
+                if self.page == ConsolationPage::QuickSettings {
+                    self.selection = ConsolationSelection::QuickSettingsButton;
+                }
+                if self.page == ConsolationPage::Launcher {
+                    self.selection = ConsolationSelection::RunButton;
+                }
+                if self.page != ConsolationPage::Windows {
+                    self.context_menu = None;
+                    self.force_quit = None;
+                    self.page = ConsolationPage::Windows;
+                }
+            }
+            Message::Menu() => {
+                if let Some(app) = self.selected_app() {
+                    return self.update(Message::OpenContextMenu(app));
+                }
+            }
+            // Key repeat sends more presses while held, only the first counts
+            Message::SelectPressed() => {
+                if self.select_held.is_none() {
+                    self.select_held = Some((Instant::now(), false));
+                }
+            }
+            Message::SelectHeld() => {
+                if let Some((since, false)) = self.select_held {
+                    if since.elapsed() >= LONG_PRESS && self.page == ConsolationPage::Windows {
+                        self.select_held = Some((since, true));
+                        return self.update(Message::Menu());
+                    }
+                }
+            }
+            Message::SelectReleased() => {
+                if let Some((_, false)) = self.select_held.take() {
+                    return self.update(Message::Select());
+                }
+            }
         }
         Command::none()
     }
 
     fn subscription(&self) -> cosmic::iced::Subscription<Self::Message> {
-        Subscription::batch([
+        let mut subscriptions = vec![
             iced::subscription::unfold(
                 "toplevel changes",
                 self.toplevel_recv.take(),
@@ -457,19 +1179,66 @@ impl Application for ConsolationSwitcherApp {
                     let change = recvr.as_mut().unwrap().recv().unwrap().clone();
                     (
                         match change {
-                            ToplevelSignal::AddUpdateToplevel(update) => {
-                                Message::UpdateApplication(update.0, update.1)
+                            ToplevelSignal::Snapshot { toplevels, mru, .. } => {
+                                Message::Snapshot(toplevels, mru)
                             }
-                            ToplevelSignal::RemoveToplevel(delete) => {
-                                Message::RemoveApplication(delete)
+                            ToplevelSignal::AddUpdateToplevel { id, details } => {
+                                Message::UpdateApplication(id, details)
                             }
+                            ToplevelSignal::RemoveToplevel { id } => Message::RemoveApplication(id),
+                            ToplevelSignal::Mru { order } => Message::Mru(order),
                             ToplevelSignal::Closed => Message::Finish(),
-                            ToplevelSignal::SeatChanged(seat) => Message::NewSeat(seat),
+                            // Only needed when picking an output, which we leave
+                            // to the compositor
+                            ToplevelSignal::OutputChanged { .. } => Message::NoOp(),
                         },
                         recvr,
                     )
                 },
             ),
+            iced::subscription::unfold(
+                "notification history",
+                self.notification_recv.take(),
+                move |mut recvr| async move {
+                    let message = match recvr.as_mut().unwrap().recv() {
+                        Ok(HistorySignal::Snapshot { notifications }) => {
+                            Message::NotificationSnapshot(notifications)
+                        }
+                        Ok(HistorySignal::Added { notification }) => {
+                            Message::NotificationAdded(notification)
+                        }
+                        Ok(HistorySignal::Removed { id }) => Message::NotificationRemoved(id),
+                        Ok(HistorySignal::Read) => Message::NotificationsRead(),
+                        // consolation-notifyd isn't running, or has gone away
+                        Err(_) => futures::future::pending().await,
+                    };
+                    (message, recvr)
+                },
+            ),
+            iced::subscription::unfold(
+                "paused apps",
+                self.apps_recv.take(),
+                move |mut recvr| async move {
+                    let message = match recvr.as_mut().unwrap().recv() {
+                        Ok(AppsSignal::Status { paused, overdue }) => {
+                            Message::AppsStatus(paused, overdue)
+                        }
+                        Ok(AppsSignal::Playtime) => Message::PlaytimeSaved(),
+                        Ok(AppsSignal::TimeUp { rule, period }) => Message::TimeUp(rule, period),
+                        Ok(AppsSignal::Granted { rule }) => Message::Granted(rule),
+                        Ok(AppsSignal::WrongPin { retry_after }) => Message::WrongPin(retry_after),
+                        Ok(AppsSignal::ForceQuit {
+                            id,
+                            signal,
+                            processes,
+                            by_name,
+                        }) => Message::ForceQuitOffered(id, signal, processes, by_name),
+                        // Not connected, or consolation-shelld has gone away
+                        Err(_) => futures::future::pending().await,
+                    };
+                    (message, recvr)
+                },
+            ),
             keyboard::on_key_press(|key, modifiers| {
                 let keyboard::Key::Named(key) = key else {
                     return None;
@@ -480,36 +1249,100 @@ impl Application for ConsolationSwitcherApp {
                     (key::Named::ArrowDown, _) => Some(Message::ArrowDown()),
                     (key::Named::ArrowLeft, _) => Some(Message::ArrowLeft()),
                     (key::Named::ArrowRight, _) => Some(Message::ArrowRight()),
-                    (key::Named::Enter, _) => Some(Message::Select()),
+                    (key::Named::Enter, _) => Some(Message::SelectPressed()),
                     (key::Named::Escape, _) => Some(Message::Back()),
+                    (key::Named::ContextMenu, _) => Some(Message::Menu()),
                     _ => None,
                 }
             }),
-        ])
+            keyboard::on_key_release(|key, _| match key {
+                keyboard::Key::Named(key::Named::Enter) => Some(Message::SelectReleased()),
+                _ => None,
+            }),
+        ];
+        if self.select_held.is_some() {
+            subscriptions
+                .push(iced::time::every(Duration::from_millis(100)).map(|_| Message::SelectHeld()));
+        }
+        if self.page == ConsolationPage::Windows {
+            subscriptions.push(
+                iced::time::every(Duration::from_secs(10)).map(|_| Message::ControllersTick()),
+            );
+        }
+        if self.closing_for.is_some() {
+            subscriptions
+                .push(iced::time::every(Duration::from_secs(1)).map(|_| Message::ClosingTick()));
+        }
+        // Brightness keys and other apps change these while we're open
+        if self.page == ConsolationPage::QuickSettings {
+            subscriptions.push(
+                iced::time::every(Duration::from_secs(2)).map(|_| Message::QuickSettingsTick()),
+            );
+        }
+        Subscription::batch(subscriptions)
     }
 
     fn on_app_exit(&mut self) -> Option<Self::Message> {
-        let _ = self.action_sender.try_send(ToplevelAction::Exit());
         Some(Message::NoOp())
     }
 
     fn view(&self) -> cosmic::Element<Self::Message> {
+        match self.page {
+            ConsolationPage::Launcher => return self.view_launcher(),
+            ConsolationPage::ContextMenu => return self.view_context_menu(),
+            ConsolationPage::ForceQuit => return self.view_force_quit(),
+            ConsolationPage::TimeUp => return self.view_time_up(),
+            ConsolationPage::Notifications => return self.view_notifications(),
+            ConsolationPage::QuickSettings => return self.view_quick_settings(),
+            ConsolationPage::PowerMenu => return self.view_power_menu(),
+            ConsolationPage::PowerConfirm => return self.view_power_confirm(),
+            ConsolationPage::Windows => {}
+        }
         let mut c = column();
+        if !self.controllers.is_empty() {
+            c = c.push(self.view_controllers());
+        }
+        let mut run_button = button(text("Launch..."));
+        run_button = run_button.on_press(Message::ShowLauncher());
+        let unread = self.history.iter().filter(|entry| !entry.read).count();
+        let notifications_label = match unread {
+            0 => "Notifications".to_owned(),
+            unread => format!("Notifications ({})", unread),
+        };
+        let mut notifications_button = button(
+            row()
+                .push(icon::from_name("preferences-system-notifications"))
+                .push(text(notifications_label)),
+        );
+        notifications_button = notifications_button.on_press(Message::ShowNotifications());
+        let quick_settings_button = button(
+            row()
+                .push(icon::from_name("preferences-system"))
+                .push(text("Quick settings")),
+        )
+        .on_press(Message::ShowQuickSettings());
+        let power_button = button(
+            row()
+                .push(icon::from_name("system-shutdown"))
+                .push(text("Power")),
+        )
+        .on_press(Message::ShowPowerMenu());
+        c = c.push(
+            row()
+                .push(run_button)
+                .push(notifications_button)
+                .push(quick_settings_button)
+                .push(power_button),
+        );
         let _row_maybe = match self.selection {
             ConsolationSelection::WindowActivate(idx) => Some(idx),
             ConsolationSelection::WindowMaxToggle(idx) => Some(idx),
             ConsolationSelection::WindowClose(idx) => Some(idx),
             _ => None,
         };
-        let is_min = zwlr_foreign_toplevel_handle_v1::State::Maximized as u8;
-        let is_act = zwlr_foreign_toplevel_handle_v1::State::Activated as u8;
         println!("{:?}", self.selection);
-        for (app, details) in self.applist.iter() {
-            if details.title.is_none() || details.title.clone().unwrap() == "nil" {
-                continue;
-            }
-
-            let highlight = details.state.contains(&is_act);
+        for (app, details) in self.visible_apps() {
+            let highlight = details.state.contains(ToplevelState::ACTIVATED);
             let mut row2 = row();
             let mut row = row();
 
@@ -525,34 +1358,849 @@ impl Application for ConsolationSwitcherApp {
             let text = text(title);
 
             row = row.push(icon);
-            row = row.push(text);
+            let mut labels = column().push(text);
+            if let Some(app_id) = &details.app_id {
+                let played = self.playtime.playtime(app_id);
+                if !played.total.is_zero() {
+                    labels = labels.push(text::caption(played.describe()));
+                }
+            }
+            row = row.push(labels);
+            if self.paused.contains(&app) {
+                row = row.push(icon::from_name("media-playback-pause").size(16));
+                row = row.push(text::caption("Paused"));
+            }
             let mut activate_button = button(row);
             //if highlight { activate_button = activate_button.style()}
-            activate_button = activate_button.on_press(Message::ActivateApplication(app.clone()));
+            activate_button = activate_button.on_press(Message::ActivateApplication(app));
             row2 = row2.push(activate_button);
             row2 = row2.push(widget::Space::with_width(Length::Fill));
 
-            if !details.state.contains(&is_min) {
+            if !details.state.contains(ToplevelState::MAXIMIZED) {
                 let mut max_button = button(icon::from_name("window-maximize"));
-                max_button = max_button.on_press(Message::MaxApplication(app.clone()));
+                max_button = max_button.on_press(Message::MaxApplication(app));
                 row2 = row2.push(max_button);
             } else {
                 let mut max_button = button(icon::from_name("window-restore"));
-                max_button = max_button.on_press(Message::UnMaxApplication(app.clone()));
+                max_button = max_button.on_press(Message::UnMaxApplication(app));
                 row2 = row2.push(max_button);
             }
 
-            let mut close_button = button(icon::from_name("window-close"));
-            close_button = close_button.on_press(Message::CloseApplication(app.clone()));
-            row2 = row2.push(close_button);
+            if self.overdue.contains(&app) {
+                let force_row = row()
+                    .push(icon::from_name("process-stop"))
+                    .push(text("Force quit"));
+                let mut force_button = button(force_row);
+                force_button = force_button.on_press(Message::ForceQuitApplication(app));
+                row2 = row2.push(force_button);
+            } else {
+                let mut close_button = button(icon::from_name("window-close"));
+                close_button = close_button.on_press(Message::CloseApplication(app));
+                row2 = row2.push(close_button);
+            }
 
             let mut container = container(row2);
             container = container.style(match highlight {
                 true => cosmic::theme::Container::Background,
                 false => cosmic::theme::Container::Transparent,
             });
+            let area = widget::mouse_area(container).on_right_press(Message::OpenContextMenu(app));
+            c = c.push(area);
+        }
+        c.into()
+    }
+}
+
+// Scanning ROM directories and every desktop file takes a while, so the
+// list fills in once the window is up. Commands are worked out here too, as
+// finding Proton looks on disk
+fn load_launcher(config: ShellConfig) -> Command<Message> {
+    Command::perform(
+        async move {
+            tokio::task::spawn_blocking(move || {
+                launcher::load_entries(&config.launcher)
+                    .into_iter()
+                    .map(|entry| {
+                        let command = entry.command(&config);
+                        (entry, command)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await
+            .unwrap_or_default()
+        },
+        |entries| cosmic::app::Message::App(Message::LauncherLoaded(entries)),
+    )
+}
+
+// Launcher entries are matched to tracked time by their desktop ID, which
+// is usually the app_id, or by Steam game for Steam shortcuts
+fn playtime_key(entry: &LauncherEntry) -> String {
+    for arg in entry.exec.iter() {
+        if let Some(id) = arg.strip_prefix("steam://rungameid/") {
+            return playtime::steam_key(id);
+        }
+    }
+    entry.id.clone()
+}
+
+impl ConsolationSwitcherApp {
+    fn send_action(&self, action: ToplevelAction) {
+        if let Err(e) = ipc::send(&self.shell, &action) {
+            println!("Unable to reach consolation-shelld: {}", e);
+        }
+    }
+
+    fn send_apps_action(&self, action: AppsAction) {
+        let Some(apps) = &self.apps else {
+            return;
+        };
+        if let Err(e) = ipc::send(apps, &action) {
+            println!("Unable to reach consolation-shelld: {}", e);
+        }
+    }
+
+    fn send_history_action(&self, action: HistoryAction) {
+        let Some(notifications) = &self.notifications else {
+            return;
+        };
+        if let Err(e) = ipc::send(notifications, &action) {
+            println!("Unable to reach consolation-notifyd: {}", e);
+        }
+    }
+
+    // Time is up for a window that was picked, or that was already
+    // open. We've likely minimised ourselves, so come back to say so
+    fn show_time_up(&mut self, rule: String, period: LimitPeriod) -> Command<Message> {
+        if self.time_up.as_ref().map(|(r, _)| r) != Some(&rule) {
+            self.pin_input.clear();
+            self.pin_error = None;
+        }
+        self.time_up = Some((rule, period));
+        self.context_menu = None;
+        self.force_quit = None;
+        self.page = ConsolationPage::TimeUp;
+        let ourselves = self.applist.iter().find(|(_, details)| {
+            details.app_id.as_deref() == Some(ipc::SWITCHER_APP_ID)
+                && !details.state.contains(ToplevelState::ACTIVATED)
+        });
+        if let Some((id, _)) = ourselves {
+            self.send_action(ToplevelAction::Activate { id: *id });
+        }
+        Command::none()
+    }
+
+    fn view_time_up(&self) -> cosmic::Element<Message> {
+        let mut c = column();
+        let Some((rule, period)) = &self.time_up else {
+            return c.into();
+        };
+        c = c.push(text::title3("Time's up"));
+        c = c.push(text(format!(
+            "No time left for {} {}",
+            rule,
+            period.describe()
+        )));
+        if self.config.limits.pin_hash.is_some() {
+            let input = widget::text_input("PIN", self.pin_input.clone())
+                .password()
+                .on_input(Message::PinInput)
+                .on_submit(Message::PinSubmit());
+            c = c.push(input);
+            let label = format!("Add {} minutes", self.config.limits.extra_minutes);
+            c = c.push(button(text(label)).on_press(Message::PinSubmit()));
+            if let Some(retry_after) = self.pin_error {
+                c = c.push(text::caption(format!(
+                    "Wrong PIN, wait {} seconds before trying again",
+                    retry_after
+                )));
+            }
+        }
+        let back = row()
+            .push(icon::from_name("go-previous"))
+            .push(text("Back"));
+        c = c.push(button(back).on_press(Message::ShowWindows()));
+        c.into()
+    }
+
+    fn view_force_quit(&self) -> cosmic::Element<Message> {
+        let mut c = column();
+        let Some(quit) = &self.force_quit else {
+            return c.into();
+        };
+        c = c.push(text::title3(quit.title.clone()));
+        if quit.processes.is_empty() {
+            c = c.push(text("Unable to find the process for this window"));
+        } else {
+            let (warning, label) = match quit.signal {
+                Signal::Term => (
+                    "This window is not responding. Ask its process to quit? Unsaved progress may be lost.",
+                    "Quit",
+                ),
+                _ => (
+                    "The process ignored the request to quit. Kill it? Unsaved progress will be lost.",
+                    "Kill",
+                ),
+            };
+            c = c.push(text(warning));
+            // A name match may have found somebody else's process, so show
+            // exactly what will be signalled
+            if quit.by_name {
+                c = c.push(text(
+                    "These were found by name only and may not belong to this window. Check them first.",
+                ));
+            }
+            for process in &quit.processes {
+                c = c.push(text::caption(format!(
+                    "{}  {}",
+                    process.pid, process.command
+                )));
+            }
+            let confirm = row()
+                .push(icon::from_name("process-stop"))
+                .push(text(label));
+            c = c.push(button(confirm).on_press(Message::ConfirmForceQuit()));
+        }
+        let back = row()
+            .push(icon::from_name("go-previous"))
+            .push(text("Cancel"));
+        c = c.push(button(back).on_press(Message::ShowWindows()));
+        c.into()
+    }
+
+    // Windows in the order they are drawn, skipping those view() hides
+    // Most recently used first. Anything not in the MRU list yet goes last
+    fn visible_apps(&self) -> Vec<(ToplevelId, &ToplevelDetails)> {
+        let mut apps: Vec<(ToplevelId, &ToplevelDetails)> = self
+            .applist
+            .iter()
+            .filter(|(_, details)| {
+                details.title.is_some() && details.title.as_deref() != Some("nil")
+            })
+            .map(|(app, details)| (*app, details))
+            .collect();
+        apps.sort_by_key(|(app, _)| {
+            self.mru
+                .iter()
+                .position(|other| other == app)
+                .unwrap_or(usize::MAX)
+        });
+        apps
+    }
+
+    fn selected_app(&self) -> Option<ToplevelId> {
+        let idx = match self.selection {
+            ConsolationSelection::WindowActivate(idx) => idx,
+            ConsolationSelection::WindowMaxToggle(idx) => idx,
+            ConsolationSelection::WindowClose(idx) => idx,
+            _ => return None,
+        };
+        self.visible_apps().get(idx).map(|(app, _)| *app)
+    }
+
+    fn view_context_menu(&self) -> cosmic::Element<Message> {
+        let mut c = column();
+        let Some(menu) = &self.context_menu else {
+            return c.into();
+        };
+        let Some(details) = self.applist.get(&menu.app) else {
+            return c.into();
+        };
+        let app = menu.app;
+
+        let title = details.title.clone().unwrap_or("No title".to_owned());
+        c = c.push(text::title3(title));
+
+        let mut items: Vec<(&str, String, Message)> = vec![(
+            "window-new",
+            "Activate".to_owned(),
+            Message::ActivateApplication(app),
+        )];
+        if details.state.contains(ToplevelState::MINIMIZED) {
+            items.push((
+                "window-restore",
+                "Restore".to_owned(),
+                Message::UnMinApplication(app),
+            ));
+        } else {
+            items.push((
+                "window-minimize",
+                "Minimise".to_owned(),
+                Message::MinApplication(app),
+            ));
+        }
+        if details.state.contains(ToplevelState::MAXIMIZED) {
+            items.push((
+                "window-restore",
+                "Unmaximise".to_owned(),
+                Message::UnMaxApplication(app),
+            ));
+        } else {
+            items.push((
+                "window-maximize",
+                "Maximise".to_owned(),
+                Message::MaxApplication(app),
+            ));
+        }
+        if details.state.contains(ToplevelState::FULLSCREEN) {
+            items.push((
+                "view-restore",
+                "Leave fullscreen".to_owned(),
+                Message::UnFullscreenApplication(app),
+            ));
+        } else {
+            items.push((
+                "view-fullscreen",
+                "Fullscreen".to_owned(),
+                Message::FullscreenApplication(app),
+            ));
+        }
+        if self.paused.contains(&app) {
+            items.push((
+                "media-playback-start",
+                "Resume".to_owned(),
+                Message::ResumeApplication(app),
+            ));
+        } else {
+            items.push((
+                "media-playback-pause",
+                "Pause".to_owned(),
+                Message::PauseApplication(app),
+            ));
+        }
+        items.push((
+            "window-close",
+            "Close".to_owned(),
+            Message::CloseApplication(app),
+        ));
+
+        for (icon_name, label, message) in items {
+            let row = row().push(icon::from_name(icon_name)).push(text(label));
+            c = c.push(button(row).on_press(message));
+        }
+
+        if !menu.actions.is_empty() {
+            c = c.push(widget::divider::horizontal::default());
+        }
+        for (idx, action) in menu.actions.iter().enumerate() {
+            let mut row = row();
+            if let Some(icon_name) = &action.icon {
+                row = row.push(icon::from_name(icon_name.clone()));
+            }
+            row = row.push(text(action.name.clone()));
+            c = c.push(button(row).on_press(Message::RunDesktopAction(idx)));
+        }
+
+        c = c.push(widget::divider::horizontal::default());
+        let back = row()
+            .push(icon::from_name("go-previous"))
+            .push(text("Back"));
+        c = c.push(button(back).on_press(Message::ShowWindows()));
+        c.into()
+    }
+
+    fn view_launcher(&self) -> cosmic::Element<Message> {
+        let mut c = column();
+        let mut back_button = button(
+            row()
+                .push(icon::from_name("go-previous"))
+                .push(text("Back")),
+        );
+        back_button = back_button.on_press(Message::ShowWindows());
+        c = c.push(back_button);
+
+        let run_prompt = widget::text_input("Run command", self.run_input.clone())
+            .on_input(Message::RunInput)
+            .on_submit(Message::RunSubmit());
+        c = c.push(run_prompt);
+        if let Some(command) = launcher::run_prompt_command(&self.run_input, &self.config) {
+            c = c.push(text::caption(command.preview()));
+        }
+
+        let mut list = column();
+        for (idx, (entry, command)) in self.launcher_entries.iter().enumerate() {
+            let mut row = row();
+            match &entry.icon {
+                Some(LauncherIcon::Name(name)) => {
+                    row = row.push(icon::from_name(name.clone()).size(48));
+                }
+                Some(LauncherIcon::Path(path)) => {
+                    row = row.push(icon::icon(icon::from_path(path.clone())).size(48));
+                }
+                None => {
+                    row = row.push(icon::from_name("application-x-executable").size(48));
+                }
+            }
+            let mut labels = column().push(text(entry.name.clone()));
+            let played = self.playtime.playtime(&playtime_key(entry));
+            if !played.total.is_zero() {
+                labels = labels.push(text::caption(played.describe()));
+            }
+            // Only worth showing the command line when a profile changed it
+            if !command.env.is_empty() || command.argv != entry.exec {
+                labels = labels.push(text::caption(command.preview()));
+            }
+            row = row.push(labels);
+            let mut launch_button = button(row);
+            launch_button = launch_button.on_press(Message::Launch(idx));
+            if self.selection == ConsolationSelection::LauncherEntry(idx) {
+                launch_button = launch_button.style(cosmic::theme::Button::Suggested);
+            }
+            list = list.push(launch_button);
+        }
+        c = c.push(widget::scrollable(list));
+        c.into()
+    }
+
+    fn view_controllers(&self) -> cosmic::Element<Message> {
+        let mut header = row().spacing(24);
+        for controller in &self.controllers {
+            let level = match (controller.capacity, controller.capacity_level) {
+                (Some(capacity), _) => format!("{}%", capacity),
+                (None, Some(level)) => format!("{:?}", level),
+                (None, None) => "Unknown".to_owned(),
+            };
+            let mut item = row()
+                .spacing(8)
+                .push(icon::from_name("input-gaming"))
+                .push(text(controller.label().to_owned()))
+                .push(icon::from_name(controller.icon()))
+                .push(text(level));
+            if controller.is_charging() {
+                item = item.push(text::caption("Charging"));
+            }
+            header = header.push(item);
+        }
+        header.into()
+    }
+
+    // Keep the selection on a notification that still exists
+    fn clamp_notification_selection(&mut self) {
+        if let ConsolationSelection::Notification(idx, button) = self.selection {
+            self.selection = match self.history.len() {
+                0 => ConsolationSelection::DismissAllButton,
+                len if idx >= len => ConsolationSelection::Notification(len - 1, 0),
+                _ => {
+                    let last = self.notification_buttons(idx).len().saturating_sub(1);
+                    ConsolationSelection::Notification(idx, button.min(last))
+                }
+            };
+        }
+    }
+
+    // A notification's buttons, in the order arrow keys move through them
+    fn notification_buttons(&self, idx: usize) -> Vec<(String, Message)> {
+        let Some(entry) = self.history.get(idx) else {
+            return vec![];
+        };
+        let notification = &entry.notification;
+        let mut buttons: Vec<(String, Message)> = notification
+            .actions
+            .iter()
+            .map(|action| {
+                (
+                    action.label.clone(),
+                    Message::InvokeNotification(notification.id, action.key.clone()),
+                )
+            })
+            .collect();
+        buttons.push((
+            "Dismiss".to_owned(),
+            Message::DismissNotification(notification.id),
+        ));
+        buttons
+    }
+
+    fn view_notifications(&self) -> cosmic::Element<Message> {
+        let mut c = column();
+        let back = row()
+            .push(icon::from_name("go-previous"))
+            .push(text("Back"));
+        c = c.push(button(back).on_press(Message::ShowWindows()));
+        c = c.push(text::title3("Notifications"));
+        if self.notifications.is_none() {
+            c = c.push(text("consolation-notifyd isn't running"));
+            return c.into();
+        }
+        if self.history.is_empty() {
+            c = c.push(text("No notifications"));
+            return c.into();
+        }
+
+        let mut dismiss_all = button(text("Dismiss all"));
+        if self.selection == ConsolationSelection::DismissAllButton {
+            dismiss_all = dismiss_all.style(cosmic::theme::Button::Suggested);
+        }
+        c = c.push(dismiss_all.on_press(Message::DismissAllNotifications()));
+
+        let mut list = column();
+        for (idx, entry) in self.history.iter().enumerate() {
+            let notification = &entry.notification;
+            let selected_button = match self.selection {
+                ConsolationSelection::Notification(selected, button) if selected == idx => {
+                    Some(button)
+                }
+                _ => None,
+            };
+
+            let mut row = row();
+            row = row.push(notification_icon(entry));
+            let mut labels = column();
+            let mut heading = notification.app_name.clone();
+            if let Some(received) = received_at(entry.received) {
+                heading = format!("{} · {}", heading, received);
+            }
+            labels = labels.push(text::caption(heading));
+            labels = labels.push(text(notification.summary.clone()));
+            if !notification.body.is_empty() {
+                labels = labels.push(text::caption(notification.body.clone()));
+            }
+            row = row.push(labels);
+            row = row.push(widget::Space::with_width(Length::Fill));
+            for (button_idx, (label, message)) in
+                self.notification_buttons(idx).into_iter().enumerate()
+            {
+                let mut action_button = button(text(label)).on_press(message);
+                if selected_button == Some(button_idx) {
+                    action_button = action_button.style(cosmic::theme::Button::Suggested);
+                }
+                row = row.push(action_button);
+            }
+
+            let mut container = container(row);
+            container = container.style(match selected_button.is_some() {
+                true => cosmic::theme::Container::Background,
+                false => cosmic::theme::Container::Transparent,
+            });
+            list = list.push(container);
+        }
+        c = c.push(widget::scrollable(list));
+        c.into()
+    }
+
+    // Every window but ours, which closes along with the session
+    fn others(&self) -> Vec<ToplevelId> {
+        self.applist
+            .iter()
+            .filter(|(_, details)| details.app_id.as_deref() != Some(ipc::SWITCHER_APP_ID))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    // Carry out the power action waiting on windows, once they're all gone
+    fn check_all_closed(&mut self) {
+        if self.others().is_empty() {
+            if let Some((end, _)) = self.closing_for.take() {
+                self.end_session(end);
+            }
+        }
+    }
+
+    fn end_session(&mut self, end: SessionEnd) {
+        self.power_confirm = None;
+        self.closing_for = None;
+        self.page = ConsolationPage::Windows;
+        self.selection = ConsolationSelection::WindowActivate(0);
+        match end {
+            SessionEnd::Lock => match std::process::Command::new("consolation-lock").spawn() {
+                // Waited on so it doesn't linger as a zombie once unlocked
+                Ok(mut child) => {
+                    std::thread::spawn(move || {
+                        let _ = child.wait();
+                    });
+                }
+                Err(e) => println!("Unable to start consolation-lock: {}", e),
+            },
+            SessionEnd::Power(action) => {
+                let logind = self.logind.clone();
+                std::thread::spawn(move || {
+                    if let Some(logind) = system_logind(&logind) {
+                        if let Err(e) = logind.perform(action) {
+                            println!("Unable to {}: {}", action.label().to_lowercase(), e);
+                        }
+                    }
+                });
+            }
+            SessionEnd::LogOut => {
+                // Compositors exit cleanly on SIGTERM. Ending the logind
+                // session kills everything in it, so is only a fallback
+                match process::compositor_pid() {
+                    Ok(pid) => match process::signal(pid, Signal::Term) {
+                        Ok(()) => return,
+                        Err(e) => println!("Unable to signal compositor {}: {}", pid, e),
+                    },
+                    Err(e) => println!("Unable to find compositor: {}", e),
+                }
+                let logind = self.logind.clone();
+                std::thread::spawn(move || {
+                    if let Some(logind) = system_logind(&logind) {
+                        if let Err(e) = logind.terminate_session() {
+                            println!("Unable to end session: {}", e);
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    // Lock and log out are always there, whatever logind allows goes
+    // in between
+    fn set_power_actions(&mut self, actions: Vec<PowerAction>) {
+        self.power_actions = std::iter::once(SessionEnd::Lock)
+            .chain(actions.into_iter().map(SessionEnd::Power))
+            .chain(std::iter::once(SessionEnd::LogOut))
+            .collect();
+    }
+
+    fn power_confirm_buttons(&self) -> Vec<(String, Message)> {
+        let Some(end) = self.power_confirm else {
+            return vec![];
+        };
+        let mut buttons = vec![];
+        // Suspending or hibernating with games running is also worth
+        // giving them the chance to save first
+        if self.closing_for.is_none() && end != SessionEnd::Lock && !self.others().is_empty() {
+            buttons.push((
+                format!("Close windows, then {}", end.label().to_lowercase()),
+                Message::CloseAllThen(end),
+            ));
+        }
+        buttons.push((format!("{} now", end.label()), Message::EndSession(end)));
+        buttons.push(("Cancel".to_owned(), Message::ShowPowerMenu()));
+        buttons
+    }
+
+    fn view_power_menu(&self) -> cosmic::Element<Message> {
+        let mut c = column();
+        let back = row()
+            .push(icon::from_name("go-previous"))
+            .push(text("Back"));
+        c = c.push(button(back).on_press(Message::ShowWindows()));
+        c = c.push(text::title3("Power"));
+        if let Some(None) = self.logind.get() {
+            c = c.push(text::caption(
+                "logind isn't available, only locking and logging out are possible",
+            ));
+        }
+        for (idx, end) in self.power_actions.iter().enumerate() {
+            let label = row()
+                .push(icon::from_name(end.icon()))
+                .push(text(end.label()));
+            let mut action_button = button(label).on_press(Message::ChooseSessionEnd(*end));
+            if self.selection == ConsolationSelection::PowerAction(idx) {
+                action_button = action_button.style(cosmic::theme::Button::Suggested);
+            }
+            c = c.push(action_button);
+        }
+        c.into()
+    }
+
+    fn view_power_confirm(&self) -> cosmic::Element<Message> {
+        let mut c = column();
+        let Some(end) = self.power_confirm else {
+            return c.into();
+        };
+        let back = row()
+            .push(icon::from_name("go-previous"))
+            .push(text("Back"));
+        c = c.push(button(back).on_press(Message::ShowPowerMenu()));
+        c = c.push(text::title3(format!("{}?", end.label())));
+        let open = self.others().len();
+        if let Some((_, since)) = self.closing_for {
+            let left = CLOSE_TIMEOUT.saturating_sub(since.elapsed());
+            c = c.push(text(format!(
+                "Waiting for {} windows to close, {}s at most",
+                open,
+                left.as_secs()
+            )));
+        } else if end.ends_session() && open > 0 {
+            c = c.push(text(format!(
+                "{} windows are open. Unsaved work in them will be lost",
+                open
+            )));
+        }
+        for (idx, (label, message)) in self.power_confirm_buttons().into_iter().enumerate() {
+            let mut confirm_button = button(text(label)).on_press(message);
+            if self.selection == ConsolationSelection::PowerConfirm(idx) {
+                confirm_button = confirm_button.style(cosmic::theme::Button::Suggested);
+            }
+            c = c.push(confirm_button);
+        }
+        c.into()
+    }
+
+    fn quick_settings_rows(&self) -> Vec<QuickSetting> {
+        self.quick
+            .as_ref()
+            .map(|quick| quick.rows())
+            .unwrap_or_default()
+    }
+
+    fn step_quick_setting(&mut self, idx: usize, direction: i64) -> Command<Message> {
+        let setting = self.quick_settings_rows().get(idx).copied();
+        if let (Some(quick), Some(setting)) = (&mut self.quick, setting) {
+            quick.step(setting, direction);
+        }
+        self.write_quick_settings()
+    }
+
+    // Sliders send a change for every step, so most of these find an
+    // earlier write already took theirs and come back with nothing
+    fn write_quick_settings(&self) -> Command<Message> {
+        let Some(quick) = &self.quick else {
+            return Command::none();
+        };
+        let write = quick.writer();
+        Command::perform(
+            async move { tokio::task::spawn_blocking(write).await.ok().flatten() },
+            |readings| cosmic::app::Message::App(Message::QuickSettingsRead(readings)),
+        )
+    }
+
+    fn view_quick_settings(&self) -> cosmic::Element<Message> {
+        let mut c = column();
+        let back = row()
+            .push(icon::from_name("go-previous"))
+            .push(text("Back"));
+        c = c.push(button(back).on_press(Message::ShowWindows()));
+        c = c.push(text::title3("Quick settings"));
+        let Some(quick) = &self.quick else {
+            return c.into();
+        };
+        let rows = quick.rows();
+        if rows.is_empty() {
+            c = c.push(text("Nothing can be adjusted on this machine"));
+            return c.into();
+        }
+
+        for (idx, setting) in rows.into_iter().enumerate() {
+            let selected = self.selection == ConsolationSelection::QuickSetting(idx);
+            let mut row = row();
+            match setting {
+                QuickSetting::Brightness => {
+                    let brightness = quick.brightness.unwrap_or_default();
+                    row = row
+                        .push(icon::from_name("display-brightness-symbolic"))
+                        .push(text("Brightness"))
+                        .push(widget::slider(0..=100, brightness, Message::SetBrightness))
+                        .push(text(format!("{}%", brightness)));
+                }
+                QuickSetting::Volume => {
+                    let volume = quick.volume.unwrap_or_default();
+                    let icon_name = match volume.muted {
+                        true => "audio-volume-muted-symbolic",
+                        false => "audio-volume-high-symbolic",
+                    };
+                    row = row
+                        .push(icon::from_name(icon_name))
+                        .push(text("Volume"))
+                        .push(widget::slider(
+                            0..=100,
+                            volume.percentage,
+                            Message::SetVolume,
+                        ))
+                        .push(text(format!("{}%", volume.percentage)));
+                }
+                QuickSetting::Mute => {
+                    let muted = quick.volume.is_some_and(|volume| volume.muted);
+                    let mut mute_button = button(text(match muted {
+                        true => "Unmute",
+                        false => "Mute",
+                    }))
+                    .on_press(Message::ToggleMute());
+                    if selected {
+                        mute_button = mute_button.style(cosmic::theme::Button::Suggested);
+                    }
+                    row = row.push(mute_button);
+                }
+                QuickSetting::PowerProfile => {
+                    let active = quick.profile.clone().unwrap_or_default();
+                    let mut profile_button = button(text(profiles::profile_label(&active)));
+                    if let Some(next) = quick.next_profile() {
+                        profile_button = profile_button.on_press(Message::SetPowerProfile(next));
+                    }
+                    if selected {
+                        profile_button = profile_button.style(cosmic::theme::Button::Suggested);
+                    }
+                    row = row
+                        .push(icon::from_name("power-profile-balanced-symbolic"))
+                        .push(text("Power profile"))
+                        .push(widget::Space::with_width(Length::Fill))
+                        .push(profile_button);
+                }
+                QuickSetting::NightLight => {
+                    let status = quick.night_light;
+                    let enabled = status.is_some_and(|status| status.enabled);
+                    let mut toggle_button = button(text(match enabled {
+                        true => "On",
+                        false => "Off",
+                    }))
+                    .on_press(Message::ToggleNightLight());
+                    if selected {
+                        toggle_button = toggle_button.style(cosmic::theme::Button::Suggested);
+                    }
+                    let mut labels = column().push(text("Night light"));
+                    if enabled && status.is_some_and(|status| status.suspended) {
+                        labels = labels.push(text::caption("Paused for a fullscreen game"));
+                    }
+                    row = row
+                        .push(icon::from_name("night-light-symbolic"))
+                        .push(labels)
+                        .push(widget::Space::with_width(Length::Fill))
+                        .push(toggle_button);
+                }
+            }
+
+            let mut container = container(row);
+            container = container.style(match selected {
+                true => cosmic::theme::Container::Background,
+                false => cosmic::theme::Container::Transparent,
+            });
             c = c.push(container);
         }
         c.into()
     }
 }
+
+fn notification_icon(entry: &StoredNotification) -> cosmic::Element<'static, Message> {
+    let notification = &entry.notification;
+    let name = match &notification.image {
+        Some(NotificationImage::Name { name }) => name.clone(),
+        _ if !notification.app_icon.is_empty() => notification.app_icon.clone(),
+        _ => "dialog-information".to_owned(),
+    };
+    let path = name.strip_prefix("file://").unwrap_or(&name);
+    if path.starts_with('/') {
+        icon::icon(icon::from_path(path.into())).size(48).into()
+    } else {
+        icon::from_name(name).size(48).into()
+    }
+}
+
+// Failing to read sysfs just leaves the header empty
+fn read_controllers() -> Vec<PowerSupply> {
+    SysfsPower::system().controllers().unwrap_or_default()
+}
+
+// Blocks, so only called off the UI thread. Not finding logind is only
+// reported once
+fn system_logind(logind: &OnceLock<Option<Logind>>) -> Option<&Logind> {
+    logind
+        .get_or_init(|| {
+            Logind::system()
+                .map_err(|e| println!("Unable to reach logind: {}", e))
+                .ok()
+        })
+        .as_ref()
+}
+
+// Time of day for today's notifications, the date for older ones
+fn received_at(received: u64) -> Option<String> {
+    let received =
+        chrono::DateTime::from_timestamp(received as i64, 0)?.with_timezone(&chrono::Local);
+    if received.date_naive() == chrono::Local::now().date_naive() {
+        Some(received.format("%H:%M").to_string())
+    } else {
+        Some(received.format("%e %b").to_string())
+    }
+}